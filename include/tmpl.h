@@ -0,0 +1,36 @@
+/* C interface of tmpl, built as libtmpl_bindings with
+ * `cargo build --release -p tmpl-bindings --features ffi`.
+ *
+ * Objects returned by these functions are owned by the caller and have to be
+ * released with the matching tmpl_*_free function. On failure functions return
+ * NULL and, if `error` isn't NULL, store a message in `*error` that has to be
+ * released with tmpl_string_free. */
+
+#ifndef TMPL_H
+#define TMPL_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct TmplGrammar TmplGrammar;
+typedef struct TmplAst TmplAst;
+
+/* Parses grammar text. */
+TmplGrammar *tmpl_grammar_compile(const char *src, char **error);
+
+/* Parses `input` with `grammar`, starting at `entry` or at Main if `entry` is NULL. */
+TmplAst *tmpl_parse(const TmplGrammar *grammar, const char *input, const char *entry, char **error);
+
+/* Serializes a syntax tree as JSON. */
+char *tmpl_ast_to_json(const TmplAst *ast);
+
+void tmpl_grammar_free(TmplGrammar *grammar);
+void tmpl_ast_free(TmplAst *ast);
+void tmpl_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TMPL_H */
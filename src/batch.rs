@@ -0,0 +1,79 @@
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use rayon::prelude::*;
+use tmpl::custom::ParseSession;
+use tmpl::definition::ParserDefinition;
+
+use crate::diagnostics::{Diagnostic, ErrorFormat, ErrorKind};
+
+/// Expands glob patterns in `sources`, keeping plain paths (and `-`) as they are.
+pub fn expand(sources: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for source in sources {
+        if source.contains(['*', '?', '[']) {
+            let before = paths.len();
+            for entry in glob::glob(source)? {
+                paths.push(entry?);
+            }
+            if paths.len() == before {
+                anyhow::bail!("no files match `{source}`");
+            }
+        } else {
+            paths.push(PathBuf::from(source));
+        }
+    }
+    Ok(paths)
+}
+
+/// Number of files [`run`] parses at once by default, one per CPU.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Parses every file in `sources` starting at `entry` on a pool of `jobs`
+/// threads, printing one line per file in the order of `sources` and a
+/// summary. A [`ParseSession`] is reused for the files a thread takes in a
+/// row. Tracing prints as the parse goes, so it always uses one thread.
+pub fn run(
+    definition: &Arc<ParserDefinition>,
+    sources: &[PathBuf],
+    entry: &str,
+    jobs: usize,
+    error_format: ErrorFormat,
+    trace: bool,
+) -> anyhow::Result<()> {
+    let jobs = if trace {
+        1
+    } else {
+        jobs.clamp(1, sources.len().max(1))
+    };
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let results: Vec<anyhow::Result<()>> = pool.install(|| {
+        sources
+            .par_iter()
+            .map_init(
+                || ParseSession::new(Arc::clone(definition)).with_trace(trace),
+                |session, src| crate::parse_source(session, src, entry).map(drop),
+            )
+            .collect()
+    });
+    let mut failed = 0;
+    for (src, result) in sources.iter().zip(results) {
+        match result {
+            Ok(()) => println!("{}: ok", src.display()),
+            Err(e) => {
+                failed += 1;
+                crate::diagnostics::emit(&e, error_format);
+            }
+        }
+    }
+    println!("{} ok / {} failed", sources.len() - failed, failed);
+    if failed > 0 {
+        let message = format!("{failed} of {} files failed to parse", sources.len());
+        return Err(Diagnostic::new(ErrorKind::Input, None, None, message).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tmpl::custom::{ParseStats, Parser, Program};
+use tmpl::definition::ParserDefinition;
+
+/// Lexes and parses every file in `sources` `iterations` times and prints
+/// throughput and rule call statistics per file, for both the interpreter
+/// and the compiled [`Program`].
+pub fn run(
+    definition: &Arc<ParserDefinition>,
+    sources: &[PathBuf],
+    iterations: u32,
+) -> anyhow::Result<()> {
+    let iterations = iterations.max(1);
+    let program = Program::compile(definition)?;
+    for src in sources {
+        let input = crate::map_input(src)?;
+        let text = input.text()?;
+
+        let start = Instant::now();
+        let mut tokens = Vec::new();
+        for _ in 0..iterations {
+            tokens = tmpl::lexer::lex_spanned(text)
+                .map_err(|(e, span)| {
+                    anyhow::anyhow!("{}: {e} at byte {}", src.display(), span.start)
+                })?
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+        }
+        let lex_time = start.elapsed() / iterations;
+
+        let mut parse_time = Duration::ZERO;
+        let mut nodes = 0;
+        let mut interpreted = None;
+        let mut stats = ParseStats::default();
+        for _ in 0..iterations {
+            let parser = Parser::new(Arc::clone(definition), tokens.clone()).with_stats(true);
+            let start = Instant::now();
+            let ast = parser.parse()?;
+            parse_time += start.elapsed();
+            nodes = ast.node_count();
+            interpreted = Some(ast);
+            stats = parser.stats().unwrap_or_default();
+        }
+        let parse_time = parse_time / iterations;
+
+        let start = Instant::now();
+        let mut compiled = None;
+        for _ in 0..iterations {
+            compiled = Some(program.parse_entry("Main", &tokens)?);
+        }
+        let compiled_time = start.elapsed() / iterations;
+        let same = compiled == interpreted;
+
+        println!("{}:", src.display());
+        println!(
+            "  lex:   {:>10.3?}/iter  {:>12.0} tokens/s  ({} tokens)",
+            lex_time,
+            per_second(tokens.len(), lex_time),
+            tokens.len()
+        );
+        println!(
+            "  parse: {:>10.3?}/iter  {:>12.0} nodes/s   ({} nodes)",
+            parse_time,
+            per_second(nodes, parse_time),
+            nodes
+        );
+        println!(
+            "  vm:    {:>10.3?}/iter  {:>12.0} nodes/s   ({:.1}x{})",
+            compiled_time,
+            per_second(nodes, compiled_time),
+            parse_time.as_secs_f64() / compiled_time.as_secs_f64().max(f64::EPSILON),
+            if same { "" } else { ", trees differ" }
+        );
+        println!(
+            "  rules: {} calls, {} repeated at the same position (memoizable)",
+            stats.rule_calls, stats.repeated_calls
+        );
+    }
+    Ok(())
+}
+
+fn per_second(count: usize, time: Duration) -> f64 {
+    count as f64 / time.as_secs_f64().max(f64::EPSILON)
+}
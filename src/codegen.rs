@@ -0,0 +1,31 @@
+//! Rust code generation for grammars.
+//!
+//! The generator can run from a build script, so the typed AST and its parser
+//! stay in sync with the grammar file:
+//!
+//! ```no_run
+//! // build.rs
+//! let grammar = tmpl::Grammar::from_file("grammar.tmpl").unwrap();
+//! let options = tmpl::codegen::Options {
+//!     embedded: true,
+//!     ..Default::default()
+//! };
+//! let code = tmpl::codegen::generate_with(grammar.definition(), &options).unwrap();
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("grammar.rs");
+//! std::fs::write(out, code).unwrap();
+//! println!("cargo::rerun-if-changed=grammar.tmpl");
+//! ```
+//!
+//! ```ignore
+//! // src/lib.rs
+//! #[allow(dead_code, non_snake_case, unused_mut, unused_variables, clippy::all)]
+//! mod grammar {
+//!     include!(concat!(env!("OUT_DIR"), "/grammar.rs"));
+//! }
+//!
+//! let ast: grammar::Main = grammar::parse("let x = 1;")?;
+//! ```
+
+mod rust;
+
+pub use rust::{generate, generate_with, CodegenError, Options};
@@ -0,0 +1,356 @@
+// Support code shared by every generated parser.
+
+use std::fmt;
+
+/// A token, lexed the same way tmpl lexes its input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ws,
+    True,
+    False,
+    String(String),
+    Symbol(char),
+    Ident(String),
+    Float(f64),
+    Integer(i64),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ws => write!(f, " "),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::String(s) => write!(f, "\"{s}\""),
+            Token::Symbol(c) => write!(f, "{c}"),
+            Token::Ident(s) => write!(f, "{s}"),
+            Token::Float(v) => write!(f, "{v}"),
+            Token::Integer(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    InvalidLexeme(usize),
+    UnexpectedToken {
+        index: usize,
+        found: String,
+        expected: String,
+    },
+    UnexpectedEof(String),
+    TrailingInput(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLexeme(offset) => write!(f, "Invalid lexeme at byte {offset}"),
+            Error::UnexpectedToken {
+                index,
+                found,
+                expected,
+            } => write!(
+                f,
+                "Unexpected token `{found}` at index {index}, expected {expected}"
+            ),
+            Error::UnexpectedEof(expected) => {
+                write!(f, "Unexpected end of input, expected {expected}")
+            }
+            Error::TrailingInput(index) => {
+                write!(f, "Unconsumed input starting at token {index}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const SYMBOLS: &str = "-+*/=>\\.:,;<>!$%&?@|()[]{}";
+
+/// Splits `src` into tokens.
+pub fn lex(src: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = src[pos..].chars().next() {
+        let rest = &src[pos..];
+        let (token, len) = if matches!(c, ' ' | '\t' | '\n') || rest.starts_with("\r\n") {
+            let mut len = 0;
+            loop {
+                let rest = &rest[len..];
+                if rest.starts_with(['\t', ' ', '\n']) {
+                    len += 1;
+                } else if rest.starts_with("\r\n") {
+                    len += 2;
+                } else {
+                    break;
+                }
+            }
+            (Token::Ws, len)
+        } else if c == '"' {
+            let mut chars = rest.char_indices().skip(1);
+            let mut end = None;
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some((_, '\n')) | None => break,
+                        Some(_) => {}
+                    },
+                    _ => {}
+                }
+            }
+            let len = end.ok_or(Error::InvalidLexeme(pos))?;
+            let content = rest[1..len - 1].replace("\\\"", "\"").replace("\\\\", "\\");
+            (Token::String(content), len)
+        } else if SYMBOLS.contains(c) {
+            (Token::Symbol(c), 1)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let token = match &rest[..len] {
+                "true" => Token::True,
+                "false" => Token::False,
+                ident => Token::Ident(ident.to_string()),
+            };
+            (token, len)
+        } else if c.is_ascii_digit() {
+            let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+            let mut len = digits(rest);
+            let token = if rest[len..].starts_with('.') {
+                len += 1 + digits(&rest[len + 1..]);
+                rest[..len].parse().map(Token::Float).ok()
+            } else {
+                rest[..len].parse().map(Token::Integer).ok()
+            };
+            (token.ok_or(Error::InvalidLexeme(pos))?, len)
+        } else {
+            return Err(Error::InvalidLexeme(pos));
+        };
+        tokens.push(token);
+        pos += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_ws(&mut self) {
+        while matches!(self.tokens.get(self.pos), Some(Token::Ws)) {
+            self.pos += 1;
+        }
+    }
+
+    fn fail<T>(&self, expected: &str) -> Result<T, Error> {
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(Error::UnexpectedToken {
+                index: self.pos,
+                found: token.to_string(),
+                expected: expected.to_string(),
+            }),
+            None => Err(Error::UnexpectedEof(expected.to_string())),
+        }
+    }
+
+    fn expect<T>(
+        &mut self,
+        expected: &str,
+        f: impl FnOnce(&Token) -> Option<T>,
+    ) -> Result<T, Error> {
+        self.skip_ws();
+        match self.tokens.get(self.pos).and_then(f) {
+            Some(value) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            None => self.fail(expected),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        self.expect("identifier", |token| match token {
+            Token::Ident(s) => Some(s.clone()),
+            _ => None,
+        })
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        self.expect("integer", |token| match token {
+            Token::Integer(i) => Some(*i),
+            _ => None,
+        })
+    }
+
+    fn float(&mut self) -> Result<f64, Error> {
+        self.expect("float", |token| match token {
+            Token::Float(f) => Some(*f),
+            _ => None,
+        })
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.expect("string", |token| match token {
+            Token::String(s) => Some(s.clone()),
+            _ => None,
+        })
+    }
+
+    fn boolean(&mut self) -> Result<bool, Error> {
+        self.expect("bool", |token| match token {
+            Token::True => Some(true),
+            Token::False => Some(false),
+            _ => None,
+        })
+    }
+
+    fn word(&mut self, word: &str) -> Result<String, Error> {
+        self.expect(&format!("`{word}`"), |token| match token {
+            Token::Ident(s) if s == word => Some(word.to_string()),
+            Token::True if word == "true" => Some(word.to_string()),
+            Token::False if word == "false" => Some(word.to_string()),
+            _ => None,
+        })
+    }
+
+    /// Symbols are lexed one character at a time, so a multi character symbol
+    /// has to match a run of adjacent symbol tokens.
+    fn literal(&mut self, literal: &str) -> Result<String, Error> {
+        if literal.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return self.word(literal);
+        }
+        self.skip_ws();
+        let start = self.pos;
+        for c in literal.chars() {
+            if self.tokens.get(self.pos) == Some(&Token::Symbol(c)) {
+                self.pos += 1;
+            } else {
+                let error = self.fail(&format!("`{literal}`"));
+                self.pos = start;
+                return error;
+            }
+        }
+        Ok(literal.to_string())
+    }
+
+    /// Number of tokens `literal` spans if it is at `index`, matched like
+    /// `literal`.
+    fn literal_len(&self, index: usize, literal: &str) -> Option<usize> {
+        if literal.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return match self.tokens.get(index)? {
+                Token::Ident(s) if s == literal => Some(1),
+                Token::True if literal == "true" => Some(1),
+                Token::False if literal == "false" => Some(1),
+                _ => None,
+            };
+        }
+        (literal.chars().enumerate())
+            .all(|(i, c)| self.tokens.get(index + i) == Some(&Token::Symbol(c)))
+            .then(|| literal.chars().count())
+    }
+
+    /// The longest of `options` that is next.
+    fn one_of(&mut self, options: &[&str], expected: &str) -> Result<String, Error> {
+        self.skip_ws();
+        let longest = (options.iter())
+            .filter_map(|option| Some((*option, self.literal_len(self.pos, option)?)))
+            .max_by_key(|(_, len)| *len);
+        match longest {
+            Some((option, len)) => {
+                self.pos += len;
+                Ok(option.to_string())
+            }
+            None => self.fail(expected),
+        }
+    }
+
+    /// `open` up to the `close` matching it, returning the tokens in between.
+    fn balanced(&mut self, open: &str, close: &str) -> Result<String, Error> {
+        self.skip_ws();
+        let start = self.pos;
+        let Some(len) = self.literal_len(start, open) else {
+            return self.fail(&format!("`{open}`"));
+        };
+        let content = start + len;
+        let mut index = content;
+        let mut depth = 1;
+        loop {
+            if let Some(len) = self.literal_len(index, close) {
+                depth -= 1;
+                if depth == 0 {
+                    let text: String = self.tokens[content..index]
+                        .iter()
+                        .map(Token::to_string)
+                        .collect();
+                    self.pos = index + len;
+                    return Ok(text.trim().to_string());
+                }
+                index += len;
+            } else if let Some(len) = self.literal_len(index, open).filter(|len| *len > 0) {
+                depth += 1;
+                index += len;
+            } else if index < self.tokens.len() {
+                index += 1;
+            } else {
+                self.pos = index;
+                let error = self.fail(&format!("`{close}`"));
+                self.pos = start;
+                return error;
+            }
+        }
+    }
+
+    fn repeat<T>(
+        &mut self,
+        at_least_one: bool,
+        separator: Option<&str>,
+        mut item: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut items = Vec::new();
+        loop {
+            let pos = self.pos;
+            if let (Some(separator), false) = (separator, items.is_empty()) {
+                if self.literal(separator).is_err() {
+                    self.pos = pos;
+                    break;
+                }
+            }
+            match item(self) {
+                Ok(value) => items.push(value),
+                Err(e) => {
+                    self.pos = pos;
+                    if items.is_empty() && at_least_one {
+                        return Err(e);
+                    }
+                    break;
+                }
+            }
+            // an item that consumes nothing would otherwise repeat forever
+            if self.pos == pos {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// Lexes and parses `src`, starting at the `Main` rule.
+pub fn parse(src: &str) -> Result<Main, Error> {
+    let mut parser = Parser {
+        tokens: lex(src)?,
+        pos: 0,
+    };
+    let main = parser.rule_Main()?;
+    parser.skip_ws();
+    if parser.pos < parser.tokens.len() {
+        return Err(Error::TrailingInput(parser.pos));
+    }
+    Ok(main)
+}
@@ -0,0 +1,476 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use crate::definition::{
+    expected_one_of, InternalPattern, InternalPatternKind, ParserDefinition, RepeatMode, Sequence,
+    TokenPattern,
+};
+
+const RUNTIME: &str = include_str!("runtime.rs");
+
+/// Names the generated code defines or refers to itself, which rules can't use.
+const RESERVED_NAMES: [&str; 17] = [
+    "Token", "Error", "Parser", "String", "Vec", "Option", "Box", "Result", "Ok", "Err", "Some",
+    "None", "fmt", "Self", "self", "super", "crate",
+];
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "try", "yield", "macro",
+];
+
+pub type Result<T> = std::result::Result<T, CodegenError>;
+
+#[derive(Error, Debug)]
+pub enum CodegenError {
+    #[error("`{0}` clashes with a name used by the generated code")]
+    ReservedName(String),
+    #[error("Rule `{rule}` references unknown rule `{reference}`")]
+    UnknownRule { rule: String, reference: String },
+    #[error("Field `{field}` of rule `{rule}` has conflicting types `{first}` and `{second}`")]
+    ConflictingField {
+        rule: String,
+        field: String,
+        first: String,
+        second: String,
+    },
+    #[error("Rule `{0}` contains an optional or repeated nested sequence")]
+    UnsupportedPattern(String),
+}
+
+/// Type a capture has inside its alternative, before wrapping.
+#[derive(Debug, Clone, PartialEq)]
+struct Base {
+    name: String,
+    /// Rule types are boxed unless they are stored in a `Vec`.
+    rule: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FieldType {
+    Plain(Base),
+    Optional(Base),
+    List(Base),
+}
+
+impl FieldType {
+    fn rust(&self) -> String {
+        let boxed = |base: &Base| match base.rule {
+            true => format!("Box<{}>", base.name),
+            false => base.name.clone(),
+        };
+        match self {
+            FieldType::Plain(base) => boxed(base),
+            FieldType::Optional(base) => format!("Option<{}>", boxed(base)),
+            FieldType::List(base) => format!("Vec<{}>", base.name),
+        }
+    }
+
+    /// The type of a field that isn't captured by every alternative.
+    fn optional(self) -> Self {
+        match self {
+            FieldType::Plain(base) => FieldType::Optional(base),
+            other => other,
+        }
+    }
+}
+
+/// Fields in the order they are first captured.
+type Fields = Vec<(String, FieldType)>;
+
+/// Tweaks to the generated code, see [`generate_with`].
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Leaves out the file level `#![allow(...)]`, which Rust rejects in code
+    /// pulled into a module with `include!`.
+    pub embedded: bool,
+    /// Derives serde's `Serialize` and `Deserialize` for the AST types, which
+    /// makes the output depend on `serde` with the `derive` feature.
+    pub serde: bool,
+}
+
+/// Generates a standalone recursive descent parser for `definition`.
+///
+/// Every rule becomes a struct holding its named captures plus a method on the
+/// generated `Parser`, and `parse` lexes and parses a source string starting at
+/// `Main`. The output only depends on the `regex` crate, and only if the
+/// grammar uses regex patterns.
+///
+/// ```
+/// use tmpl::Grammar;
+///
+/// let src = "Main:\n<name:ident> = <value:int> <items:Item>*\n~~~\n\nItem:\n<flag:bool>\n~~~\n";
+/// let code = tmpl::codegen::generate(Grammar::parse(src)?.definition())?;
+/// let main = "pub struct Main {\n    pub name: String,\n    pub value: i64,\n    \
+///     pub items: Vec<Item>,\n}";
+/// assert!(code.contains(main));
+/// # let dir = std::env::temp_dir().join(format!("tmpl-codegen-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir)?;
+/// # std::fs::write(dir.join("grammar.rs"), &code)?;
+/// # let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+/// # let status = std::process::Command::new(rustc)
+/// #     .args(["--edition", "2021", "--crate-type", "lib", "--out-dir"])
+/// #     .arg(&dir)
+/// #     .arg(dir.join("grammar.rs"))
+/// #     .status()?;
+/// # std::fs::remove_dir_all(&dir)?;
+/// # assert!(status.success(), "the generated parser doesn't compile");
+///
+/// // Rules can't take names the generated code uses itself.
+/// let ok = Grammar::parse("Main:\n<ok:Ok>\n~~~\n\nOk:\n<n:int>\n~~~\n")?;
+/// assert!(tmpl::codegen::generate(ok.definition()).is_err());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub fn generate(definition: &ParserDefinition) -> Result<String> {
+    generate_with(definition, &Options::default())
+}
+
+/// Same as [`generate`], with `options` applied.
+pub fn generate_with(definition: &ParserDefinition, options: &Options) -> Result<String> {
+    let mut generator = Generator {
+        definition,
+        regexes: Vec::new(),
+    };
+    let mut types = String::new();
+    let mut methods = String::new();
+    for (name, patterns) in definition.all_rules() {
+        if RESERVED_NAMES.contains(&name) || RUST_KEYWORDS.contains(&name) {
+            return Err(CodegenError::ReservedName(name.to_string()));
+        }
+        let alternatives: Vec<&Sequence> = patterns.iter().flat_map(|p| p.alternatives()).collect();
+        let captured = alternatives
+            .iter()
+            .map(|alternative| generator.fields(name, alternative))
+            .collect::<Result<Vec<_>>>()?;
+        let fields = merge_fields(name, &captured)?;
+        types.push_str(&struct_definition(name, &fields, options.serde));
+        methods.push_str(&generator.rule_method(name, alternatives.len()));
+        for (index, alternative) in alternatives.iter().enumerate() {
+            methods.push_str(&generator.alternative_method(name, index, alternative, &fields)?);
+        }
+    }
+
+    let mut out = String::from("// Generated by `tmpl codegen`, do not edit by hand.\n");
+    if !options.embedded {
+        out.push_str(
+            "#![allow(dead_code, non_snake_case, unused_mut, unused_variables, clippy::all)]\n",
+        );
+    }
+    out.push('\n');
+    out.push_str(RUNTIME);
+    out.push('\n');
+    out.push_str(&types);
+    let _ = writeln!(out, "impl Parser {{{methods}}}");
+    if !generator.regexes.is_empty() {
+        out.push_str(&regex_support(&generator.regexes));
+    }
+    Ok(out)
+}
+
+struct Generator<'a> {
+    definition: &'a ParserDefinition,
+    /// Source of every regex pattern, indexed by the static holding it.
+    regexes: Vec<String>,
+}
+
+impl Generator<'_> {
+    /// Collects the captures of one alternative. A field captured twice keeps
+    /// the last value, like the runtime parser does.
+    fn fields(&self, rule: &str, sequence: &[TokenPattern]) -> Result<Fields> {
+        let mut fields = Fields::new();
+        for token in sequence {
+            match &token.pattern {
+                InternalPattern::Exact { pattern } => {
+                    if token.is_optional || token.repeat_mode.is_some() {
+                        return Err(CodegenError::UnsupportedPattern(rule.to_string()));
+                    }
+                    for (field, ty) in self.fields(rule, pattern)? {
+                        insert_field(rule, &mut fields, field, ty)?;
+                    }
+                }
+                InternalPattern::Named {
+                    name: Some(name),
+                    kind,
+                } => {
+                    let base = self.base(rule, kind)?;
+                    let ty = match (&token.repeat_mode, token.is_optional) {
+                        (Some(_), _) => FieldType::List(base),
+                        (None, true) => FieldType::Optional(base),
+                        (None, false) => FieldType::Plain(base),
+                    };
+                    insert_field(rule, &mut fields, field_name(name)?, ty)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(fields)
+    }
+
+    fn base(&self, rule: &str, kind: &InternalPatternKind) -> Result<Base> {
+        let name = match kind {
+            InternalPatternKind::Int => "i64",
+            InternalPatternKind::Float => "f64",
+            InternalPatternKind::Bool => "bool",
+            InternalPatternKind::Custom(name) => {
+                if self.definition.rule(name).is_none() {
+                    return Err(CodegenError::UnknownRule {
+                        rule: rule.to_string(),
+                        reference: name.clone(),
+                    });
+                }
+                return Ok(Base {
+                    name: name.clone(),
+                    rule: true,
+                });
+            }
+            _ => "String",
+        };
+        Ok(Base {
+            name: name.to_string(),
+            rule: false,
+        })
+    }
+
+    fn rule_method(&self, rule: &str, alternatives: usize) -> String {
+        let mut out = format!(
+            "\n    fn rule_{rule}(&mut self) -> Result<{rule}, Error> {{\n        \
+             let start = self.pos;\n        \
+             let mut result = self.alt_{rule}_0();\n"
+        );
+        for index in 1..alternatives {
+            let _ = writeln!(
+                out,
+                "        if result.is_err() {{\n            \
+                 self.pos = start;\n            \
+                 result = self.alt_{rule}_{index}();\n        }}"
+            );
+        }
+        out.push_str(
+            "        if result.is_err() {\n            self.pos = start;\n        }\n        \
+             result\n    }\n",
+        );
+        out
+    }
+
+    fn alternative_method(
+        &mut self,
+        rule: &str,
+        index: usize,
+        sequence: &[TokenPattern],
+        fields: &Fields,
+    ) -> Result<String> {
+        let mut out =
+            format!("\n    fn alt_{rule}_{index}(&mut self) -> Result<{rule}, Error> {{\n");
+        let captured = self.fields(rule, sequence)?;
+        self.sequence(sequence, &mut out)?;
+        let _ = writeln!(out, "        Ok({rule} {{");
+        for (field, ty) in fields {
+            let value = match captured.iter().find(|(name, _)| name == field) {
+                Some((_, local)) if local == ty => format!("v_{}", unescaped(field)),
+                Some(_) => format!("Some(v_{})", unescaped(field)),
+                None if matches!(ty, FieldType::List(_)) => "Vec::new()".to_string(),
+                None => "None".to_string(),
+            };
+            let _ = writeln!(out, "            {field}: {value},");
+        }
+        out.push_str("        })\n    }\n");
+        Ok(out)
+    }
+
+    fn sequence(&mut self, sequence: &[TokenPattern], out: &mut String) -> Result<()> {
+        for token in sequence {
+            if let InternalPattern::Exact { pattern } = &token.pattern {
+                self.sequence(pattern, out)?;
+                continue;
+            }
+            let binding = match &token.pattern {
+                InternalPattern::Named {
+                    name: Some(name), ..
+                } => format!("let v_{} = ", unescaped(&field_name(name)?)),
+                _ => "let _ = ".to_string(),
+            };
+            match &token.repeat_mode {
+                Some(repeat_mode) => {
+                    let at_least_one = matches!(repeat_mode, RepeatMode::OneOrMore);
+                    let separator = match &token.separator {
+                        Some(separator) => format!("Some({separator:?})"),
+                        None => "None".to_string(),
+                    };
+                    let item = self.atom(&token.pattern, "p", false);
+                    let _ = writeln!(
+                        out,
+                        "        {binding}self.repeat({at_least_one}, {separator}, |p| {item})?;"
+                    );
+                }
+                None if token.is_optional => {
+                    let item = self.atom(&token.pattern, "self", true);
+                    let _ = writeln!(
+                        out,
+                        "        let pos = self.pos;\n        \
+                         {binding}match {item} {{\n            \
+                         Ok(value) => Some(value),\n            \
+                         Err(_) => {{\n                \
+                         self.pos = pos;\n                \
+                         None\n            }}\n        }};"
+                    );
+                }
+                None => {
+                    let item = self.atom(&token.pattern, "self", true);
+                    let _ = writeln!(out, "        {binding}{item}?;");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Expression parsing a single pattern through `receiver`.
+    fn atom(&mut self, pattern: &InternalPattern, receiver: &str, boxed: bool) -> String {
+        match pattern {
+            InternalPattern::Named { kind, .. } => match kind {
+                InternalPatternKind::Ident => format!("{receiver}.ident()"),
+                InternalPatternKind::Int => format!("{receiver}.int()"),
+                InternalPatternKind::Float => format!("{receiver}.float()"),
+                InternalPatternKind::String => format!("{receiver}.string()"),
+                InternalPatternKind::Bool => format!("{receiver}.boolean()"),
+                InternalPatternKind::Regex(regex) => {
+                    let index = match self.regexes.iter().position(|r| r == regex.as_str()) {
+                        Some(index) => index,
+                        None => {
+                            self.regexes.push(regex.as_str().to_string());
+                            self.regexes.len() - 1
+                        }
+                    };
+                    format!("{receiver}.regex(&REGEX_{index}, {:?})", regex.as_str())
+                }
+                InternalPatternKind::Keyword(word) => format!("{receiver}.word({word:?})"),
+                InternalPatternKind::Symbol(symbol) => format!("{receiver}.literal({symbol:?})"),
+                InternalPatternKind::OneOf(options) => format!(
+                    "{receiver}.one_of(&{options:?}, {:?})",
+                    expected_one_of(options)
+                ),
+                InternalPatternKind::Balanced { open, close } => {
+                    format!("{receiver}.balanced({open:?}, {close:?})")
+                }
+                InternalPatternKind::Custom(name) if boxed => {
+                    format!("{receiver}.rule_{name}().map(Box::new)")
+                }
+                InternalPatternKind::Custom(name) => format!("{receiver}.rule_{name}()"),
+            },
+            InternalPattern::Raw { value } => format!("{receiver}.literal({value:?})"),
+            InternalPattern::Exact { .. } => unreachable!("nested sequences are inlined"),
+        }
+    }
+}
+
+fn insert_field(rule: &str, fields: &mut Fields, field: String, ty: FieldType) -> Result<()> {
+    match fields.iter_mut().find(|(name, _)| *name == field) {
+        Some((_, existing)) if *existing == ty => {}
+        Some((_, existing)) => {
+            return Err(CodegenError::ConflictingField {
+                rule: rule.to_string(),
+                field,
+                first: existing.rust(),
+                second: ty.rust(),
+            })
+        }
+        None => fields.push((field, ty)),
+    }
+    Ok(())
+}
+
+/// Combines the captures of all alternatives into the fields of the rule's
+/// struct. Fields missing from some alternative become optional.
+fn merge_fields(rule: &str, alternatives: &[Fields]) -> Result<Fields> {
+    let mut merged = Fields::new();
+    for fields in alternatives {
+        for (field, ty) in fields {
+            let everywhere = alternatives
+                .iter()
+                .all(|other| other.iter().any(|(name, _)| name == field));
+            let ty = if everywhere {
+                ty.clone()
+            } else {
+                ty.clone().optional()
+            };
+            let existing = merged.iter_mut().find(|(name, _)| name == field);
+            match existing {
+                None => merged.push((field.clone(), ty)),
+                Some((_, existing)) if *existing == ty => {}
+                Some((_, existing)) => match (existing.clone(), ty) {
+                    (FieldType::Plain(a), FieldType::Optional(b))
+                    | (FieldType::Optional(a), FieldType::Plain(b))
+                        if a == b =>
+                    {
+                        *existing = FieldType::Optional(a)
+                    }
+                    (first, second) => {
+                        return Err(CodegenError::ConflictingField {
+                            rule: rule.to_string(),
+                            field: field.clone(),
+                            first: first.rust(),
+                            second: second.rust(),
+                        })
+                    }
+                },
+            }
+        }
+    }
+    Ok(merged)
+}
+
+fn struct_definition(rule: &str, fields: &Fields, serde: bool) -> String {
+    let derives = match serde {
+        true => "Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize",
+        false => "Debug, Clone, PartialEq",
+    };
+    let mut out = format!("#[derive({derives})]\npub struct {rule} {{\n");
+    for (field, ty) in fields {
+        let _ = writeln!(out, "    pub {field}: {},", ty.rust());
+    }
+    out.push_str("}\n\n");
+    out
+}
+
+/// Turns a capture name into a field name, escaping Rust keywords.
+fn field_name(name: &str) -> Result<String> {
+    match name {
+        "self" | "Self" | "super" | "crate" | "_" => {
+            Err(CodegenError::ReservedName(name.to_string()))
+        }
+        _ if RUST_KEYWORDS.contains(&name) => Ok(format!("r#{name}")),
+        _ => Ok(name.to_string()),
+    }
+}
+
+fn unescaped(field: &str) -> &str {
+    field.strip_prefix("r#").unwrap_or(field)
+}
+
+fn regex_support(regexes: &[String]) -> String {
+    let mut out = String::from(
+        "\nimpl Parser {\n    \
+         fn regex(\n        \
+         &mut self,\n        \
+         regex: &'static std::sync::OnceLock<regex::Regex>,\n        \
+         pattern: &str,\n    \
+         ) -> Result<String, Error> {\n        \
+         let regex = regex.get_or_init(|| regex::Regex::new(pattern).expect(\"invalid regex\"));\n        \
+         self.expect(&format!(\"/{pattern}/\"), |token| {\n            \
+         let text = token.to_string();\n            \
+         let full = regex\n                \
+         .find(&text)\n                \
+         .is_some_and(|m| m.start() == 0 && m.end() == text.len());\n            \
+         (full && *token != Token::Ws).then_some(text)\n        \
+         })\n    }\n}\n\n",
+    );
+    for index in 0..regexes.len() {
+        let _ = writeln!(
+            out,
+            "static REGEX_{index}: std::sync::OnceLock<regex::Regex> = std::sync::OnceLock::new();"
+        );
+    }
+    out
+}
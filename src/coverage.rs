@@ -0,0 +1,181 @@
+use std::fmt::{self, Display, Write};
+
+use serde::Serialize;
+
+use crate::custom::RuleCoverage;
+use crate::definition::ParserDefinition;
+
+/// Which alternatives of every rule of a grammar matched while parsing a
+/// corpus, to find the branches the corpus doesn't test.
+///
+/// ```
+/// use tmpl::coverage::CoverageReport;
+/// use tmpl::custom::ParseSession;
+///
+/// let definition = tmpl::definition::parse("Main:\n| <n:int>\n| <s:string>\n~~~\n")?;
+/// let mut session = ParseSession::new(definition).with_coverage(true);
+/// session.lex("1").unwrap();
+/// session.parse().unwrap();
+/// let report = CoverageReport::new(session.definition(), session.coverage().unwrap());
+/// assert_eq!((report.covered(), report.total()), (1, 2));
+/// assert!(report.to_string().contains("! 0"));
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverageReport {
+    pub rules: Vec<RuleReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleReport {
+    pub name: String,
+    pub alternatives: Vec<AlternativeReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlternativeReport {
+    /// The alternative as it's written in the grammar.
+    pub pattern: String,
+    /// How often the alternative matched.
+    pub hits: usize,
+}
+
+impl RuleReport {
+    /// Whether any alternative of the rule matched.
+    pub fn is_used(&self) -> bool {
+        self.alternatives
+            .iter()
+            .any(|alternative| alternative.hits > 0)
+    }
+}
+
+impl CoverageReport {
+    /// The report of the matches in `coverage` for the rules of `definition`.
+    pub fn new(definition: &ParserDefinition, coverage: &RuleCoverage) -> Self {
+        let rules = (definition.all_rules().into_iter())
+            .map(|(name, patterns)| RuleReport {
+                name: name.to_string(),
+                alternatives: (patterns.iter().flat_map(|p| p.alternatives()))
+                    .enumerate()
+                    .map(|(index, alternative)| AlternativeReport {
+                        pattern: (alternative.iter())
+                            .map(|token| token.to_string())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        hits: coverage.hits(name, index),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Number of alternatives that matched at least once.
+    pub fn covered(&self) -> usize {
+        self.alternatives()
+            .filter(|alternative| alternative.hits > 0)
+            .count()
+    }
+
+    /// Number of alternatives of all rules.
+    pub fn total(&self) -> usize {
+        self.alternatives().count()
+    }
+
+    /// Share of alternatives that matched, between 0 and 1. A grammar
+    /// without alternatives is fully covered.
+    pub fn ratio(&self) -> f64 {
+        match self.total() {
+            0 => 1.0,
+            total => self.covered() as f64 / total as f64,
+        }
+    }
+
+    fn alternatives(&self) -> impl Iterator<Item = &AlternativeReport> {
+        self.rules.iter().flat_map(|rule| &rule.alternatives)
+    }
+
+    /// The report as an HTML page, with the alternatives that never matched
+    /// highlighted.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Grammar coverage</title>\n<style>\n");
+        html.push_str(CSS);
+        html.push_str("</style>\n</head>\n<body>\n");
+        let _ = writeln!(
+            html,
+            "<h1>{} of {} alternatives covered ({:.1}%)</h1>",
+            self.covered(),
+            self.total(),
+            self.ratio() * 100.0
+        );
+        for rule in &self.rules {
+            let class = if rule.is_used() {
+                "rule"
+            } else {
+                "rule unused"
+            };
+            let _ = writeln!(html, "<h2 id=\"{0}\" class=\"{class}\">{0}</h2>", rule.name);
+            html.push_str("<table>\n");
+            for alternative in &rule.alternatives {
+                let class = if alternative.hits > 0 { "hit" } else { "miss" };
+                let _ = writeln!(
+                    html,
+                    "<tr class=\"{class}\"><td>{}</td><td><code>{}</code></td></tr>",
+                    alternative.hits,
+                    escape(&alternative.pattern)
+                );
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+/// Lists every rule with the match count of each of its alternatives,
+/// marking those that never matched with `!`.
+impl Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = (self.alternatives())
+            .map(|alternative| alternative.hits.to_string().len())
+            .max()
+            .unwrap_or(1);
+        for rule in &self.rules {
+            let marker = if rule.is_used() { "" } else { "  (never used)" };
+            writeln!(f, "{}:{marker}", rule.name)?;
+            for alternative in &rule.alternatives {
+                let marker = if alternative.hits > 0 { ' ' } else { '!' };
+                writeln!(
+                    f,
+                    "{marker} {:>width$}  {}",
+                    alternative.hits, alternative.pattern
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} alternatives covered ({:.1}%)",
+            self.covered(),
+            self.total(),
+            self.ratio() * 100.0
+        )
+    }
+}
+
+const CSS: &str = "\
+body { font-family: sans-serif; }
+table { border-collapse: collapse; }
+td { padding: 2px 8px; text-align: right; }
+td + td { text-align: left; }
+.unused { color: #b00; }
+.miss { background: #fdd; }
+.hit { background: #dfd; }
+";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,5 +1,15 @@
 pub mod ast;
+mod diff;
+mod intern;
 mod parser;
+mod program;
+mod session;
 
-pub use ast::Ast;
-pub use parser::Parser;
+pub use ast::{Ast, Node};
+pub use diff::{diff, AstChange};
+pub use intern::{Interner, Symbol};
+pub use parser::{
+    ErrorContext, ParseError, ParseStats, Parser, RuleCoverage, RuleSpan, CONTEXT_TOKENS,
+};
+pub use program::Program;
+pub use session::ParseSession;
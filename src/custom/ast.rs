@@ -1,4 +1,98 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Ast {}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ast {
+    pub rule: String,
+    pub fields: BTreeMap<String, Node>,
+}
+
+impl Ast {
+    pub fn new(rule: &str) -> Self {
+        Self {
+            rule: rule.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Number of nodes in the tree, counting this one.
+    pub fn node_count(&self) -> usize {
+        1 + self.fields.values().map(Node::node_count).sum::<usize>()
+    }
+
+    /// Converts the tree to JSON in a shape that doesn't depend on how the
+    /// types are serialized: every tree becomes `{"rule": .., "fields": {..}}`,
+    /// lists become arrays, matched text of any kind becomes a string,
+    /// numbers and bools stay as they are and missing optional matches
+    /// become `null`.
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse("Main:\n<name:ident> = <values:int>* ;\n~~~\n")?;
+    /// let ast = grammar.parse_str("x = 1 2;")?;
+    /// let expected = serde_json::json!({
+    ///     "rule": "Main",
+    ///     "fields": { "name": "x", "values": [1, 2] },
+    /// });
+    /// assert_eq!(ast.to_json_value(), expected);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(name, node)| (name.clone(), node.to_json_value()))
+            .collect();
+        serde_json::json!({
+            "rule": self.rule,
+            "fields": serde_json::Value::Object(fields),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Node {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    Text(String),
+    Ast(Ast),
+    List(Vec<Node>),
+    None,
+}
+
+impl Node {
+    /// Number of nodes in the tree, counting this one.
+    pub fn node_count(&self) -> usize {
+        match self {
+            Node::Ast(ast) => ast.node_count(),
+            Node::List(items) => 1 + items.iter().map(Node::node_count).sum::<usize>(),
+            _ => 1,
+        }
+    }
+
+    /// Converts the node to JSON, see [`Ast::to_json_value`]. Floats that
+    /// JSON can't represent, like NaN, become `null`.
+    #[cfg(feature = "std")]
+    pub fn to_json_value(&self) -> serde_json::Value {
+        use serde_json::Value;
+
+        match self {
+            Node::Ident(s) | Node::String(s) | Node::Text(s) => Value::String(s.clone()),
+            Node::Int(i) => Value::from(*i),
+            Node::Float(f) => serde_json::Number::from_f64(*f).map_or(Value::Null, Value::Number),
+            Node::Bool(b) => Value::Bool(*b),
+            Node::Ast(ast) => ast.to_json_value(),
+            Node::List(items) => Value::Array(items.iter().map(Node::to_json_value).collect()),
+            Node::None => Value::Null,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use serde::Serialize;
+
+use crate::custom::ast::{Ast, Node};
+
+/// A single structural difference between two syntax trees, located by the
+/// capture path leading to it, e.g. `items[2].value`. List indices of removed
+/// items refer to the old tree, all others to the new one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum AstChange {
+    Added {
+        path: String,
+        value: String,
+    },
+    Removed {
+        path: String,
+        value: String,
+    },
+    Changed {
+        path: String,
+        old: String,
+        new: String,
+    },
+    /// The node was matched by a different rule, its fields aren't compared.
+    RuleChanged {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Display for AstChange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AstChange::Added { path, value } => write!(f, "+ {path}: {value}"),
+            AstChange::Removed { path, value } => write!(f, "- {path}: {value}"),
+            AstChange::Changed { path, old, new } => write!(f, "~ {path}: {old} -> {new}"),
+            AstChange::RuleChanged { path, old, new } => {
+                write!(f, "~ {path}: rule {old} -> {new}")
+            }
+        }
+    }
+}
+
+/// Compares two syntax trees capture by capture. List items are matched up
+/// by their longest common subsequence, so inserting an item reports only
+/// that item instead of every item after it.
+pub fn diff(old: &Ast, new: &Ast) -> Vec<AstChange> {
+    let mut changes = Vec::new();
+    diff_ast(String::new(), old, new, &mut changes);
+    changes
+}
+
+fn diff_ast(path: String, old: &Ast, new: &Ast, changes: &mut Vec<AstChange>) {
+    if old.rule != new.rule {
+        changes.push(AstChange::RuleChanged {
+            path: display_path(&path),
+            old: old.rule.clone(),
+            new: new.rule.clone(),
+        });
+        return;
+    }
+    let field_path = |name: &str| match path.as_str() {
+        "" => name.to_string(),
+        _ => format!("{path}.{name}"),
+    };
+    for (name, node) in &old.fields {
+        match new.fields.get(name) {
+            Some(other) => diff_node(field_path(name), node, other, changes),
+            None => changes.push(AstChange::Removed {
+                path: field_path(name),
+                value: summary(node),
+            }),
+        }
+    }
+    for (name, node) in &new.fields {
+        if !old.fields.contains_key(name) {
+            changes.push(AstChange::Added {
+                path: field_path(name),
+                value: summary(node),
+            });
+        }
+    }
+}
+
+fn diff_node(path: String, old: &Node, new: &Node, changes: &mut Vec<AstChange>) {
+    match (old, new) {
+        (Node::Ast(old), Node::Ast(new)) => diff_ast(path, old, new, changes),
+        (Node::List(old), Node::List(new)) => diff_list(&path, old, new, changes),
+        _ if old != new => changes.push(AstChange::Changed {
+            path,
+            old: summary(old),
+            new: summary(new),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_list(path: &str, old: &[Node], new: &[Node], changes: &mut Vec<AstChange>) {
+    // lengths[i][j] is the length of the common subsequence of old[i..] and new[j..].
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let item = |index: usize| format!("{path}[{index}]");
+    let (mut i, mut j) = (0, 0);
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            flush(&item, old, new, &mut removed, &mut added, changes);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            added.push(j);
+            j += 1;
+        } else {
+            removed.push(i);
+            i += 1;
+        }
+    }
+    flush(&item, old, new, &mut removed, &mut added, changes);
+}
+
+/// Reports a run of unmatched list items. Removed items are compared with
+/// the next added item of the same shape, everything else is reported as a
+/// whole item.
+fn flush(
+    item: &impl Fn(usize) -> String,
+    old: &[Node],
+    new: &[Node],
+    removed: &mut Vec<usize>,
+    added: &mut Vec<usize>,
+    changes: &mut Vec<AstChange>,
+) {
+    let mut next = 0;
+    for &i in removed.iter() {
+        let Some(offset) = added[next..]
+            .iter()
+            .position(|&j| same_shape(&old[i], &new[j]))
+        else {
+            changes.push(AstChange::Removed {
+                path: item(i),
+                value: summary(&old[i]),
+            });
+            continue;
+        };
+        for &j in &added[next..next + offset] {
+            changes.push(AstChange::Added {
+                path: item(j),
+                value: summary(&new[j]),
+            });
+        }
+        let j = added[next + offset];
+        diff_node(item(j), &old[i], &new[j], changes);
+        next += offset + 1;
+    }
+    for &j in &added[next..] {
+        changes.push(AstChange::Added {
+            path: item(j),
+            value: summary(&new[j]),
+        });
+    }
+    removed.clear();
+    added.clear();
+}
+
+/// Whether two nodes are close enough to compare field by field: trees of
+/// the same rule with the same captures, or leaves of the same kind.
+fn same_shape(old: &Node, new: &Node) -> bool {
+    match (old, new) {
+        (Node::Ast(old), Node::Ast(new)) => {
+            old.rule == new.rule && old.fields.keys().eq(new.fields.keys())
+        }
+        _ => core::mem::discriminant(old) == core::mem::discriminant(new),
+    }
+}
+
+fn display_path(path: &str) -> String {
+    match path {
+        "" => "(root)".to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Short description of a node for change reports.
+fn summary(node: &Node) -> String {
+    match node {
+        Node::Ident(s) | Node::Text(s) => s.clone(),
+        Node::String(s) => format!("{s:?}"),
+        Node::Int(i) => i.to_string(),
+        Node::Float(f) => f.to_string(),
+        Node::Bool(b) => b.to_string(),
+        Node::Ast(ast) => format!("<{}>", ast.rule),
+        Node::List(items) => format!("[{} items]", items.len()),
+        Node::None => "nothing".to_string(),
+    }
+}
@@ -0,0 +1,56 @@
+use alloc::boxed::Box;
+use core::hash::BuildHasherDefault;
+
+use indexmap::IndexSet;
+use siphasher::sip::SipHasher13;
+
+/// An interned string, see [`Interner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+/// Maps strings to [`Symbol`]s, so text that is compared often can be
+/// compared as numbers. Interning the same text again gives the same symbol.
+///
+/// ```
+/// use tmpl::custom::Interner;
+///
+/// let mut interner = Interner::default();
+/// let let_ = interner.intern("let");
+/// assert_eq!(interner.intern("let"), let_);
+/// assert_eq!(interner.get("let"), Some(let_));
+/// assert_eq!(interner.get("fn"), None);
+/// assert_eq!(interner.resolve(let_), "let");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    strings: IndexSet<Box<str>, BuildHasherDefault<SipHasher13>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.get(text) {
+            return symbol;
+        }
+        let (index, _) = self.strings.insert_full(text.into());
+        Symbol(index as u32)
+    }
+
+    /// The symbol of `text` if it was interned, without interning it.
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.strings
+            .get_index_of(text)
+            .map(|index| Symbol(index as u32))
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
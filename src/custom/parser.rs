@@ -1,72 +1,848 @@
-use crate::custom::Ast;
+use crate::custom::ast::{Ast, Node};
 use crate::definition::*;
+use crate::lexer::{Span, Token};
 
-use std::cell::RefCell;
-use std::num::ParseIntError;
-use std::rc::Rc;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::{Cell, RefCell};
+use core::fmt::Display;
 
 use thiserror::Error;
 
-pub type Result<T> = std::result::Result<T, ParseError>;
+pub type Result<T> = core::result::Result<T, ParseError>;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
     #[error("Unknown Error")]
     Unknown,
+    #[error("Unknown rule: {0}")]
+    UnknownRule(String),
+    #[error("Unexpected token `{found}` at index {index}, expected {expected}")]
+    UnexpectedToken {
+        index: usize,
+        found: String,
+        expected: String,
+    },
+    #[error("Unexpected end of input, expected {0}")]
+    UnexpectedEof(String),
+    #[error("Unconsumed input starting at token {0}")]
+    TrailingInput(usize),
+    /// An error with the input and rules around where it happened.
+    #[error("{error}{context}")]
+    WithContext {
+        error: Box<ParseError>,
+        context: Box<ErrorContext>,
+    },
 }
 
+impl ParseError {
+    /// Index of the token the error points at, if it points at a token.
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            ParseError::UnexpectedToken { index, .. } | ParseError::TrailingInput(index) => {
+                Some(*index)
+            }
+            ParseError::WithContext { error, .. } => error.index(),
+            _ => None,
+        }
+    }
+
+    /// The error without its [`ErrorContext`].
+    pub fn kind(&self) -> &ParseError {
+        match self {
+            ParseError::WithContext { error, .. } => error.kind(),
+            error => error,
+        }
+    }
+
+    /// Where the error happened, for errors returned by [`Parser::parse_entry`].
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            ParseError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+}
+
+/// Number of tokens before and after the failing one an [`ErrorContext`] keeps.
+pub const CONTEXT_TOKENS: usize = 3;
+
+/// Where a parse failed: the failing token, the tokens around it and the
+/// rules that were being parsed.
+///
+/// ```
+/// let grammar = tmpl::Grammar::parse("Main:\n<first:Item> <second:Item>\n~~~\n\nItem:\n<key:ident> = <value:int>;\n~~~\n")?;
+/// let error = grammar.parse_str("a = 1; b = c;").unwrap_err();
+/// let tmpl::Error::Parse(error) = error else { unreachable!() };
+/// let context = error.context().unwrap();
+/// assert_eq!(context.span, Some(11..12));
+/// assert_eq!(context.rules, ["Main", "Item"]);
+/// assert_eq!(error.to_string(), "Unexpected token `c` at index 11, expected integer, near `; b = c ;` in Main > Item");
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorContext {
+    /// Index of the failing token, the number of tokens if the input ended.
+    pub index: usize,
+    /// Byte range of the failing token, if the parser knows the byte ranges
+    /// of the tokens, see [`Parser::with_token_spans`].
+    pub span: Option<Span>,
+    /// Up to [`CONTEXT_TOKENS`] tokens before the failing one, skipping whitespace.
+    pub before: Vec<Token>,
+    /// The failing token and up to [`CONTEXT_TOKENS`] tokens after it,
+    /// skipping whitespace. Empty if the input ended.
+    pub after: Vec<Token>,
+    /// The rules being parsed where the parse got furthest, outermost first.
+    /// Empty if the error isn't at that token, e.g. for unconsumed input
+    /// after a complete parse.
+    pub rules: Vec<String>,
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let tokens: Vec<_> = self
+            .before
+            .iter()
+            .chain(&self.after)
+            .map(Token::to_string)
+            .collect();
+        if !tokens.is_empty() {
+            write!(f, ", near `{}`", tokens.join(" "))?;
+        }
+        if !self.rules.is_empty() {
+            write!(f, " in {}", self.rules.join(" > "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The first failure at the furthest token a parse failed at.
+#[derive(Default)]
+pub(super) struct Failure {
+    pub(super) index: usize,
+    pub(super) rules: Vec<String>,
+}
+
+/// Wraps `error` of a parse of `tokens` with its [`ErrorContext`]. Errors
+/// without a token index point at the `failure`, if any, or at `position`.
+pub(super) fn with_context(
+    error: ParseError,
+    tokens: &[Token],
+    spans: &[Span],
+    failure: Option<Failure>,
+    position: usize,
+) -> ParseError {
+    let index = match (&error, error.index(), &failure) {
+        (_, Some(index), _) => index,
+        (ParseError::UnexpectedEof(_), _, _) => tokens.len(),
+        (_, None, Some(failure)) => failure.index,
+        (_, None, None) => position,
+    };
+    let rules = failure
+        .filter(|failure| failure.index == index)
+        .map(|failure| failure.rules)
+        .unwrap_or_default();
+    let significant = |token: &&Token| **token != Token::Ws;
+    let end = index.min(tokens.len());
+    let mut before: Vec<_> = tokens[..end]
+        .iter()
+        .rev()
+        .filter(significant)
+        .take(CONTEXT_TOKENS)
+        .cloned()
+        .collect();
+    before.reverse();
+    let after = tokens[end..]
+        .iter()
+        .filter(significant)
+        .take(CONTEXT_TOKENS + 1)
+        .cloned()
+        .collect();
+    ParseError::WithContext {
+        error: Box::new(error),
+        context: Box::new(ErrorContext {
+            index,
+            span: spans.get(index).cloned(),
+            before,
+            after,
+            rules,
+        }),
+    }
+}
+
+/// Counters collected while parsing, see [`Parser::with_stats`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseStats {
+    /// Number of times any rule was entered.
+    pub rule_calls: usize,
+    /// Rule calls at a token index the same rule was already tried at. The
+    /// parser doesn't memoize, so these are the calls a packrat cache would save.
+    pub repeated_calls: usize,
+    seen: BTreeSet<(String, usize)>,
+}
+
+/// How often the rules and their alternatives matched, see
+/// [`Parser::with_coverage`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RuleCoverage {
+    /// Matches by rule name and index of the alternative, counting all
+    /// alternatives of the rule in the order [`Pattern::alternatives`] of its
+    /// patterns lists them. Matches that were backtracked out of later count
+    /// as well.
+    pub alternatives: BTreeMap<(String, usize), usize>,
+}
+
+impl RuleCoverage {
+    /// How often alternative `alternative` of `rule` matched.
+    pub fn hits(&self, rule: &str, alternative: usize) -> usize {
+        let key = (rule.to_string(), alternative);
+        self.alternatives.get(&key).copied().unwrap_or(0)
+    }
+
+    /// Adds the matches of `other`, e.g. of the parser of another input.
+    pub fn merge(&mut self, other: &RuleCoverage) {
+        for (key, hits) in &other.alternatives {
+            *self.alternatives.entry(key.clone()).or_default() += hits;
+        }
+    }
+}
+
+/// Tokens a rule matched, see [`Parser::with_spans`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSpan {
+    pub rule: String,
+    /// Index of the first token the rule matched, past leading whitespace.
+    pub start: usize,
+    /// Index one past the last token the rule matched.
+    pub end: usize,
+}
+
+/// Parses tokens by walking the definition directly. Keywords and symbols are
+/// compared as text here, only [`super::Program`] interns them.
 pub struct Parser {
-    definition: crate::definition::ParserDefinition,
+    definition: Arc<ParserDefinition>,
     index: Rc<RefCell<usize>>,
     lexer: Vec<crate::lexer::Token>,
+    trace: bool,
+    depth: RefCell<usize>,
+    stats: Option<RefCell<ParseStats>>,
+    coverage: Option<RefCell<RuleCoverage>>,
+    spans: Option<RefCell<Vec<RuleSpan>>>,
+    /// One past the highest token index looked at since the last reset to the
+    /// start, see [`Parser::looked_past_end`].
+    furthest: Cell<usize>,
+    token_spans: Vec<Span>,
+    /// The rules being parsed, innermost last.
+    rules: RefCell<Vec<String>>,
+    failure: RefCell<Option<Failure>>,
 }
 
 impl Parser {
+    /// A parser for `lexer` using `definition`, which can be shared: parsers
+    /// made from clones of one `Arc` use the same definition and compiled
+    /// regexes, also across threads.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use tmpl::custom::Parser;
+    ///
+    /// let definition = Arc::new(tmpl::definition::parse("Main:\n<n:int>\n~~~\n")?);
+    /// let threads: Vec<_> = ["1", "2", "3"]
+    ///     .map(|source| {
+    ///         let definition = Arc::clone(&definition);
+    ///         std::thread::spawn(move || {
+    ///             let tokens = tmpl::lexer::lex_spanned(source).unwrap();
+    ///             let tokens = tokens.into_iter().map(|(token, _)| token).collect();
+    ///             Parser::new(definition, tokens).parse().is_ok()
+    ///         })
+    ///     })
+    ///     .into();
+    /// assert!(threads.into_iter().all(|thread| thread.join().unwrap()));
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     pub fn new(
-        definition: crate::definition::ParserDefinition,
+        definition: impl Into<Arc<ParserDefinition>>,
         lexer: Vec<crate::lexer::Token>,
     ) -> Self {
         Self {
-            definition,
+            definition: definition.into(),
             lexer,
             index: Rc::new(RefCell::new(0)),
+            trace: false,
+            depth: RefCell::new(0),
+            stats: None,
+            coverage: None,
+            spans: None,
+            furthest: Cell::new(0),
+            token_spans: Vec::new(),
+            rules: RefCell::default(),
+            failure: RefCell::default(),
         }
     }
 
-    fn parse_pattern(&self, pattern: &[TokenPattern]) -> Result<Ast> {
-        Ok(Ast {})
+    /// Byte ranges of the tokens, like [`crate::lexer::lex_spanned`] returns
+    /// them, for the [`ErrorContext::span`] of errors.
+    pub fn with_token_spans(mut self, spans: Vec<Span>) -> Self {
+        self.token_spans = spans;
+        self
+    }
+
+    /// Gives back the tokens and their byte ranges, e.g. to reuse the buffers
+    /// for the next input.
+    pub fn into_tokens(self) -> (Vec<Token>, Vec<Span>) {
+        (self.lexer, self.token_spans)
     }
 
-    fn parse_alternative(&self, left: &[TokenPattern], right: &Pattern) -> Result<Ast> {
-        let current_pos = *self.index.borrow();
-        if let Ok(ast) = self.parse_pattern(left) {
-            return Ok(ast);
+    /// Prints every rule the parser enters and leaves to stderr. Without the
+    /// `std` feature there is no stderr and this does nothing.
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Counts rule calls while parsing, see [`Parser::stats`].
+    pub fn with_stats(mut self, stats: bool) -> Self {
+        self.stats = stats.then(RefCell::default);
+        self
+    }
+
+    /// Counters of all parses so far, if enabled with [`Parser::with_stats`].
+    pub fn stats(&self) -> Option<ParseStats> {
+        self.stats.as_ref().map(|stats| stats.borrow().clone())
+    }
+
+    /// Records which alternatives of the rules match, see [`Parser::coverage`].
+    ///
+    /// ```
+    /// use tmpl::custom::Parser;
+    ///
+    /// let definition = tmpl::definition::parse("Main:\n| <n:int>\n| <s:string>\n~~~\n")?;
+    /// let tokens = tmpl::lexer::lex_spanned("1").unwrap();
+    /// let tokens = tokens.into_iter().map(|(token, _)| token).collect();
+    /// let parser = Parser::new(definition, tokens).with_coverage(true);
+    /// parser.parse().unwrap();
+    /// let coverage = parser.coverage().unwrap();
+    /// assert_eq!((coverage.hits("Main", 0), coverage.hits("Main", 1)), (1, 0));
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn with_coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage.then(RefCell::default);
+        self
+    }
+
+    /// Matches of all parses so far, if enabled with [`Parser::with_coverage`].
+    pub fn coverage(&self) -> Option<RuleCoverage> {
+        self.coverage
+            .as_ref()
+            .map(|coverage| coverage.borrow().clone())
+    }
+
+    /// Records the tokens every rule of the last parse matched, see [`Parser::spans`].
+    pub fn with_spans(mut self, spans: bool) -> Self {
+        self.spans = spans.then(RefCell::default);
+        self
+    }
+
+    /// Spans of the rules that are part of the last parse, in the order they
+    /// finished, if enabled with [`Parser::with_spans`]. Rules from alternatives
+    /// that were backtracked out of are not included.
+    pub fn spans(&self) -> Option<Vec<RuleSpan>> {
+        self.spans.as_ref().map(|spans| spans.borrow().clone())
+    }
+
+    fn position(&self) -> usize {
+        *self.index.borrow()
+    }
+
+    fn reset(&self, pos: usize) {
+        if let Some(spans) = &self.spans {
+            if pos < self.position() {
+                spans.borrow_mut().retain(|span| span.start < pos);
+            }
+        }
+        *self.index.borrow_mut() = pos;
+    }
+
+    fn token(&self, index: usize) -> Option<&Token> {
+        self.furthest.set(self.furthest.get().max(index + 1));
+        self.lexer.get(index)
+    }
+
+    fn skip_ws(&self) {
+        let mut index = self.position();
+        while matches!(self.token(index), Some(Token::Ws)) {
+            index += 1;
+        }
+        *self.index.borrow_mut() = index;
+    }
+
+    /// Remembers the rules being parsed if `index` is further than any
+    /// failure before.
+    fn record_failure(&self, index: usize) {
+        let mut failure = self.failure.borrow_mut();
+        if failure.as_ref().is_none_or(|failure| index > failure.index) {
+            *failure = Some(Failure {
+                index,
+                rules: self.rules.borrow().clone(),
+            });
         }
-        *self.index.borrow_mut() = current_pos;
-        match right {
-            Pattern::Token(t) => {}
-            Pattern::Alternative { left, right } => {
-                let ast = self.parse_alternative(left, right)?;
+    }
+
+    fn fail<T>(&self, expected: impl Display) -> Result<T> {
+        let index = self.position();
+        self.record_failure(index);
+        match self.token(index) {
+            Some(token) => Err(ParseError::UnexpectedToken {
+                index,
+                found: token.to_string(),
+                expected: expected.to_string(),
+            }),
+            None => Err(ParseError::UnexpectedEof(expected.to_string())),
+        }
+    }
+
+    fn expect<T>(&self, expected: impl Display, f: impl FnOnce(&Token) -> Option<T>) -> Result<T> {
+        self.skip_ws();
+        let pos = self.position();
+        match self.token(pos).and_then(f) {
+            Some(value) => {
+                self.reset(pos + 1);
+                Ok(value)
             }
+            None => self.fail(expected),
         }
-        Ok(Ast {})
     }
 
-    fn parse_rule(&self, rule_name: &str) -> Result<Ast> {
-        let pattern = self.definition.rules.get(rule_name).unwrap();
-        for p in pattern {
-            match p {
-                Pattern::Token(t) => {}
-                Pattern::Alternative { left, right } => {
-                    let ast = self.parse_alternative(left, right)?;
+    fn expect_word(&self, word: &str) -> Result<Node> {
+        self.expect(format!("`{word}`"), |token| match token {
+            Token::Ident(s) if s == word => Some(Node::Text(word.to_string())),
+            Token::True if word == "true" => Some(Node::Text(word.to_string())),
+            Token::False if word == "false" => Some(Node::Text(word.to_string())),
+            _ => None,
+        })
+    }
+
+    /// Symbols are lexed one character at a time, so a multi character symbol
+    /// has to match a run of adjacent symbol tokens without whitespace in between.
+    fn expect_literal(&self, literal: &str) -> Result<Node> {
+        if literal.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return self.expect_word(literal);
+        }
+        self.skip_ws();
+        let start = self.position();
+        for c in literal.chars() {
+            let pos = self.position();
+            match self.token(pos) {
+                Some(Token::Symbol(s)) if s.chars().eq(core::iter::once(c)) => self.reset(pos + 1),
+                _ => {
+                    let error = self.fail(format!("`{literal}`"));
+                    self.reset(start);
+                    return error;
+                }
+            }
+        }
+        Ok(Node::Text(literal.to_string()))
+    }
+
+    /// Number of tokens `literal` spans if it is at `index`, matched like
+    /// [`Parser::expect_literal`].
+    fn literal_at(&self, index: usize, literal: &str) -> Option<usize> {
+        if literal.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            return match self.token(index)? {
+                Token::Ident(s) if s == literal => Some(1),
+                Token::True if literal == "true" => Some(1),
+                Token::False if literal == "false" => Some(1),
+                _ => None,
+            };
+        }
+        let mut len = 0;
+        for c in literal.chars() {
+            match self.token(index + len) {
+                Some(Token::Symbol(s)) if s.chars().eq(core::iter::once(c)) => len += 1,
+                _ => return None,
+            }
+        }
+        Some(len)
+    }
+
+    /// Matches the longest of `options` at once instead of trying them one
+    /// after the other, so `<oneof[-, ->]>` reads `->` whole.
+    fn expect_one_of(&self, options: &[String]) -> Result<Node> {
+        self.skip_ws();
+        let index = self.position();
+        let longest = (options.iter())
+            .filter_map(|option| Some((option, self.literal_at(index, option)?)))
+            .max_by_key(|(_, len)| *len);
+        match longest {
+            Some((option, len)) => {
+                self.reset(index + len);
+                Ok(Node::Text(option.clone()))
+            }
+            None => self.fail(expected_one_of(options)),
+        }
+    }
+
+    /// Matches `open` and everything up to the `close` matching it, counting
+    /// the pairs nested in between.
+    fn expect_balanced(&self, open: &str, close: &str) -> Result<Node> {
+        self.skip_ws();
+        let start = self.position();
+        let Some(len) = self.literal_at(start, open) else {
+            return self.fail(format!("`{open}`"));
+        };
+        let content = start + len;
+        let mut index = content;
+        let mut depth = 1;
+        loop {
+            if let Some(len) = self.literal_at(index, close) {
+                depth -= 1;
+                if depth == 0 {
+                    let text: String = (content..index)
+                        .filter_map(|i| self.token(i).map(Token::to_string))
+                        .collect();
+                    self.reset(index + len);
+                    return Ok(Node::Text(text.trim().to_string()));
                 }
+                index += len;
+            } else if let Some(len) = self.literal_at(index, open).filter(|len| *len > 0) {
+                depth += 1;
+                index += len;
+            } else if self.token(index).is_some() {
+                index += 1;
+            } else {
+                self.reset(index);
+                let error = self.fail(format!("`{close}`"));
+                self.reset(start);
+                return error;
             }
         }
+    }
+
+    fn parse_kind(&self, kind: &InternalPatternKind) -> Result<Node> {
+        match kind {
+            InternalPatternKind::Ident => self.expect("identifier", |token| match token {
+                Token::Ident(s) => Some(Node::Ident(s.clone())),
+                _ => None,
+            }),
+            InternalPatternKind::Int => self.expect("integer", |token| match token {
+                Token::Integer(i) => Some(Node::Int(*i)),
+                _ => None,
+            }),
+            InternalPatternKind::Float => self.expect("float", |token| match token {
+                Token::Float(f) => Some(Node::Float(*f)),
+                _ => None,
+            }),
+            InternalPatternKind::String => self.expect("string", |token| match token {
+                Token::String(s) => Some(Node::String(s.clone())),
+                _ => None,
+            }),
+            InternalPatternKind::Bool => self.expect("bool", |token| match token {
+                Token::True => Some(Node::Bool(true)),
+                Token::False => Some(Node::Bool(false)),
+                _ => None,
+            }),
+            InternalPatternKind::Regex(re) => self.expect(format!("/{re}/"), |token| {
+                let text = token.to_string();
+                (re.matches_whole(&text) && *token != Token::Ws).then_some(Node::Text(text))
+            }),
+            InternalPatternKind::Keyword(kw) => self.expect_word(kw),
+            InternalPatternKind::Symbol(sym) => self.expect_literal(sym),
+            InternalPatternKind::OneOf(options) => self.expect_one_of(options),
+            InternalPatternKind::Balanced { open, close } => self.expect_balanced(open, close),
+            InternalPatternKind::Custom(name) => self.parse_rule(name).map(Node::Ast),
+        }
+    }
 
-        Ok(Ast {})
+    fn parse_internal(
+        &self,
+        pattern: &InternalPattern,
+        fields: &mut BTreeMap<String, Node>,
+    ) -> Result<Node> {
+        match pattern {
+            InternalPattern::Named { kind, .. } => self.parse_kind(kind),
+            InternalPattern::Raw { value } => self.expect_literal(value),
+            InternalPattern::Exact { pattern } => {
+                self.parse_sequence(pattern, fields)?;
+                Ok(Node::None)
+            }
+        }
+    }
+
+    fn parse_repeated(
+        &self,
+        token: &TokenPattern,
+        repeat_mode: &RepeatMode,
+        fields: &mut BTreeMap<String, Node>,
+    ) -> Result<Vec<Node>> {
+        let mut items = Vec::new();
+        loop {
+            let pos = self.position();
+            if let (Some(separator), false) = (&token.separator, items.is_empty()) {
+                if self.expect_literal(separator).is_err() {
+                    self.reset(pos);
+                    break;
+                }
+            }
+            match self.parse_internal(&token.pattern, fields) {
+                Ok(node) => items.push(node),
+                Err(e) => {
+                    self.reset(pos);
+                    if items.is_empty() && matches!(repeat_mode, RepeatMode::OneOrMore) {
+                        return Err(e);
+                    }
+                    break;
+                }
+            }
+            // a pattern that consumes nothing would otherwise repeat forever
+            if self.position() == pos {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn parse_token_pattern(
+        &self,
+        token: &TokenPattern,
+        fields: &mut BTreeMap<String, Node>,
+    ) -> Result<()> {
+        let node = match &token.repeat_mode {
+            Some(repeat_mode) => Node::List(self.parse_repeated(token, repeat_mode, fields)?),
+            None if token.is_optional => {
+                let pos = self.position();
+                match self.parse_internal(&token.pattern, fields) {
+                    Ok(node) => node,
+                    Err(_) => {
+                        self.reset(pos);
+                        Node::None
+                    }
+                }
+            }
+            None => self.parse_internal(&token.pattern, fields)?,
+        };
+        if let InternalPattern::Named {
+            name: Some(name), ..
+        } = &token.pattern
+        {
+            fields.insert(name.clone(), node);
+        }
+        Ok(())
+    }
+
+    fn parse_sequence(
+        &self,
+        pattern: &[TokenPattern],
+        fields: &mut BTreeMap<String, Node>,
+    ) -> Result<()> {
+        for token in pattern {
+            self.parse_token_pattern(token, fields)?;
+        }
+        Ok(())
+    }
+
+    /// Parses `pattern`, whose first alternative is alternative `offset` of
+    /// the rule.
+    fn parse_pattern(&self, rule_name: &str, pattern: &Pattern, offset: usize) -> Result<Ast> {
+        match pattern {
+            Pattern::Token(t) => {
+                let mut ast = Ast::new(rule_name);
+                self.parse_sequence(t, &mut ast.fields)?;
+                self.record_match(rule_name, offset);
+                Ok(ast)
+            }
+            Pattern::Choice(alternatives) => self.parse_choice(rule_name, alternatives, offset),
+        }
+    }
+
+    fn parse_choice(
+        &self,
+        rule_name: &str,
+        alternatives: &[Sequence],
+        offset: usize,
+    ) -> Result<Ast> {
+        let current_pos = self.position();
+        let mut error = ParseError::Unknown;
+        for (index, alternative) in alternatives.iter().enumerate() {
+            let mut ast = Ast::new(rule_name);
+            match self.parse_sequence(alternative, &mut ast.fields) {
+                Ok(()) => {
+                    self.record_match(rule_name, offset + index);
+                    return Ok(ast);
+                }
+                Err(e) => {
+                    self.reset(current_pos);
+                    error = e;
+                }
+            }
+        }
+        Err(error)
+    }
+
+    fn parse_patterns(&self, rule_name: &str, patterns: &[Pattern]) -> Result<Ast> {
+        let current_pos = self.position();
+        if patterns.is_empty() {
+            self.record_failure(current_pos);
+        }
+        let mut error = ParseError::Unknown;
+        let mut offset = 0;
+        for p in patterns {
+            match self.parse_pattern(rule_name, p, offset) {
+                Ok(ast) => return Ok(ast),
+                Err(e) => {
+                    self.reset(current_pos);
+                    error = e;
+                }
+            }
+            offset += p.alternatives().len();
+        }
+        Err(error)
+    }
+
+    fn record_match(&self, rule_name: &str, alternative: usize) {
+        if let Some(coverage) = &self.coverage {
+            let key = (rule_name.to_string(), alternative);
+            *coverage.borrow_mut().alternatives.entry(key).or_default() += 1;
+        }
+    }
+
+    fn parse_rule(&self, rule_name: &str) -> Result<Ast> {
+        tracing::trace!(rule = rule_name, index = self.position(), "enter rule");
+        if let Some(stats) = &self.stats {
+            let mut stats = stats.borrow_mut();
+            stats.rule_calls += 1;
+            if !stats.seen.insert((rule_name.to_string(), self.position())) {
+                stats.repeated_calls += 1;
+            }
+        }
+        self.rules.borrow_mut().push(rule_name.to_string());
+        #[cfg(feature = "std")]
+        let result = match self.trace {
+            true => self.parse_rule_traced(rule_name),
+            false => self.parse_rule_untraced(rule_name),
+        };
+        #[cfg(not(feature = "std"))]
+        let result = self.parse_rule_untraced(rule_name);
+        self.rules.borrow_mut().pop();
+        result
+    }
+
+    #[cfg(feature = "std")]
+    fn parse_rule_traced(&self, rule_name: &str) -> Result<Ast> {
+        let depth = *self.depth.borrow();
+        let indent = "  ".repeat(depth);
+        let start = self.position();
+        eprintln!("[trace] {indent}{rule_name} at token {start}");
+        *self.depth.borrow_mut() += 1;
+        let result = self.parse_rule_untraced(rule_name);
+        *self.depth.borrow_mut() = depth;
+        match &result {
+            Ok(_) => eprintln!(
+                "[trace] {indent}{rule_name} matched {start}..{}",
+                self.position()
+            ),
+            Err(e) => eprintln!("[trace] {indent}{rule_name} failed: {e}"),
+        }
+        result
+    }
+
+    fn parse_rule_untraced(&self, rule_name: &str) -> Result<Ast> {
+        let Some(pattern) = self.definition.rule(rule_name) else {
+            self.record_failure(self.position());
+            return Err(ParseError::UnknownRule(rule_name.to_string()));
+        };
+        let Some(spans) = &self.spans else {
+            return self.parse_patterns(rule_name, pattern);
+        };
+        self.skip_ws();
+        let start = self.position();
+        let ast = self.parse_patterns(rule_name, pattern)?;
+        spans.borrow_mut().push(RuleSpan {
+            rule: rule_name.to_string(),
+            start,
+            end: self.position(),
+        });
+        Ok(ast)
     }
 
     pub fn parse(&self) -> Result<Ast> {
-        Ok(Ast {})
+        self.parse_entry("Main")
+    }
+
+    /// Parses the whole input using `rule_name` as the start rule. Errors
+    /// come with an [`ErrorContext`].
+    pub fn parse_entry(&self, rule_name: &str) -> Result<Ast> {
+        let _span =
+            tracing::debug_span!("parse", rule = rule_name, tokens = self.lexer.len()).entered();
+        self.reset(0);
+        self.failure.take();
+        if let Some(spans) = &self.spans {
+            spans.borrow_mut().clear();
+        }
+        if let Some(stats) = &self.stats {
+            stats.borrow_mut().seen.clear();
+        }
+        let ast = self
+            .parse_rule(rule_name)
+            .map_err(|error| self.with_context(error))?;
+        self.skip_ws();
+        let end = self.position();
+        if end < self.lexer.len() {
+            return Err(self.with_context(ParseError::TrailingInput(end)));
+        }
+        tracing::debug!("parsed input");
+        Ok(ast)
+    }
+
+    fn with_context(&self, error: ParseError) -> ParseError {
+        with_context(
+            error,
+            &self.lexer,
+            &self.token_spans,
+            self.failure.take(),
+            self.position(),
+        )
+    }
+
+    /// Appends tokens to the input, for parsing input that arrives in pieces.
+    pub(crate) fn feed(&mut self, tokens: impl IntoIterator<Item = Token>) {
+        self.lexer.extend(tokens);
+    }
+
+    /// Drops the first `count` tokens, e.g. the ones a [`Parser::parse_prefix`]
+    /// matched, so later indices are relative to the remaining ones.
+    pub(crate) fn consume(&mut self, count: usize) {
+        self.lexer.drain(..count);
+        self.token_spans.drain(..count.min(self.token_spans.len()));
+        self.reset(0);
+    }
+
+    /// Parses `rule_name` at the start of the input without requiring it to
+    /// match all of it, returning the tree and the number of tokens matched.
+    pub(crate) fn parse_prefix(&self, rule_name: &str) -> Result<(Ast, usize)> {
+        self.reset(0);
+        self.furthest.set(0);
+        self.failure.take();
+        let ast = self
+            .parse_rule(rule_name)
+            .map_err(|error| self.with_context(error))?;
+        Ok((ast, self.position()))
+    }
+
+    /// Whether the last [`Parser::parse_prefix`] tried to look at a token past
+    /// the end of the input, i.e. whether more input could change its result.
+    pub(crate) fn looked_past_end(&self) -> bool {
+        self.furthest.get() > self.lexer.len()
+    }
+
+    /// Whether the input has no tokens besides whitespace.
+    pub(crate) fn is_blank(&self) -> bool {
+        self.lexer.iter().all(|token| *token == Token::Ws)
     }
 }
@@ -0,0 +1,710 @@
+use alloc::collections::BTreeMap;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::custom::ast::{Ast, Node};
+use crate::custom::intern::{Interner, Symbol};
+use crate::custom::parser::{with_context, Failure, ParseError, Result};
+use crate::definition::{
+    expected_one_of, first_of_sequence, FirstSet, InternalPattern, InternalPatternKind,
+    ParserDefinition, Pattern, Regex, RepeatMode, Terminal, TokenPattern,
+};
+use crate::lexer::{Span, Token};
+use smallvec::SmallVec;
+
+/// A definition lowered into tables for a small virtual machine, an
+/// alternative to [`super::Parser`] that produces the same trees faster.
+///
+/// Rule references are resolved to indices and literals, regexes and
+/// expectations are prepared once. Keywords and symbols are interned, so
+/// matching them compares [`Symbol`]s instead of text. Rules with several alternatives get a
+/// table keyed by the next token that lists the alternatives that can start
+/// with it, so the others aren't tried at all. The alternatives a table
+/// lists are tried in their original order, which keeps the result the
+/// same as trying all of them. When an input doesn't parse, the error can
+/// name a different expectation than the one [`super::Parser`] reports,
+/// as alternatives that can't match aren't tried to produce their errors.
+///
+/// ```
+/// let grammar = tmpl::Grammar::parse("Main:\n<items:Item>*\n~~~\n\nItem:\n| <kw[let]> <name:ident> = <value:int> ;\n| <name:ident> ;\n~~~\n")?;
+/// let program = tmpl::custom::Program::compile(grammar.definition())?;
+/// let tokens: Vec<_> = tmpl::lexer::lex_spanned("let x = 1; y;")?
+///     .into_iter()
+///     .map(|(token, _)| token)
+///     .collect();
+/// assert_eq!(program.parse_entry("Main", &tokens)?, grammar.parse_str("let x = 1; y;")?);
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Program {
+    rules: Vec<Rule>,
+    ids: BTreeMap<String, usize>,
+    interner: Interner,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    name: String,
+    alternatives: Vec<Sequence>,
+    dispatch: Option<Dispatch>,
+    /// What the rule can start with, for when no alternative can.
+    expected: String,
+}
+
+type Sequence = Vec<Step>;
+
+#[derive(Debug, Clone)]
+struct Step {
+    op: Op,
+    field: Option<String>,
+    optional: bool,
+    repeat: Option<Repeat>,
+}
+
+#[derive(Debug, Clone)]
+struct Repeat {
+    at_least_one: bool,
+    separator: Option<Literal>,
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Ident,
+    Int,
+    Float,
+    String,
+    Bool,
+    Regex {
+        regex: Regex,
+        expected: String,
+    },
+    Literal(Literal),
+    OneOf {
+        literals: Vec<Literal>,
+        expected: String,
+    },
+    Balanced {
+        open: Literal,
+        close: Literal,
+    },
+    Call(usize),
+    Exact(Sequence),
+}
+
+/// Literal text, matched as a word if it starts like an identifier and as a
+/// run of adjacent symbols otherwise.
+#[derive(Debug, Clone)]
+struct Literal {
+    text: String,
+    /// The word, or every character of the symbols.
+    symbols: Vec<Symbol>,
+    word: bool,
+    expected: String,
+}
+
+impl Literal {
+    fn new(text: &str, interner: &mut Interner) -> Self {
+        match text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            true => Self::word(text, interner),
+            false => Self {
+                text: text.to_string(),
+                symbols: text
+                    .chars()
+                    .map(|c| interner.intern(c.encode_utf8(&mut [0; 4])))
+                    .collect(),
+                word: false,
+                expected: format!("`{text}`"),
+            },
+        }
+    }
+
+    fn word(text: &str, interner: &mut Interner) -> Self {
+        Self {
+            text: text.to_string(),
+            symbols: Vec::from([interner.intern(text)]),
+            word: true,
+            expected: format!("`{text}`"),
+        }
+    }
+}
+
+/// The alternatives worth trying for each kind of next token, as indices
+/// into [`Rule::alternatives`].
+#[derive(Debug, Clone, Default)]
+struct Dispatch {
+    words: BTreeMap<Symbol, Vec<usize>>,
+    ident: Vec<usize>,
+    int: Vec<usize>,
+    float: Vec<usize>,
+    string: Vec<usize>,
+    true_: Vec<usize>,
+    false_: Vec<usize>,
+    symbols: BTreeMap<Symbol, Vec<usize>>,
+    symbol: Vec<usize>,
+    end: Vec<usize>,
+}
+
+/// The next token as far as a [`Dispatch`] is concerned.
+#[derive(Clone, Copy)]
+enum Key<'a> {
+    Word(&'a str),
+    Ident,
+    Int,
+    Float,
+    String,
+    True,
+    False,
+    Symbol(char),
+    OtherSymbol,
+    End,
+}
+
+impl Key<'_> {
+    /// Whether a sequence starting with `first` can match input starting
+    /// with this key. Regexes and empty literals could match anything.
+    fn admits(self, first: &FirstSet) -> bool {
+        first.nullable
+            || first
+                .terminals
+                .iter()
+                .any(|terminal| match (terminal, self) {
+                    (Terminal::Regex(_), _) => true,
+                    (Terminal::Symbol(text), _) if text.is_empty() => true,
+                    (Terminal::Ident, Key::Word(_) | Key::Ident) => true,
+                    (Terminal::Int, Key::Int) => true,
+                    (Terminal::Float, Key::Float) => true,
+                    (Terminal::String, Key::String) => true,
+                    (Terminal::Bool, Key::True | Key::False) => true,
+                    (Terminal::Keyword(word), Key::True) => word == "true",
+                    (Terminal::Keyword(word), Key::False) => word == "false",
+                    (Terminal::Keyword(word), Key::Word(key)) => word == key,
+                    (Terminal::Symbol(text), Key::Symbol(c)) => text.starts_with(c),
+                    _ => false,
+                })
+    }
+}
+
+impl Dispatch {
+    fn new(firsts: &[FirstSet], interner: &mut Interner) -> Self {
+        let admitted = |key: Key| -> Vec<usize> {
+            (0..firsts.len())
+                .filter(|&i| key.admits(&firsts[i]))
+                .collect()
+        };
+        let mut dispatch = Dispatch {
+            ident: admitted(Key::Ident),
+            int: admitted(Key::Int),
+            float: admitted(Key::Float),
+            string: admitted(Key::String),
+            true_: admitted(Key::True),
+            false_: admitted(Key::False),
+            symbol: admitted(Key::OtherSymbol),
+            end: admitted(Key::End),
+            ..Dispatch::default()
+        };
+        for terminal in firsts.iter().flat_map(|first| &first.terminals) {
+            match terminal {
+                Terminal::Keyword(word) => {
+                    let alternatives = admitted(Key::Word(word));
+                    dispatch.words.insert(interner.intern(word), alternatives);
+                }
+                Terminal::Symbol(text) => {
+                    if let Some(c) = text.chars().next() {
+                        let alternatives = admitted(Key::Symbol(c));
+                        let symbol = interner.intern(c.encode_utf8(&mut [0; 4]));
+                        dispatch.symbols.insert(symbol, alternatives);
+                    }
+                }
+                _ => {}
+            }
+        }
+        dispatch
+    }
+
+    /// The alternatives for `token`, whose interned text is `symbol`.
+    fn alternatives(&self, token: Option<&Token>, symbol: Option<Symbol>) -> &[usize] {
+        match token {
+            None | Some(Token::Ws) => &self.end,
+            Some(Token::Ident(_)) => listed(&self.words, symbol).unwrap_or(&self.ident),
+            Some(Token::Integer(_)) => &self.int,
+            Some(Token::Float(_)) => &self.float,
+            Some(Token::String(_)) => &self.string,
+            Some(Token::True) => &self.true_,
+            Some(Token::False) => &self.false_,
+            Some(Token::Symbol(_)) => listed(&self.symbols, symbol).unwrap_or(&self.symbol),
+        }
+    }
+}
+
+fn listed(table: &BTreeMap<Symbol, Vec<usize>>, symbol: Option<Symbol>) -> Option<&Vec<usize>> {
+    symbol.and_then(|symbol| table.get(&symbol))
+}
+
+impl Program {
+    /// Lowers `definition`, failing if a rule refers to an unknown rule.
+    pub fn compile(definition: &ParserDefinition) -> Result<Self> {
+        let ids: BTreeMap<String, usize> = definition
+            .all_rules()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, _))| (name.to_string(), id))
+            .collect();
+        let first = definition.first_sets();
+        let mut interner = Interner::default();
+        for word in ["true", "false"] {
+            interner.intern(word);
+        }
+        let mut rules = Vec::new();
+        for (name, patterns) in definition.all_rules() {
+            let alternatives: Vec<_> = patterns.iter().flat_map(Pattern::alternatives).collect();
+            let firsts: Vec<_> = alternatives
+                .iter()
+                .map(|alternative| first_of_sequence(alternative, &first))
+                .collect();
+            let terminals: Vec<_> = first[name]
+                .terminals
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            rules.push(Rule {
+                name: name.to_string(),
+                alternatives: alternatives
+                    .iter()
+                    .map(|alternative| lower_sequence(alternative, &ids, &mut interner))
+                    .collect::<Result<_>>()?,
+                dispatch: (alternatives.len() > 1).then(|| Dispatch::new(&firsts, &mut interner)),
+                expected: match terminals.is_empty() {
+                    true => format!("`{name}`"),
+                    false => terminals.join(" or "),
+                },
+            });
+        }
+        Ok(Self {
+            rules,
+            ids,
+            interner,
+        })
+    }
+
+    /// Parses all of `tokens` starting at the rule named `entry`, like
+    /// [`super::Parser::parse_entry`].
+    pub fn parse_entry(&self, entry: &str, tokens: &[Token]) -> Result<Ast> {
+        self.parse_entry_spanned(entry, tokens, &[])
+    }
+
+    /// Parses like [`Program::parse_entry`], with the byte ranges of the
+    /// tokens for the [`super::ErrorContext::span`] of errors.
+    pub fn parse_entry_spanned(
+        &self,
+        entry: &str,
+        tokens: &[Token],
+        spans: &[Span],
+    ) -> Result<Ast> {
+        let id = *self
+            .ids
+            .get(entry)
+            .ok_or_else(|| ParseError::UnknownRule(entry.to_string()))?;
+        let mut vm = Vm {
+            program: self,
+            tokens,
+            symbols: tokens.iter().map(|token| self.symbol(token)).collect(),
+            position: 0,
+            stack: RuleStack::new(),
+            failure: None,
+        };
+        let result = vm.rule(id).and_then(|ast| {
+            vm.skip_ws();
+            match vm.position < tokens.len() {
+                true => Err(ParseError::TrailingInput(vm.position)),
+                false => Ok(ast),
+            }
+        });
+        result.map_err(|error| {
+            let failure = vm.failure.take().map(|(index, stack)| Failure {
+                index,
+                rules: stack
+                    .into_iter()
+                    .map(|id| self.rules[id].name.clone())
+                    .collect(),
+            });
+            with_context(error, tokens, spans, failure, vm.position)
+        })
+    }
+
+    /// The interned text of `token`, if it is a word or symbol some literal
+    /// of the program consists of.
+    fn symbol(&self, token: &Token) -> Option<Symbol> {
+        match token {
+            Token::Ident(text) | Token::Symbol(text) => self.interner.get(text),
+            Token::True => self.interner.get("true"),
+            Token::False => self.interner.get("false"),
+            _ => None,
+        }
+    }
+}
+
+fn lower_sequence(
+    sequence: &[TokenPattern],
+    ids: &BTreeMap<String, usize>,
+    interner: &mut Interner,
+) -> Result<Sequence> {
+    sequence
+        .iter()
+        .map(|token| lower_token(token, ids, interner))
+        .collect()
+}
+
+fn lower_token(
+    token: &TokenPattern,
+    ids: &BTreeMap<String, usize>,
+    interner: &mut Interner,
+) -> Result<Step> {
+    let (op, field) = match &token.pattern {
+        InternalPattern::Named { name, kind } => {
+            let op = match kind {
+                InternalPatternKind::Ident => Op::Ident,
+                InternalPatternKind::Int => Op::Int,
+                InternalPatternKind::Float => Op::Float,
+                InternalPatternKind::String => Op::String,
+                InternalPatternKind::Bool => Op::Bool,
+                InternalPatternKind::Regex(regex) => Op::Regex {
+                    regex: regex.clone(),
+                    expected: format!("/{regex}/"),
+                },
+                InternalPatternKind::Keyword(word) => Op::Literal(Literal::word(word, interner)),
+                InternalPatternKind::Symbol(text) => Op::Literal(Literal::new(text, interner)),
+                InternalPatternKind::OneOf(options) => Op::OneOf {
+                    literals: (options.iter())
+                        .map(|option| Literal::new(option, interner))
+                        .collect(),
+                    expected: expected_one_of(options),
+                },
+                InternalPatternKind::Balanced { open, close } => Op::Balanced {
+                    open: Literal::new(open, interner),
+                    close: Literal::new(close, interner),
+                },
+                InternalPatternKind::Custom(rule) => Op::Call(
+                    *ids.get(rule)
+                        .ok_or_else(|| ParseError::UnknownRule(rule.clone()))?,
+                ),
+            };
+            (op, name.clone())
+        }
+        InternalPattern::Raw { value } => (Op::Literal(Literal::new(value, interner)), None),
+        InternalPattern::Exact { pattern } => {
+            (Op::Exact(lower_sequence(pattern, ids, interner)?), None)
+        }
+    };
+    Ok(Step {
+        op,
+        field,
+        optional: token.is_optional,
+        repeat: token.repeat_mode.as_ref().map(|mode| Repeat {
+            at_least_one: *mode == RepeatMode::OneOrMore,
+            separator: token
+                .separator
+                .as_deref()
+                .map(|separator| Literal::new(separator, interner)),
+        }),
+    })
+}
+
+/// Rule ids, stored inline for the nesting depths most inputs stay within,
+/// as the stack is copied for every failure further than the ones before.
+type RuleStack = SmallVec<[usize; 16]>;
+
+/// The state of one parse with a [`Program`]. It mirrors the interpreter in
+/// [`super::Parser`] step by step, which is what keeps the trees the same.
+struct Vm<'a> {
+    program: &'a Program,
+    tokens: &'a [Token],
+    /// The interned text of every token, see [`Program::symbol`].
+    symbols: Vec<Option<Symbol>>,
+    position: usize,
+    /// The rules being parsed, innermost last.
+    stack: RuleStack,
+    /// The first failure at the furthest index, with the rules at that point.
+    failure: Option<(usize, RuleStack)>,
+}
+
+impl Vm<'_> {
+    fn skip_ws(&mut self) {
+        while matches!(self.tokens.get(self.position), Some(Token::Ws)) {
+            self.position += 1;
+        }
+    }
+
+    /// Index of the next token that isn't whitespace.
+    fn peek(&self) -> usize {
+        let start = self.position.min(self.tokens.len());
+        self.tokens[start..]
+            .iter()
+            .position(|token| *token != Token::Ws)
+            .map_or(self.tokens.len(), |offset| start + offset)
+    }
+
+    fn record_failure(&mut self, index: usize) {
+        if self
+            .failure
+            .as_ref()
+            .is_none_or(|(furthest, _)| index > *furthest)
+        {
+            self.failure = Some((index, self.stack.clone()));
+        }
+    }
+
+    fn fail<T>(&mut self, expected: &str) -> Result<T> {
+        let index = self.position;
+        self.record_failure(index);
+        match self.tokens.get(index) {
+            Some(token) => Err(ParseError::UnexpectedToken {
+                index,
+                found: token.to_string(),
+                expected: expected.to_string(),
+            }),
+            None => Err(ParseError::UnexpectedEof(expected.to_string())),
+        }
+    }
+
+    fn expect(&mut self, expected: &str, f: impl FnOnce(&Token) -> Option<Node>) -> Result<Node> {
+        self.skip_ws();
+        match self.tokens.get(self.position).and_then(f) {
+            Some(node) => {
+                self.position += 1;
+                Ok(node)
+            }
+            None => self.fail(expected),
+        }
+    }
+
+    /// Words match an identifier or bool token with the same text, which
+    /// is never the text of a symbol token. Symbols match a run of symbol
+    /// tokens, one character each.
+    fn literal(&mut self, literal: &Literal) -> Result<Node> {
+        self.skip_ws();
+        let start = self.position;
+        for symbol in &literal.symbols {
+            let token = self.tokens.get(self.position);
+            let matches = literal.word || matches!(token, Some(Token::Symbol(_)));
+            match self.symbols.get(self.position) {
+                Some(Some(found)) if found == symbol && matches => self.position += 1,
+                _ => {
+                    let error = self.fail(&literal.expected);
+                    self.position = start;
+                    return error;
+                }
+            }
+        }
+        Ok(Node::Text(literal.text.clone()))
+    }
+
+    /// Whether `literal` is at `index`, matched like [`Vm::literal`].
+    fn literal_at(&self, index: usize, literal: &Literal) -> bool {
+        literal.symbols.iter().enumerate().all(|(offset, symbol)| {
+            let token = self.tokens.get(index + offset);
+            let matches = literal.word || matches!(token, Some(Token::Symbol(_)));
+            matches && self.symbols.get(index + offset).copied().flatten() == Some(*symbol)
+        })
+    }
+
+    /// The longest of `literals` at the next token, like the interpreter.
+    fn one_of(&mut self, literals: &[Literal], expected: &str) -> Result<Node> {
+        self.skip_ws();
+        let index = self.position;
+        let longest = (literals.iter())
+            .filter(|literal| self.literal_at(index, literal))
+            .max_by_key(|literal| literal.symbols.len());
+        match longest {
+            Some(literal) => {
+                self.position += literal.symbols.len();
+                Ok(Node::Text(literal.text.clone()))
+            }
+            None => self.fail(expected),
+        }
+    }
+
+    /// `open` up to the `close` matching it, like the interpreter.
+    fn balanced(&mut self, open: &Literal, close: &Literal) -> Result<Node> {
+        self.skip_ws();
+        let start = self.position;
+        if !self.literal_at(start, open) {
+            return self.fail(&open.expected);
+        }
+        let content = start + open.symbols.len();
+        let mut index = content;
+        let mut depth = 1;
+        loop {
+            if self.literal_at(index, close) {
+                depth -= 1;
+                if depth == 0 {
+                    let text: String = (self.tokens[content..index].iter())
+                        .map(Token::to_string)
+                        .collect();
+                    self.position = index + close.symbols.len();
+                    return Ok(Node::Text(text.trim().to_string()));
+                }
+                index += close.symbols.len();
+            } else if !open.symbols.is_empty() && self.literal_at(index, open) {
+                depth += 1;
+                index += open.symbols.len();
+            } else if index < self.tokens.len() {
+                index += 1;
+            } else {
+                self.position = index;
+                let error = self.fail(&close.expected);
+                self.position = start;
+                return error;
+            }
+        }
+    }
+
+    fn op(&mut self, op: &Op, fields: &mut BTreeMap<String, Node>) -> Result<Node> {
+        match op {
+            Op::Ident => self.expect("identifier", |token| match token {
+                Token::Ident(s) => Some(Node::Ident(s.clone())),
+                _ => None,
+            }),
+            Op::Int => self.expect("integer", |token| match token {
+                Token::Integer(i) => Some(Node::Int(*i)),
+                _ => None,
+            }),
+            Op::Float => self.expect("float", |token| match token {
+                Token::Float(f) => Some(Node::Float(*f)),
+                _ => None,
+            }),
+            Op::String => self.expect("string", |token| match token {
+                Token::String(s) => Some(Node::String(s.clone())),
+                _ => None,
+            }),
+            Op::Bool => self.expect("bool", |token| match token {
+                Token::True => Some(Node::Bool(true)),
+                Token::False => Some(Node::Bool(false)),
+                _ => None,
+            }),
+            Op::Regex { regex, expected } => self.expect(expected, |token| {
+                let text = token.to_string();
+                (regex.matches_whole(&text) && *token != Token::Ws).then_some(Node::Text(text))
+            }),
+            Op::Literal(literal) => self.literal(literal),
+            Op::OneOf { literals, expected } => self.one_of(literals, expected),
+            Op::Balanced { open, close } => self.balanced(open, close),
+            Op::Call(id) => self.rule(*id).map(Node::Ast),
+            Op::Exact(sequence) => {
+                self.sequence(sequence, fields)?;
+                Ok(Node::None)
+            }
+        }
+    }
+
+    fn repeated(
+        &mut self,
+        step: &Step,
+        repeat: &Repeat,
+        fields: &mut BTreeMap<String, Node>,
+    ) -> Result<Vec<Node>> {
+        let mut items = Vec::new();
+        loop {
+            let pos = self.position;
+            if let (Some(separator), false) = (&repeat.separator, items.is_empty()) {
+                if self.literal(separator).is_err() {
+                    self.position = pos;
+                    break;
+                }
+            }
+            match self.op(&step.op, fields) {
+                Ok(node) => items.push(node),
+                Err(e) => {
+                    self.position = pos;
+                    if items.is_empty() && repeat.at_least_one {
+                        return Err(e);
+                    }
+                    break;
+                }
+            }
+            // a pattern that consumes nothing would otherwise repeat forever
+            if self.position == pos {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn step(&mut self, step: &Step, fields: &mut BTreeMap<String, Node>) -> Result<()> {
+        let node = match &step.repeat {
+            Some(repeat) => Node::List(self.repeated(step, repeat, fields)?),
+            None if step.optional => {
+                let pos = self.position;
+                match self.op(&step.op, fields) {
+                    Ok(node) => node,
+                    Err(_) => {
+                        self.position = pos;
+                        Node::None
+                    }
+                }
+            }
+            None => self.op(&step.op, fields)?,
+        };
+        if let Some(field) = &step.field {
+            fields.insert(field.clone(), node);
+        }
+        Ok(())
+    }
+
+    fn sequence(&mut self, sequence: &Sequence, fields: &mut BTreeMap<String, Node>) -> Result<()> {
+        for step in sequence {
+            self.step(step, fields)?;
+        }
+        Ok(())
+    }
+
+    fn rule(&mut self, id: usize) -> Result<Ast> {
+        self.stack.push(id);
+        let result = self.alternatives(id);
+        self.stack.pop();
+        result
+    }
+
+    fn alternatives(&mut self, id: usize) -> Result<Ast> {
+        let program = self.program;
+        let rule = &program.rules[id];
+        let start = self.position;
+        let candidates = match (&rule.dispatch, rule.alternatives.is_empty()) {
+            (Some(dispatch), _) => {
+                let next = self.peek();
+                dispatch.alternatives(
+                    self.tokens.get(next),
+                    self.symbols.get(next).copied().flatten(),
+                )
+            }
+            (None, false) => &[0],
+            (None, true) => &[],
+        };
+        let mut error = None;
+        for &i in candidates {
+            let mut ast = Ast::new(&rule.name);
+            match self.sequence(&rule.alternatives[i], &mut ast.fields) {
+                Ok(()) => return Ok(ast),
+                Err(e) => {
+                    self.position = start;
+                    error = Some(e);
+                }
+            }
+        }
+        match error {
+            Some(error) => Err(error),
+            None if rule.alternatives.is_empty() => {
+                self.record_failure(start);
+                Err(ParseError::Unknown)
+            }
+            None => {
+                self.skip_ws();
+                let error = self.fail(&rule.expected);
+                self.position = start;
+                error
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem;
+
+use super::ast::Ast;
+use super::parser::{Parser, Result, RuleCoverage};
+use crate::definition::ParserDefinition;
+use crate::lexer::{LexingError, Span, Token};
+
+/// Buffers for parsing one input after another with the same definition,
+/// like the files of a batch. Each input is lexed into the buffers of the
+/// previous one, so they only grow to the size of the largest input
+/// instead of being allocated for every input anew.
+///
+/// ```
+/// use tmpl::custom::ParseSession;
+///
+/// let definition = tmpl::definition::parse("Main:\n<n:int>\n~~~\n")?;
+/// let mut session = ParseSession::new(definition);
+/// for (source, valid) in [("1", true), ("x", false), ("2", true)] {
+///     session.lex(source).unwrap();
+///     assert_eq!(session.parse().is_ok(), valid);
+/// }
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseSession {
+    definition: Arc<ParserDefinition>,
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    trace: bool,
+    coverage: Option<RuleCoverage>,
+}
+
+impl ParseSession {
+    pub fn new(definition: impl Into<Arc<ParserDefinition>>) -> Self {
+        Self {
+            definition: definition.into(),
+            tokens: Vec::new(),
+            spans: Vec::new(),
+            trace: false,
+            coverage: None,
+        }
+    }
+
+    /// Traces the parses like [`Parser::with_trace`].
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Records the matches of all parses, see [`ParseSession::coverage`].
+    pub fn with_coverage(mut self, coverage: bool) -> Self {
+        self.coverage = coverage.then(RuleCoverage::default);
+        self
+    }
+
+    /// Matches of all parses of the session so far, like
+    /// [`Parser::coverage`], if enabled with [`ParseSession::with_coverage`].
+    pub fn coverage(&self) -> Option<&RuleCoverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn definition(&self) -> &Arc<ParserDefinition> {
+        &self.definition
+    }
+
+    /// Lexes `src` as the input the next parse uses, replacing the last one.
+    /// If lexing fails there is no input until the next `lex`.
+    pub fn lex(&mut self, src: &str) -> core::result::Result<(), (LexingError, Span)> {
+        let lexed = crate::lexer::lex_into(src, &mut self.tokens, &mut self.spans);
+        if lexed.is_err() {
+            self.reset();
+        }
+        lexed
+    }
+
+    /// The tokens of the input.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Byte ranges of the tokens of the input.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    pub fn parse(&mut self) -> Result<Ast> {
+        self.parse_entry("Main")
+    }
+
+    /// Parses the input using `rule_name` as the start rule, like
+    /// [`Parser::parse_entry`]. The input stays, so it can be parsed again.
+    pub fn parse_entry(&mut self, rule_name: &str) -> Result<Ast> {
+        let parser = Parser::new(Arc::clone(&self.definition), mem::take(&mut self.tokens))
+            .with_token_spans(mem::take(&mut self.spans))
+            .with_trace(self.trace)
+            .with_coverage(self.coverage.is_some());
+        let parsed = parser.parse_entry(rule_name);
+        if let (Some(coverage), Some(parsed)) = (&mut self.coverage, parser.coverage()) {
+            coverage.merge(&parsed);
+        }
+        (self.tokens, self.spans) = parser.into_tokens();
+        parsed
+    }
+
+    /// Drops the input, keeping the buffers for the next one.
+    pub fn reset(&mut self) {
+        self.tokens.clear();
+        self.spans.clear();
+    }
+}
@@ -1,5 +1,49 @@
 mod ast;
+#[cfg(feature = "std")]
+mod binary;
+mod builder;
+mod merge;
+mod optimize;
+#[cfg(feature = "std")]
 mod parser;
+mod reachability;
+mod recursion;
+mod regexes;
+#[cfg(feature = "miette")]
+mod report;
+#[cfg(feature = "std")]
+mod schema;
+#[cfg(feature = "std")]
+mod serialized;
+mod sets;
+mod symbols;
+#[cfg(feature = "std")]
+mod validate;
+mod version;
+mod visit;
 
+pub(crate) use ast::expected_one_of;
 pub use ast::*;
-pub use parser::parse;
+#[cfg(feature = "std")]
+pub use binary::BinaryError;
+pub use builder::{BuildError, DefinitionBuilder};
+pub use merge::{MergeError, MergePolicy};
+pub use optimize::{
+    CollapseWrapperRules, FactorPrefixes, InlineSingleUseRules, InlineTrivialRules,
+    RemoveUnreachableRules, Rewrite,
+};
+#[cfg(feature = "std")]
+pub use parser::{parse, parse_lazy, parse_with_diagnostics, set_trace, SyntaxError};
+pub use reachability::Reachability;
+pub use regexes::RegexTable;
+#[cfg(feature = "std")]
+pub use serialized::LoadError;
+pub(crate) use sets::first_of_sequence;
+pub use sets::{FirstSet, Terminal};
+pub use symbols::{Location, SymbolIndex};
+#[cfg(feature = "std")]
+pub use validate::{Diagnostics, Severity, ValidationIssue, ValidationKind};
+pub use version::{check_version, GRAMMAR_VERSION};
+pub use visit::{
+    walk_definition, walk_pattern, walk_rule, walk_sequence, walk_token, PatternVisitor,
+};
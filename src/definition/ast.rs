@@ -1,11 +1,27 @@
-use std::{collections::HashMap, fmt::Display, num::ParseIntError};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Display, hash::BuildHasherDefault, num::ParseIntError};
 
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use stringlit::s;
+use indexmap::IndexMap;
+use once_cell::race::OnceBox;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use siphasher::sip::SipHasher13;
+use smallvec::{smallvec, SmallVec};
+
+use super::regexes::RegexTable;
+use super::symbols::SymbolIndex;
+use super::visit::PatternVisitor;
 use thiserror::Error;
 
-pub type Result<T> = std::result::Result<T, DefinitionParseError>;
+pub type Result<T> = core::result::Result<T, DefinitionParseError>;
 
 #[derive(Error, Debug)]
 pub enum DefinitionParseError {
@@ -14,31 +30,298 @@ pub enum DefinitionParseError {
     #[error("Missing main rule")]
     MissingMainRule,
     #[error("Invalid regex: {0}")]
-    InvalidRegex(#[from] regex::Error),
+    InvalidRegex(Box<regex_automata::meta::BuildError>),
     #[error("Invalid integer: {0}")]
     ParseIntError(#[from] ParseIntError),
     #[error("Invalid float: {0}")]
-    ParseFloatError(#[from] std::num::ParseFloatError),
+    ParseFloatError(#[from] core::num::ParseFloatError),
     #[error("Invalid repeat mode: {0}")]
     InvalidRepeatMode(String),
     #[error("Invalid char: {0}")]
     InvalidChar(char),
+    #[error("Rule `{rule}` refers to unknown rule `{reference}`")]
+    UnknownRule { rule: String, reference: String },
+    #[error("Rule `{0}` is already defined, declare it as `override {0}:` to replace it")]
+    DuplicateRule(String),
+    #[error("Rule `{0}` is named like a built-in pattern or keyword")]
+    ReservedRuleName(String),
+    #[error("Left recursion: {}", .0.join(" -> "))]
+    LeftRecursion(Vec<String>),
+    #[error("Regex /{regex}/ takes {size} bytes, more than the limit of {limit}")]
+    RegexTooLarge {
+        regex: String,
+        size: usize,
+        limit: usize,
+    },
+    #[error("Invalid grammar version `{0}`, expected one like \"0.1\"")]
+    InvalidVersion(String),
+    #[error("Grammar is written for tmpl_version {found}, but only {supported} is supported")]
+    UnsupportedVersion { found: String, supported: String },
+    #[error("Invalid emit template: {0}")]
+    InvalidEmit(crate::template::TemplateError),
+    #[error("Test `{0}` has no `input`")]
+    MissingTestInput(String),
+    /// An error about the grammar text in the byte range `span`.
+    #[error("{error}")]
+    At {
+        error: Box<DefinitionParseError>,
+        span: core::ops::Range<usize>,
+    },
+}
+
+impl DefinitionParseError {
+    /// Attaches the byte range of the grammar text the error is about,
+    /// unless the error already has a more precise one.
+    pub fn at(self, span: core::ops::Range<usize>) -> Self {
+        match self {
+            error @ DefinitionParseError::At { .. } => error,
+            error => DefinitionParseError::At {
+                error: Box::new(error),
+                span,
+            },
+        }
+    }
+
+    /// Byte range of the grammar text the error is about, if known.
+    pub fn span(&self) -> Option<core::ops::Range<usize>> {
+        match self {
+            DefinitionParseError::At { span, .. } => Some(span.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A compiled regex that keeps its source, which is what it prints,
+/// compares and serializes as. Clones share the compiled program.
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    compiled: Arc<OnceBox<CompiledRegex>>,
+}
+
+type CompiledRegex =
+    core::result::Result<regex_automata::meta::Regex, Box<regex_automata::meta::BuildError>>;
+
+impl Regex {
+    pub fn new(source: &str) -> core::result::Result<Self, Box<regex_automata::meta::BuildError>> {
+        let compiled = regex_automata::meta::Regex::new(source)?;
+        Ok(Self {
+            source: source.to_string(),
+            compiled: Arc::new(OnceBox::with_value(Box::new(Ok(compiled)))),
+        })
+    }
+
+    /// Checks the syntax of `source` like [`Regex::new`], but leaves
+    /// compiling it to the first match. Compiling can still fail then, e.g.
+    /// when the regex is too large, and a regex that failed to compile
+    /// matches nothing. [`Regex::compile`] reports such errors.
+    pub fn lazy(source: &str) -> core::result::Result<Self, Box<regex_automata::meta::BuildError>> {
+        if regex_automata::util::syntax::parse(source).is_err() {
+            // Compiling reports the error the way `new` does.
+            return Self::new(source);
+        }
+        Ok(Self {
+            source: source.to_string(),
+            compiled: Arc::default(),
+        })
+    }
+
+    /// Compiles the regex unless that happened already.
+    pub fn compile(&self) -> core::result::Result<(), Box<regex_automata::meta::BuildError>> {
+        self.compiled().as_ref().map(|_| ()).map_err(Clone::clone)
+    }
+
+    /// Whether the regex was compiled, which [`Regex::lazy`] regexes only
+    /// are once they were used.
+    pub fn is_compiled(&self) -> bool {
+        self.compiled.get().is_some()
+    }
+
+    fn compiled(&self) -> &CompiledRegex {
+        self.compiled.get_or_init(|| {
+            Box::new(regex_automata::meta::Regex::new(&self.source).map_err(Box::new))
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.compiled()
+            .as_ref()
+            .is_ok_and(|regex| regex.is_match(text))
+    }
+
+    /// Heap memory the compiled regex uses, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.compiled()
+            .as_ref()
+            .map_or(0, |regex| regex.memory_usage())
+    }
+
+    /// Whether the leftmost match of the regex covers all of `text`.
+    pub fn matches_whole(&self, text: &str) -> bool {
+        self.compiled().as_ref().is_ok_and(|regex| {
+            regex
+                .find(text)
+                .is_some_and(|m| m.start() == 0 && m.end() == text.len())
+        })
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl core::fmt::Debug for Regex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Regex").field(&self.source).finish()
+    }
+}
+
+impl Display for Regex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Serialize for Regex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        self.source.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Regex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Regex::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum InternalPatternKind {
+    /// An identifier, written like `<name:ident>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<name:ident>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<name:ident>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Ident,
+    /// An integer, written like `<n:int>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<n:int>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<n:int>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Int,
+    /// A float, written like `<x:float>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<x:float>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<x:float>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Float,
+    /// A string literal, written like `<s:string>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<s:string>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<s:string>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     String,
+    /// `true` or `false`, written like `<b:bool>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<b:bool>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<b:bool>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Bool,
-    Regex(#[serde(with = "serde_regex")] Regex),
+    /// A token matching the regex, written like `<path:s/[a-z]+\/[a-z]+/>` with
+    /// slashes in the regex escaped.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<path:s/[a-z]+\\/[a-z]+/>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<path:s/[a-z]+\\/[a-z]+/>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    Regex(Regex),
+    /// The keyword, written like `<kw[let]>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<kw[let]> <name:ident>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<kw[let]>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Keyword(String),
+    /// The rule with this name, written like `<item:Item>`.
+    ///
+    /// ```
+    /// let src = "Main:\n<item:Item>\n~~~\n\nItem:\n<n:int>\n~~~\n";
+    /// let definition = tmpl::definition::parse(src)?;
+    /// assert!(definition.to_string().contains("<item:Item>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Custom(String),
+    /// The symbols, written like `<sym[=>]>`.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse("Main:\n<sym[=>]> <n:int>\n~~~\n")?;
+    /// assert!(definition.to_string().contains("<sym[=>]>"));
+    /// assert!(definition.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
     Symbol(String),
+    /// Any one of the listed keywords and symbols, the longest one that
+    /// matches if several do, written like `<op:oneof[+, -, *, /]>`. The
+    /// options are matched at once, which is faster than a rule with an
+    /// alternative for each.
+    ///
+    /// ```
+    /// use tmpl::custom::Node;
+    ///
+    /// let grammar = tmpl::Grammar::parse("Main:\n<l:int> <op:oneof[+, -, ->, mod]> <r:int>\n~~~\n")?;
+    /// assert_eq!(grammar.parse_str("1 -> 2")?.fields["op"], Node::Text("->".to_string()));
+    /// assert_eq!(grammar.parse_str("1 mod 2")?.fields["op"], Node::Text("mod".to_string()));
+    /// assert!(grammar.parse_str("1 * 2").is_err());
+    /// assert!(grammar.definition().round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    OneOf(Vec<String>),
+    /// The `open` keyword or symbols, everything up to the `close` matching
+    /// it and `close` itself, written like `<body:balanced('{', '}')>`.
+    /// Nested pairs are skipped as a whole, so grammars can treat embedded
+    /// blocks as opaque. It captures the tokens between the delimiters as
+    /// text, with whitespace in between shortened to a single space.
+    ///
+    /// ```
+    /// use tmpl::custom::Node;
+    ///
+    /// let grammar = tmpl::Grammar::parse("Main:\n<kw[code]> <body:balanced('{', '}')>\n~~~\n")?;
+    /// let ast = grammar.parse_str("code { if x { y(); } }")?;
+    /// assert_eq!(ast.fields["body"], Node::Text("if x { y(); }".to_string()));
+    /// assert!(grammar.parse_str("code { if x { y(); }").is_err());
+    /// assert!(grammar.definition().round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    Balanced { open: String, close: String },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum InternalPattern {
     Named {
         name: Option<String>,
@@ -90,7 +373,25 @@ pub fn bool(name: Option<String>) -> InternalPattern {
 pub fn regex(name: Option<String>, value: &str) -> Result<InternalPattern> {
     Ok(InternalPattern::Named {
         name,
-        kind: InternalPatternKind::Regex(regex::Regex::new(value)?),
+        kind: InternalPatternKind::Regex(
+            Regex::new(value).map_err(DefinitionParseError::InvalidRegex)?,
+        ),
+    })
+}
+
+/// Like [`regex`], taking the compiled regex from `regexes`.
+pub fn shared_regex(
+    name: Option<String>,
+    value: &str,
+    regexes: &RefCell<RegexTable>,
+) -> Result<InternalPattern> {
+    let regex = regexes
+        .borrow_mut()
+        .get_or_compile(value)
+        .map_err(DefinitionParseError::InvalidRegex)?;
+    Ok(InternalPattern::Named {
+        name,
+        kind: InternalPatternKind::Regex(regex),
     })
 }
 
@@ -114,6 +415,26 @@ pub fn symbol(name: Option<String>, value: &str) -> InternalPattern {
     }
 }
 
+pub fn one_of(name: Option<String>, options: Vec<String>) -> InternalPattern {
+    InternalPattern::Named {
+        name,
+        kind: InternalPatternKind::OneOf(options),
+    }
+}
+
+pub fn balanced(name: Option<String>, open: String, close: String) -> InternalPattern {
+    InternalPattern::Named {
+        name,
+        kind: InternalPatternKind::Balanced { open, close },
+    }
+}
+
+/// What a parser reports to expect when none of `options` matches.
+pub(crate) fn expected_one_of(options: &[String]) -> String {
+    let options: Vec<_> = options.iter().map(|option| format!("`{option}`")).collect();
+    format!("one of {}", options.join(", "))
+}
+
 pub fn custom(name: Option<String>, value: &str) -> InternalPattern {
     InternalPattern::Named {
         name,
@@ -121,13 +442,13 @@ pub fn custom(name: Option<String>, value: &str) -> InternalPattern {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RepeatMode {
     ZeroOrMore,
     OneOrMore,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenPattern {
     pub pattern: InternalPattern,
     pub is_optional: bool,
@@ -175,18 +496,11 @@ pub fn rw(pattern: InternalPattern) -> Result<TokenPattern> {
     })
 }
 
-pub fn alternative(left: Vec<TokenPattern>, right: Pattern) -> Result<Pattern> {
-    Ok(Pattern::Alternative {
-        left: left.into_iter().collect(),
-        right: Box::new(right),
-    })
+pub fn choice(alternatives: Vec<Sequence>) -> Result<Pattern> {
+    Ok(Pattern::from_alternatives(alternatives))
 }
 
-pub fn token(left: Vec<TokenPattern>) -> Result<Pattern> {
-    Ok(Pattern::Token(left))
-}
-
-impl std::iter::FromIterator<TokenPattern> for Vec<Pattern> {
+impl core::iter::FromIterator<TokenPattern> for Vec<Pattern> {
     fn from_iter<I: IntoIterator<Item = TokenPattern>>(iter: I) -> Self {
         iter.into_iter().collect()
     }
@@ -217,28 +531,64 @@ pub fn with_repeat_mode(pat: InternalPattern, re: Option<String>) -> Result<Toke
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Tokens matched one after another. Most sequences are short, so the
+/// first couple of tokens are stored inline instead of allocated.
+pub type Sequence = SmallVec<[TokenPattern; 2]>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SerializedPattern")]
 pub enum Pattern {
+    Token(Sequence),
+    /// Two or more alternatives, the first one matching wins.
+    Choice(Vec<Sequence>),
+}
+
+/// What [`Pattern`] is deserialized from. Besides the current variants, it
+/// accepts the `Alternative` chains earlier versions serialized choices as.
+/// The variant order matches [`Pattern`], so binary formats keep working.
+#[derive(Deserialize)]
+enum SerializedPattern {
+    Token(Sequence),
+    Choice(Vec<Sequence>),
     Alternative {
-        left: Vec<TokenPattern>,
-        right: Box<Pattern>,
+        left: Sequence,
+        right: Box<SerializedPattern>,
     },
-    Token(Vec<TokenPattern>),
+}
+
+impl From<SerializedPattern> for Pattern {
+    fn from(pattern: SerializedPattern) -> Self {
+        let mut alternatives = Vec::new();
+        let mut next = pattern;
+        loop {
+            match next {
+                SerializedPattern::Token(sequence) => alternatives.push(sequence),
+                SerializedPattern::Choice(sequences) => alternatives.extend(sequences),
+                SerializedPattern::Alternative { left, right } => {
+                    alternatives.push(left);
+                    next = *right;
+                    continue;
+                }
+            }
+            break;
+        }
+        Pattern::from_alternatives(alternatives)
+    }
 }
 
 impl From<TokenPattern> for Pattern {
     fn from(pattern: TokenPattern) -> Self {
-        Pattern::Token(vec![pattern])
+        Pattern::Token(smallvec![pattern])
     }
 }
 
 impl From<Vec<TokenPattern>> for Pattern {
     fn from(pattern: Vec<TokenPattern>) -> Self {
-        Pattern::Token(pattern)
+        Pattern::Token(pattern.into())
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Char(char),
     String(String),
@@ -248,103 +598,430 @@ pub enum Value {
     List(Vec<Value>),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Define {
     pub name: String,
     pub value: Value,
 }
 
+/// An example input declared next to the rules in grammar text, which
+/// `tmpl test` parses along with the test files:
+///
+/// ```text
+/// test "addition" { input: "1 + 2" expect: Ok }
+/// test "missing operand" { rule: Expr input: "1 +" expect: Error }
+/// ```
+///
+/// ```
+/// let src = "Main:\n<a:int> + <b:int>\n~~~\n\ntest \"addition\" { input: \"1 + 2\" }\n";
+/// let definition = tmpl::definition::parse(src)?;
+/// assert_eq!(definition.tests[0].input, "1 + 2");
+/// assert_eq!(tmpl::definition::parse(&definition.to_string())?, definition);
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GrammarTest {
+    pub name: String,
+    pub input: String,
+    /// Rule to start parsing from, defaults to `Main`.
+    #[serde(default)]
+    pub rule: Option<String>,
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+/// Whether a test input is expected to parse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Expectation {
+    #[default]
+    Ok,
+    Error,
+}
+
 pub enum RuleOrDefine {
-    Rule { name: String, pattern: Vec<Pattern> },
+    Rule {
+        name: String,
+        pattern: Vec<Pattern>,
+        /// Declared with `override`, so it may replace an earlier rule.
+        is_override: bool,
+        emit: Option<String>,
+        /// The `///` lines before the rule, without the slashes.
+        doc: Option<String>,
+    },
     Define(Define),
+    Test(GrammarTest),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The rules of a definition besides `Main`, in the order they were declared.
+/// The hasher has fixed keys, so building the same map always gives the same
+/// result, without depending on `std` for random ones.
+pub type RuleMap = IndexMap<String, Vec<Pattern>, BuildHasherDefault<SipHasher13>>;
+
+/// Names with a meaning of their own in grammar text: the built-in pattern
+/// kinds like `<n:int>`, the prefixes of `<sym[..]>` and `<kw[..]>` and the
+/// words starting declarations. A rule with one of these names can't be
+/// referred to, and captures with them are easily mistaken for patterns.
+pub const RESERVED_NAMES: &[&str] = &[
+    "ident",
+    "int",
+    "float",
+    "string",
+    "bool",
+    "sym",
+    "kw",
+    "oneof",
+    "balanced",
+    "define",
+    "override",
+    "tmpl_version",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ParserDefinition {
+    /// The grammar language version declared with `tmpl_version`, if any.
+    #[serde(default)]
+    pub version: Option<String>,
     pub entry: Vec<Pattern>,
-    pub rules: HashMap<String, Vec<Pattern>>,
+    pub rules: RuleMap,
     pub defines: Vec<Define>,
+    /// The templates rules declare with `~~~ emit "..."`, by rule name,
+    /// see [`crate::template::Template`].
+    #[serde(default)]
+    pub emits: BTreeMap<String, String>,
+    /// The doc comments of rules, written as `///` lines before them, by
+    /// rule name.
+    #[serde(default)]
+    pub docs: BTreeMap<String, String>,
+    /// The `test "..." { ... }` blocks of the grammar, in the order they
+    /// were declared.
+    #[serde(default)]
+    pub tests: Vec<GrammarTest>,
+    /// Where rules are declared and referenced in the grammar text, see
+    /// [`ParserDefinition::references`].
+    #[serde(skip)]
+    pub symbols: SymbolIndex,
+}
+
+impl InternalPattern {
+    /// Names of the rules this pattern refers to.
+    pub fn references(&self) -> Vec<&str> {
+        struct References<'a>(Vec<&'a str>);
+
+        impl<'a> PatternVisitor<'a> for References<'a> {
+            fn visit_kind(&mut self, kind: &'a InternalPatternKind) {
+                if let InternalPatternKind::Custom(name) = kind {
+                    self.0.push(name);
+                }
+            }
+        }
+
+        let mut references = References(Vec::new());
+        references.visit_pattern(self);
+        references.0
+    }
+}
+
+impl Pattern {
+    /// Makes a pattern trying `alternatives` in order, a [`Pattern::Token`]
+    /// for a single one and a [`Pattern::Choice`] otherwise.
+    pub fn from_alternatives<S: Into<Sequence>>(alternatives: Vec<S>) -> Self {
+        let mut alternatives: Vec<Sequence> = alternatives.into_iter().map(Into::into).collect();
+        match alternatives.len() {
+            0 => Pattern::Token(Sequence::new()),
+            1 => Pattern::Token(alternatives.remove(0)),
+            _ => Pattern::Choice(alternatives),
+        }
+    }
+
+    /// The alternatives of this pattern, in the order they are tried.
+    pub fn alternatives(&self) -> &[Sequence] {
+        match self {
+            Pattern::Token(sequence) => core::slice::from_ref(sequence),
+            Pattern::Choice(alternatives) => alternatives,
+        }
+    }
+}
+
+fn fmt_sequence(sequence: &[TokenPattern]) -> String {
+    sequence
+        .iter()
+        .map(|p| format!("{p}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn fmt_rule(
+    f: &mut core::fmt::Formatter<'_>,
+    name: &str,
+    patterns: &[Pattern],
+    emit: Option<&String>,
+) -> core::fmt::Result {
+    writeln!(f, "{name}:")?;
+    let alternatives: Vec<_> = patterns.iter().flat_map(|p| p.alternatives()).collect();
+    match &alternatives[..] {
+        [single] => writeln!(f, "{}", fmt_sequence(single))?,
+        _ => {
+            for alternative in &alternatives {
+                writeln!(f, "| {}", fmt_sequence(alternative))?;
+            }
+        }
+    }
+    match emit {
+        Some(emit) => {
+            let escaped = emit.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "~~~ emit \"{escaped}\"")
+        }
+        None => writeln!(f, "~~~"),
+    }
+}
+
+/// Undoes the `\"` and `\\` escapes of an emit template.
+pub fn unescape_emit(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+impl ParserDefinition {
+    /// Looks up a rule by name, including `Main`.
+    pub fn rule(&self, name: &str) -> Option<&[Pattern]> {
+        match name {
+            "Main" => Some(&self.entry),
+            _ => self.rules.get(name).map(|p| &p[..]),
+        }
+    }
+
+    /// The doc comment of the rule `name`, see [`ParserDefinition::docs`].
+    pub fn doc(&self, name: &str) -> Option<&str> {
+        self.docs.get(name).map(String::as_str)
+    }
+
+    /// The rule `name` as grammar text, like printing the definition writes
+    /// it but without its doc comment.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse(concat!(
+    ///     "Main:\n<item:Item>\n~~~\n\n",
+    ///     "/// A key and its value.\n",
+    ///     "Item:\n<key:ident> = <value:int>\n~~~\n",
+    /// ))?;
+    /// assert_eq!(definition.rule_text("Item").unwrap(), "Item:\n<key:ident> = <value:int>\n~~~\n");
+    /// assert_eq!(definition.doc("Item"), Some("A key and its value."));
+    /// assert_eq!(definition.captures("Item"), ["key", "value"]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn rule_text(&self, name: &str) -> Option<String> {
+        struct Rule<'a>(&'a str, &'a [Pattern], Option<&'a String>);
+
+        impl Display for Rule<'_> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                fmt_rule(f, self.0, self.1, self.2)
+            }
+        }
+
+        let patterns = self.rule(name)?;
+        Some(Rule(name, patterns, self.emits.get(name)).to_string())
+    }
+
+    /// Names of the fields the trees of the rule `name` can have, in the
+    /// order they first appear in its alternatives.
+    pub fn captures(&self, name: &str) -> Vec<&str> {
+        self.rule(name)
+            .map(super::visit::captures)
+            .unwrap_or_default()
+    }
+
+    /// Whether printing the definition gives grammar text that parses back
+    /// to the same definition. This holds for every definition parsed from
+    /// text, but definitions built in code can contain patterns the text
+    /// syntax can't express, like groups or empty alternatives.
+    ///
+    /// ```
+    /// let definition = tmpl::definition::parse(concat!(
+    ///     "tmpl_version \"0.1\";\n",
+    ///     "define greeting: \"say \\\"hi\\\" \\\\ bye\";\n",
+    ///     "define quote: '\\'';\n",
+    ///     "define limits: [1, 2.5, true, \"x\"];\n\n",
+    ///     "/// A list of items.\n",
+    ///     "///\n",
+    ///     "/// Or a block.\n",
+    ///     "Main:\n",
+    ///     "| <items:Item> ** \",\" ;\n",
+    ///     "| <kw[begin]> <body:Block>* <kw[end]>\n",
+    ///     "~~~ emit \"{for i in items sep \\\", \\\"}{i}{end}\"\n\n",
+    ///     "Item:\n<name:ident> = <value:Value>?\n~~~\n\n",
+    ///     "Value:\n",
+    ///     "| <int> | <float> | <string> | <bool>\n",
+    ///     "| <s/[a-z]+\\/[0-9]+/>\n",
+    ///     "| <sym[=>]> <ident>+\n",
+    ///     "| <op:oneof[+, -, and]>\n",
+    ///     "| <inner:balanced('(', ')')>\n",
+    ///     "| <words:ident> ++ \";\"\n",
+    ///     "~~~\n\n",
+    ///     "Block:\nbegin <Item>* end\n~~~\n\n",
+    ///     "override Block:\n{ <Item>* }\n~~~\n\n",
+    ///     "test \"items\" { input: \"a = 1, b = \\\"x\\\"\", expect: Ok }\n",
+    /// ))?;
+    /// assert!(definition.round_trips());
+    ///
+    /// let sample = tmpl::definition::parse(include_str!("../../test.tmpl"))?;
+    /// assert!(sample.round_trips());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn round_trips(&self) -> bool {
+        matches!(super::parse(&self.to_string()), Ok(parsed) if parsed == *self)
+    }
+
+    /// All rules with `Main` first and the remaining rules in declaration order.
+    pub fn all_rules(&self) -> Vec<(&str, &[Pattern])> {
+        core::iter::once(("Main", &self.entry[..]))
+            .chain(self.rules.iter().map(|(n, p)| (n.as_str(), &p[..])))
+            .collect()
+    }
 }
 
 impl Display for ParserDefinition {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(version) = &self.version {
+            writeln!(f, "tmpl_version \"{}\";\n", version)?;
+        }
         for def in &self.defines {
             writeln!(f, "{}", def)?;
         }
-        writeln!(f)?;
-        write!(f, "Main:")?;
-        let formatted: Vec<_> = self.entry.iter().map(|p| format!("{p}")).collect();
-        writeln!(f, "{}", formatted.join(""))?;
+        if !self.defines.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, (name, patterns)) in self.all_rules().into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            if let Some(doc) = self.docs.get(name) {
+                for line in doc.lines() {
+                    match line {
+                        "" => writeln!(f, "///")?,
+                        line => writeln!(f, "/// {line}")?,
+                    }
+                }
+            }
+            fmt_rule(f, name, patterns, self.emits.get(name))?;
+        }
+        if !self.tests.is_empty() {
+            writeln!(f)?;
+        }
+        for test in &self.tests {
+            writeln!(f, "{test}")?;
+        }
         Ok(())
     }
 }
 
+impl Display for GrammarTest {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = Value::String(self.name.clone());
+        let input = Value::String(self.input.clone());
+        write!(f, "test {name} {{ ")?;
+        if let Some(rule) = &self.rule {
+            write!(f, "rule: {rule} ")?;
+        }
+        write!(f, "input: {input} expect: {:?} }}", self.expect)
+    }
+}
+
 impl Display for Define {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "define {}: {}", self.name, self.value)
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "define {}: {};", self.name, self.value)
     }
 }
 
 impl Display for Pattern {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            Pattern::Alternative { left, right } => {
-                write!(
-                    f,
-                    "{}\n| {right}",
-                    left.iter()
-                        .map(|p| format!("{p}"))
-                        .collect::<Vec<_>>()
-                        .join("")
-                )
+            Pattern::Token(token_patterns) => fmt_sequence(token_patterns).fmt(f),
+            Pattern::Choice(alternatives) => {
+                let alternatives: Vec<_> = alternatives.iter().map(|a| fmt_sequence(a)).collect();
+                alternatives.join("\n| ").fmt(f)
             }
-            Pattern::Token(token_patterns) => token_patterns
-                .iter()
-                .map(|p| format!("{p}"))
-                .collect::<Vec<_>>()
-                .join("")
-                .fmt(f),
         }
     }
 }
 
 impl Display for InternalPatternKind {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InternalPatternKind::Ident => write!(f, "ident"),
+            InternalPatternKind::Int => write!(f, "int"),
+            InternalPatternKind::Float => write!(f, "float"),
+            InternalPatternKind::String => write!(f, "string"),
+            InternalPatternKind::Bool => write!(f, "bool"),
+            InternalPatternKind::Regex(regex) => {
+                write!(f, "s/{}/", regex.as_str().replace('/', "\\/"))
+            }
+            InternalPatternKind::Keyword(kw) => write!(f, "kw[{}]", kw),
+            InternalPatternKind::Custom(name) => name.fmt(f),
+            InternalPatternKind::Symbol(sym) => write!(f, "sym[{}]", sym),
+            InternalPatternKind::OneOf(options) => write!(f, "oneof[{}]", options.join(", ")),
+            InternalPatternKind::Balanced { open, close } => {
+                write!(f, "balanced('{open}', '{close}')")
+            }
+        }
+    }
+}
+
+/// Whether `sym` can be written without the `<sym[...]>` wrapper.
+fn is_raw_symbol(sym: &str) -> bool {
+    let mut chars = sym.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => !(c.is_alphanumeric() || c.is_whitespace() || "~|<".contains(c)),
+        _ => false,
     }
 }
 
 impl Display for TokenPattern {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let rep = match (&self.repeat_mode, &self.separator) {
-            (Some(RepeatMode::ZeroOrMore), None) => s!("*"),
-            (Some(RepeatMode::OneOrMore), None) => s!("+"),
+            (Some(RepeatMode::ZeroOrMore), None) => String::from("*"),
+            (Some(RepeatMode::OneOrMore), None) => String::from("+"),
             (Some(RepeatMode::ZeroOrMore), Some(sep)) => format!("** \"{sep}\""),
             (Some(RepeatMode::OneOrMore), Some(sep)) => format!("++ \"{sep}\""),
-            _ => s!(""),
+            _ => String::from(""),
         };
         match &self.pattern {
             InternalPattern::Named {
                 name: Some(name),
                 kind,
-            } => write!(f, "<{}:{}>", name, kind),
-            InternalPattern::Named { name: None, kind } => write!(f, "<{}>", kind),
-            InternalPattern::Raw { value } => value.fmt(f),
-            InternalPattern::Exact { pattern } => pattern
-                .iter()
-                .map(|p| format!("{p}"))
-                .collect::<Vec<_>>()
-                .join("")
-                .fmt(f),
+            } => write!(f, "<{}:{}>", name, kind)?,
+            InternalPattern::Named {
+                name: None,
+                kind: InternalPatternKind::Symbol(sym),
+            } if is_raw_symbol(sym) => sym.fmt(f)?,
+            InternalPattern::Named { name: None, kind } => write!(f, "<{}>", kind)?,
+            InternalPattern::Raw { value } => value.fmt(f)?,
+            InternalPattern::Exact { pattern } => fmt_sequence(pattern).fmt(f)?,
         }
+        if self.is_optional {
+            write!(f, "?")?;
+        }
+        write!(f, "{rep}")
     }
 }
 
 impl Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
+            Value::Char(c @ ('\\' | '\'')) => write!(f, "'\\{c}'"),
             Value::Char(c) => write!(f, "'{}'", c),
-            Value::String(s) => write!(f, "\"{}\"", s),
+            Value::String(s) => {
+                let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "\"{escaped}\"")
+            }
             Value::Int(i) => write!(f, "{}", i),
             Value::Float(fl) => write!(f, "{}", fl),
             Value::Bool(b) => write!(f, "{}", b),
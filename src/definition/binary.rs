@@ -0,0 +1,75 @@
+use thiserror::Error;
+
+use super::ast::ParserDefinition;
+
+/// Marks a compiled grammar. It is followed by a format version byte and the
+/// bincode encoded `ParserDefinition`.
+const MAGIC: &[u8] = b"TMPLC";
+const VERSION: u8 = 8;
+
+#[derive(Error, Debug)]
+pub enum BinaryError {
+    #[error("Not a compiled grammar")]
+    NotCompiled,
+    #[error("Truncated compiled grammar")]
+    Truncated,
+    #[error("Compiled with format version {0}, expected {VERSION}; recompile the grammar")]
+    Version(u8),
+    #[error("Invalid compiled grammar: {0}")]
+    Encoding(#[from] bincode::Error),
+}
+
+impl ParserDefinition {
+    /// Whether `bytes` start like the output of [`ParserDefinition::to_bytes`].
+    pub fn is_compiled(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Encodes the definition in a compact binary format that loads without
+    /// parsing grammar text. Regexes are stored as their source.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ParserDefinition};
+    ///
+    /// let src = "Main:\n<items:Item> ** \",\"\n~~~\n\nItem:\n| <n:int>\n| <word:s/[a-z]+/>\n~~~\n";
+    /// let definition = parse(src)?;
+    /// let bytes = definition.to_bytes()?;
+    /// assert!(ParserDefinition::is_compiled(&bytes));
+    /// assert_eq!(ParserDefinition::from_bytes(&bytes)?, definition);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BinaryError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Decodes the output of [`ParserDefinition::to_bytes`], compiling the
+    /// regexes again and sharing them like [`ParserDefinition::share_regexes`].
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, BinaryError, ParserDefinition};
+    ///
+    /// let mut bytes = parse("Main:\n<n:int>\n~~~\n")?.to_bytes()?;
+    /// assert!(matches!(ParserDefinition::from_bytes(b"Main:"), Err(BinaryError::NotCompiled)));
+    /// assert!(matches!(ParserDefinition::from_bytes(&bytes[..5]), Err(BinaryError::Truncated)));
+    /// assert!(matches!(ParserDefinition::from_bytes(&bytes[..8]), Err(BinaryError::Encoding(_))));
+    /// bytes[5] += 1;
+    /// assert!(matches!(ParserDefinition::from_bytes(&bytes), Err(BinaryError::Version(_))));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BinaryError> {
+        if !Self::is_compiled(bytes) {
+            return Err(BinaryError::NotCompiled);
+        }
+        match bytes.get(MAGIC.len()) {
+            Some(&VERSION) => {}
+            Some(version) => return Err(BinaryError::Version(*version)),
+            None => return Err(BinaryError::Truncated),
+        }
+        let mut definition: Self = bincode::deserialize(&bytes[MAGIC.len() + 1..])?;
+        definition.share_regexes();
+        Ok(definition)
+    }
+}
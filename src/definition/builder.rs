@@ -0,0 +1,334 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use thiserror::Error;
+
+use super::ast::*;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum BuildError {
+    #[error("Missing main rule")]
+    MissingMainRule,
+    #[error("Alternative added before any rule was started")]
+    NoRule,
+    #[error("Rule `{0}` has no alternatives")]
+    EmptyRule(String),
+    #[error("Rule `{rule}` refers to unknown rule `{reference}`")]
+    UnknownRule { rule: String, reference: String },
+    #[error("Left recursion: {}", .0.join(" -> "))]
+    LeftRecursion(Vec<String>),
+    #[error("Invalid emit template: {0}")]
+    InvalidEmit(crate::template::TemplateError),
+}
+
+/// Builds a [`ParserDefinition`] in code instead of parsing it from text.
+///
+/// ```
+/// use tmpl::definition::{DefinitionBuilder, TokenPattern as P};
+///
+/// let definition = DefinitionBuilder::new()
+///     .rule("Main")
+///     .seq([P::rule("Expr").named("items").zero_or_more()])
+///     .rule("Expr")
+///     .seq([P::int().named("num")])
+///     .alt([P::literal("("), P::rule("Expr").named("inner"), P::literal(")")])
+///     .build()?;
+/// assert_eq!(definition.rule("Expr").unwrap()[0].alternatives().len(), 2);
+/// # Ok::<(), tmpl::definition::BuildError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DefinitionBuilder {
+    rules: Vec<(String, Vec<Sequence>)>,
+    defines: Vec<Define>,
+    emits: BTreeMap<String, String>,
+    docs: BTreeMap<String, String>,
+    tests: Vec<GrammarTest>,
+    current: Option<usize>,
+    error: Option<BuildError>,
+}
+
+impl DefinitionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the rule `name`, replacing any earlier rule of that name. The
+    /// following [`seq`](Self::seq) and [`alt`](Self::alt) calls add to it.
+    pub fn rule(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        self.emits.remove(&name);
+        self.docs.remove(&name);
+        match self.rules.iter().position(|(n, _)| *n == name) {
+            Some(index) => {
+                self.rules[index].1.clear();
+                self.current = Some(index);
+            }
+            None => {
+                self.rules.push((name, Vec::new()));
+                self.current = Some(self.rules.len() - 1);
+            }
+        }
+        self
+    }
+
+    /// Continues the existing rule `name`, so further alternatives are tried
+    /// after its current ones. Starts the rule if it doesn't exist yet.
+    pub fn extend(mut self, name: &str) -> Self {
+        match self.rules.iter().position(|(n, _)| n == name) {
+            Some(index) => {
+                self.current = Some(index);
+                self
+            }
+            None => self.rule(name),
+        }
+    }
+
+    /// Adds a sequence of patterns as the next alternative of the current rule.
+    pub fn seq(mut self, sequence: impl IntoIterator<Item = TokenPattern>) -> Self {
+        match self.current {
+            Some(index) => self.rules[index].1.push(sequence.into_iter().collect()),
+            None => {
+                self.error.get_or_insert(BuildError::NoRule);
+            }
+        }
+        self
+    }
+
+    /// Same as [`seq`](Self::seq), reads better for the second alternative on.
+    pub fn alt(self, sequence: impl IntoIterator<Item = TokenPattern>) -> Self {
+        self.seq(sequence)
+    }
+
+    /// Sets the emit template of the current rule, like `~~~ emit "..."`
+    /// in grammar text.
+    pub fn emit(mut self, template: impl Into<String>) -> Self {
+        let template = template.into();
+        let Some(index) = self.current else {
+            self.error.get_or_insert(BuildError::NoRule);
+            return self;
+        };
+        match crate::template::Template::parse(&template) {
+            Ok(_) => {
+                self.emits.insert(self.rules[index].0.clone(), template);
+            }
+            Err(error) => {
+                self.error.get_or_insert(BuildError::InvalidEmit(error));
+            }
+        }
+        self
+    }
+
+    /// Sets the doc comment of the current rule, like `///` lines before
+    /// it in grammar text.
+    pub fn doc(mut self, text: impl Into<String>) -> Self {
+        match self.current {
+            Some(index) => {
+                self.docs.insert(self.rules[index].0.clone(), text.into());
+            }
+            None => {
+                self.error.get_or_insert(BuildError::NoRule);
+            }
+        }
+        self
+    }
+
+    pub fn define(mut self, name: impl Into<String>, value: Value) -> Self {
+        let name = name.into();
+        self.defines.retain(|d| d.name != name);
+        self.defines.push(Define { name, value });
+        self
+    }
+
+    pub fn build(self) -> core::result::Result<ParserDefinition, BuildError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut entry = None;
+        let mut rules = RuleMap::default();
+        for (name, alternatives) in self.rules {
+            if alternatives.is_empty() {
+                return Err(BuildError::EmptyRule(name));
+            }
+            let patterns = vec![Pattern::from_alternatives(alternatives)];
+            if name == "Main" {
+                entry = Some(patterns);
+            } else {
+                rules.insert(name, patterns);
+            }
+        }
+        let definition = ParserDefinition {
+            version: None,
+            entry: entry.ok_or(BuildError::MissingMainRule)?,
+            rules,
+            defines: self.defines,
+            emits: self.emits,
+            docs: self.docs,
+            tests: self.tests,
+            symbols: Default::default(),
+        };
+        for (rule, patterns) in definition.all_rules() {
+            let references = patterns.iter().flat_map(Pattern::alternatives).flatten();
+            for reference in references.flat_map(|token| token.pattern.references()) {
+                if definition.rule(reference).is_none() {
+                    return Err(BuildError::UnknownRule {
+                        rule: rule.to_string(),
+                        reference: reference.to_string(),
+                    });
+                }
+            }
+        }
+        if let Some(cycle) = definition.left_recursion().into_iter().next() {
+            return Err(BuildError::LeftRecursion(cycle));
+        }
+        Ok(definition)
+    }
+}
+
+impl From<ParserDefinition> for DefinitionBuilder {
+    /// Starts from an existing definition, e.g. to add or replace rules.
+    fn from(definition: ParserDefinition) -> Self {
+        let alternatives = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .flat_map(Pattern::alternatives)
+                .cloned()
+                .collect()
+        };
+        let mut rules = vec![("Main".to_string(), alternatives(&definition.entry))];
+        for (name, patterns) in &definition.rules {
+            rules.push((name.clone(), alternatives(patterns)));
+        }
+        Self {
+            rules,
+            defines: definition.defines,
+            emits: definition.emits,
+            docs: definition.docs,
+            tests: definition.tests,
+            current: None,
+            error: None,
+        }
+    }
+}
+
+impl From<InternalPattern> for TokenPattern {
+    fn from(pattern: InternalPattern) -> Self {
+        TokenPattern {
+            pattern,
+            is_optional: false,
+            repeat_mode: None,
+            separator: None,
+        }
+    }
+}
+
+/// Constructors and modifiers for use with [`DefinitionBuilder`].
+impl TokenPattern {
+    fn kind(kind: InternalPatternKind) -> Self {
+        InternalPattern::Named { name: None, kind }.into()
+    }
+
+    pub fn ident() -> Self {
+        Self::kind(InternalPatternKind::Ident)
+    }
+
+    pub fn int() -> Self {
+        Self::kind(InternalPatternKind::Int)
+    }
+
+    pub fn float() -> Self {
+        Self::kind(InternalPatternKind::Float)
+    }
+
+    pub fn string() -> Self {
+        Self::kind(InternalPatternKind::String)
+    }
+
+    pub fn boolean() -> Self {
+        Self::kind(InternalPatternKind::Bool)
+    }
+
+    pub fn regex(regex: Regex) -> Self {
+        Self::kind(InternalPatternKind::Regex(regex))
+    }
+
+    pub fn keyword(keyword: impl Into<String>) -> Self {
+        Self::kind(InternalPatternKind::Keyword(keyword.into()))
+    }
+
+    pub fn symbol(symbol: impl Into<String>) -> Self {
+        Self::kind(InternalPatternKind::Symbol(symbol.into()))
+    }
+
+    /// `open`, everything up to the `close` matching it and `close`.
+    pub fn balanced(open: impl Into<String>, close: impl Into<String>) -> Self {
+        Self::kind(InternalPatternKind::Balanced {
+            open: open.into(),
+            close: close.into(),
+        })
+    }
+
+    /// Any one of the keywords and symbols `options`.
+    pub fn one_of<S: Into<String>>(options: impl IntoIterator<Item = S>) -> Self {
+        Self::kind(InternalPatternKind::OneOf(
+            options.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// A reference to the rule `name`.
+    pub fn rule(name: impl Into<String>) -> Self {
+        Self::kind(InternalPatternKind::Custom(name.into()))
+    }
+
+    /// Literal text that has to appear as is and isn't captured.
+    pub fn literal(value: impl Into<String>) -> Self {
+        InternalPattern::Raw {
+            value: value.into(),
+        }
+        .into()
+    }
+
+    /// Several patterns that repeat or are optional together.
+    pub fn group(sequence: impl IntoIterator<Item = TokenPattern>) -> Self {
+        InternalPattern::Exact {
+            pattern: sequence.into_iter().collect(),
+        }
+        .into()
+    }
+
+    /// Captures the match under `name`. Literals and groups can't be
+    /// captured and stay as they are.
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        if let InternalPattern::Named { name: n, .. } = &mut self.pattern {
+            *n = Some(name.into());
+        }
+        self
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.is_optional = true;
+        self
+    }
+
+    pub fn zero_or_more(mut self) -> Self {
+        self.repeat_mode = Some(RepeatMode::ZeroOrMore);
+        self
+    }
+
+    pub fn one_or_more(mut self) -> Self {
+        self.repeat_mode = Some(RepeatMode::OneOrMore);
+        self
+    }
+
+    /// Repeats with `separator` between the matches, zero or more times
+    /// unless [`one_or_more`](Self::one_or_more) was called.
+    pub fn separated_by(mut self, separator: impl Into<String>) -> Self {
+        self.repeat_mode.get_or_insert(RepeatMode::ZeroOrMore);
+        self.separator = Some(separator.into());
+        self
+    }
+}
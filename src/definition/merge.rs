@@ -0,0 +1,125 @@
+use alloc::{string::String, vec::Vec};
+
+use thiserror::Error;
+
+use super::ast::{ParserDefinition, Pattern};
+
+/// How [`ParserDefinition::merge`] resolves rules and defines both
+/// definitions have. Identical rules and defines are never a conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Fails on the first conflict.
+    #[default]
+    Reject,
+    /// Keeps the rule or define of the definition merged into.
+    KeepExisting,
+    /// Replaces the rule or define with the one of the merged definition.
+    Replace,
+    /// Adds the alternatives of the merged rule after the existing ones, so
+    /// an extension can add cases to a base rule. Conflicting defines are
+    /// rejected, as they can't be combined.
+    Extend,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum MergeError {
+    #[error("Both grammars define rule `{0}`")]
+    RuleConflict(String),
+    #[error("Both grammars define `{0}` with different values")]
+    DefineConflict(String),
+}
+
+impl ParserDefinition {
+    /// Adds the rules and defines of `other`, resolving the ones both have
+    /// according to `policy`, with `Main` treated like any other rule. On
+    /// error the definition is left unchanged.
+    ///
+    /// ```
+    /// use tmpl::definition::MergePolicy;
+    ///
+    /// let mut base = tmpl::Grammar::parse("Main:\n<v:Value>\n~~~\n\nValue:\n<n:int>\n~~~\n")?
+    ///     .definition()
+    ///     .clone();
+    /// let extension = tmpl::Grammar::parse("Main:\n<v:Value>\n~~~\n\nValue:\n<b:bool>\n~~~\n")?;
+    /// base.merge(extension.definition().clone(), MergePolicy::Extend)?;
+    /// assert_eq!(base.rule("Value").unwrap().len(), 2);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn merge(
+        &mut self,
+        other: ParserDefinition,
+        policy: MergePolicy,
+    ) -> Result<(), MergeError> {
+        for define in &other.defines {
+            let conflict = self
+                .defines
+                .iter()
+                .any(|d| d.name == define.name && d.value != define.value);
+            if conflict && matches!(policy, MergePolicy::Reject | MergePolicy::Extend) {
+                return Err(MergeError::DefineConflict(define.name.clone()));
+            }
+        }
+        if policy == MergePolicy::Reject {
+            for (name, patterns) in other.all_rules() {
+                if self.rule(name).is_some_and(|existing| existing != patterns) {
+                    return Err(MergeError::RuleConflict(name.into()));
+                }
+            }
+        }
+
+        merge_rule(&mut self.entry, other.entry, policy);
+        for (name, patterns) in other.rules {
+            match self.rules.get_mut(&name) {
+                Some(existing) => merge_rule(existing, patterns, policy),
+                None => {
+                    self.rules.insert(name, patterns);
+                }
+            }
+        }
+        for define in other.defines {
+            match self.defines.iter_mut().find(|d| d.name == define.name) {
+                Some(existing) if policy == MergePolicy::Replace => *existing = define,
+                Some(_) => {}
+                None => self.defines.push(define),
+            }
+        }
+        for (name, emit) in other.emits {
+            match policy {
+                MergePolicy::Replace => {
+                    self.emits.insert(name, emit);
+                }
+                _ => {
+                    self.emits.entry(name).or_insert(emit);
+                }
+            }
+        }
+        for (name, doc) in other.docs {
+            match policy {
+                MergePolicy::Replace => {
+                    self.docs.insert(name, doc);
+                }
+                _ => {
+                    self.docs.entry(name).or_insert(doc);
+                }
+            }
+        }
+        for test in other.tests {
+            if !self.tests.iter().any(|t| t.name == test.name) {
+                self.tests.push(test);
+            }
+        }
+        self.version = self.version.take().or(other.version);
+        Ok(())
+    }
+}
+
+fn merge_rule(existing: &mut Vec<Pattern>, patterns: Vec<Pattern>, policy: MergePolicy) {
+    if *existing == patterns {
+        return;
+    }
+    match policy {
+        MergePolicy::Reject | MergePolicy::KeepExisting => {}
+        MergePolicy::Replace => *existing = patterns,
+        MergePolicy::Extend => existing.extend(patterns),
+    }
+}
@@ -0,0 +1,513 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use super::ast::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, Sequence, TokenPattern,
+};
+use super::visit::{walk_pattern, PatternVisitor};
+
+/// A transformation of a definition that leaves the syntax trees it parses
+/// input to unchanged.
+pub trait Rewrite {
+    /// Applies the rewrite and returns whether it changed anything.
+    fn apply(&self, definition: &mut ParserDefinition) -> bool;
+}
+
+/// Replaces unnamed references to rules made of a single token that neither
+/// captures anything nor refers to other rules with the token itself, e.g.
+/// `<Semi>` with `;` after `Semi: ;`. The rules stay, as they may still be
+/// used as entry rules or by named references.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InlineTrivialRules;
+
+/// Moves the common leading tokens of consecutive alternatives in front of a
+/// new rule holding the rest, so the parser matches them once instead of once
+/// per alternative: `<kw[let]> <name:ident> = <v:Expr> | <kw[let]> <name:ident> ;`
+/// becomes `<kw[let]> <name:ident> <LetTail>` with a rule `LetTail` matching
+/// `= <v:Expr>` or `;`.
+///
+/// Alternatives are only factored if what remains of them doesn't capture
+/// anything, as the captures would end up in the new rule's syntax tree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FactorPrefixes;
+
+/// Removes the rules no entry point reaches, along with their templates and
+/// doc comments. Rules inline tests start from count as entry points.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoveUnreachableRules;
+
+/// Replaces the only reference to a rule with the rule's pattern, if the
+/// reference is unnamed and the rule doesn't capture anything, so the
+/// syntax tree has nothing to lose. A single alternative goes in place of
+/// the reference, several replace the alternative if the reference is all
+/// of it: `<Sign> <n:int>` with `Sign: <kw[minus]>` becomes
+/// `<kw[minus]> <n:int>`. The rule itself is left to
+/// [`RemoveUnreachableRules`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InlineSingleUseRules;
+
+/// Points unnamed references to rules whose only alternative is an unnamed
+/// reference to another rule, like `Stmt: <Expr>`, at that rule instead.
+/// Named references stay, as the rule's name is part of the syntax tree.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CollapseWrapperRules;
+
+impl ParserDefinition {
+    /// Applies `rewrites` in order until none of them changes anything.
+    pub fn rewrite(&mut self, rewrites: &[&dyn Rewrite]) {
+        while rewrites
+            .iter()
+            .fold(false, |changed, rewrite| rewrite.apply(self) | changed)
+        {}
+    }
+
+    /// Rewrites the definition into one that parses faster, with
+    /// [`InlineTrivialRules`] and [`FactorPrefixes`].
+    pub fn optimize(&mut self) {
+        self.rewrite(&[&InlineTrivialRules, &FactorPrefixes]);
+    }
+
+    /// Rewrites the definition into a smaller one that parses input to the
+    /// same syntax trees, with [`CollapseWrapperRules`],
+    /// [`RemoveUnreachableRules`] and [`InlineSingleUseRules`], e.g. before
+    /// storing it with [`ParserDefinition::to_bytes`]. Only the
+    /// [`ParserDefinition::entry_points`] and the rules of inline tests are
+    /// sure to remain, so parsing from another rule may no longer work.
+    ///
+    /// ```
+    /// let mut definition = tmpl::definition::parse(
+    ///     "Main:\n<Sign> <n:int>\n~~~\n\nSign:\n<kw[minus]>\n~~~\n\nUnused:\n<b:bool>\n~~~\n",
+    /// )?;
+    /// definition.minimize();
+    /// assert!(definition.rules.is_empty());
+    /// assert_eq!(definition.rule_text("Main").unwrap(), "Main:\n<kw[minus]> <n:int>\n~~~\n");
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn minimize(&mut self) {
+        self.rewrite(&[
+            &CollapseWrapperRules,
+            &RemoveUnreachableRules,
+            &InlineSingleUseRules,
+        ]);
+    }
+
+    /// Factors the common leading tokens out of consecutive alternatives
+    /// with [`FactorPrefixes`], returning whether anything changed.
+    ///
+    /// ```
+    /// let mut definition = tmpl::definition::parse(
+    ///     "Main:\n| <kw[let]> <name:ident> = <kw[none]> ;\n| <kw[let]> <name:ident> ;\n~~~\n",
+    /// )?;
+    /// let before = definition.clone();
+    /// assert!(definition.factor_prefixes());
+    /// assert_eq!(definition.rule("Main").unwrap()[0].alternatives().len(), 1);
+    /// assert_ne!(definition, before);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn factor_prefixes(&mut self) -> bool {
+        let mut changed = false;
+        while FactorPrefixes.apply(self) {
+            changed = true;
+        }
+        changed
+    }
+
+    fn alternatives_of(&self, name: &str) -> Vec<Sequence> {
+        self.rule(name)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(Pattern::alternatives)
+            .cloned()
+            .collect()
+    }
+
+    fn set_alternatives(&mut self, name: &str, alternatives: Vec<Sequence>) {
+        let patterns = vec![Pattern::from_alternatives(alternatives)];
+        match name {
+            "Main" => self.entry = patterns,
+            _ => {
+                self.rules.insert(name.to_string(), patterns);
+            }
+        }
+    }
+
+    fn rule_names(&self) -> Vec<String> {
+        core::iter::once("Main".to_string())
+            .chain(self.rules.keys().cloned())
+            .collect()
+    }
+
+    /// The rules parsing may start from: the entry points and the rules of
+    /// inline tests.
+    fn roots(&self) -> Vec<&str> {
+        let mut roots = self.entry_points();
+        for test in &self.tests {
+            let rule = test.rule.as_deref().unwrap_or("Main");
+            if !roots.contains(&rule) {
+                roots.push(rule);
+            }
+        }
+        roots
+    }
+
+    /// Rewrites every token of every rule with `f`, which returns whether it
+    /// changed the token, descending into groups.
+    fn rewrite_tokens(&mut self, mut f: impl FnMut(&mut TokenPattern) -> bool) -> bool {
+        fn walk(
+            sequence: &mut [TokenPattern],
+            f: &mut impl FnMut(&mut TokenPattern) -> bool,
+        ) -> bool {
+            let mut changed = false;
+            for token in sequence {
+                changed |= f(token);
+                if let InternalPattern::Exact { pattern } = &mut token.pattern {
+                    changed |= walk(pattern, f);
+                }
+            }
+            changed
+        }
+
+        let mut changed = false;
+        for name in self.rule_names() {
+            let mut alternatives = self.alternatives_of(&name);
+            let mut rule_changed = false;
+            for alternative in &mut alternatives {
+                rule_changed |= walk(alternative, &mut f);
+            }
+            if rule_changed {
+                self.set_alternatives(&name, alternatives);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+impl Rewrite for InlineTrivialRules {
+    fn apply(&self, definition: &mut ParserDefinition) -> bool {
+        let trivial: BTreeMap<String, TokenPattern> = definition
+            .rules
+            .keys()
+            .filter_map(|name| match &definition.alternatives_of(name)[..] {
+                [alternative] => match &alternative[..] {
+                    [token] if !captures(token) && token.pattern.references().is_empty() => {
+                        Some((name.clone(), token.clone()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        if trivial.is_empty() {
+            return false;
+        }
+        let mut changed = false;
+        for name in definition.rule_names() {
+            let mut alternatives = definition.alternatives_of(&name);
+            let mut rule_changed = false;
+            for alternative in &mut alternatives {
+                rule_changed |= inline(alternative, &trivial);
+            }
+            if rule_changed {
+                definition.set_alternatives(&name, alternatives);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn inline(sequence: &mut [TokenPattern], trivial: &BTreeMap<String, TokenPattern>) -> bool {
+    let mut changed = false;
+    for token in sequence {
+        match &mut token.pattern {
+            InternalPattern::Named {
+                name: None,
+                kind: InternalPatternKind::Custom(rule),
+            } => {
+                let Some(body) = trivial.get(rule.as_str()) else {
+                    continue;
+                };
+                *token = match (has_modifiers(token), has_modifiers(body)) {
+                    (false, _) => body.clone(),
+                    (true, false) => TokenPattern {
+                        pattern: body.pattern.clone(),
+                        ..token.clone()
+                    },
+                    (true, true) => TokenPattern {
+                        pattern: InternalPattern::Exact {
+                            pattern: vec![body.clone()],
+                        },
+                        ..token.clone()
+                    },
+                };
+                changed = true;
+            }
+            InternalPattern::Exact { pattern } => changed |= inline(pattern, trivial),
+            _ => {}
+        }
+    }
+    changed
+}
+
+impl Rewrite for FactorPrefixes {
+    fn apply(&self, definition: &mut ParserDefinition) -> bool {
+        let mut changed = false;
+        for name in definition.rule_names() {
+            let alternatives = definition.alternatives_of(&name);
+            let mut factored = Vec::new();
+            let mut rest = &alternatives[..];
+            while let Some(first) = rest.first() {
+                let run = rest
+                    .iter()
+                    .take_while(|a| !a.is_empty() && a.first() == first.first())
+                    .count()
+                    .max(1);
+                let (group, remaining) = rest.split_at(run);
+                rest = remaining;
+                let prefix = common_prefix(group);
+                let tails: Vec<_> = group.iter().map(|a| Sequence::from(&a[prefix..])).collect();
+                if group.len() < 2 || tails.iter().any(|t| t.is_empty() || t.iter().any(captures)) {
+                    factored.extend(group.iter().cloned());
+                    continue;
+                }
+                let tail = fresh_name(definition, &name);
+                definition.set_alternatives(&tail, tails);
+                let mut alternative = Sequence::from(&group[0][..prefix]);
+                alternative.push(TokenPattern::rule(tail));
+                factored.push(alternative);
+                changed = true;
+            }
+            if factored.len() < alternatives.len() {
+                definition.set_alternatives(&name, factored);
+            }
+        }
+        changed
+    }
+}
+
+impl Rewrite for RemoveUnreachableRules {
+    fn apply(&self, definition: &mut ParserDefinition) -> bool {
+        let roots = definition.roots();
+        let reachable = definition.reachable_from(&roots, &[]);
+        let dead: Vec<String> = (definition.rules.keys())
+            .filter(|name| !reachable.contains(name.as_str()))
+            .cloned()
+            .collect();
+        for name in &dead {
+            definition.rules.shift_remove(name);
+            definition.emits.remove(name);
+            definition.docs.remove(name);
+        }
+        !dead.is_empty()
+    }
+}
+
+impl Rewrite for InlineSingleUseRules {
+    fn apply(&self, definition: &mut ParserDefinition) -> bool {
+        let mut uses: BTreeMap<&str, (usize, bool)> = BTreeMap::new();
+        for (rule, patterns) in definition.all_rules() {
+            for token in patterns.iter().flat_map(Pattern::alternatives).flatten() {
+                for (name, named) in rule_references(token) {
+                    let entry = uses.entry(name).or_default();
+                    entry.0 += 1;
+                    entry.1 |= named || name == rule;
+                }
+            }
+        }
+        let roots = definition.roots();
+        let inlined: BTreeMap<String, Vec<Sequence>> = (uses.into_iter())
+            .filter(|(name, (count, named))| *count == 1 && !named && !roots.contains(name))
+            .map(|(name, _)| (name.to_string(), definition.alternatives_of(name)))
+            .filter(|(_, alternatives)| !alternatives.iter().flatten().any(captures))
+            .collect();
+        if inlined.is_empty() {
+            return false;
+        }
+        let mut changed = false;
+        for name in definition.rule_names() {
+            let mut alternatives = Vec::new();
+            let mut rule_changed = false;
+            for alternative in definition.alternatives_of(&name) {
+                if let [token] = &alternative[..] {
+                    match (unnamed_reference(token), has_modifiers(token)) {
+                        (Some(rule), false) if inlined.contains_key(rule) => {
+                            alternatives.extend(inlined[rule].iter().cloned());
+                            rule_changed = true;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                }
+                let (alternative, inlined_any) = inline_single(alternative.into_vec(), &inlined);
+                alternatives.push(Sequence::from(alternative));
+                rule_changed |= inlined_any;
+            }
+            if rule_changed {
+                definition.set_alternatives(&name, alternatives);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Replaces the references in `sequence` to rules of `inlined` with a single
+/// alternative by that alternative, returning whether there were any.
+fn inline_single(
+    sequence: Vec<TokenPattern>,
+    inlined: &BTreeMap<String, Vec<Sequence>>,
+) -> (Vec<TokenPattern>, bool) {
+    let mut changed = false;
+    let mut tokens = Vec::new();
+    for mut token in sequence {
+        let body = match unnamed_reference(&token).and_then(|rule| inlined.get(rule)) {
+            Some(body) if body.len() == 1 => body[0].to_vec(),
+            _ => {
+                if let InternalPattern::Exact { pattern } = &mut token.pattern {
+                    let (inner, inner_changed) = inline_single(core::mem::take(pattern), inlined);
+                    *pattern = inner;
+                    changed |= inner_changed;
+                }
+                tokens.push(token);
+                continue;
+            }
+        };
+        match has_modifiers(&token) {
+            false => tokens.extend(body),
+            true => tokens.push(TokenPattern {
+                pattern: InternalPattern::Exact { pattern: body },
+                ..token
+            }),
+        }
+        changed = true;
+    }
+    (tokens, changed)
+}
+
+impl Rewrite for CollapseWrapperRules {
+    fn apply(&self, definition: &mut ParserDefinition) -> bool {
+        let wrapped: BTreeMap<String, String> = (definition.rules.keys())
+            .filter_map(|name| match &definition.alternatives_of(name)[..] {
+                [alternative] => match &alternative[..] {
+                    [token] if !has_modifiers(token) => {
+                        Some((name.clone(), unnamed_reference(token)?.to_string()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        // follow chains of wrappers to the rule at their end, leaving cycles
+        let targets: BTreeMap<&str, &str> = (wrapped.keys())
+            .filter_map(|name| {
+                let mut seen = BTreeSet::from([name.as_str()]);
+                let mut target = wrapped[name].as_str();
+                while let Some(next) = wrapped.get(target) {
+                    if !seen.insert(target) {
+                        return None;
+                    }
+                    target = next;
+                }
+                (!seen.contains(target)).then_some((name.as_str(), target))
+            })
+            .collect();
+        if targets.is_empty() {
+            return false;
+        }
+        definition.rewrite_tokens(|token| match &mut token.pattern {
+            InternalPattern::Named {
+                name: None,
+                kind: InternalPatternKind::Custom(rule),
+            } => match targets.get(rule.as_str()) {
+                Some(target) => {
+                    *rule = target.to_string();
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        })
+    }
+}
+
+/// The rule `token` refers to without a name, like `<Expr>`.
+fn unnamed_reference(token: &TokenPattern) -> Option<&str> {
+    match &token.pattern {
+        InternalPattern::Named {
+            name: None,
+            kind: InternalPatternKind::Custom(rule),
+        } => Some(rule),
+        _ => None,
+    }
+}
+
+/// The rules `token` refers to, with whether the reference is named.
+fn rule_references(token: &TokenPattern) -> Vec<(&str, bool)> {
+    struct References<'a>(Vec<(&'a str, bool)>);
+
+    impl<'a> PatternVisitor<'a> for References<'a> {
+        fn visit_pattern(&mut self, pattern: &'a InternalPattern) {
+            if let InternalPattern::Named {
+                name,
+                kind: InternalPatternKind::Custom(rule),
+            } = pattern
+            {
+                self.0.push((rule, name.is_some()));
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    let mut references = References(Vec::new());
+    references.visit_token(token);
+    references.0
+}
+
+/// Number of leading tokens all of `alternatives` have in common.
+fn common_prefix(alternatives: &[Sequence]) -> usize {
+    let first = &alternatives[0];
+    alternatives[1..]
+        .iter()
+        .map(|a| first.iter().zip(a).take_while(|(x, y)| x == y).count())
+        .min()
+        .unwrap_or(first.len())
+}
+
+fn fresh_name(definition: &ParserDefinition, rule: &str) -> String {
+    let base = format!("{rule}Tail");
+    (1..)
+        .map(|i| match i {
+            1 => base.clone(),
+            _ => format!("{base}{i}"),
+        })
+        .find(|name| definition.rule(name).is_none())
+        .expect("some name is free")
+}
+
+fn has_modifiers(token: &TokenPattern) -> bool {
+    token.is_optional || token.repeat_mode.is_some()
+}
+
+/// Whether the token stores anything in the syntax tree.
+fn captures(token: &TokenPattern) -> bool {
+    struct Captures(bool);
+
+    impl PatternVisitor<'_> for Captures {
+        fn visit_pattern(&mut self, pattern: &InternalPattern) {
+            if let InternalPattern::Named { name: Some(_), .. } = pattern {
+                self.0 = true;
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    let mut captures = Captures(false);
+    captures.visit_token(token);
+    captures.0
+}
@@ -1,9 +1,35 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use peg::str::LineCol;
+use thiserror::Error;
 
 use crate::definition::ast::*;
+use crate::definition::{check_version, Diagnostics, RegexTable, SymbolIndex, ValidationKind};
+
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables tracing of the definition parser at runtime.
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+fn trace_enabled() -> bool {
+    TRACE.load(Ordering::Relaxed)
+}
+
+/// What the grammar parser collects besides the definition. It is a single
+/// grammar argument as peg passes every argument to every rule.
+#[derive(Default)]
+struct GrammarState {
+    regexes: RefCell<RegexTable>,
+    symbols: RefCell<SymbolIndex>,
+}
 
 peg::parser! {
-    grammar parser() for str {
+    grammar parser(state: &GrammarState) for str {
         rule traced<T>(e: rule<T>) -> T =
             &(input:$([_]*) {
                 #[cfg(feature = "trace")]
@@ -15,42 +41,150 @@ peg::parser! {
                 e.ok_or("")
             }
 
-        pub rule main() -> Result<ParserDefinition>
+        rule logged<T>(name: &'static str, e: rule<T>) -> T
+            = start:position!() e:e() end:position!() {
+                if trace_enabled() {
+                    eprintln!("[trace] {name} matched {start}..{end}");
+                }
+                e
+            }
+
+        // Attaches the span of the text `e` matched, past leading whitespace,
+        // to the errors it returns.
+        rule spanned<T>(e: rule<Result<T>>) -> Result<T>
+            = _ start:position!() e:e() end:position!() {
+                e.map_err(|error| error.at(start..end))
+            }
+
+        // Always fails, the trailing `[_]` only keeps peg from treating the
+        // rules using it as able to match empty input.
+        rule log_failure<T>(name: &'static str) -> T
+            = trace_failure(name) [_] {? Err(name) }
+
+        rule trace_failure(name: &'static str)
+            = start:position!() {?
+                if trace_enabled() {
+                    eprintln!("[trace] {name} failed at {start}");
+                }
+                Err(name)
+            }
+
+        pub rule main() -> Result<(ParserDefinition, Declarations)>
             = traced(<top()>)
 
-        rule top() -> Result<ParserDefinition>
-            = _ other:rule_or_define()* _ {
-                let mut rules = HashMap::new();
+        rule top() -> Result<(ParserDefinition, Declarations)>
+            = _ version:spanned(<version()>)? other:located(<rule_or_define()>)* _ {
+                let version = version.transpose()?;
+                let mut rules = RuleMap::default();
                 let mut defines = Vec::new();
-                for rod in unpack(other)? {
-                    match rod {
-                        RuleOrDefine::Rule{name, pattern} => _ = rules.insert(name, pattern),
-                        RuleOrDefine::Define(d) => defines.push(d)
+                let mut emits = BTreeMap::new();
+                let mut tests = Vec::new();
+                let mut docs = BTreeMap::new();
+                let mut declarations = Declarations::default();
+                for (rod, span) in other {
+                    match rod? {
+                        RuleOrDefine::Rule{name, pattern, is_override, emit, doc} => {
+                            let earlier = declarations.rules.iter_mut().find(|(n, _)| *n == name);
+                            match (earlier, is_override) {
+                                (Some((_, earlier)), true) => *earlier = span,
+                                (Some(_), false) => {
+                                    return Err(DefinitionParseError::DuplicateRule(name).at(span));
+                                }
+                                (None, _) => declarations.rules.push((name.clone(), span)),
+                            }
+                            match emit {
+                                Some(emit) => emits.insert(name.clone(), emit),
+                                None => emits.remove(&name),
+                            };
+                            match doc {
+                                Some(doc) => docs.insert(name.clone(), doc),
+                                None => docs.remove(&name),
+                            };
+                            rules.insert(name, pattern);
+                        }
+                        RuleOrDefine::Define(d) => {
+                            declarations.defines.push((d.name.clone(), span));
+                            defines.push(d);
+                        }
+                        RuleOrDefine::Test(test) => tests.push(test),
                     }
                 }
-                match rules.remove("Main") {
+                match rules.shift_remove("Main") {
                     Some(entry) =>
-                        Ok(ParserDefinition {
+                        Ok((ParserDefinition {
+                            version,
                             entry,
                             rules,
                             defines,
-                        }),
+                            emits,
+                            docs,
+                            tests,
+                            symbols: state.symbols.take(),
+                        }, declarations)),
                     None => Err(DefinitionParseError::MissingMainRule),
                 }
             }
             / expected!("Main Rule")
 
+        rule version() -> Result<String>
+            = "tmpl_version" [' ' | '\t']+ "\"" v:$([^'"' | '\n']*) "\"" _ ";" {
+                check_version(v)?;
+                Ok(v.to_string())
+            }
+
+        rule located<T>(e: rule<T>) -> (T, Range<usize>)
+            = _ start:position!() e:e() end:position!() { (e, start..end) }
+
         rule rule_or_define() -> Result<RuleOrDefine>
+            = logged("rule_or_define", <rule_or_define_untraced()>)
+            / log_failure("rule_or_define")
+
+        rule rule_or_define_untraced() -> Result<RuleOrDefine>
             = d:define() { Ok(RuleOrDefine::Define(d?)) }
-            / r:r#rule() { let (name, pattern) = r?; Ok(RuleOrDefine::Rule{name, pattern}) }
+            / t:test() { Ok(RuleOrDefine::Test(t?)) }
+            / docs:doc_line()* _ o:("override" [' ' | '\t']+)? r:r#rule() {
+                let (name, pattern, emit) = r?;
+                let doc = (!docs.is_empty()).then(|| docs.join("\n"));
+                Ok(RuleOrDefine::Rule{name, pattern, is_override: o.is_some(), emit, doc})
+            }
             / expected!("Rule or Define")
 
+        rule doc_line() -> &'input str
+            = quiet!{_ "///" " "? d:$([^'\n']*) { d.trim_end() }}
+
         rule define() -> Result<Define>
-            = _ "define" _ r:ident() _ ":" _ rs:value() _ ";" _ {
+            = _ "define" _ r:ident() _ ":" _ rs:spanned(<value()>) _ ";" _ {
                 Ok(Define { name: r, value: rs? })
             }
             / expected!("Define")
 
+        rule quoted() -> String
+            = "\"" v:$(([^'"' | '\\'] / "\\\\" / "\\\"")*) "\"" {
+                v.replace("\\\"", "\"").replace("\\\\", "\\")
+            }
+
+        rule test() -> Result<GrammarTest>
+            = _ "test" __ name:quoted() _ "{" fields:(_ f:test_field() _ ","? { f })* _ "}" _ {
+                let mut input = None;
+                let mut rule = None;
+                let mut expect = Expectation::Ok;
+                for field in fields {
+                    match field {
+                        TestField::Input(text) => input = Some(text),
+                        TestField::Rule(name) => rule = Some(name),
+                        TestField::Expect(expected) => expect = expected,
+                    }
+                }
+                let input = input.ok_or_else(|| DefinitionParseError::MissingTestInput(name.clone()))?;
+                Ok(GrammarTest { name, input, rule, expect })
+            }
+
+        rule test_field() -> TestField
+            = "input" _ ":" _ s:quoted() { TestField::Input(s) }
+            / "rule" _ ":" _ r:ident() { TestField::Rule(r) }
+            / "expect" _ ":" _ "Ok" { TestField::Expect(Expectation::Ok) }
+            / "expect" _ ":" _ "Error" { TestField::Expect(Expectation::Error) }
+
         rule value() -> Result<Value>
             = _ "[" _ v:value() ** "," _ "]" _ {
                 Ok(Value::List(unpack(v)?))
@@ -69,9 +203,8 @@ peg::parser! {
                     Err(DefinitionParseError::InvalidChar(chars[0]))
                 }
             }
-            / _ "\"" v:$([^'"' | '\\'] / "\\\\" / "\\\"") "\"" _ {
-                let str = v.chars().collect::<String>();
-                Ok(Value::String(str.replace("\\\"", "\"").replace("\\\\", "\\")))
+            / _ s:quoted() _ {
+                Ok(Value::String(s))
             }
             / _ v:float() _ {
                 Ok(Value::Float(v?))
@@ -84,36 +217,57 @@ peg::parser! {
             }
             / expected!("value")
 
-        rule r#rule() -> Result<(String, Vec<Pattern>)>
-            = _ r:ident() _ ":" _ rs:pattern()+ _ "~~~" _ {
-                Ok((r, unpack(rs)?))
+        rule r#rule() -> Result<(String, Vec<Pattern>, Option<String>)>
+            = _ start:position!() r:ident() name_end:position!() _ ":" _ "|"? rs:pattern()+ _ "~~~" e:emit()? end:position!() _ {
+                state.symbols.borrow_mut().declare(&r, start..name_end, start..end);
+                Ok((r, unpack(rs)?, e.transpose()?))
             }
             / expected!("Rule")
 
-        rule pattern() -> Result<Pattern>
-            = _ left:token()+ _ "|" _ right:pattern() {
-                alternative(unpack(left)?, right?)
+        rule emit() -> Result<String>
+            = [' ' | '\t']* "emit" _ e:spanned(<emit_template()>) { e }
+
+        rule emit_template() -> Result<String>
+            = "\"" v:$(([^'"' | '\\'] / "\\" [_])*) "\"" {
+                let template = unescape_emit(v);
+                crate::template::Template::parse(&template)
+                    .map_err(DefinitionParseError::InvalidEmit)?;
+                Ok(template)
             }
-            / _ left:token()+ {
-                token(unpack(left)?)
+            / expected!("emit template")
+
+        rule pattern() -> Result<Pattern>
+            = logged("pattern", <pattern_untraced()>)
+            / log_failure("pattern")
+
+        rule pattern_untraced() -> Result<Pattern>
+            = alternatives:(sequence() ++ (_ "|")) {
+                choice(unpack(alternatives)?)
             }
 
+        rule sequence() -> Result<Sequence>
+            = _ tokens:token()+ { unpack(tokens).map(Sequence::from) }
+
         rule pat<'a, T>(p: rule<T>) -> Option<String>
             = _ "<" _ r:ident() _ ":" _ p() _ ">" { Some(r) }
             / _ "<" _ p() _ ">" { None }
 
         rule repeat() -> String
-            = "?" { "?".to_string() }
+            = __ "**" __ sym:string() { format!("**{sym}") }
+            / __ "++" __ sym:string() { format!("++{sym}") }
+            / "?" { "?".to_string() }
             / "*" { "*".to_string() }
             / "+" { "+".to_string() }
-            / __ "**" __ sym:string() { format!("**{sym}") }
-            / __ "++" __ sym:string() { format!("++{sym}") }
 
         rule string() -> String
             = "\"" s:$(([^'\\' | '"'] / "\\\\" / "\\\"")+) "\"" { s.to_string() }
             / expected!("string")
 
         rule token() -> Result<TokenPattern>
+            = logged("token", <spanned(<token_untraced()>)>)
+            / log_failure("token")
+
+        rule token_untraced() -> Result<TokenPattern>
             = r:pat(<"ident">) re:repeat()? { with_repeat_mode(ident(r), re) }
             / r:pat(<"int">) re:repeat()? { with_repeat_mode(int(r), re) }
             / r:pat(<"float">) re:repeat()? { with_repeat_mode(float(r), re) }
@@ -122,16 +276,21 @@ peg::parser! {
             / _ "<" _ r:(r:ident() _ ":" _ { r })? "sym[" v:symbol() "]" _ ">" re:repeat()? { with_repeat_mode(symbol(r, &v), re) }
             / _ "<" _ r:(r:ident() _ ":" _ { r })? "string" _ ">" re:repeat()? { with_repeat_mode(string(r), re) }
             / _ "<" _ r:(r:ident() _ ":" _ { r })? "bool" _ ">" re:repeat()? { with_repeat_mode(bool(r), re) }
-            / _ "<" _ r:(r:ident() _ ":" _ { r })? "s/" v:regex() "/" _ ">" re:repeat()? { with_repeat_mode(regex(r, &v)?, re) }
+            / _ "<" _ r:(r:ident() _ ":" _ { r })? "s/" v:regex() "/" _ ">" re:repeat()? { with_repeat_mode(shared_regex(r, &v, &state.regexes)?, re) }
             / _ "<" _ r:(r:ident() _ ":" _ { r })? "kw[" _ v:ident()  _ "]" _ ">" re:repeat()? { with_repeat_mode(keyword(r, &v), re) }
-            / _ "<" _ r:(r:ident() _ ":" _ { r })? v:ident() _ ">" re:repeat()? { with_repeat_mode(custom(r, &v), re) }
+            / _ "<" _ r:(r:ident() _ ":" _ { r })? "balanced(" _ o:delimiter() _ "," _ c:delimiter() _ ")" _ ">" re:repeat()? { with_repeat_mode(balanced(r, o, c), re) }
+            / _ "<" _ r:(r:ident() _ ":" _ { r })? "oneof[" _ v:(one_of_option() ++ (_ "," _)) _ "]" _ ">" re:repeat()? { with_repeat_mode(one_of(r, v), re) }
+            / _ "<" _ r:(r:ident() _ ":" _ { r })? start:position!() v:ident() end:position!() _ ">" re:repeat()? {
+                state.symbols.borrow_mut().reference(&v, start..end);
+                with_repeat_mode(custom(r, &v), re)
+            }
             / _ r:ident() re:repeat()? { with_repeat_mode(raw(&r), re) }
-            / _ r:$(([^'\n' | ' ' | '\t' | '~' | '0' ..= '9' | 'a' ..= 'z' | 'A' ..= 'Z'] / "\\~~~")) { rw(symbol(None, r)) }
+            / _ r:$(([^'\n' | ' ' | '\t' | '~' | '|' | '0' ..= '9' | 'a' ..= 'z' | 'A' ..= 'Z'] / "\\~~~")) { rw(symbol(None, r)) }
             / expected!("pattern")
 
         rule regex() -> String
-            = s:$([^'/'] / "\\/")+ {
-                s.join("")
+            = s:$("\\/" / [^'/'])+ {
+                s.join("").replace("\\/", "/")
             }
             / expected!("regex")
 
@@ -156,16 +315,269 @@ peg::parser! {
 
         rule symbol() -> String
             = s:$(['-' | '+' | '*' | '/' | '=' | '>' | '\\' | '_' | '.' | ':' | ',' |
-            ';' | '<' | '>' | '!' | '$' | '%' | '&' | '?' | '@']+) { s.to_string() }
+            ';' | '<' | '>' | '!' | '$' | '%' | '&' | '?' | '@' | '|']+) { s.to_string() }
             / expected!("symbol")
 
+        rule one_of_option() -> String
+            = ident()
+            / s:$(['-' | '+' | '*' | '/' | '=' | '>' | '\\' | '.' | ':' |
+            ';' | '<' | '!' | '$' | '%' | '&' | '?' | '@' | '|']+) { s.to_string() }
+            / expected!("keyword or symbol")
+
+        rule delimiter() -> String
+            = "'" s:$([^'\'' | '\n' | ' ' | '\t']+) "'" { s.to_string() }
+            / expected!("delimiter")
+
         rule _() = quiet!{[' ' | '\n' | '\t' | '\r']*}
         rule __() = quiet!{[' ' | '\t']*}
     }
 }
 
-pub fn parse(
+/// A field of a `test "..." { ... }` block.
+enum TestField {
+    Input(String),
+    Rule(String),
+    Expect(Expectation),
+}
+
+/// Byte ranges of the rules and defines in the grammar text, in the order
+/// they were declared.
+#[derive(Default)]
+struct Declarations {
+    rules: Vec<(String, Range<usize>)>,
+    defines: Vec<(String, Range<usize>)>,
+}
+
+/// Grammar text that doesn't follow the grammar syntax, with everything the
+/// parser would have accepted at the furthest point it got to.
+///
+/// ```
+/// let error = tmpl::definition::parse("Main:\n<n:int>\n").unwrap_err();
+/// let tmpl::Error::Syntax(error) = error else { unreachable!() };
+/// assert_eq!(error.declaration.as_deref(), Some("Main"));
+/// assert!(error.expected.contains(&"`~~~`".to_string()));
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("expected {}{}", describe_expected(&self.expected), self.declaration.as_ref().map(|name| format!(" in `{name}`")).unwrap_or_default())]
+pub struct SyntaxError {
+    pub location: LineCol,
+    /// Readable descriptions of what could have come next, like `` `~~~` ``
+    /// or `a pattern`, sorted and without duplicates.
+    pub expected: Vec<String>,
+    /// The rule or define that was being declared, if any.
+    pub declaration: Option<String>,
+}
+
+impl SyntaxError {
+    fn new(src: &str, error: peg::error::ParseError<LineCol>) -> Self {
+        let expected: BTreeSet<_> = error.expected.tokens().filter_map(readable).collect();
+        Self {
+            declaration: declaration_at(src, error.location.offset),
+            location: error.location,
+            expected: expected.into_iter().collect(),
+        }
+    }
+}
+
+/// What an expectation of the definition parser means to someone writing a
+/// grammar, `None` for the ones that only repeat another one, like
+/// character classes.
+fn readable(expected: &str) -> Option<String> {
+    let text = match expected {
+        "EOF" => "end of input",
+        "Main Rule" => "a `Main` rule",
+        "Rule or Define" | "rule_or_define" => "a rule or define",
+        "Rule" => "a rule",
+        "Define" => "a define",
+        "value" => "a value",
+        "pattern" | "token" => "a pattern",
+        "string" => "a string",
+        "regex" => "a regex",
+        "identifier" => "an identifier",
+        "float" => "a float",
+        "int" => "an integer",
+        "bool" => "`true` or `false`",
+        "symbol" => "a symbol",
+        _ if expected.starts_with('[') => return None,
+        _ => match expected.strip_prefix('"').and_then(|e| e.strip_suffix('"')) {
+            Some(literal) => return Some(format!("`{}`", unescape(literal))),
+            None => expected,
+        },
+    };
+    Some(text.to_string())
+}
+
+/// Undoes the escaping of literals in peg's expectations.
+fn unescape(literal: &str) -> String {
+    let mut chars = literal.chars();
+    let mut unescaped = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn describe_expected(expected: &[String]) -> String {
+    match expected {
+        [] => "valid grammar syntax".to_string(),
+        [only] => only.clone(),
+        [init @ .., last] => format!("{} or {last}", init.join(", ")),
+    }
+}
+
+/// Name of the rule or define whose declaration `offset` is in. Declarations
+/// start at the beginning of a line and rules end with a `~~~` line.
+fn declaration_at(src: &str, offset: usize) -> Option<String> {
+    let before = &src[..offset.min(src.len())];
+    for line in before.lines().rev() {
+        let line = line.trim_end();
+        if line == "~~~" {
+            return None;
+        }
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let declaration = line.strip_prefix("override ").unwrap_or(line).trim_start();
+        let (name, define) = match declaration.strip_prefix("define ") {
+            Some(rest) => (rest.trim_start(), true),
+            None => (declaration, false),
+        };
+        let end = name
+            .find(|c: char| !c.is_alphanumeric() && c != '_')
+            .unwrap_or(name.len());
+        if end > 0 && (define || name[end..].trim_start().starts_with(':')) {
+            return Some(name[..end].to_string());
+        }
+    }
+    None
+}
+
+pub fn parse(src: &str) -> std::result::Result<ParserDefinition, crate::Error> {
+    Ok(parse_declarations(src, RegexTable::new())?.0)
+}
+
+/// Parses grammar text like [`parse`], but only checks the syntax of its
+/// regexes and compiles each on first use, which speeds up loading large
+/// grammars of which only a few rules are used, like in a REPL.
+/// [`ParserDefinition::compile_regexes`] compiles the rest, e.g. in CI.
+pub fn parse_lazy(src: &str) -> std::result::Result<ParserDefinition, crate::Error> {
+    Ok(parse_declarations(src, RegexTable::lazy())?.0)
+}
+
+/// Parses grammar text like [`parse`], also returning the warnings
+/// [`ParserDefinition::validate`] reports, pointing at the rule or define
+/// they are about.
+///
+/// ```
+/// let src = "define sep: \",\";\ndefine sep: \";\";\n\nMain:\n<n:int>\n~~~\n";
+/// let (_, diagnostics) = tmpl::definition::parse_with_diagnostics(src)?;
+/// let warning = &diagnostics.warnings()[0];
+/// assert_eq!(warning.span.clone().map(|span| &src[span]), Some("define sep: \";\";"));
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub fn parse_with_diagnostics(
     src: &str,
-) -> std::result::Result<Result<ParserDefinition>, peg::error::ParseError<peg::str::LineCol>> {
-    parser::main(src)
+) -> std::result::Result<(ParserDefinition, Diagnostics), crate::Error> {
+    let (definition, declarations) = parse_declarations(src, RegexTable::new())?;
+    let mut diagnostics = Diagnostics::default();
+    let mut defines_seen = BTreeMap::new();
+    for mut warning in definition.validate() {
+        if warning.severity != crate::definition::Severity::Warning {
+            continue;
+        }
+        warning.span = match warning.kind {
+            ValidationKind::ShadowedDefine => {
+                let seen = defines_seen.entry(warning.rule.clone()).or_insert(0);
+                *seen += 1;
+                declarations
+                    .defines
+                    .iter()
+                    .filter(|(name, _)| *name == warning.rule)
+                    .nth(*seen)
+                    .map(|(_, span)| span.clone())
+            }
+            _ => declarations
+                .rules
+                .iter()
+                .find(|(name, _)| *name == warning.rule)
+                .map(|(_, span)| span.clone()),
+        };
+        diagnostics.push(warning);
+    }
+    Ok((definition, diagnostics))
+}
+
+/// Finds where the rule declared at `rule` in `src` refers to `reference`,
+/// like `<Name>` or `<field:Name>`, falling back to the whole rule.
+fn reference_span(src: &str, rule: Range<usize>, reference: &str) -> Range<usize> {
+    let text = &src[rule.clone()];
+    text.match_indices(reference)
+        .find(|(i, _)| {
+            let before = text[..*i].trim_end();
+            let after = text[i + reference.len()..].trim_start();
+            (before.ends_with('<') || before.ends_with(':')) && after.starts_with('>')
+        })
+        .map_or(rule.clone(), |(i, _)| {
+            rule.start + i..rule.start + i + reference.len()
+        })
+}
+
+#[tracing::instrument(name = "parse_grammar", level = "debug", skip_all, fields(bytes = src.len()))]
+fn parse_declarations(
+    src: &str,
+    regexes: RegexTable,
+) -> std::result::Result<(ParserDefinition, Declarations), crate::Error> {
+    let state = GrammarState {
+        regexes: RefCell::new(regexes),
+        ..GrammarState::default()
+    };
+    let (definition, mut declarations) =
+        parser::main(src, &state).map_err(|error| SyntaxError::new(src, error))??;
+    for (_, span) in declarations
+        .rules
+        .iter_mut()
+        .chain(&mut declarations.defines)
+    {
+        span.end = span.start + src[span.clone()].trim_end().len();
+    }
+    for (rule, patterns) in definition.all_rules() {
+        if RESERVED_NAMES.contains(&rule) {
+            let declaration = declarations.rules.iter().find(|(name, _)| name == rule);
+            let span = declaration.map_or(0..0, |(_, span)| span.clone());
+            let error = DefinitionParseError::ReservedRuleName(rule.to_string());
+            return Err(error.at(span).into());
+        }
+        let references = patterns.iter().flat_map(Pattern::alternatives).flatten();
+        for reference in references.flat_map(|token| token.pattern.references()) {
+            if definition.rule(reference).is_some() {
+                continue;
+            }
+            let declaration = declarations.rules.iter().find(|(name, _)| name == rule);
+            let span = declaration.map_or(0..0, |(_, span)| span.clone());
+            let error = DefinitionParseError::UnknownRule {
+                rule: rule.to_string(),
+                reference: reference.to_string(),
+            };
+            return Err(error.at(reference_span(src, span, reference)).into());
+        }
+    }
+    if let Some(cycle) = definition.left_recursion().into_iter().next() {
+        let declaration = declarations
+            .rules
+            .iter()
+            .find(|(name, _)| *name == cycle[0]);
+        let span = declaration.map_or(0..0, |(_, span)| span.clone());
+        let span = reference_span(src, span, &cycle[1]);
+        return Err(DefinitionParseError::LeftRecursion(cycle).at(span).into());
+    }
+    tracing::debug!(
+        rules = definition.rules.len() + 1,
+        defines = definition.defines.len(),
+        "parsed grammar"
+    );
+    Ok((definition, declarations))
 }
@@ -0,0 +1,121 @@
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+
+use super::ast::{ParserDefinition, Pattern, Value};
+
+/// Which rules of a definition can be used, see [`ParserDefinition::reachability`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reachability<'a> {
+    /// Rules some entry point refers to, directly or through other rules,
+    /// including the entry points themselves.
+    pub reachable: BTreeSet<&'a str>,
+    /// Rules no entry point reaches, in [`ParserDefinition::all_rules`] order.
+    pub dead: Vec<&'a str>,
+    /// Rules that aren't deprecated themselves, but are only reachable
+    /// through deprecated ones, in [`ParserDefinition::all_rules`] order.
+    /// They can go once the deprecated rules do.
+    pub only_deprecated: Vec<&'a str>,
+}
+
+impl ParserDefinition {
+    /// The rules parsing can start from: `Main` and the rules listed in an
+    /// `entries` define, like `define entries: ["Expr", "Statement"];`.
+    pub fn entry_points(&self) -> Vec<&str> {
+        let mut entries = vec!["Main"];
+        for name in self.define_names("entries") {
+            if !entries.contains(&name) {
+                entries.push(name);
+            }
+        }
+        entries
+    }
+
+    /// The rules listed in a `deprecated` define, like
+    /// `define deprecated: ["OldCall"];`.
+    pub fn deprecated_rules(&self) -> Vec<&str> {
+        self.define_names("deprecated")
+    }
+
+    /// Names listed by the define `name`, which is a string or a list of
+    /// strings. Like everywhere else, only the first define of a name counts.
+    pub(crate) fn define_names(&self, name: &str) -> Vec<&str> {
+        let Some(define) = self.defines.iter().find(|d| d.name == name) else {
+            return Vec::new();
+        };
+        match &define.value {
+            Value::String(name) => vec![name.as_str()],
+            Value::List(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::String(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Computes which rules the [`ParserDefinition::entry_points`] reach,
+    /// which are dead and which are only reachable through
+    /// [`ParserDefinition::deprecated_rules`].
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse(
+    ///     "define deprecated: [\"Old\"];\n\nMain:\n<a:New> <b:Old>\n~~~\n\nNew:\n<n:int>\n~~~\n\nOld:\n<h:Helper>\n~~~\n\nHelper:\n<i:ident>\n~~~\n\nDead:\n<d:bool>\n~~~\n",
+    /// )?;
+    /// let reachability = grammar.definition().reachability();
+    /// assert_eq!(reachability.dead, ["Dead"]);
+    /// assert_eq!(reachability.only_deprecated, ["Helper"]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn reachability(&self) -> Reachability<'_> {
+        let deprecated = self.deprecated_rules();
+        let entries = self.entry_points();
+        let reachable = self.reachable_from(&entries, &[]);
+        let current: Vec<_> = entries
+            .iter()
+            .copied()
+            .filter(|entry| !deprecated.contains(entry))
+            .collect();
+        let without_deprecated = self.reachable_from(&current, &deprecated);
+        let rules: Vec<_> = self.all_rules().into_iter().map(|(name, _)| name).collect();
+        Reachability {
+            dead: rules
+                .iter()
+                .copied()
+                .filter(|name| !reachable.contains(name))
+                .collect(),
+            only_deprecated: rules
+                .iter()
+                .copied()
+                .filter(|name| {
+                    reachable.contains(name)
+                        && !without_deprecated.contains(name)
+                        && !deprecated.contains(name)
+                })
+                .collect(),
+            reachable,
+        }
+    }
+
+    /// Rules reachable from `entries` without passing through `avoid`.
+    pub(super) fn reachable_from<'a>(
+        &'a self,
+        entries: &[&'a str],
+        avoid: &[&str],
+    ) -> BTreeSet<&'a str> {
+        let mut reachable: BTreeSet<_> = entries.iter().copied().collect();
+        let mut pending = entries.to_vec();
+        while let Some(name) = pending.pop() {
+            let Some(patterns) = self.rule(name) else {
+                continue;
+            };
+            let alternatives = patterns.iter().flat_map(Pattern::alternatives).flatten();
+            for reference in alternatives.flat_map(|token| token.pattern.references()) {
+                if !avoid.contains(&reference) && reachable.insert(reference) {
+                    pending.push(reference);
+                }
+            }
+        }
+        reachable
+    }
+}
@@ -0,0 +1,126 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use super::ast::{
+    InternalPattern, InternalPatternKind, ParserDefinition, RepeatMode, TokenPattern,
+};
+use super::sets::FirstSet;
+
+impl ParserDefinition {
+    /// Cycles of rules that call each other before consuming any input,
+    /// which the parser would follow until the stack overflows. Every cycle
+    /// is a path from a rule back to itself, like `["Expr", "Term", "Expr"]`
+    /// for `Expr` starting with `Term` and `Term` starting with `Expr`. Each
+    /// rule is only part of one reported cycle, the shortest one found from
+    /// the first of its rules in [`ParserDefinition::all_rules`] order.
+    ///
+    /// Parsing grammar text and [`DefinitionBuilder::build`] reject
+    /// definitions with such cycles:
+    ///
+    /// ```
+    /// use tmpl::definition::{BuildError, DefinitionBuilder, TokenPattern as P};
+    ///
+    /// let error = DefinitionBuilder::new()
+    ///     .rule("Main")
+    ///     .seq([P::rule("Expr").named("expr")])
+    ///     .rule("Expr")
+    ///     .seq([P::rule("Term").named("left"), P::literal("+"), P::int().named("right")])
+    ///     .alt([P::int().named("num")])
+    ///     .rule("Term")
+    ///     .seq([P::literal("-").optional(), P::rule("Expr").named("inner")])
+    ///     .build()
+    ///     .unwrap_err();
+    /// let cycle = ["Expr", "Term", "Expr"].map(String::from).to_vec();
+    /// assert_eq!(error, BuildError::LeftRecursion(cycle));
+    /// ```
+    ///
+    /// [`DefinitionBuilder::build`]: super::DefinitionBuilder::build
+    pub fn left_recursion(&self) -> Vec<Vec<String>> {
+        let first = self.first_sets();
+        let calls: BTreeMap<&str, BTreeSet<&str>> = self
+            .all_rules()
+            .into_iter()
+            .map(|(name, patterns)| {
+                let mut called = BTreeSet::new();
+                for alternative in patterns.iter().flat_map(|p| p.alternatives()) {
+                    leftmost_calls(alternative, &first, &mut called);
+                }
+                (name, called)
+            })
+            .collect();
+
+        let mut covered = BTreeSet::new();
+        let mut cycles = Vec::new();
+        for (name, _) in self.all_rules() {
+            if covered.contains(name) {
+                continue;
+            }
+            if let Some(cycle) = shortest_cycle(&calls, name) {
+                covered.extend(cycle.iter().copied());
+                cycles.push(cycle.into_iter().map(str::to_string).collect());
+            }
+        }
+        cycles
+    }
+}
+
+/// Adds the rules `sequence` can call before consuming input to `called`,
+/// returning whether the whole sequence can match empty input.
+fn leftmost_calls<'a>(
+    sequence: &'a [TokenPattern],
+    first: &BTreeMap<String, FirstSet>,
+    called: &mut BTreeSet<&'a str>,
+) -> bool {
+    for token in sequence {
+        let nullable = match &token.pattern {
+            InternalPattern::Named {
+                kind: InternalPatternKind::Custom(name),
+                ..
+            } => {
+                called.insert(name.as_str());
+                first.get(name).is_some_and(|set| set.nullable)
+            }
+            InternalPattern::Exact { pattern } => leftmost_calls(pattern, first, called),
+            _ => false,
+        };
+        let optional = token.is_optional || token.repeat_mode == Some(RepeatMode::ZeroOrMore);
+        if !nullable && !optional {
+            return false;
+        }
+    }
+    true
+}
+
+/// The shortest path of calls from `start` back to itself, if there is one.
+fn shortest_cycle<'a>(
+    calls: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+    start: &'a str,
+) -> Option<Vec<&'a str>> {
+    let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut pending = VecDeque::from([start]);
+    while let Some(name) = pending.pop_front() {
+        for &next in calls.get(name).into_iter().flatten() {
+            if next == start {
+                let mut path = Vec::new();
+                let mut current = name;
+                while current != start {
+                    path.push(current);
+                    current = previous[current];
+                }
+                let mut cycle = vec![start];
+                cycle.extend(path.into_iter().rev());
+                cycle.push(start);
+                return Some(cycle);
+            }
+            if !previous.contains_key(next) {
+                previous.insert(next, name);
+                pending.push_back(next);
+            }
+        }
+    }
+    None
+}
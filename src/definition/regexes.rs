@@ -0,0 +1,162 @@
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+
+use super::ast::{
+    DefinitionParseError, InternalPattern, InternalPatternKind, ParserDefinition, Pattern, Regex,
+    Sequence, TokenPattern,
+};
+use super::visit::PatternVisitor;
+
+/// The distinct regexes of a grammar by source, each compiled once.
+/// Looking a source up again gives a [`Regex`] sharing the compiled program,
+/// so a grammar using `<s/[a-z]+/>` in many places only holds it once.
+#[derive(Debug, Clone, Default)]
+pub struct RegexTable {
+    regexes: BTreeMap<String, Regex>,
+    lazy: bool,
+}
+
+impl RegexTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table whose new regexes are only compiled on first use, see
+    /// [`Regex::lazy`].
+    pub fn lazy() -> Self {
+        Self {
+            lazy: true,
+            ..Self::default()
+        }
+    }
+
+    /// The regex for `source`, compiling it if the table doesn't have it yet.
+    pub fn get_or_compile(
+        &mut self,
+        source: &str,
+    ) -> Result<Regex, Box<regex_automata::meta::BuildError>> {
+        if let Some(regex) = self.regexes.get(source) {
+            return Ok(regex.clone());
+        }
+        let regex = match self.lazy {
+            true => Regex::lazy(source)?,
+            false => Regex::new(source)?,
+        };
+        self.regexes.insert(String::from(source), regex.clone());
+        Ok(regex)
+    }
+
+    /// Adds `regex` unless the table already has one with the same source,
+    /// returning the one in the table.
+    pub fn share(&mut self, regex: &Regex) -> Regex {
+        self.regexes
+            .entry(String::from(regex.as_str()))
+            .or_insert_with(|| regex.clone())
+            .clone()
+    }
+
+    pub fn get(&self, source: &str) -> Option<&Regex> {
+        self.regexes.get(source)
+    }
+
+    pub fn len(&self) -> usize {
+        self.regexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regexes.is_empty()
+    }
+
+    /// The regexes ordered by source.
+    pub fn iter(&self) -> impl Iterator<Item = &Regex> {
+        self.regexes.values()
+    }
+
+    /// Heap memory the compiled regexes use together, in bytes. This
+    /// compiles the regexes that weren't yet.
+    pub fn memory_usage(&self) -> usize {
+        self.iter().map(Regex::memory_usage).sum()
+    }
+}
+
+impl ParserDefinition {
+    /// The distinct regexes the definition uses.
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse(
+    ///     "Main:\n<a:s/[a-z]+/> <b:s/[0-9]+/> <c:s/[a-z]+/>\n~~~\n",
+    /// )?;
+    /// let table = grammar.definition().regex_table();
+    /// assert_eq!(table.len(), 2);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn regex_table(&self) -> RegexTable {
+        struct Regexes(RegexTable);
+
+        impl<'a> PatternVisitor<'a> for Regexes {
+            fn visit_kind(&mut self, kind: &'a InternalPatternKind) {
+                if let InternalPatternKind::Regex(regex) = kind {
+                    self.0.share(regex);
+                }
+            }
+        }
+
+        let mut regexes = Regexes(RegexTable::new());
+        regexes.visit_definition(self);
+        regexes.0
+    }
+
+    /// Compiles the regexes a lazily parsed definition hasn't used yet, see
+    /// [`crate::definition::parse_lazy`], failing on the first that doesn't
+    /// compile. Definitions parsed otherwise are compiled already.
+    ///
+    /// ```
+    /// let src = "Main:\n<a:s/[a-z]+/>\n~~~\n";
+    /// let definition = tmpl::definition::parse_lazy(src)?;
+    /// assert!(definition.regex_table().iter().all(|regex| !regex.is_compiled()));
+    /// definition.compile_regexes()?;
+    /// assert!(definition.regex_table().iter().all(|regex| regex.is_compiled()));
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn compile_regexes(&self) -> Result<(), DefinitionParseError> {
+        self.regex_table()
+            .iter()
+            .try_for_each(Regex::compile)
+            .map_err(DefinitionParseError::InvalidRegex)
+    }
+
+    /// Makes all regexes with the same source share one compiled program.
+    /// Parsing grammar text does this already, definitions built or loaded
+    /// some other way compile every occurrence of a regex separately.
+    pub fn share_regexes(&mut self) {
+        let mut table = RegexTable::new();
+        share_in_rule(&mut self.entry, &mut table);
+        for patterns in self.rules.values_mut() {
+            share_in_rule(patterns, &mut table);
+        }
+    }
+}
+
+fn share_in_rule(patterns: &mut [Pattern], table: &mut RegexTable) {
+    for pattern in patterns {
+        let alternatives: &mut [Sequence] = match pattern {
+            Pattern::Token(sequence) => core::slice::from_mut(sequence),
+            Pattern::Choice(alternatives) => alternatives,
+        };
+        for sequence in alternatives {
+            share_in_sequence(sequence, table);
+        }
+    }
+}
+
+fn share_in_sequence(sequence: &mut [TokenPattern], table: &mut RegexTable) {
+    for token in sequence {
+        match &mut token.pattern {
+            InternalPattern::Named {
+                kind: InternalPatternKind::Regex(regex),
+                ..
+            } => *regex = table.share(regex),
+            InternalPattern::Exact { pattern } => share_in_sequence(pattern, table),
+            _ => {}
+        }
+    }
+}
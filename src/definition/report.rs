@@ -0,0 +1,78 @@
+use std::fmt::Display;
+
+use miette::{Diagnostic, LabeledSpan};
+
+use super::ast::DefinitionParseError;
+use super::parser::SyntaxError;
+
+/// Points at where the parser stopped. The grammar text isn't part of the
+/// error, attach it with [`miette::Report::with_source_code`].
+///
+/// ```
+/// use miette::Diagnostic;
+///
+/// let src = "Main:\n<n:int>\n";
+/// let tmpl::Error::Syntax(error) = tmpl::definition::parse(src).unwrap_err() else {
+///     unreachable!()
+/// };
+/// let label = error.labels().unwrap().next().unwrap();
+/// assert_eq!(label.offset(), src.len());
+/// let report = miette::Report::new(error).with_source_code(src);
+/// assert!(report.to_string().starts_with("expected"));
+/// ```
+impl Diagnostic for SyntaxError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new("tmpl::syntax"))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let label = LabeledSpan::at_offset(self.location.offset, "here");
+        Some(Box::new(std::iter::once(label)))
+    }
+}
+
+/// Underlines the part of the grammar text the error is about, for errors
+/// with a [`DefinitionParseError::span`].
+///
+/// ```
+/// use miette::Diagnostic;
+///
+/// let src = "Main:\n<n:int>\n~~~\n\nItem:\n<s/[a-/>\n~~~\n";
+/// let tmpl::Error::Definition(error) = tmpl::definition::parse(src).unwrap_err() else {
+///     unreachable!()
+/// };
+/// let label = error.labels().unwrap().next().unwrap();
+/// assert_eq!(&src[label.offset()..label.offset() + label.len()], "<s/[a-/>");
+/// ```
+impl Diagnostic for DefinitionParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        Some(Box::new("tmpl::definition"))
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let label = LabeledSpan::underline(self.span()?);
+        Some(Box::new(std::iter::once(label)))
+    }
+}
+
+/// Forwards to the [`SyntaxError`] or [`DefinitionParseError`] of grammar
+/// text errors, the other errors have no location in the grammar.
+impl Diagnostic for crate::Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.grammar_diagnostic()?.code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.grammar_diagnostic()?.labels()
+    }
+}
+
+impl crate::Error {
+    fn grammar_diagnostic(&self) -> Option<&dyn Diagnostic> {
+        match self {
+            crate::Error::Syntax(error) => Some(error),
+            crate::Error::Definition(error) => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use serde_json::{json, Value};
+
+use super::ast::ParserDefinition;
+
+impl ParserDefinition {
+    /// A JSON Schema describing definitions as they are serialized to JSON,
+    /// e.g. by `tmpl expand --tree --format json`, and loaded by
+    /// [`ParserDefinition::from_json`]. The schema only covers the structure,
+    /// loading also checks the regexes and the references between rules.
+    ///
+    /// ```
+    /// let schema = tmpl::definition::ParserDefinition::json_schema();
+    /// assert_eq!(schema["required"], serde_json::json!(["entry", "rules", "defines"]));
+    /// ```
+    pub fn json_schema() -> Value {
+        let nullable_string = json!({ "type": ["string", "null"] });
+        let wrapper = |name: &str, value: Value| {
+            json!({
+                "type": "object",
+                "properties": { name: value },
+                "required": [name],
+                "additionalProperties": false,
+            })
+        };
+        let object = |properties: Value, required: Value| {
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            })
+        };
+        let string = json!({ "type": "string" });
+        let pattern_list = json!({ "type": "array", "items": { "$ref": "#/$defs/Pattern" } });
+        let sequence = json!({ "type": "array", "items": { "$ref": "#/$defs/TokenPattern" } });
+
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "ParserDefinition",
+            "description": "A tmpl grammar, as serialized by `tmpl expand --tree --format json`.",
+            "type": "object",
+            "properties": {
+                "version": nullable_string.clone(),
+                "entry": pattern_list.clone(),
+                "rules": { "type": "object", "additionalProperties": pattern_list },
+                "defines": { "type": "array", "items": { "$ref": "#/$defs/Define" } },
+                "emits": {
+                    "description": "Emit templates of rules by rule name.",
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                },
+                "docs": {
+                    "description": "Doc comments of rules by rule name.",
+                    "type": "object",
+                    "additionalProperties": { "type": "string" },
+                },
+                "tests": { "type": "array", "items": { "$ref": "#/$defs/GrammarTest" } },
+            },
+            "required": ["entry", "rules", "defines"],
+            "additionalProperties": false,
+            "$defs": {
+                "Pattern": {
+                    "description": "A sequence of tokens or a choice between sequences, the first one is tried first.",
+                    "oneOf": [
+                        wrapper("Token", sequence.clone()),
+                        wrapper("Choice", json!({ "type": "array", "items": sequence.clone() })),
+                        {
+                            "description": "Chain of alternatives written by earlier versions, read as a `Choice`.",
+                            "deprecated": true,
+                            "allOf": [wrapper("Alternative", object(
+                                json!({ "left": sequence.clone(), "right": { "$ref": "#/$defs/Pattern" } }),
+                                json!(["left", "right"]),
+                            ))],
+                        },
+                    ],
+                },
+                "TokenPattern": object(
+                    json!({
+                        "pattern": { "$ref": "#/$defs/InternalPattern" },
+                        "is_optional": { "type": "boolean" },
+                        "repeat_mode": { "enum": ["ZeroOrMore", "OneOrMore", null] },
+                        "separator": nullable_string.clone(),
+                    }),
+                    json!(["pattern", "is_optional"]),
+                ),
+                "InternalPattern": {
+                    "oneOf": [
+                        wrapper("Named", object(
+                            json!({ "name": nullable_string.clone(), "kind": { "$ref": "#/$defs/PatternKind" } }),
+                            json!(["kind"]),
+                        )),
+                        wrapper("Raw", object(json!({ "value": string.clone() }), json!(["value"]))),
+                        wrapper("Exact", object(json!({ "pattern": sequence }), json!(["pattern"]))),
+                    ],
+                },
+                "PatternKind": {
+                    "description": "What a token matches. `Regex` holds the regex source.",
+                    "oneOf": [
+                        { "enum": ["Ident", "Int", "Float", "String", "Bool"] },
+                        wrapper("Regex", string.clone()),
+                        wrapper("Keyword", string.clone()),
+                        wrapper("Custom", string.clone()),
+                        wrapper("Symbol", string.clone()),
+                        wrapper("OneOf", json!({ "type": "array", "items": string.clone() })),
+                        wrapper("Balanced", object(
+                            json!({ "open": string.clone(), "close": string.clone() }),
+                            json!(["open", "close"]),
+                        )),
+                    ],
+                },
+                "GrammarTest": object(
+                    json!({
+                        "name": string.clone(),
+                        "input": string.clone(),
+                        "rule": nullable_string.clone(),
+                        "expect": { "enum": ["ok", "error"] },
+                    }),
+                    json!(["name", "input"]),
+                ),
+                "Define": object(
+                    json!({ "name": string.clone(), "value": { "$ref": "#/$defs/Value" } }),
+                    json!(["name", "value"]),
+                ),
+                "Value": {
+                    "description": "A define's value. Numbers keep the text they were written as.",
+                    "oneOf": [
+                        wrapper("Char", json!({ "type": "string", "minLength": 1, "maxLength": 1 })),
+                        wrapper("String", string.clone()),
+                        wrapper("Int", string.clone()),
+                        wrapper("Float", string),
+                        wrapper("Bool", json!({ "type": "boolean" })),
+                        wrapper("List", json!({ "type": "array", "items": { "$ref": "#/$defs/Value" } })),
+                    ],
+                },
+            },
+        })
+    }
+}
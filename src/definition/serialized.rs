@@ -0,0 +1,56 @@
+use thiserror::Error;
+
+use super::ast::{DefinitionParseError, ParserDefinition};
+use super::validate::{Severity, ValidationIssue};
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Invalid JSON grammar: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid YAML grammar: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Invalid grammar: {}", describe(.0))]
+    Invalid(Vec<ValidationIssue>),
+    #[error("Invalid grammar: {0}")]
+    Version(#[from] DefinitionParseError),
+}
+
+/// The issues without the `error: ` every one of them starts with.
+fn describe(issues: &[ValidationIssue]) -> String {
+    let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+    let issues: Vec<_> = issues
+        .iter()
+        .map(|issue| issue.strip_prefix("error: ").unwrap_or(issue))
+        .collect();
+    issues.join("; ")
+}
+
+impl ParserDefinition {
+    /// Loads a definition serialized as JSON, e.g. by `tmpl expand --tree
+    /// --format json` or by a tool generating grammars in another language.
+    /// Regexes are compiled from their source and the definition has to pass
+    /// [`ParserDefinition::validate`] without errors and declare a supported
+    /// `version`, if any.
+    pub fn from_json(src: &str) -> Result<Self, LoadError> {
+        serde_json::from_str::<Self>(src)?.validated()
+    }
+
+    /// Same as [`ParserDefinition::from_json`] for YAML.
+    pub fn from_yaml(src: &str) -> Result<Self, LoadError> {
+        serde_yaml::from_str::<Self>(src)?.validated()
+    }
+
+    fn validated(mut self) -> Result<Self, LoadError> {
+        self.share_regexes();
+        self.check_version()?;
+        let errors: Vec<_> = self
+            .validate()
+            .into_iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .collect();
+        match errors.is_empty() {
+            true => Ok(self),
+            false => Err(LoadError::Invalid(errors)),
+        }
+    }
+}
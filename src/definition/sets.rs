@@ -0,0 +1,274 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use serde::Serialize;
+
+use super::ast::{
+    InternalPattern, InternalPatternKind, ParserDefinition, RepeatMode, TokenPattern,
+};
+
+/// A kind of token a pattern can start with.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "lowercase")]
+pub enum Terminal {
+    Ident,
+    Int,
+    Float,
+    String,
+    Bool,
+    /// A token matching the regex with this source.
+    Regex(String),
+    /// A keyword or a literal word like `let`.
+    Keyword(String),
+    /// A literal symbol like `;` or `->`.
+    Symbol(String),
+    /// The end of the input, which only follows rules.
+    End,
+}
+
+impl Terminal {
+    /// The terminal literal text is matched as, see [`crate::custom::Parser`].
+    fn literal(text: &str) -> Self {
+        match text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            true => Terminal::Keyword(text.to_string()),
+            false => Terminal::Symbol(text.to_string()),
+        }
+    }
+}
+
+impl Display for Terminal {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Terminal::Ident => write!(f, "identifier"),
+            Terminal::Int => write!(f, "integer"),
+            Terminal::Float => write!(f, "float"),
+            Terminal::String => write!(f, "string"),
+            Terminal::Bool => write!(f, "bool"),
+            Terminal::Regex(source) => write!(f, "/{source}/"),
+            Terminal::Keyword(text) | Terminal::Symbol(text) => write!(f, "`{text}`"),
+            Terminal::End => write!(f, "end of input"),
+        }
+    }
+}
+
+/// The terminals a pattern can start with, see [`ParserDefinition::first_sets`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FirstSet {
+    pub terminals: BTreeSet<Terminal>,
+    /// Whether the pattern can match without consuming any input.
+    pub nullable: bool,
+}
+
+impl FirstSet {
+    /// Whether an input starting with a terminal of both sets could be
+    /// matched by either, i.e. the order of the two decides which one matches.
+    pub fn overlaps(&self, other: &FirstSet) -> bool {
+        !self.terminals.is_disjoint(&other.terminals)
+    }
+
+    /// Adds `other`, returning whether anything was new.
+    fn extend(&mut self, other: &FirstSet) -> bool {
+        let before = (self.terminals.len(), self.nullable);
+        self.terminals.extend(other.terminals.iter().cloned());
+        self.nullable |= other.nullable;
+        before != (self.terminals.len(), self.nullable)
+    }
+}
+
+impl ParserDefinition {
+    /// The terminals every rule, including `Main`, can start with and whether
+    /// it can match empty input.
+    ///
+    /// The sets are computed from the patterns alone: an identifier and a
+    /// keyword are distinct terminals, even though the keyword is lexed as an
+    /// identifier too.
+    pub fn first_sets(&self) -> BTreeMap<String, FirstSet> {
+        let mut sets: BTreeMap<String, FirstSet> = self
+            .all_rules()
+            .into_iter()
+            .map(|(name, _)| (name.to_string(), FirstSet::default()))
+            .collect();
+        loop {
+            let mut changed = false;
+            for (name, patterns) in self.all_rules() {
+                for alternative in patterns.iter().flat_map(|p| p.alternatives()) {
+                    let first = first_of_sequence(alternative, &sets);
+                    changed |= sets
+                        .get_mut(name)
+                        .expect("every rule has a set")
+                        .extend(&first);
+                }
+            }
+            if !changed {
+                return sets;
+            }
+        }
+    }
+
+    /// The terminals that can come right after every rule, including `Main`,
+    /// which is followed by [`Terminal::End`]. Rules that are never referenced
+    /// have an empty set.
+    ///
+    /// ```
+    /// use tmpl::definition::Terminal;
+    ///
+    /// let src = "Main:\n<items:Item>*\n~~~\n\nItem:\n| <n:int> ;\n| <kw[let]> <name:ident>?\n~~~\n";
+    /// let grammar = tmpl::Grammar::parse(src)?;
+    /// let first = grammar.definition().first_sets();
+    /// assert!(first["Main"].nullable);
+    /// assert!(!first["Item"].nullable);
+    /// let follow = grammar.definition().follow_sets();
+    /// let expected = [Terminal::Int, Terminal::Keyword("let".into()), Terminal::End];
+    /// assert_eq!(follow["Item"], expected.into());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn follow_sets(&self) -> BTreeMap<String, BTreeSet<Terminal>> {
+        let first = self.first_sets();
+        let mut follow: BTreeMap<String, BTreeSet<Terminal>> = self
+            .all_rules()
+            .into_iter()
+            .map(|(name, _)| (name.to_string(), BTreeSet::new()))
+            .collect();
+        follow
+            .get_mut("Main")
+            .expect("Main has a set")
+            .insert(Terminal::End);
+        loop {
+            let mut follows = Follows {
+                first: &first,
+                follow: &mut follow,
+                changed: false,
+            };
+            for (name, patterns) in self.all_rules() {
+                for alternative in patterns.iter().flat_map(|p| p.alternatives()) {
+                    let after = FirstSet {
+                        terminals: BTreeSet::new(),
+                        nullable: true,
+                    };
+                    follows.sequence(name, alternative, &after);
+                }
+            }
+            if !follows.changed {
+                return follow;
+            }
+        }
+    }
+}
+
+pub(crate) fn first_of_sequence(
+    sequence: &[TokenPattern],
+    sets: &BTreeMap<String, FirstSet>,
+) -> FirstSet {
+    let mut first = FirstSet {
+        terminals: BTreeSet::new(),
+        nullable: true,
+    };
+    for token in sequence {
+        let token_first = first_of_token(token, sets);
+        first.terminals.extend(token_first.terminals);
+        if !token_first.nullable {
+            first.nullable = false;
+            break;
+        }
+    }
+    first
+}
+
+fn first_of_token(token: &TokenPattern, sets: &BTreeMap<String, FirstSet>) -> FirstSet {
+    let mut first = first_of_pattern(&token.pattern, sets);
+    first.nullable |= token.is_optional || token.repeat_mode == Some(RepeatMode::ZeroOrMore);
+    first
+}
+
+fn first_of_pattern(pattern: &InternalPattern, sets: &BTreeMap<String, FirstSet>) -> FirstSet {
+    let terminal = match pattern {
+        InternalPattern::Named { kind, .. } => match kind {
+            InternalPatternKind::Ident => Terminal::Ident,
+            InternalPatternKind::Int => Terminal::Int,
+            InternalPatternKind::Float => Terminal::Float,
+            InternalPatternKind::String => Terminal::String,
+            InternalPatternKind::Bool => Terminal::Bool,
+            InternalPatternKind::Regex(regex) => Terminal::Regex(regex.as_str().to_string()),
+            InternalPatternKind::Keyword(word) => Terminal::Keyword(word.clone()),
+            InternalPatternKind::Symbol(text) => Terminal::literal(text),
+            InternalPatternKind::Balanced { open, .. } => Terminal::literal(open),
+            InternalPatternKind::OneOf(options) => {
+                return FirstSet {
+                    terminals: options
+                        .iter()
+                        .map(|option| Terminal::literal(option))
+                        .collect(),
+                    nullable: false,
+                };
+            }
+            InternalPatternKind::Custom(name) => {
+                return sets.get(name).cloned().unwrap_or_default();
+            }
+        },
+        InternalPattern::Raw { value } => Terminal::literal(value),
+        InternalPattern::Exact { pattern } => return first_of_sequence(pattern, sets),
+    };
+    FirstSet {
+        terminals: BTreeSet::from([terminal]),
+        nullable: false,
+    }
+}
+
+struct Follows<'a> {
+    first: &'a BTreeMap<String, FirstSet>,
+    follow: &'a mut BTreeMap<String, BTreeSet<Terminal>>,
+    changed: bool,
+}
+
+impl Follows<'_> {
+    /// Adds what follows the rules referenced in `sequence`, which is part
+    /// of rule `owner` and followed by `after`. A nullable `after` means the
+    /// sequence can end the rule, so the rule's own follow set applies too.
+    fn sequence(&mut self, owner: &str, sequence: &[TokenPattern], after: &FirstSet) {
+        let mut rest = after.clone();
+        for token in sequence.iter().rev() {
+            let mut here = rest.clone();
+            if token.repeat_mode.is_some() {
+                match &token.separator {
+                    Some(separator) => {
+                        here.terminals.insert(Terminal::literal(separator));
+                    }
+                    None => {
+                        let first = first_of_pattern(&token.pattern, self.first);
+                        here.terminals.extend(first.terminals);
+                    }
+                }
+            }
+            match &token.pattern {
+                InternalPattern::Named {
+                    kind: InternalPatternKind::Custom(name),
+                    ..
+                } => self.add(owner, name, &here),
+                InternalPattern::Exact { pattern } => self.sequence(owner, pattern, &here),
+                _ => {}
+            }
+            let first = first_of_token(token, self.first);
+            if !first.nullable {
+                rest.terminals.clear();
+                rest.nullable = false;
+            }
+            rest.terminals.extend(first.terminals);
+        }
+    }
+
+    fn add(&mut self, owner: &str, name: &str, here: &FirstSet) {
+        let mut terminals: Vec<_> = here.terminals.iter().cloned().collect();
+        if here.nullable {
+            terminals.extend(self.follow.get(owner).into_iter().flatten().cloned());
+        }
+        if let Some(set) = self.follow.get_mut(name) {
+            for terminal in terminals {
+                self.changed |= set.insert(terminal);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::Range;
+
+use super::ast::ParserDefinition;
+
+/// A place in the grammar text a definition was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The rule the place is in.
+    pub rule: String,
+    /// Byte range of the rule name in the grammar text.
+    pub span: Range<usize>,
+}
+
+/// Where the rules of a definition are declared and referenced in the
+/// grammar text it was parsed from, recorded by the definition parser.
+///
+/// It only describes that text, so definitions built in code or loaded from
+/// compiled grammars have an empty index and it doesn't take part in
+/// comparing definitions: the same grammar parsed from differently laid out
+/// text is still the same grammar.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolIndex {
+    /// Declarations by the start of the rule name, with the byte range of
+    /// the whole rule.
+    declarations: BTreeMap<usize, (String, Range<usize>, Range<usize>)>,
+    /// References by the start of the rule name.
+    references: BTreeMap<usize, (String, Range<usize>)>,
+}
+
+impl PartialEq for SymbolIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl SymbolIndex {
+    /// Records that the rule `name` is declared at `name_span`, spanning
+    /// `span`. The definition parser may record the same declaration more
+    /// than once while backtracking, which is fine.
+    pub(crate) fn declare(&mut self, name: &str, name_span: Range<usize>, span: Range<usize>) {
+        self.declarations
+            .insert(name_span.start, (name.to_string(), name_span, span));
+    }
+
+    /// Records a reference to the rule `name` at `span`.
+    pub(crate) fn reference(&mut self, name: &str, span: Range<usize>) {
+        self.references.insert(span.start, (name.to_string(), span));
+    }
+
+    /// Whether nothing was recorded, as for definitions not parsed from text.
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
+
+    /// The declaration the byte `offset` is in.
+    fn rule_at(&self, offset: usize) -> Option<&str> {
+        let (_, (name, _, span)) = self.declarations.range(..=offset).next_back()?;
+        span.contains(&offset).then_some(name.as_str())
+    }
+
+    fn declarations(&self, name: &str) -> Vec<Location> {
+        (self.declarations.values())
+            .filter(|(declared, _, _)| declared == name)
+            .map(|(declared, name_span, _)| Location {
+                rule: declared.clone(),
+                span: name_span.clone(),
+            })
+            .collect()
+    }
+
+    fn references(&self, name: &str) -> Vec<Location> {
+        (self.references.values())
+            .filter(|(referenced, _)| referenced == name)
+            .filter_map(|(_, span)| {
+                Some(Location {
+                    rule: self.rule_at(span.start)?.to_string(),
+                    span: span.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl ParserDefinition {
+    /// Where the rule `name` is referenced, like `<Name>` or `<field:Name>`,
+    /// in the grammar text the definition was parsed from, in the order of
+    /// the text. Empty for definitions not parsed from text, see
+    /// [`SymbolIndex`].
+    ///
+    /// ```
+    /// let src = "Main:\n<first:Item> <second:Item>\n~~~\n\nItem:\n<n:int>\n~~~\n";
+    /// let definition = tmpl::definition::parse(src)?;
+    /// let references = definition.references("Item");
+    /// assert_eq!(references.len(), 2);
+    /// assert_eq!(references[0].rule, "Main");
+    /// assert_eq!(&src[references[1].span.clone()], "Item");
+    /// assert_eq!(definition.declarations("Item")[0].span, 38..42);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn references(&self, name: &str) -> Vec<Location> {
+        self.symbols.references(name)
+    }
+
+    /// Where the rule `name` is declared, more than once if it is
+    /// overridden, like [`ParserDefinition::references`]. The spans are
+    /// those of the name, so together with the references they are what
+    /// renaming the rule has to replace.
+    pub fn declarations(&self, name: &str) -> Vec<Location> {
+        self.symbols.declarations(name)
+    }
+}
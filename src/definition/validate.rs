@@ -0,0 +1,335 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::ops::Range;
+
+use serde::Serialize;
+
+use super::ast::{ParserDefinition, Pattern, RESERVED_NAMES};
+use super::visit::captures;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Parsing with the definition will fail or misbehave.
+    Error,
+    /// The definition works, but likely not as intended.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValidationKind {
+    /// A pattern refers to a rule that isn't defined.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let mut definition = parse("Main:\n<a:A>\n~~~\n\nA:\n<n:int>\n~~~\n")?;
+    /// definition.rules.shift_remove("A");
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::UnknownRule { reference: "A".into() }]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    UnknownRule { reference: String },
+    /// The rule isn't reachable from any of the
+    /// [`ParserDefinition::entry_points`].
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let definition = parse("Main:\n<n:int>\n~~~\n\nUnused:\n<b:bool>\n~~~\n")?;
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::UnusedRule]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    UnusedRule,
+    /// The rule has no alternatives at all.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let mut definition = parse("Main:\n<a:A>\n~~~\n\nA:\n<n:int>\n~~~\n")?;
+    /// definition.rules["A"].clear();
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::EmptyRule]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    EmptyRule,
+    /// An alternative without any patterns, which always matches.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, Pattern, Sequence, ValidationKind};
+    ///
+    /// let mut definition = parse("Main:\n<n:int>\n~~~\n")?;
+    /// let int = definition.entry[0].alternatives()[0].clone();
+    /// definition.entry[0] = Pattern::from_alternatives(vec![int, Sequence::new()]);
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::EmptyAlternative { alternative: 2 }]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    EmptyAlternative { alternative: usize },
+    /// A define with the name of an earlier one, which is the one used.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let definition = parse("define sep: \",\";\ndefine sep: \";\";\n\nMain:\n<n:int>\n~~~\n")?;
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::ShadowedDefine]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    ShadowedDefine,
+    /// Every token of the rule is optional, so it always matches.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let definition = parse("Main:\n<n:int>? <b:bool>?\n~~~\n")?;
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::AlwaysOptional]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    AlwaysOptional,
+    /// The rule calls itself before consuming any input, through the rules
+    /// in `cycle`, see [`ParserDefinition::left_recursion`].
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let mut definition = parse("Main:\n<a:A>\n~~~\n\nA:\n<n:int>\n~~~\n")?;
+    /// definition.rules["A"] = definition.entry.clone();
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// let cycle = vec!["A".to_string(), "A".to_string()];
+    /// assert_eq!(kinds, [ValidationKind::LeftRecursion { cycle }]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    LeftRecursion { cycle: Vec<String> },
+    /// An alternative after one that always matches, so it is never tried.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let definition = parse("Main:\n| <n:int>?\n| <b:bool>\n~~~\n")?;
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// let kind = ValidationKind::AfterCatchAll { alternative: 2, catch_all: 1 };
+    /// assert_eq!(kinds, [kind]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    AfterCatchAll {
+        alternative: usize,
+        catch_all: usize,
+    },
+    /// The rule is named like a built-in pattern kind or keyword, see
+    /// [`RESERVED_NAMES`], so `<name>` doesn't refer to it.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let mut definition = parse("Main:\n<n:int>\n~~~\n\nUnused:\n<b:bool>\n~~~\n")?;
+    /// let patterns = definition.rules.shift_remove("Unused").unwrap();
+    /// definition.rules.insert("int".into(), patterns);
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::UnusedRule, ValidationKind::ReservedRuleName]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    ReservedRuleName,
+    /// A capture named like a built-in pattern kind or keyword, like
+    /// `<int:int>`.
+    ///
+    /// ```
+    /// use tmpl::definition::{parse, ValidationKind};
+    ///
+    /// let definition = parse("Main:\n<int:int>\n~~~\n")?;
+    /// let kinds: Vec<_> = definition.validate().into_iter().map(|issue| issue.kind).collect();
+    /// assert_eq!(kinds, [ValidationKind::ReservedCapture { capture: "int".into() }]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    ReservedCapture { capture: String },
+}
+
+/// A problem [`ParserDefinition::validate`] found in a rule, or in a define
+/// for [`ValidationKind::ShadowedDefine`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub rule: String,
+    #[serde(flatten)]
+    pub kind: ValidationKind,
+    /// Byte range of the declaration in the grammar text, only known for
+    /// issues reported by [`super::parse_with_diagnostics`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<Range<usize>>,
+}
+
+/// The warnings about a definition, returned alongside it by
+/// [`super::parse_with_diagnostics`]. Unlike errors they don't keep the
+/// definition from being used, but likely point at a mistake.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Diagnostics {
+    warnings: Vec<ValidationIssue>,
+}
+
+impl Diagnostics {
+    pub fn warnings(&self) -> &[ValidationIssue] {
+        &self.warnings
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    pub fn push(&mut self, warning: ValidationIssue) {
+        self.warnings.push(warning);
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = ValidationIssue;
+    type IntoIter = std::vec::IntoIter<ValidationIssue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.warnings.into_iter()
+    }
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}: {}: ", self.rule)?;
+        match &self.kind {
+            ValidationKind::UnknownRule { reference } => {
+                write!(f, "refers to unknown rule `{reference}`")
+            }
+            ValidationKind::UnusedRule => write!(f, "rule is never used"),
+            ValidationKind::EmptyRule => write!(f, "rule has no alternatives"),
+            ValidationKind::EmptyAlternative { alternative } => {
+                write!(f, "alternative {alternative} is empty and always matches")
+            }
+            ValidationKind::LeftRecursion { cycle } => {
+                write!(f, "rule is left recursive: {}", cycle.join(" -> "))
+            }
+            ValidationKind::ShadowedDefine => {
+                write!(f, "define is declared again, only the first value is used")
+            }
+            ValidationKind::AlwaysOptional => {
+                write!(
+                    f,
+                    "every token of the rule is optional, so it always matches"
+                )
+            }
+            ValidationKind::AfterCatchAll {
+                alternative,
+                catch_all,
+            } => write!(
+                f,
+                "alternative {alternative} is never tried, alternative {catch_all} always matches"
+            ),
+            ValidationKind::ReservedRuleName => {
+                write!(
+                    f,
+                    "rule name is reserved, `<{}>` doesn't refer to it",
+                    self.rule
+                )
+            }
+            ValidationKind::ReservedCapture { capture } => {
+                write!(
+                    f,
+                    "capture `{capture}` is named like a built-in pattern or keyword"
+                )
+            }
+        }
+    }
+}
+
+impl ParserDefinition {
+    /// Checks that every rule reference resolves and reports unused rules,
+    /// empty alternatives and the other [`ValidationKind`]s, ordered by rule
+    /// as in [`ParserDefinition::all_rules`], followed by left recursion and
+    /// shadowed defines.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let reachable = self.reachability().reachable;
+        let nullable = crate::lint::nullable_rules(self);
+        let mut issues = Vec::new();
+        for (name, patterns) in self.all_rules() {
+            let mut push = |severity, kind| {
+                issues.push(ValidationIssue {
+                    severity,
+                    rule: name.to_string(),
+                    kind,
+                    span: None,
+                })
+            };
+            if !reachable.contains(name) {
+                push(Severity::Warning, ValidationKind::UnusedRule);
+            }
+            if RESERVED_NAMES.contains(&name) {
+                push(Severity::Error, ValidationKind::ReservedRuleName);
+            }
+            for capture in captures(patterns) {
+                if RESERVED_NAMES.contains(&capture) {
+                    let kind = ValidationKind::ReservedCapture {
+                        capture: capture.to_string(),
+                    };
+                    push(Severity::Warning, kind);
+                }
+            }
+            let alternatives: Vec<_> = patterns.iter().flat_map(Pattern::alternatives).collect();
+            if alternatives.is_empty() {
+                push(Severity::Error, ValidationKind::EmptyRule);
+            }
+            let all_optional = alternatives
+                .iter()
+                .all(|a| !a.is_empty() && a.iter().all(|t| t.is_optional));
+            if !alternatives.is_empty() && all_optional {
+                push(Severity::Warning, ValidationKind::AlwaysOptional);
+            }
+            let catch_all = alternatives
+                .iter()
+                .position(|a| a.iter().all(|t| crate::lint::token_nullable(t, &nullable)));
+            for (i, alternative) in alternatives.iter().enumerate() {
+                if alternative.is_empty() {
+                    let kind = ValidationKind::EmptyAlternative { alternative: i + 1 };
+                    push(Severity::Warning, kind);
+                }
+                if let Some(j) = catch_all.filter(|j| *j < i) {
+                    let kind = ValidationKind::AfterCatchAll {
+                        alternative: i + 1,
+                        catch_all: j + 1,
+                    };
+                    push(Severity::Warning, kind);
+                }
+                for reference in alternative.iter().flat_map(|t| t.pattern.references()) {
+                    if self.rule(reference).is_none() {
+                        let kind = ValidationKind::UnknownRule {
+                            reference: reference.to_string(),
+                        };
+                        push(Severity::Error, kind);
+                    }
+                }
+            }
+        }
+        for cycle in self.left_recursion() {
+            issues.push(ValidationIssue {
+                severity: Severity::Error,
+                rule: cycle[0].clone(),
+                kind: ValidationKind::LeftRecursion { cycle },
+                span: None,
+            });
+        }
+        let mut defines = BTreeSet::new();
+        for define in &self.defines {
+            if !defines.insert(define.name.as_str()) {
+                issues.push(ValidationIssue {
+                    severity: Severity::Warning,
+                    rule: define.name.clone(),
+                    kind: ValidationKind::ShadowedDefine,
+                    span: None,
+                });
+            }
+        }
+        issues
+    }
+}
@@ -0,0 +1,52 @@
+use alloc::string::{String, ToString};
+
+use super::ast::{DefinitionParseError, ParserDefinition};
+
+/// Version of the grammar language this crate reads. Grammars can declare the
+/// version they were written for with `tmpl_version "0.1";` before their
+/// first rule or define.
+pub const GRAMMAR_VERSION: &str = "0.1";
+
+/// Checks that a grammar written for language version `version` can be read
+/// by this crate, which is the case for the same major version up to the
+/// minor version of [`GRAMMAR_VERSION`]. A patch version is allowed but
+/// ignored.
+///
+/// ```
+/// use tmpl::definition::check_version;
+///
+/// assert!(check_version("0.1").is_ok());
+/// assert!(check_version("0.0.3").is_ok());
+/// assert!(check_version("0.9").is_err());
+/// assert!(check_version("one").is_err());
+/// ```
+pub fn check_version(version: &str) -> Result<(), DefinitionParseError> {
+    let found = major_minor(version)
+        .ok_or_else(|| DefinitionParseError::InvalidVersion(version.to_string()))?;
+    let (major, minor) = major_minor(GRAMMAR_VERSION).expect("GRAMMAR_VERSION is valid");
+    if found.0 != major || found.1 > minor {
+        return Err(DefinitionParseError::UnsupportedVersion {
+            found: version.to_string(),
+            supported: String::from(GRAMMAR_VERSION),
+        });
+    }
+    Ok(())
+}
+
+fn major_minor(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    if let Some(patch) = parts.next() {
+        patch.parse::<u32>().ok()?;
+    }
+    parts.next().is_none().then_some((major, minor))
+}
+
+impl ParserDefinition {
+    /// Checks the language version the definition declares, if it declares
+    /// one, with [`check_version`].
+    pub fn check_version(&self) -> Result<(), DefinitionParseError> {
+        self.version.as_deref().map_or(Ok(()), check_version)
+    }
+}
@@ -0,0 +1,119 @@
+use alloc::vec::Vec;
+
+use super::ast::{InternalPattern, InternalPatternKind, ParserDefinition, Pattern, TokenPattern};
+
+/// Walks the patterns of a definition.
+///
+/// Every method defaults to descending into the node's children through the
+/// matching `walk_*` function, so an analysis only overrides the methods for
+/// the nodes it cares about, calling `walk_*` itself if it still wants to
+/// descend further.
+///
+/// ```
+/// use tmpl::definition::{InternalPatternKind, PatternVisitor};
+///
+/// #[derive(Default)]
+/// struct Keywords<'a>(Vec<&'a str>);
+///
+/// impl<'a> PatternVisitor<'a> for Keywords<'a> {
+///     fn visit_kind(&mut self, kind: &'a InternalPatternKind) {
+///         if let InternalPatternKind::Keyword(word) = kind {
+///             self.0.push(word);
+///         }
+///     }
+/// }
+///
+/// let definition = tmpl::Grammar::parse("Main:\n<kw[let]> <name:ident>\n~~~\n")?;
+/// let mut keywords = Keywords::default();
+/// keywords.visit_definition(definition.definition());
+/// assert_eq!(keywords.0, ["let"]);
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub trait PatternVisitor<'a> {
+    /// Visits every rule, in the order of [`ParserDefinition::all_rules`].
+    fn visit_definition(&mut self, definition: &'a ParserDefinition) {
+        walk_definition(self, definition);
+    }
+
+    fn visit_rule(&mut self, name: &'a str, patterns: &'a [Pattern]) {
+        walk_rule(self, patterns);
+    }
+
+    /// Called for every alternative of a rule and for the contents of groups.
+    fn visit_sequence(&mut self, sequence: &'a [TokenPattern]) {
+        walk_sequence(self, sequence);
+    }
+
+    fn visit_token(&mut self, token: &'a TokenPattern) {
+        walk_token(self, token);
+    }
+
+    fn visit_pattern(&mut self, pattern: &'a InternalPattern) {
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_kind(&mut self, kind: &'a InternalPatternKind) {}
+}
+
+pub fn walk_definition<'a, V: PatternVisitor<'a> + ?Sized>(
+    visitor: &mut V,
+    definition: &'a ParserDefinition,
+) {
+    for (name, patterns) in definition.all_rules() {
+        visitor.visit_rule(name, patterns);
+    }
+}
+
+pub fn walk_rule<'a, V: PatternVisitor<'a> + ?Sized>(visitor: &mut V, patterns: &'a [Pattern]) {
+    for alternative in patterns.iter().flat_map(Pattern::alternatives) {
+        visitor.visit_sequence(alternative);
+    }
+}
+
+pub fn walk_sequence<'a, V: PatternVisitor<'a> + ?Sized>(
+    visitor: &mut V,
+    sequence: &'a [TokenPattern],
+) {
+    for token in sequence {
+        visitor.visit_token(token);
+    }
+}
+
+pub fn walk_token<'a, V: PatternVisitor<'a> + ?Sized>(visitor: &mut V, token: &'a TokenPattern) {
+    visitor.visit_pattern(&token.pattern);
+}
+
+pub fn walk_pattern<'a, V: PatternVisitor<'a> + ?Sized>(
+    visitor: &mut V,
+    pattern: &'a InternalPattern,
+) {
+    match pattern {
+        InternalPattern::Named { kind, .. } => visitor.visit_kind(kind),
+        InternalPattern::Raw { .. } => {}
+        InternalPattern::Exact { pattern } => visitor.visit_sequence(pattern),
+    }
+}
+
+/// Names of the captures in a rule, in the order they first appear in its
+/// alternatives.
+pub(crate) fn captures(patterns: &[Pattern]) -> Vec<&str> {
+    struct Captures<'a>(Vec<&'a str>);
+
+    impl<'a> PatternVisitor<'a> for Captures<'a> {
+        fn visit_pattern(&mut self, pattern: &'a InternalPattern) {
+            if let InternalPattern::Named {
+                name: Some(name), ..
+            } = pattern
+            {
+                if !self.0.contains(&name.as_str()) {
+                    self.0.push(name);
+                }
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    let mut captures = Captures(Vec::new());
+    walk_rule(&mut captures, patterns);
+    captures.0
+}
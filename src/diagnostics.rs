@@ -0,0 +1,205 @@
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use clap::{ColorChoice, ValueEnum};
+use serde::Serialize;
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_GRAMMAR_ERROR: i32 = 2;
+pub const EXIT_INPUT_ERROR: i32 = 3;
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Decides whether human readable diagnostics are colored. `auto` colors them
+/// if stderr is a terminal and `NO_COLOR` isn't set.
+pub fn set_color(choice: ColorChoice) {
+    let color = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+        }
+    };
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Wraps `text` in the ANSI escape `code` if colors are enabled.
+fn paint(text: &str, code: &str) -> String {
+    match COLOR.load(Ordering::Relaxed) {
+        true => format!("\x1b[{code}m{text}\x1b[0m"),
+        false => text.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ErrorFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    Grammar,
+    Input,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Grammar => EXIT_GRAMMAR_ERROR,
+            ErrorKind::Input => EXIT_INPUT_ERROR,
+            ErrorKind::Other => EXIT_FAILURE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+impl Span {
+    /// Computes the 1-based line and column of the byte `offset` in `src`.
+    pub fn at(src: &str, offset: usize) -> Self {
+        let offset = offset.min(src.len());
+        let before = &src[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        Span {
+            line,
+            column,
+            offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    pub message: String,
+    pub expected: Vec<String>,
+    /// The source line the span points at, shown below the message in
+    /// human output.
+    #[serde(skip)]
+    pub snippet: Option<Snippet>,
+}
+
+/// A source line with part of it underlined, see [`Diagnostic::with_snippet`].
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    number: usize,
+    line: String,
+    /// Whitespace up to the underlined part, keeping tabs so it lines up.
+    indent: String,
+    width: usize,
+}
+
+impl Diagnostic {
+    pub fn new(kind: ErrorKind, file: Option<&Path>, span: Option<Span>, message: String) -> Self {
+        Self {
+            kind,
+            file: file.map(|f| f.display().to_string()),
+            span,
+            message,
+            expected: Vec::new(),
+            snippet: None,
+        }
+    }
+
+    pub fn with_expected(mut self, expected: Vec<String>) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Shows the line of `src` the span points at, with the `len` bytes
+    /// starting at the span underlined, or a single caret for an empty span.
+    pub fn with_snippet(mut self, src: &str, len: usize) -> Self {
+        let Some(span) = &self.span else {
+            return self;
+        };
+        let start = src[..span.offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = src[span.offset..]
+            .find('\n')
+            .map_or(src.len(), |i| span.offset + i);
+        let line = src[start..end].trim_end_matches('\r');
+        let end = (span.offset + len).min(start + line.len());
+        let indent: String = src[start..span.offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = src[span.offset.min(end)..end]
+            .trim_end()
+            .chars()
+            .count()
+            .max(1);
+        self.snippet = Some(Snippet {
+            number: span.line,
+            line: line.to_string(),
+            indent,
+            width,
+        });
+        self
+    }
+
+    /// Renders the diagnostic for a terminal, as `label` (like `error`) followed
+    /// by the message and the snippet, if any, with the underline colored
+    /// like the label.
+    pub fn render(&self, label: &str) -> String {
+        let code = match label {
+            "warning" => "1;33",
+            _ => "1;31",
+        };
+        let mut out = format!("{}: {}", paint(label, code), self);
+        if let Some(snippet) = &self.snippet {
+            let number = snippet.number.to_string();
+            let gutter = paint(&format!("{} |", " ".repeat(number.len())), "1;34");
+            let underline = paint(&"^".repeat(snippet.width), code);
+            out.push_str(&format!(
+                "\n{gutter}\n{} {}\n{gutter} {}{underline}",
+                paint(&format!("{number} |"), "1;34"),
+                snippet.line,
+                snippet.indent,
+            ));
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.file, &self.span) {
+            (Some(file), Some(span)) => write!(f, "{file}:{}:{}: ", span.line, span.column)?,
+            (Some(file), None) => write!(f, "{file}: ")?,
+            (None, Some(span)) => write!(f, "{}:{}: ", span.line, span.column)?,
+            (None, None) => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Prints `error` to stderr in the requested format and returns the exit code for it.
+pub fn emit(error: &anyhow::Error, format: ErrorFormat) -> i32 {
+    let diagnostic = match error.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => diagnostic.clone(),
+        None => Diagnostic::new(ErrorKind::Other, None, None, format!("{error:#}")),
+    };
+    match format {
+        ErrorFormat::Human => eprintln!("{}", diagnostic.render("error")),
+        ErrorFormat::Json => match serde_json::to_string(&diagnostic) {
+            Ok(json) => eprintln!("{json}"),
+            Err(_) => eprintln!("{}", diagnostic.render("error")),
+        },
+    }
+    diagnostic.kind.exit_code()
+}
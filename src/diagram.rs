@@ -0,0 +1,84 @@
+use railroad::{
+    Choice, Diagram, Empty, End, Link, Node, NonTerminal, Optional, Repeat, Sequence, Start,
+    Terminal,
+};
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, RepeatMode, TokenPattern,
+};
+
+type BoxedNode = Box<dyn Node>;
+
+/// Renders every rule of `definition` as a railroad diagram on a single HTML page.
+pub fn render_html(definition: &ParserDefinition) -> String {
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<style>\n");
+    html.push_str(railroad::DEFAULT_CSS);
+    html.push_str("</style>\n</head>\n<body>\n");
+    for (name, patterns) in definition.all_rules() {
+        html.push_str(&format!("<h2 id=\"{name}\">{name}</h2>\n"));
+        html.push_str(&rule_diagram(patterns).to_string());
+        html.push('\n');
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Builds the railroad diagram of a single rule.
+pub fn rule_diagram(patterns: &[Pattern]) -> Diagram<Sequence<BoxedNode>> {
+    let mut alternatives: Vec<BoxedNode> = patterns
+        .iter()
+        .flat_map(|p| p.alternatives())
+        .map(|alternative| sequence_node(alternative))
+        .collect();
+    let body: BoxedNode = if alternatives.len() == 1 {
+        alternatives.remove(0)
+    } else {
+        Box::new(Choice::new(alternatives))
+    };
+    Diagram::new(Sequence::new(vec![
+        Box::new(Start) as BoxedNode,
+        body,
+        Box::new(End),
+    ]))
+}
+
+fn sequence_node(sequence: &[TokenPattern]) -> BoxedNode {
+    Box::new(Sequence::new(sequence.iter().map(token_node).collect()))
+}
+
+fn token_node(token: &TokenPattern) -> BoxedNode {
+    let node = pattern_node(&token.pattern);
+    let separator: BoxedNode = match &token.separator {
+        Some(separator) => Box::new(Terminal::new(separator.clone())),
+        None => Box::new(Empty),
+    };
+    match &token.repeat_mode {
+        Some(RepeatMode::OneOrMore) => Box::new(Repeat::new(node, separator)),
+        Some(RepeatMode::ZeroOrMore) => Box::new(Optional::new(Repeat::new(node, separator))),
+        None if token.is_optional => Box::new(Optional::new(node)),
+        None => node,
+    }
+}
+
+fn pattern_node(pattern: &InternalPattern) -> BoxedNode {
+    match pattern {
+        InternalPattern::Named { kind, .. } => match kind {
+            InternalPatternKind::Custom(name) => Box::new(Link::new(
+                NonTerminal::new(name.clone()),
+                format!("#{name}"),
+            )),
+            InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                Box::new(Terminal::new(text.clone()))
+            }
+            InternalPatternKind::OneOf(options) => Box::new(Choice::new(
+                (options.iter())
+                    .map(|option| Box::new(Terminal::new(option.clone())) as BoxedNode)
+                    .collect(),
+            )),
+            _ => Box::new(NonTerminal::new(kind.to_string())),
+        },
+        InternalPattern::Raw { value } => Box::new(Terminal::new(value.clone())),
+        InternalPattern::Exact { pattern } => sequence_node(pattern),
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::definition::{ParserDefinition, TokenPattern};
+
+/// A single structural difference between two grammars.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    RuleAdded {
+        rule: String,
+    },
+    RuleRemoved {
+        rule: String,
+    },
+    AlternativeAdded {
+        rule: String,
+        alternative: String,
+    },
+    AlternativeRemoved {
+        rule: String,
+        alternative: String,
+    },
+    /// Same alternatives in a different order, which changes what a PEG matches.
+    AlternativesReordered {
+        rule: String,
+    },
+    DefineAdded {
+        define: String,
+    },
+    DefineRemoved {
+        define: String,
+    },
+    DefineChanged {
+        old: String,
+        new: String,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::RuleAdded { rule } => write!(f, "+ rule {rule}"),
+            Change::RuleRemoved { rule } => write!(f, "- rule {rule}"),
+            Change::AlternativeAdded { rule, alternative } => {
+                write!(f, "~ rule {rule}\n    + {alternative}")
+            }
+            Change::AlternativeRemoved { rule, alternative } => {
+                write!(f, "~ rule {rule}\n    - {alternative}")
+            }
+            Change::AlternativesReordered { rule } => {
+                write!(f, "~ rule {rule}: alternatives reordered")
+            }
+            Change::DefineAdded { define } => write!(f, "+ {define}"),
+            Change::DefineRemoved { define } => write!(f, "- {define}"),
+            Change::DefineChanged { old, new } => write!(f, "- {old}\n+ {new}"),
+        }
+    }
+}
+
+/// Compares two grammars rule by rule and alternative by alternative.
+pub fn diff(old: &ParserDefinition, new: &ParserDefinition) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for define in &old.defines {
+        match new.defines.iter().find(|d| d.name == define.name) {
+            None => changes.push(Change::DefineRemoved {
+                define: define.to_string(),
+            }),
+            Some(other) if other.to_string() != define.to_string() => {
+                changes.push(Change::DefineChanged {
+                    old: define.to_string(),
+                    new: other.to_string(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for define in &new.defines {
+        if !old.defines.iter().any(|d| d.name == define.name) {
+            changes.push(Change::DefineAdded {
+                define: define.to_string(),
+            });
+        }
+    }
+
+    let old_rules = old.all_rules();
+    let new_rules = new.all_rules();
+    let names: BTreeSet<&str> = old_rules
+        .iter()
+        .chain(&new_rules)
+        .map(|(name, _)| *name)
+        .collect();
+    for name in names {
+        let rule = name.to_string();
+        let (before, after) = match (old.rule(name), new.rule(name)) {
+            (Some(before), Some(after)) => (alternatives(before), alternatives(after)),
+            (Some(_), None) => {
+                changes.push(Change::RuleRemoved { rule });
+                continue;
+            }
+            (None, _) => {
+                changes.push(Change::RuleAdded { rule });
+                continue;
+            }
+        };
+        let mut reordered = true;
+        for alternative in before.iter().filter(|a| !after.contains(a)) {
+            reordered = false;
+            changes.push(Change::AlternativeRemoved {
+                rule: rule.clone(),
+                alternative: alternative.clone(),
+            });
+        }
+        for alternative in after.iter().filter(|a| !before.contains(a)) {
+            reordered = false;
+            changes.push(Change::AlternativeAdded {
+                rule: rule.clone(),
+                alternative: alternative.clone(),
+            });
+        }
+        if reordered && before != after {
+            changes.push(Change::AlternativesReordered { rule });
+        }
+    }
+    changes
+}
+
+fn alternatives(patterns: &[crate::definition::Pattern]) -> Vec<String> {
+    patterns
+        .iter()
+        .flat_map(|p| p.alternatives())
+        .map(|alternative| {
+            alternative
+                .iter()
+                .map(TokenPattern::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+use thiserror::Error;
+
+use crate::custom::ParseError;
+use crate::definition::{
+    BinaryError, BuildError, DefinitionParseError, LoadError, MergeError, SyntaxError,
+    ValidationIssue,
+};
+use crate::lexer::{LexingError, Span};
+use crate::macros::MacroError;
+use crate::rewrite::RewriteError;
+use crate::scaffold::SampleError;
+use crate::template::TemplateError;
+
+/// Any error the library reports, with `From` impls for the errors of the
+/// individual modules so `?` works across them.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to read grammar: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid grammar at {}:{}: {}", .0.location.line, .0.location.column, .0)]
+    Syntax(#[from] SyntaxError),
+    #[error("Invalid grammar: {0}")]
+    Definition(#[from] DefinitionParseError),
+    #[error(transparent)]
+    Binary(#[from] BinaryError),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error("Invalid grammar: {0}")]
+    Build(#[from] BuildError),
+    #[error(transparent)]
+    Merge(#[from] MergeError),
+    #[error("{0} at byte {start}", start = .1.start)]
+    Lex(LexingError, Span),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("Failed to read input: {0}")]
+    Input(std::io::Error),
+    #[error(
+        "Grammar has {} warning(s), which strict mode rejects:\n{}",
+        .0.len(),
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
+    )]
+    Strict(Vec<ValidationIssue>),
+    #[error(
+        "`Main` has to be a single repeated rule like `<items:Item>*` to parse input incrementally"
+    )]
+    NotStreamable,
+    #[error("Formatting the source would change its syntax tree")]
+    Unformattable,
+    #[error("Invalid template: {0}")]
+    Template(#[from] TemplateError),
+    #[error(transparent)]
+    Rewrite(#[from] RewriteError),
+    #[error(transparent)]
+    Macro(#[from] MacroError),
+    #[error(transparent)]
+    Sample(#[from] SampleError),
+}
+
+/// The error of [`crate::lexer::lex_spanned`].
+impl From<(LexingError, Span)> for Error {
+    fn from((error, span): (LexingError, Span)) -> Self {
+        Error::Lex(error, span)
+    }
+}
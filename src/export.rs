@@ -0,0 +1,15 @@
+mod antlr;
+mod ebnf;
+mod pest;
+
+pub use antlr::to_antlr;
+pub use ebnf::to_ebnf;
+pub use pest::to_pest;
+
+/// A grammar converted into another notation.
+#[derive(Debug, Clone)]
+pub struct Exported {
+    pub text: String,
+    /// Constructs that could not be translated exactly.
+    pub warnings: Vec<String>,
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, RepeatMode, TokenPattern,
+};
+use crate::export::Exported;
+
+/// Converts `definition` into an ANTLR 4 grammar called `name`.
+///
+/// ANTLR parser rules have to start with a lower case letter, so rule names
+/// are lower cased at the front. Named captures become ANTLR labels. Regexes
+/// have no parser rule equivalent and are replaced by the `.` wildcard.
+///
+/// ```
+/// let src = "Main:\n<items:Item> ** \",\"\n~~~\n\nItem:\n| let <name:ident> = <value:int>;\n| <word:s/[a-z]+/>\n~~~\n";
+/// let exported = tmpl::export::to_antlr(&tmpl::definition::parse(src)?, "Items");
+/// assert_eq!(exported.warnings.len(), 1);
+/// assert_eq!(
+///     exported.text,
+///     r#"grammar Items;
+///
+/// main
+///     : (items+=item (',' items+=item)*)? EOF
+///     ;
+///
+/// item
+///     : 'let' name=IDENT '=' value=INT ';'
+///     | word=.
+///     ;
+///
+/// IDENT : [a-zA-Z_] [a-zA-Z_0-9]* ;
+/// INT : [0-9]+ ;
+/// WS : [ \t\r\n]+ -> skip ;
+/// "#
+/// );
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub fn to_antlr(definition: &ParserDefinition, name: &str) -> Exported {
+    let mut exporter = Exporter::default();
+    for (rule, _) in definition.all_rules() {
+        let renamed = rule_name(rule);
+        if let Some(other) = exporter.renamed.values().find(|r| **r == renamed) {
+            exporter.warnings.push(format!(
+                "{rule}: name collides with another rule as `{other}`"
+            ));
+        }
+        exporter.renamed.insert(rule.to_string(), renamed);
+    }
+    let mut out = format!("grammar {name};\n\n");
+    for (rule, patterns) in definition.all_rules() {
+        exporter.rule = rule.to_string();
+        let body = exporter.rule(patterns);
+        let eof = if rule == "Main" { " EOF" } else { "" };
+        out.push_str(&format!(
+            "{}\n    : {body}{eof}\n    ;\n\n",
+            exporter.renamed[rule]
+        ));
+    }
+    for lexer_rule in &exporter.lexer_rules {
+        out.push_str(&format!(
+            "{lexer_rule} : {} ;\n",
+            lexer_rule_definition(lexer_rule)
+        ));
+    }
+    out.push_str("WS : [ \\t\\r\\n]+ -> skip ;\n");
+    Exported {
+        text: out,
+        warnings: exporter.warnings,
+    }
+}
+
+fn rule_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[derive(Default)]
+struct Exporter {
+    rule: String,
+    renamed: HashMap<String, String>,
+    lexer_rules: BTreeSet<&'static str>,
+    warnings: Vec<String>,
+}
+
+impl Exporter {
+    fn rule(&mut self, patterns: &[Pattern]) -> String {
+        patterns
+            .iter()
+            .flat_map(|p| p.alternatives())
+            .map(|a| self.sequence(a))
+            .collect::<Vec<_>>()
+            .join("\n    | ")
+    }
+
+    fn sequence(&mut self, sequence: &[TokenPattern]) -> String {
+        sequence
+            .iter()
+            .map(|t| self.token(t))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn token(&mut self, token: &TokenPattern) -> String {
+        let inner = match &token.pattern {
+            InternalPattern::Exact { pattern } if pattern.len() > 1 => {
+                format!("({})", self.sequence(pattern))
+            }
+            pattern => self.pattern(pattern),
+        };
+        let repeated = token.repeat_mode.is_some();
+        let labeled = match &token.pattern {
+            InternalPattern::Named {
+                name: Some(name),
+                kind,
+            } if !matches!(
+                kind,
+                InternalPatternKind::Bool
+                    | InternalPatternKind::OneOf(_)
+                    | InternalPatternKind::Balanced { .. }
+            ) =>
+            {
+                format!("{name}{}{inner}", if repeated { "+=" } else { "=" })
+            }
+            _ => inner.clone(),
+        };
+        match (&token.repeat_mode, &token.separator) {
+            (Some(RepeatMode::OneOrMore), Some(sep)) => {
+                format!("{labeled} ({} {labeled})*", literal(sep))
+            }
+            (Some(RepeatMode::ZeroOrMore), Some(sep)) => {
+                format!("({labeled} ({} {labeled})*)?", literal(sep))
+            }
+            (Some(RepeatMode::OneOrMore), None) => format!("{labeled}+"),
+            (Some(RepeatMode::ZeroOrMore), None) => format!("{labeled}*"),
+            (None, _) if token.is_optional => format!("{labeled}?"),
+            (None, _) => labeled,
+        }
+    }
+
+    fn pattern(&mut self, pattern: &InternalPattern) -> String {
+        let lexer_rule = match pattern {
+            InternalPattern::Named { kind, .. } => match kind {
+                InternalPatternKind::Ident => "IDENT",
+                InternalPatternKind::Int => "INT",
+                InternalPatternKind::Float => "FLOAT",
+                InternalPatternKind::String => "STRING",
+                InternalPatternKind::Bool => return "('true' | 'false')".to_string(),
+                InternalPatternKind::Regex(re) => {
+                    self.warnings.push(format!(
+                        "{}: regex /{re}/ can't be expressed in a parser rule, replaced by `.`",
+                        self.rule
+                    ));
+                    return ".".to_string();
+                }
+                InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                    return literal(text)
+                }
+                InternalPatternKind::OneOf(options) => {
+                    let options: Vec<_> = options.iter().map(|o| literal(o)).collect();
+                    return format!("({})", options.join(" | "));
+                }
+                InternalPatternKind::Balanced { open, close } => {
+                    self.warnings.push(format!(
+                        "{}: {kind} can't be expressed in a parser rule, nested pairs aren't matched",
+                        self.rule
+                    ));
+                    return format!("({} .*? {})", literal(open), literal(close));
+                }
+                InternalPatternKind::Custom(name) => {
+                    return self.renamed.get(name).cloned().unwrap_or_else(|| {
+                        self.warnings
+                            .push(format!("{}: reference to unknown rule `{name}`", self.rule));
+                        rule_name(name)
+                    })
+                }
+            },
+            InternalPattern::Raw { value } => return literal(value),
+            InternalPattern::Exact { pattern } => return self.sequence(pattern),
+        };
+        self.lexer_rules.insert(lexer_rule);
+        lexer_rule.to_string()
+    }
+}
+
+fn literal(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn lexer_rule_definition(lexer_rule: &str) -> &'static str {
+    match lexer_rule {
+        "IDENT" => "[a-zA-Z_] [a-zA-Z_0-9]*",
+        "INT" => "[0-9]+",
+        "FLOAT" => "[0-9]+ '.' [0-9]*",
+        _ => "'\"' ( ~[\"\\\\] | '\\\\' . )* '\"'",
+    }
+}
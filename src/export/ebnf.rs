@@ -0,0 +1,140 @@
+use std::collections::BTreeSet;
+
+use stringlit::s;
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, RepeatMode, TokenPattern,
+};
+use crate::export::Exported;
+
+/// Converts `definition` into W3C style EBNF.
+///
+/// Built-in token kinds are referenced as upper case terminals which are
+/// defined at the end of the output. Regexes have no EBNF equivalent and are
+/// emitted as a `TOKEN` terminal with the regex in a comment.
+pub fn to_ebnf(definition: &ParserDefinition) -> Exported {
+    let mut out = String::new();
+    for define in &definition.defines {
+        out.push_str(&format!("/* {define} */\n"));
+    }
+    if !definition.defines.is_empty() {
+        out.push('\n');
+    }
+    let mut terminals = BTreeSet::new();
+    for (name, patterns) in definition.all_rules() {
+        out.push_str(&format!("{name} ::= {}\n", rule(patterns, &mut terminals)));
+    }
+    if !terminals.is_empty() {
+        out.push('\n');
+    }
+    let mut warnings = Vec::new();
+    for terminal in terminals {
+        out.push_str(&format!(
+            "{terminal} ::= {}\n",
+            terminal_definition(terminal)
+        ));
+        if terminal == "TOKEN" {
+            warnings.push(s!(
+                "regexes can't be expressed in EBNF and are exported as TOKEN"
+            ));
+        }
+    }
+    Exported {
+        text: out,
+        warnings,
+    }
+}
+
+fn rule(patterns: &[Pattern], terminals: &mut BTreeSet<&'static str>) -> String {
+    patterns
+        .iter()
+        .flat_map(|p| p.alternatives())
+        .map(|a| sequence(a, terminals))
+        .collect::<Vec<_>>()
+        .join("\n    | ")
+}
+
+fn sequence(sequence: &[TokenPattern], terminals: &mut BTreeSet<&'static str>) -> String {
+    sequence
+        .iter()
+        .map(|t| token(t, terminals))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn token(token: &TokenPattern, terminals: &mut BTreeSet<&'static str>) -> String {
+    let inner = pattern(&token.pattern, terminals);
+    let grouped = match &token.pattern {
+        InternalPattern::Exact { pattern } if pattern.len() > 1 => format!("( {inner} )"),
+        _ => inner.clone(),
+    };
+    match (&token.repeat_mode, &token.separator) {
+        (Some(RepeatMode::OneOrMore), Some(sep)) => {
+            format!("{inner} ( {} {inner} )*", quote(sep))
+        }
+        (Some(RepeatMode::ZeroOrMore), Some(sep)) => {
+            format!("( {inner} ( {} {inner} )* )?", quote(sep))
+        }
+        (Some(RepeatMode::OneOrMore), None) => format!("{grouped}+"),
+        (Some(RepeatMode::ZeroOrMore), None) => format!("{grouped}*"),
+        (None, _) if token.is_optional => format!("{grouped}?"),
+        (None, _) => inner,
+    }
+}
+
+fn pattern(pattern: &InternalPattern, terminals: &mut BTreeSet<&'static str>) -> String {
+    match pattern {
+        InternalPattern::Named { kind, .. } => {
+            let terminal = match kind {
+                InternalPatternKind::Ident => "IDENT",
+                InternalPatternKind::Int => "INT",
+                InternalPatternKind::Float => "FLOAT",
+                InternalPatternKind::String => "STRING",
+                InternalPatternKind::Bool => "BOOL",
+                InternalPatternKind::Regex(re) => {
+                    terminals.insert("TOKEN");
+                    return format!("TOKEN /* {} */", re.as_str().replace("*/", "*\\/"));
+                }
+                InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                    return quote(text)
+                }
+                InternalPatternKind::OneOf(options) => {
+                    let options: Vec<_> = options.iter().map(|o| quote(o)).collect();
+                    return format!("({})", options.join(" | "));
+                }
+                InternalPatternKind::Balanced { open, close } => {
+                    terminals.insert("TOKEN");
+                    return format!(
+                        "( {} TOKEN* {} /* with balanced pairs in between */ )",
+                        quote(open),
+                        quote(close)
+                    );
+                }
+                InternalPatternKind::Custom(name) => return name.clone(),
+            };
+            terminals.insert(terminal);
+            terminal.to_string()
+        }
+        InternalPattern::Raw { value } => quote(value),
+        InternalPattern::Exact { pattern } => sequence(pattern, terminals),
+    }
+}
+
+fn quote(text: &str) -> String {
+    if text.contains('\'') {
+        format!("\"{text}\"")
+    } else {
+        format!("'{text}'")
+    }
+}
+
+fn terminal_definition(terminal: &str) -> &'static str {
+    match terminal {
+        "IDENT" => "[a-zA-Z_] [a-zA-Z_0-9]*",
+        "INT" => "[0-9]+",
+        "FLOAT" => "[0-9]+ '.' [0-9]*",
+        "STRING" => "'\"' ( [^\"\\] | '\\' . )* '\"'",
+        "BOOL" => "'true' | 'false'",
+        _ => "/* any single token matching the regex given at its use */ [^#x20#x9#xA#xD]+",
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeSet;
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, RepeatMode, TokenPattern,
+};
+use crate::export::Exported;
+
+/// Converts `definition` into a pest grammar.
+///
+/// Keywords get their own atomic rules so they don't match the start of a
+/// longer identifier. Regexes have no pest equivalent and are replaced by `ANY`.
+///
+/// ```
+/// let src = "Main:\n<items:Item> ** \",\"\n~~~\n\nItem:\n| let <name:ident> = <value:int>;\n| <flag:bool>?\n~~~\n";
+/// let exported = tmpl::export::to_pest(&tmpl::definition::parse(src)?);
+/// assert!(exported.warnings.is_empty());
+/// assert_eq!(
+///     exported.text,
+///     r#"WHITESPACE = _{ " " | "\t" | "\r" | "\n" }
+///
+/// Main = { SOI ~ ((Item ~ ("," ~ Item)*)?) ~ EOI }
+/// Item = { KW_let ~ IDENT ~ "=" ~ INT ~ ";" | BOOL? }
+///
+/// BOOL = @{ "true" | "false" }
+/// IDENT = @{ (ASCII_ALPHA | "_") ~ (ASCII_ALPHANUMERIC | "_")* }
+/// INT = @{ ASCII_DIGIT+ }
+/// KW_let = @{ "let" ~ !(ASCII_ALPHANUMERIC | "_") }
+/// "#
+/// );
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub fn to_pest(definition: &ParserDefinition) -> Exported {
+    let mut exporter = Exporter::default();
+    let mut out = String::from("WHITESPACE = _{ \" \" | \"\\t\" | \"\\r\" | \"\\n\" }\n\n");
+    for (name, patterns) in definition.all_rules() {
+        exporter.rule = name.to_string();
+        let body = exporter.rule(patterns);
+        if name == "Main" {
+            out.push_str(&format!("Main = {{ SOI ~ ({body}) ~ EOI }}\n"));
+        } else {
+            out.push_str(&format!("{name} = {{ {body} }}\n"));
+        }
+    }
+    if !exporter.builtins.is_empty() || !exporter.keywords.is_empty() {
+        out.push('\n');
+    }
+    for builtin in &exporter.builtins {
+        out.push_str(&format!(
+            "{builtin} = @{{ {} }}\n",
+            builtin_definition(builtin)
+        ));
+    }
+    for keyword in &exporter.keywords {
+        out.push_str(&format!(
+            "KW_{keyword} = @{{ \"{keyword}\" ~ !(ASCII_ALPHANUMERIC | \"_\") }}\n"
+        ));
+    }
+    Exported {
+        text: out,
+        warnings: exporter.warnings,
+    }
+}
+
+#[derive(Default)]
+struct Exporter {
+    rule: String,
+    builtins: BTreeSet<&'static str>,
+    keywords: BTreeSet<String>,
+    warnings: Vec<String>,
+}
+
+impl Exporter {
+    fn rule(&mut self, patterns: &[Pattern]) -> String {
+        patterns
+            .iter()
+            .flat_map(|p| p.alternatives())
+            .map(|a| self.sequence(a))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    fn sequence(&mut self, sequence: &[TokenPattern]) -> String {
+        sequence
+            .iter()
+            .map(|t| self.token(t))
+            .collect::<Vec<_>>()
+            .join(" ~ ")
+    }
+
+    fn token(&mut self, token: &TokenPattern) -> String {
+        let inner = match &token.pattern {
+            InternalPattern::Exact { pattern } if pattern.len() > 1 => {
+                format!("({})", self.sequence(pattern))
+            }
+            pattern => self.pattern(pattern),
+        };
+        match (&token.repeat_mode, &token.separator) {
+            (Some(RepeatMode::OneOrMore), Some(sep)) => {
+                format!("{inner} ~ ({} ~ {inner})*", self.literal(sep))
+            }
+            (Some(RepeatMode::ZeroOrMore), Some(sep)) => {
+                format!("({inner} ~ ({} ~ {inner})*)?", self.literal(sep))
+            }
+            (Some(RepeatMode::OneOrMore), None) => format!("{inner}+"),
+            (Some(RepeatMode::ZeroOrMore), None) => format!("{inner}*"),
+            (None, _) if token.is_optional => format!("{inner}?"),
+            (None, _) => inner,
+        }
+    }
+
+    fn pattern(&mut self, pattern: &InternalPattern) -> String {
+        let builtin = match pattern {
+            InternalPattern::Named { kind, .. } => match kind {
+                InternalPatternKind::Ident => "IDENT",
+                InternalPatternKind::Int => "INT",
+                InternalPatternKind::Float => "FLOAT",
+                InternalPatternKind::String => "STRING",
+                InternalPatternKind::Bool => "BOOL",
+                InternalPatternKind::Regex(re) => {
+                    self.warnings.push(format!(
+                        "{}: regex /{re}/ can't be expressed in pest, replaced by ANY",
+                        self.rule
+                    ));
+                    "ANY"
+                }
+                InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                    return self.literal(text)
+                }
+                InternalPatternKind::OneOf(options) => {
+                    let options: Vec<_> = options.iter().map(|o| self.literal(o)).collect();
+                    return format!("({})", options.join(" | "));
+                }
+                InternalPatternKind::Balanced { open, close } => {
+                    self.warnings.push(format!(
+                        "{}: {kind} can't be expressed in pest, nested pairs aren't matched",
+                        self.rule
+                    ));
+                    let (open, close) = (self.literal(open), self.literal(close));
+                    return format!("({open} ~ (!{close} ~ ANY)* ~ {close})");
+                }
+                InternalPatternKind::Custom(name) => return name.clone(),
+            },
+            InternalPattern::Raw { value } => return self.literal(value),
+            InternalPattern::Exact { pattern } => return self.sequence(pattern),
+        };
+        if builtin != "ANY" {
+            self.builtins.insert(builtin);
+        }
+        builtin.to_string()
+    }
+
+    fn literal(&mut self, text: &str) -> String {
+        if text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            self.keywords.insert(text.to_string());
+            format!("KW_{text}")
+        } else {
+            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+    }
+}
+
+fn builtin_definition(builtin: &str) -> &'static str {
+    match builtin {
+        "IDENT" => "(ASCII_ALPHA | \"_\") ~ (ASCII_ALPHANUMERIC | \"_\")*",
+        "INT" => "ASCII_DIGIT+",
+        "FLOAT" => "ASCII_DIGIT+ ~ \".\" ~ ASCII_DIGIT*",
+        "STRING" => "\"\\\"\" ~ (\"\\\\\" ~ ANY | !(\"\\\"\" | \"\\\\\") ~ ANY)* ~ \"\\\"\"",
+        _ => "\"true\" | \"false\"",
+    }
+}
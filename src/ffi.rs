@@ -0,0 +1,152 @@
+//! C bindings, enabled with the `ffi` feature and linked into a library by
+//! `tmpl-bindings`. `include/tmpl.h` declares them.
+//!
+//! Every object returned by these functions is owned by the caller and has to
+//! be released with the matching `tmpl_*_free` function. On failure functions
+//! return null and, if `error` isn't null, store a message in `*error` that
+//! has to be released with `tmpl_string_free`.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+use crate::custom::Ast;
+use crate::Grammar;
+
+/// Opaque grammar handle.
+pub struct TmplGrammar(Grammar);
+
+/// Opaque syntax tree handle.
+pub struct TmplAst(Ast);
+
+/// Stores `message` in `*error` if the caller asked for it.
+unsafe fn set_error(error: *mut *mut c_char, message: impl ToString) {
+    if !error.is_null() {
+        *error = into_c_string(message.to_string());
+    }
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Interior NULs can't cross the boundary, so they are dropped.
+    CString::new(s.replace('\0', ""))
+        .expect("NULs were removed")
+        .into_raw()
+}
+
+/// Reads a NUL terminated UTF-8 string argument.
+unsafe fn read_str<'a>(s: *const c_char, error: *mut *mut c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_error(error, "unexpected null pointer");
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(e) => {
+            set_error(error, e);
+            None
+        }
+    }
+}
+
+/// Parses grammar text.
+///
+/// # Safety
+///
+/// `src` must be a valid NUL terminated string and `error` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_grammar_compile(
+    src: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut TmplGrammar {
+    let Some(src) = read_str(src, error) else {
+        return ptr::null_mut();
+    };
+    match Grammar::parse(src) {
+        Ok(grammar) => Box::into_raw(Box::new(TmplGrammar(grammar))),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Parses `input` with `grammar`, starting at `entry` or at `Main` if `entry` is null.
+///
+/// # Safety
+///
+/// `grammar` must come from `tmpl_grammar_compile`, `input` and `entry` (unless
+/// null) must be valid NUL terminated strings and `error` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_parse(
+    grammar: *const TmplGrammar,
+    input: *const c_char,
+    entry: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut TmplAst {
+    if grammar.is_null() {
+        set_error(error, "unexpected null pointer");
+        return ptr::null_mut();
+    }
+    let Some(input) = read_str(input, error) else {
+        return ptr::null_mut();
+    };
+    let entry = match entry.is_null() {
+        true => "Main",
+        false => match read_str(entry, error) {
+            Some(entry) => entry,
+            None => return ptr::null_mut(),
+        },
+    };
+    match (*grammar).0.parse_entry(entry, input) {
+        Ok(ast) => Box::into_raw(Box::new(TmplAst(ast))),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Serializes a syntax tree as JSON, in the format `tmpl parse --format json` prints.
+///
+/// # Safety
+///
+/// `ast` must come from `tmpl_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_ast_to_json(ast: *const TmplAst) -> *mut c_char {
+    if ast.is_null() {
+        return ptr::null_mut();
+    }
+    match serde_json::to_string(&(*ast).0) {
+        Ok(json) => into_c_string(json),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `grammar` must be null or come from `tmpl_grammar_compile` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_grammar_free(grammar: *mut TmplGrammar) {
+    if !grammar.is_null() {
+        drop(Box::from_raw(grammar));
+    }
+}
+
+/// # Safety
+///
+/// `ast` must be null or come from `tmpl_parse` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_ast_free(ast: *mut TmplAst) {
+    if !ast.is_null() {
+        drop(Box::from_raw(ast));
+    }
+}
+
+/// # Safety
+///
+/// `s` must be null or a string returned by this library and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tmpl_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,111 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::str::Utf8Error;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::custom::{Ast, Parser};
+use crate::definition::ParserDefinition;
+
+/// Everything [`run`] can end with besides a syntax tree. Only
+/// [`FuzzError::is_bug`] errors are problems of tmpl, the others are inputs
+/// it rightly rejects.
+#[derive(Debug, Error)]
+pub enum FuzzError {
+    #[error("Grammar is not UTF-8: {0}")]
+    GrammarEncoding(Utf8Error),
+    #[error("Input is not UTF-8: {0}")]
+    InputEncoding(Utf8Error),
+    #[error("{0}")]
+    Grammar(crate::Error),
+    #[error("{0}")]
+    Input(crate::Error),
+    /// An error that points outside of the input.
+    #[error("{0}")]
+    Invariant(String),
+    #[error("panic: {0}")]
+    Panic(String),
+}
+
+impl FuzzError {
+    /// Whether the error is a bug of tmpl that a fuzz target should report.
+    pub fn is_bug(&self) -> bool {
+        matches!(self, FuzzError::Invariant(_) | FuzzError::Panic(_))
+    }
+}
+
+/// Parses the grammar `grammar` and lexes and parses `input` with it,
+/// starting at `Main`. Any bytes are fine: invalid ones end in an error, and
+/// so do panics, so the result of a fuzz target is a matter of
+/// [`FuzzError::is_bug`]:
+///
+/// ```
+/// use tmpl::fuzz::run;
+///
+/// // fuzz_target!(|data: (&[u8], &[u8])| { ... });
+/// let target = |(grammar, input): (&[u8], &[u8])| {
+///     if let Err(error) = run(grammar, input) {
+///         assert!(!error.is_bug(), "{error}");
+///     }
+/// };
+/// target((b"Main:\n<n:int>\n~~~\n", b"1"));
+/// target((b"Main:\n<n:int>\n~~~\n", b"\xff"));
+/// target((b"Main:\n<n:", b"1"));
+/// ```
+pub fn run(grammar: &[u8], input: &[u8]) -> Result<Ast, FuzzError> {
+    let definition = run_definition(grammar)?;
+    let input = std::str::from_utf8(input).map_err(FuzzError::InputEncoding)?;
+    check_input(&Arc::new(definition), input)
+}
+
+/// Parses the grammar `grammar` like [`run`], to fuzz the definition parser
+/// on its own.
+pub fn run_definition(grammar: &[u8]) -> Result<ParserDefinition, FuzzError> {
+    let grammar = std::str::from_utf8(grammar).map_err(FuzzError::GrammarEncoding)?;
+    catch(|| crate::definition::parse(grammar).map_err(FuzzError::Grammar))
+}
+
+/// Lexes and parses `input` with `definition` like [`run`], checking that
+/// errors point into the input.
+pub fn check_input(definition: &Arc<ParserDefinition>, input: &str) -> Result<Ast, FuzzError> {
+    catch(|| {
+        let tokens = match crate::lexer::lex_spanned(input) {
+            Ok(tokens) => tokens,
+            Err((e, span)) => {
+                let valid = span.start <= span.end
+                    && span.end <= input.len()
+                    && input.is_char_boundary(span.start)
+                    && input.is_char_boundary(span.end);
+                return Err(match valid {
+                    true => FuzzError::Input((e, span).into()),
+                    false => {
+                        FuzzError::Invariant(format!("lexer error `{e}` has invalid span {span:?}"))
+                    }
+                });
+            }
+        };
+        let count = tokens.len();
+        let parser = Parser::new(
+            Arc::clone(definition),
+            tokens.into_iter().map(|(token, _)| token).collect(),
+        );
+        parser.parse().map_err(|e| match e.index() {
+            Some(index) if index >= count => FuzzError::Invariant(format!(
+                "parse error `{e}` points past the last of {count} tokens"
+            )),
+            _ => FuzzError::Input(e.into()),
+        })
+    })
+}
+
+/// Runs `f`, turning a panic into a [`FuzzError::Panic`].
+fn catch<T>(f: impl FnOnce() -> Result<T, FuzzError>) -> Result<T, FuzzError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        Err(FuzzError::Panic(message))
+    })
+}
@@ -0,0 +1,151 @@
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tmpl::definition::ParserDefinition;
+use tmpl::highlight::Vocabulary;
+
+/// Characters the lexer turns into symbol tokens.
+const SYMBOLS: &str = "-+*/=>\\.:,;<>!$%&?@|()[]{}";
+
+/// Feeds mutated versions of `samples` (or random token soup without samples)
+/// to the lexer and parser, reporting panics and errors pointing outside the
+/// input. Every distinct problem is printed with a minimized reproducer.
+pub fn run(
+    definition: &Arc<ParserDefinition>,
+    samples: &[PathBuf],
+    iterations: u32,
+    seed: Option<u64>,
+) -> anyhow::Result<()> {
+    let seed = seed.unwrap_or_else(|| fastrand::u64(..));
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let samples = samples
+        .iter()
+        .map(|path| crate::read_input(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let vocabulary = Vocabulary::of(definition);
+    let fragments: Vec<String> = vocabulary
+        .keywords
+        .iter()
+        .chain(&vocabulary.operators)
+        .chain(&vocabulary.punctuation)
+        .cloned()
+        .collect();
+
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let mut problems: BTreeMap<String, String> = BTreeMap::new();
+    for _ in 0..iterations {
+        let input = if samples.is_empty() {
+            soup(&mut rng, &fragments)
+        } else {
+            let sample = &samples[rng.usize(..samples.len())];
+            mutate(&mut rng, sample, &fragments)
+        };
+        if let Some(problem) = check(definition, &input) {
+            if let Entry::Vacant(entry) = problems.entry(problem) {
+                let reproducer = minimize(definition, &input, entry.key());
+                entry.insert(reproducer);
+            }
+        }
+    }
+    panic::set_hook(hook);
+
+    for (problem, reproducer) in &problems {
+        println!("{problem}\n    reproducer: {reproducer:?}");
+    }
+    println!(
+        "{iterations} inputs, {} problem(s) found (seed {seed})",
+        problems.len()
+    );
+    if !problems.is_empty() {
+        anyhow::bail!("fuzzing found {} problem(s)", problems.len());
+    }
+    Ok(())
+}
+
+/// Describes what went wrong while lexing and parsing `input`, if it's a
+/// bug rather than an input the grammar rejects.
+fn check(definition: &Arc<ParserDefinition>, input: &str) -> Option<String> {
+    match tmpl::fuzz::check_input(definition, input) {
+        Err(e) if e.is_bug() => Some(e.to_string()),
+        _ => None,
+    }
+}
+
+/// Removes ever smaller chunks of `input` as long as the same problem remains.
+fn minimize(definition: &Arc<ParserDefinition>, input: &str, problem: &str) -> String {
+    let mut chars: Vec<char> = input.chars().collect();
+    let mut chunk = chars.len().div_ceil(2).max(1);
+    loop {
+        let mut start = 0;
+        while start < chars.len() {
+            let end = (start + chunk).min(chars.len());
+            let candidate: String = chars[..start].iter().chain(&chars[end..]).collect();
+            if check(definition, &candidate).as_deref() == Some(problem) {
+                chars.drain(start..end);
+            } else {
+                start += chunk;
+            }
+        }
+        if chunk == 1 {
+            break;
+        }
+        chunk = chunk.div_ceil(2);
+    }
+    chars.into_iter().collect()
+}
+
+fn mutate(rng: &mut fastrand::Rng, sample: &str, fragments: &[String]) -> String {
+    let mut chars: Vec<char> = sample.chars().collect();
+    for _ in 0..rng.usize(1..=4) {
+        let start = rng.usize(..=chars.len());
+        let end = (start + rng.usize(1..8)).min(chars.len());
+        match rng.u8(..4) {
+            0 => {
+                chars.drain(start..end);
+            }
+            1 => {
+                let copy: Vec<char> = chars[start..end].to_vec();
+                chars.splice(start..start, copy);
+            }
+            2 => {
+                chars.splice(start..start, fragment(rng, fragments).chars());
+            }
+            _ => {
+                chars.splice(start..end, fragment(rng, fragments).chars());
+            }
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// A random sequence of tokens, separated by whitespace most of the time.
+fn soup(rng: &mut fastrand::Rng, fragments: &[String]) -> String {
+    let mut out = String::new();
+    for _ in 0..rng.usize(0..32) {
+        out.push_str(&fragment(rng, fragments));
+        if rng.bool() {
+            out.push(' ');
+        }
+    }
+    out
+}
+
+fn fragment(rng: &mut fastrand::Rng, fragments: &[String]) -> String {
+    match rng.u8(..8) {
+        0 | 1 if !fragments.is_empty() => fragments[rng.usize(..fragments.len())].clone(),
+        2 => format!("x{}", rng.u32(..100)),
+        3 => rng.i64(..).to_string(),
+        4 => format!("{}.{}", rng.u16(..), rng.u8(..)),
+        5 => format!("\"{}\"", rng.alphanumeric()),
+        6 => SYMBOLS
+            .chars()
+            .nth(rng.usize(..SYMBOLS.len()))
+            .unwrap_or('+')
+            .to_string(),
+        _ => rng.char(..).to_string(),
+    }
+}
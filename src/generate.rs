@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, RepeatMode, TokenPattern,
+};
+use crate::Error;
+
+/// How often a regex pattern is tried against random candidates.
+const REGEX_ATTEMPTS: usize = 1000;
+/// Upper bound for items of a repetition when the depth allows it.
+const MAX_REPEAT: usize = 3;
+
+#[derive(Error, Debug)]
+pub enum GenerateError {
+    #[error("Unknown rule: {0}")]
+    UnknownRule(String),
+    #[error("No token matching /{0}/ found")]
+    Regex(String),
+}
+
+/// Produces random inputs matching a grammar.
+///
+/// Below `max_depth` alternatives and repetitions are picked at random. Past
+/// it, optional parts are left out and every rule takes the alternative that
+/// terminates the quickest, so generation always ends.
+pub struct Generator<'a> {
+    definition: &'a ParserDefinition,
+    rng: fastrand::Rng,
+    max_depth: usize,
+    /// Smallest nesting depth each rule can be finished in.
+    min_depth: HashMap<&'a str, usize>,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(definition: &'a ParserDefinition, max_depth: usize) -> Self {
+        Self {
+            definition,
+            rng: fastrand::Rng::new(),
+            max_depth,
+            min_depth: min_depths(definition),
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = fastrand::Rng::with_seed(seed);
+        self
+    }
+
+    /// Generates an input for the whole grammar.
+    pub fn generate(&mut self) -> Result<String, GenerateError> {
+        self.generate_rule("Main")
+    }
+
+    /// Generates an input matching the rule `name`.
+    pub fn generate_rule(&mut self, name: &str) -> Result<String, GenerateError> {
+        let mut pieces = Vec::new();
+        self.rule(name, 0, &mut pieces)?;
+        Ok(pieces.join(" "))
+    }
+
+    fn rule(
+        &mut self,
+        name: &str,
+        depth: usize,
+        out: &mut Vec<String>,
+    ) -> Result<(), GenerateError> {
+        let definition = self.definition;
+        let patterns = definition
+            .rule(name)
+            .ok_or_else(|| GenerateError::UnknownRule(name.to_string()))?;
+        let alternatives: Vec<&[TokenPattern]> = patterns
+            .iter()
+            .flat_map(Pattern::alternatives)
+            .map(|alternative| &alternative[..])
+            .collect();
+        let alternative = if depth < self.max_depth {
+            alternatives[self.rng.usize(..alternatives.len())]
+        } else {
+            alternatives
+                .iter()
+                .min_by_key(|a| sequence_depth(a, &self.min_depth))
+                .copied()
+                .unwrap_or_default()
+        };
+        self.sequence(alternative, depth + 1, out)
+    }
+
+    fn sequence(
+        &mut self,
+        sequence: &[TokenPattern],
+        depth: usize,
+        out: &mut Vec<String>,
+    ) -> Result<(), GenerateError> {
+        let deep = depth >= self.max_depth;
+        for token in sequence {
+            let count = match &token.repeat_mode {
+                Some(RepeatMode::OneOrMore) if deep => 1,
+                Some(RepeatMode::ZeroOrMore) if deep => 0,
+                Some(RepeatMode::OneOrMore) => self.rng.usize(1..=MAX_REPEAT),
+                Some(RepeatMode::ZeroOrMore) => self.rng.usize(0..=MAX_REPEAT),
+                None if token.is_optional && (deep || self.rng.bool()) => 0,
+                None => 1,
+            };
+            for i in 0..count {
+                if let (Some(separator), true) = (&token.separator, i > 0) {
+                    out.push(separator.clone());
+                }
+                self.pattern(&token.pattern, depth, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pattern(
+        &mut self,
+        pattern: &InternalPattern,
+        depth: usize,
+        out: &mut Vec<String>,
+    ) -> Result<(), GenerateError> {
+        let piece = match pattern {
+            InternalPattern::Named { kind, .. } => match kind {
+                InternalPatternKind::Ident => self.ident(),
+                InternalPatternKind::Int => self.rng.u16(..1000).to_string(),
+                InternalPatternKind::Float => {
+                    format!("{}.{}", self.rng.u16(..1000), self.rng.u8(..100))
+                }
+                InternalPatternKind::String => self.string(),
+                InternalPatternKind::Bool => self.rng.bool().to_string(),
+                InternalPatternKind::Regex(regex) => {
+                    let candidate = (0..REGEX_ATTEMPTS)
+                        .map(|_| self.candidate())
+                        .find(|c| regex.matches_whole(c));
+                    candidate.ok_or_else(|| GenerateError::Regex(regex.to_string()))?
+                }
+                InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                    text.clone()
+                }
+                InternalPatternKind::OneOf(options) => match options.len() {
+                    0 => String::new(),
+                    len => options[self.rng.usize(..len)].clone(),
+                },
+                InternalPatternKind::Balanced { open, close } => format!("{open} {close}"),
+                InternalPatternKind::Custom(name) => return self.rule(name, depth, out),
+            },
+            InternalPattern::Raw { value } => value.clone(),
+            InternalPattern::Exact { pattern } => return self.sequence(pattern, depth, out),
+        };
+        out.push(piece);
+        Ok(())
+    }
+
+    fn ident(&mut self) -> String {
+        let len = self.rng.usize(1..8);
+        let ident: String = std::iter::once(self.rng.lowercase())
+            .chain((1..len).map(|_| self.rng.alphanumeric()))
+            .collect();
+        match ident.as_str() {
+            "true" | "false" => format!("{ident}_"),
+            _ => ident,
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.rng.usize(0..8);
+        let content: String = (0..len).map(|_| self.rng.alphanumeric()).collect();
+        format!("\"{content}\"")
+    }
+
+    /// A random token text, to be checked against a regex.
+    fn candidate(&mut self) -> String {
+        match self.rng.u8(..5) {
+            0 => self.rng.u32(..).to_string(),
+            1 => self.string(),
+            2 => format!("{}.{}", self.rng.u16(..), self.rng.u8(..)),
+            3 => "-+*/=>\\.:,;<>!$%&?@|()[]{}"
+                .chars()
+                .nth(self.rng.usize(..26))
+                .unwrap_or('+')
+                .to_string(),
+            _ => self.ident(),
+        }
+    }
+}
+
+/// Generating inputs from the bytes of [`arbitrary::Unstructured`], for
+/// fuzzers and property tests built on `arbitrary`.
+#[cfg(feature = "arbitrary")]
+impl Generator<'_> {
+    /// Generates an input for the whole grammar with the choices seeded
+    /// from `u`, so the same bytes give the same input.
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse(concat!(
+    ///     "Main:\n<stmts:Stmt>*\n~~~\n\n",
+    ///     "Stmt:\n<name:ident> = <value:int> ;\n~~~\n",
+    /// ))?;
+    /// let definition = grammar.shared_definition();
+    /// let mut generator = tmpl::generate::Generator::new(&definition, 4);
+    /// let mut u = arbitrary::Unstructured::new(&[7; 32]);
+    /// let input = generator.arbitrary(&mut u).expect("enough bytes");
+    /// tmpl::generate::check_round_trip(&definition, &input)?;
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn arbitrary(&mut self, u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<String> {
+        self.rng = fastrand::Rng::with_seed(u.arbitrary()?);
+        self.generate()
+            .map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+/// Checks that `input` parses from `Main` and survives being reprinted:
+/// [`crate::source_format::format_source`] has to give text that parses to
+/// the same tree and that formats to itself again. Meant for generated
+/// inputs in property tests, as every grammar should hold up to it.
+pub fn check_round_trip(definition: &Arc<ParserDefinition>, input: &str) -> Result<(), Error> {
+    let formatted = crate::source_format::format_source(definition, input, "Main")?;
+    match crate::source_format::format_source(definition, &formatted, "Main")? == formatted {
+        true => Ok(()),
+        false => Err(Error::Unformattable),
+    }
+}
+
+/// Computes how deep each rule has to nest at least before it can finish.
+/// Rules that can never finish are left out.
+fn min_depths(definition: &ParserDefinition) -> HashMap<&str, usize> {
+    let rules = definition.all_rules();
+    let mut depths = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (name, patterns) in &rules {
+            let best = patterns
+                .iter()
+                .flat_map(Pattern::alternatives)
+                .map(|a| sequence_depth(a, &depths))
+                .min()
+                .unwrap_or(usize::MAX);
+            if best != usize::MAX && depths.get(name).is_none_or(|d| best < *d) {
+                depths.insert(*name, best);
+                changed = true;
+            }
+        }
+        if !changed {
+            return depths;
+        }
+    }
+}
+
+/// Depth needed to finish `sequence` when leaving out everything optional.
+fn sequence_depth(sequence: &[TokenPattern], depths: &HashMap<&str, usize>) -> usize {
+    let mut deepest = 1;
+    for token in sequence {
+        if token.is_optional || matches!(token.repeat_mode, Some(RepeatMode::ZeroOrMore)) {
+            continue;
+        }
+        let depth = match &token.pattern {
+            InternalPattern::Named {
+                kind: InternalPatternKind::Custom(name),
+                ..
+            } => match depths.get(name.as_str()) {
+                Some(depth) => depth.saturating_add(1),
+                None => return usize::MAX,
+            },
+            InternalPattern::Exact { pattern } => sequence_depth(pattern, depths),
+            _ => 1,
+        };
+        deepest = deepest.max(depth);
+    }
+    deepest
+}
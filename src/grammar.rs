@@ -0,0 +1,557 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::custom::{Ast, Node, ParseError, ParseSession, Parser, Program};
+use crate::definition::{
+    DefinitionParseError, InternalPattern, InternalPatternKind, ParserDefinition, PatternVisitor,
+    Regex, RepeatMode, Severity,
+};
+use crate::lexer::{LexingError, Span};
+use crate::macros::{MacroError, Macros};
+use crate::rewrite::{RewriteError, Rewrites};
+use crate::style::OutputStyle;
+use crate::template::{Template, TemplateError};
+use crate::Error;
+
+/// Bytes read from a reader at a time by [`Grammar::parse_reader`].
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How [`Grammar::parse_with`] and [`Grammar::from_file_with`] load a grammar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GrammarOptions {
+    /// Rejects grammars with any of the warnings [`ParserDefinition::validate`]
+    /// reports, like unused rules or alternatives that are never tried, e.g.
+    /// to keep them out of a repository in CI.
+    pub strict: bool,
+    /// Rejects grammars with a regex whose compiled form takes more than
+    /// this many bytes, see [`Regex::memory_usage`], e.g. for grammars from
+    /// untrusted sources. Regexes are checked after they were compiled,
+    /// which the regex engine's own limits bound.
+    pub max_regex_size: Option<usize>,
+    /// Compiles the regexes of grammar text on first use instead of while
+    /// loading it, see [`crate::definition::parse_lazy`]. Strict loading
+    /// validates the whole grammar and stays eager.
+    pub lazy: bool,
+}
+
+impl GrammarOptions {
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// ```
+    /// use tmpl::{Grammar, GrammarOptions};
+    ///
+    /// let src = "Main:\n<word:s/\\w{1,100}/>\n~~~\n";
+    /// let options = GrammarOptions::default().max_regex_size(Some(64 * 1024));
+    /// assert!(Grammar::parse_with(src, options).is_err());
+    /// ```
+    pub fn max_regex_size(mut self, max_regex_size: Option<usize>) -> Self {
+        self.max_regex_size = max_regex_size;
+        self
+    }
+
+    /// ```
+    /// use tmpl::{Grammar, GrammarOptions};
+    ///
+    /// let src = "Main:\n<word:s/[a-z]+/>\n~~~\n";
+    /// let grammar = Grammar::parse_with(src, GrammarOptions::default().lazy(true))?;
+    /// assert!(grammar.definition().regex_table().iter().all(|regex| !regex.is_compiled()));
+    /// assert!(grammar.parse_str("word").is_ok());
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+}
+
+/// A loaded grammar, ready to parse sources.
+///
+/// ```no_run
+/// let grammar = tmpl::Grammar::from_file("grammar.tmpl")?;
+/// let ast = grammar.parse_str("let x = 1;")?;
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+/// Cloning a grammar is cheap, clones share the definition.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    definition: Arc<ParserDefinition>,
+}
+
+impl Grammar {
+    /// Reads the grammar file at `path`, which may also be a compiled grammar
+    /// written by [`Grammar::to_bytes`] or, going by the extension, a
+    /// definition serialized as JSON or YAML.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_file_with(path, GrammarOptions::default())
+    }
+
+    /// Reads the grammar file at `path` like [`Grammar::from_file`], with `options`.
+    pub fn from_file_with(path: impl AsRef<Path>, options: GrammarOptions) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        if ParserDefinition::is_compiled(&bytes) {
+            return Self::from_bytes(&bytes)?.checked(options);
+        }
+        let src = String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let definition = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => ParserDefinition::from_json(&src)?,
+            Some("yaml" | "yml") => ParserDefinition::from_yaml(&src)?,
+            _ => return Self::parse_with(&src, options),
+        };
+        Self::from(definition).checked(options)
+    }
+
+    /// Parses grammar text.
+    ///
+    /// A rule can't be named like a built-in pattern, a capture named like
+    /// one is only a warning:
+    ///
+    /// ```
+    /// use tmpl::{Grammar, GrammarOptions};
+    ///
+    /// assert!(Grammar::parse("int:\n<ident>\n~~~").is_err());
+    /// let src = "Main:\n<int:int>\n~~~\n";
+    /// assert!(Grammar::parse(src).is_ok());
+    /// let error = Grammar::parse_with(src, GrammarOptions::default().strict(true)).unwrap_err();
+    /// assert!(matches!(error, tmpl::Error::Strict(warnings) if warnings.len() == 1));
+    /// ```
+    pub fn parse(src: &str) -> Result<Self, Error> {
+        Ok(Self::from(crate::definition::parse(src)?))
+    }
+
+    /// Parses grammar text like [`Grammar::parse`], with `options`.
+    ///
+    /// ```
+    /// use tmpl::{Grammar, GrammarOptions};
+    ///
+    /// let src = "Main:\n<n:int>\n~~~\n\nUnused:\n<b:bool>\n~~~\n";
+    /// assert!(Grammar::parse(src).is_ok());
+    /// let error = Grammar::parse_with(src, GrammarOptions::default().strict(true)).unwrap_err();
+    /// assert!(matches!(error, tmpl::Error::Strict(warnings) if warnings.len() == 1));
+    /// ```
+    pub fn parse_with(src: &str, options: GrammarOptions) -> Result<Self, Error> {
+        if !options.strict {
+            let definition = match options.lazy {
+                true => crate::definition::parse_lazy(src)?,
+                false => crate::definition::parse(src)?,
+            };
+            return Self::from(definition).within_budget(options);
+        }
+        let (definition, diagnostics) = crate::definition::parse_with_diagnostics(src)?;
+        match diagnostics.is_empty() {
+            true => Self::from(definition).within_budget(options),
+            false => Err(Error::Strict(diagnostics.into_iter().collect())),
+        }
+    }
+
+    /// Fails if a regex takes more memory than `options` allow.
+    fn within_budget(self, options: GrammarOptions) -> Result<Self, Error> {
+        let Some(limit) = options.max_regex_size else {
+            return Ok(self);
+        };
+        for regex in self.definition.regex_table().iter() {
+            let size = regex.memory_usage();
+            if size > limit {
+                return Err(DefinitionParseError::RegexTooLarge {
+                    regex: regex.to_string(),
+                    size,
+                    limit,
+                }
+                .into());
+            }
+        }
+        Ok(self)
+    }
+
+    /// Fails if `options` reject the grammar's warnings or regexes.
+    fn checked(self, options: GrammarOptions) -> Result<Self, Error> {
+        if !options.strict {
+            return self.within_budget(options);
+        }
+        let warnings: Vec<_> = self
+            .definition
+            .validate()
+            .into_iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+            .collect();
+        match warnings.is_empty() {
+            true => self.within_budget(options),
+            false => Err(Error::Strict(warnings)),
+        }
+    }
+
+    /// Loads a compiled grammar, see [`ParserDefinition::from_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(Self::from(ParserDefinition::from_bytes(bytes)?))
+    }
+
+    /// Compiles the grammar for [`Grammar::from_bytes`], e.g. to ship it
+    /// precompiled and skip parsing grammar text at startup.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(self.definition.to_bytes()?)
+    }
+
+    pub fn definition(&self) -> &ParserDefinition {
+        &self.definition
+    }
+
+    /// The definition for parsers of its own, see [`Parser::new`].
+    pub fn shared_definition(&self) -> Arc<ParserDefinition> {
+        Arc::clone(&self.definition)
+    }
+
+    /// Parses `src` and renders its tree with the emit templates the rules
+    /// declare with `~~~ emit "..."`, see [`Grammar::emit`].
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse(concat!(
+    ///     "Main:\n<stmts:Assign>*\n~~~ emit \"{for s in stmts}{s}\n{end}\"\n\n",
+    ///     "Assign:\n<name:ident> = <value:int> ;\n~~~ emit \"{name} := {value};\"\n",
+    /// ))?;
+    /// assert_eq!(grammar.transpile("a = 1; b = 2;")?, "a := 1;\nb := 2;\n");
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn transpile(&self, src: &str) -> Result<String, Error> {
+        self.transpile_entry("Main", src)
+    }
+
+    /// Parses `src` starting at the rule named `entry` and renders it like
+    /// [`Grammar::transpile`], after expanding its macros.
+    pub fn transpile_entry(&self, entry: &str, src: &str) -> Result<String, Error> {
+        let ast = self.parse_entry(entry, src)?;
+        Ok(self.emit(&self.expand_macros(ast)?)?)
+    }
+
+    /// Expands the macros of `ast` if the grammar declares macro rules, see
+    /// [`Macros`].
+    pub fn expand_macros(&self, ast: Ast) -> Result<Ast, MacroError> {
+        let macros = Macros::new(&self.definition);
+        if macros.is_empty() {
+            return Ok(ast);
+        }
+        match macros.expand(Node::Ast(ast))? {
+            Node::Ast(ast) => Ok(ast),
+            _ => Err(MacroError::Root),
+        }
+    }
+
+    /// Translates `src` from this grammar's language into the one of
+    /// `target`: parses it, rewrites the syntax tree into one of `target`'s
+    /// rules with `mapping` and renders it with `target`'s emit templates.
+    ///
+    /// ```
+    /// use tmpl::rewrite::Rewrites;
+    ///
+    /// let from = tmpl::Grammar::parse(concat!(
+    ///     "Main:\n<stmts:Assign>*\n~~~\n\n",
+    ///     "Assign:\n<name:ident> = <value:int> ;\n~~~\n",
+    /// ))?;
+    /// let to = tmpl::Grammar::parse(concat!(
+    ///     "Main:\n<lets:Let>*\n~~~ emit \"{for l in lets}{l}\n{end}\"\n\n",
+    ///     "Let:\n<kw[let]> <binding:ident> = <init:int> ;\n~~~ emit \"let {binding} = {init};\"\n",
+    /// ))?;
+    /// let mapping = Rewrites::parse(concat!(
+    ///     "match Main(stmts) => Main(lets: stmts)\n",
+    ///     "match Assign(name, value) => Let(binding: name, init: value)\n",
+    /// ))?;
+    /// assert_eq!(from.translate(&to, &mapping, "a = 1; b = 2;")?, "let a = 1;\nlet b = 2;\n");
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn translate(
+        &self,
+        target: &Grammar,
+        mapping: &Rewrites,
+        src: &str,
+    ) -> Result<String, Error> {
+        self.translate_entry("Main", target, mapping, src)
+    }
+
+    /// Translates `src` like [`Grammar::translate`], parsing it starting at
+    /// the rule named `entry`.
+    pub fn translate_entry(
+        &self,
+        entry: &str,
+        target: &Grammar,
+        mapping: &Rewrites,
+        src: &str,
+    ) -> Result<String, Error> {
+        let ast = self.expand_macros(self.parse_entry(entry, src)?)?;
+        match mapping.apply(Node::Ast(ast))? {
+            Node::Ast(ast) => Ok(target.emit(&ast)?),
+            _ => Err(RewriteError::Root.into()),
+        }
+    }
+
+    /// Renders `ast` with the emit template of its rule, bottom-up: a
+    /// placeholder for a tree is filled in with the tree rendered by the
+    /// emit template of its own rule. The output is laid out in the style
+    /// the grammar sets, see [`OutputStyle::from_definition`].
+    pub fn emit(&self, ast: &Ast) -> Result<String, TemplateError> {
+        self.emit_with_style(ast, &OutputStyle::from_definition(&self.definition))
+    }
+
+    /// Renders `ast` like [`Grammar::emit`], laid out in `style` instead.
+    pub fn emit_with_style(&self, ast: &Ast, style: &OutputStyle) -> Result<String, TemplateError> {
+        let templates = self
+            .definition
+            .emits
+            .iter()
+            .map(|(rule, emit)| {
+                let template = Template::parse(emit)?.with_style(style.clone());
+                Ok((rule.as_str(), template))
+            })
+            .collect::<Result<BTreeMap<_, _>, TemplateError>>()?;
+        Ok(style.reindent(&emit(&templates, ast)?))
+    }
+
+    /// A session for parsing many inputs one after another, see [`ParseSession`].
+    pub fn session(&self) -> ParseSession {
+        ParseSession::new(Arc::clone(&self.definition))
+    }
+
+    /// Lowers the grammar for the faster table-driven parser, see [`Program`].
+    pub fn compile(&self) -> Result<Program, Error> {
+        Ok(Program::compile(&self.definition)?)
+    }
+
+    /// Parses `src` starting at the `Main` rule.
+    pub fn parse_str(&self, src: &str) -> Result<Ast, Error> {
+        self.parse_entry("Main", src)
+    }
+
+    /// Parses `src` starting at the rule named `entry`.
+    pub fn parse_entry(&self, entry: &str, src: &str) -> Result<Ast, Error> {
+        let (tokens, spans) = crate::lexer::lex_spanned(src)?.into_iter().unzip();
+        let parser = Parser::new(self.definition.clone(), tokens).with_token_spans(spans);
+        Ok(parser.parse_entry(entry)?)
+    }
+
+    /// Parses the input of `reader` item by item, for inputs too large to
+    /// keep in memory, like log files. `Main` has to be a single repeated
+    /// rule without a separator, like `<items:Item>*`, and the returned
+    /// iterator yields the trees of that rule as they are parsed. The input is
+    /// read in chunks and lexed line by line, so only the tokens not parsed
+    /// yet and the text after the last complete line are held at a time.
+    ///
+    /// The iterator stops after the first error, whose token index counts
+    /// from the start of the failing item. An item is only yielded
+    /// once the parser didn't need to look past the input read so far, so
+    /// the items are the same ones [`Grammar::parse_str`] would produce.
+    ///
+    /// ```
+    /// let grammar = tmpl::Grammar::parse("Main:\n<entries:Entry>*\n~~~\n\nEntry:\n<level:ident> <code:int>\n~~~\n")?;
+    /// let log = "info 1\nwarn 2\nerror 3\n";
+    /// let entries = grammar.parse_reader(log.as_bytes())?.collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(entries.len(), 3);
+    /// assert_eq!(entries[2].rule, "Entry");
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    pub fn parse_reader<R: Read>(&self, reader: R) -> Result<ReaderItems<R>, Error> {
+        let alternatives: Vec<_> = self
+            .definition
+            .entry
+            .iter()
+            .flat_map(|pattern| pattern.alternatives())
+            .map(|alternative| &alternative[..])
+            .collect();
+        let (item, repeat_mode) = match &alternatives[..] {
+            [[token]] if token.separator.is_none() && !token.is_optional => {
+                match (&token.pattern, &token.repeat_mode) {
+                    (
+                        InternalPattern::Named {
+                            kind: InternalPatternKind::Custom(item),
+                            ..
+                        },
+                        Some(repeat_mode),
+                    ) => (item.clone(), repeat_mode.clone()),
+                    _ => return Err(Error::NotStreamable),
+                }
+            }
+            _ => return Err(Error::NotStreamable),
+        };
+        Ok(ReaderItems {
+            reader,
+            parser: Parser::new(self.definition.clone(), Vec::new()),
+            item,
+            at_least_one: repeat_mode == RepeatMode::OneOrMore,
+            bytes: Vec::new(),
+            offset: 0,
+            eof: false,
+            open_string: false,
+            quote: None,
+            done: false,
+        })
+    }
+}
+
+/// The items of an input parsed with [`Grammar::parse_reader`].
+pub struct ReaderItems<R> {
+    reader: R,
+    parser: Parser,
+    item: String,
+    at_least_one: bool,
+    /// Input read but not lexed yet.
+    bytes: Vec<u8>,
+    /// Byte offset of `bytes` in the input, for lexer errors.
+    offset: usize,
+    eof: bool,
+    /// Whether `bytes` starts with a string that was still open at the end
+    /// of the lines read so far.
+    open_string: bool,
+    /// Index in `bytes` of the first quote read since then, which may close
+    /// the string.
+    quote: Option<usize>,
+    done: bool,
+}
+
+impl<R: Read> ReaderItems<R> {
+    /// Reads the next chunk and lexes the complete lines in it. Lines are
+    /// lexed as a whole as only strings can span them. The tokens before a
+    /// string still open at the end of the lines are kept, and the string is
+    /// lexed again once a later chunk has a quote that may close it. Any
+    /// other lexer error is returned right away.
+    fn fill(&mut self) -> Result<(), Error> {
+        let len = self.bytes.len();
+        self.bytes.resize(len + CHUNK_SIZE, 0);
+        let read = loop {
+            match self.reader.read(&mut self.bytes[len..]) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        self.bytes
+            .truncate(len + read.as_ref().map_or(0, |read| *read));
+        let read = read.map_err(Error::Input)?;
+        self.eof = read == 0;
+
+        // The text before the new chunk ends in an incomplete line or an open
+        // string, so there is nothing new to lex without a newline in the
+        // chunk and, for an open string, a quote before it.
+        let chunk = &self.bytes[len..];
+        if self.open_string && self.quote.is_none() {
+            self.quote = chunk
+                .iter()
+                .position(|b| *b == b'"')
+                .map(|quote| len + quote);
+        }
+        let end = match self.eof {
+            true => self.bytes.len(),
+            false => match chunk.iter().rposition(|b| *b == b'\n') {
+                Some(newline) if !self.open_string || self.quote < Some(len + newline) => {
+                    len + newline + 1
+                }
+                _ => return Ok(()),
+            },
+        };
+        let text = std::str::from_utf8(&self.bytes[..end])
+            .map_err(|e| Error::Input(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+        let offset = self.offset;
+        let lex_error =
+            |(e, span): (LexingError, Span)| Error::Lex(e, span.start + offset..span.end + offset);
+        let (tokens, end) = match crate::lexer::lex_spanned(text) {
+            Ok(tokens) => (tokens, end),
+            Err((_, span)) if !self.eof && is_open_string(&text[span.start..]) => {
+                let before = crate::lexer::lex_spanned(&text[..span.start]).map_err(lex_error)?;
+                (before, span.start)
+            }
+            Err(error) => return Err(lex_error(error)),
+        };
+        self.open_string = end < text.len();
+        self.quote = None;
+        self.parser.feed(tokens.into_iter().map(|(token, _)| token));
+        self.bytes.drain(..end);
+        self.offset += end;
+        Ok(())
+    }
+
+    fn next_item(&mut self) -> Result<Option<Ast>, Error> {
+        loop {
+            if !self.parser.is_blank() {
+                let result = self.parser.parse_prefix(&self.item);
+                if !self.eof && self.parser.looked_past_end() {
+                    self.fill()?;
+                    continue;
+                }
+                let (ast, matched) = result?;
+                self.parser.consume(matched);
+                self.at_least_one = false;
+                return Ok(Some(ast));
+            }
+            if self.eof {
+                return match self.at_least_one {
+                    true => Err(ParseError::UnexpectedEof(self.item.clone()).into()),
+                    false => Ok(None),
+                };
+            }
+            self.fill()?;
+        }
+    }
+}
+
+/// Whether `text` starts with a string literal that isn't closed before the
+/// end of `text`.
+fn is_open_string(text: &str) -> bool {
+    let Some(rest) = text.strip_prefix('"') else {
+        return false;
+    };
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+impl<R: Read> Iterator for ReaderItems<R> {
+    type Item = Result<Ast, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.next_item().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+impl From<ParserDefinition> for Grammar {
+    fn from(definition: ParserDefinition) -> Self {
+        Self {
+            definition: Arc::new(definition),
+        }
+    }
+}
+
+impl From<Arc<ParserDefinition>> for Grammar {
+    fn from(definition: Arc<ParserDefinition>) -> Self {
+        Self { definition }
+    }
+}
+
+impl std::str::FromStr for Grammar {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Self::parse(src)
+    }
+}
+
+/// Renders `ast` with the templates by rule name, see [`Grammar::emit`].
+fn emit(templates: &BTreeMap<&str, Template>, ast: &Ast) -> Result<String, TemplateError> {
+    let template = templates
+        .get(ast.rule.as_str())
+        .ok_or_else(|| TemplateError::NoEmit(ast.rule.clone()))?;
+    template.render_with(ast, |tree| emit(templates, tree))
+}
@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+
+use crate::definition::{ParserDefinition, Pattern};
+
+/// Which rules reference which, see [`RuleGraph::of`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleGraph {
+    /// Every rule with the rules it references, including references to rules
+    /// that don't exist.
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl RuleGraph {
+    pub fn of(definition: &ParserDefinition) -> Self {
+        let edges = definition
+            .all_rules()
+            .into_iter()
+            .map(|(name, patterns)| {
+                let references = patterns
+                    .iter()
+                    .flat_map(Pattern::alternatives)
+                    .flatten()
+                    .flat_map(|token| token.pattern.references())
+                    .map(str::to_string)
+                    .collect();
+                (name.to_string(), references)
+            })
+            .collect();
+        Self { edges }
+    }
+
+    /// Groups of rules that reference each other, directly or through other
+    /// rules, sorted by name. A rule referencing itself is a cycle of one.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan::default();
+        for name in self.edges.keys() {
+            if !tarjan.index.contains_key(name.as_str()) {
+                tarjan.visit(self, name);
+            }
+        }
+        let mut cycles: Vec<_> = tarjan
+            .components
+            .into_iter()
+            .filter(|component| match &component[..] {
+                [name] => self.edges[name].contains(name),
+                _ => true,
+            })
+            .map(|mut component| {
+                component.sort();
+                component
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    /// Whether the edge from `from` to `to` is part of a cycle.
+    fn in_cycle(cycles: &[Vec<String>], from: &str, to: &str) -> bool {
+        cycles
+            .iter()
+            .any(|cycle| cycle.iter().any(|n| n == from) && cycle.iter().any(|n| n == to))
+    }
+
+    /// Renders the graph in GraphViz DOT, with rules and references in cycles
+    /// drawn in red and references to missing rules dashed.
+    pub fn to_dot(&self) -> String {
+        let cycles = self.cycles();
+        let mut out = String::from("digraph grammar {\n    node [shape=box];\n");
+        for name in self.edges.keys() {
+            let cyclic = cycles.iter().any(|cycle| cycle.contains(name));
+            let style = if cyclic { " [color=red]" } else { "" };
+            let _ = writeln!(out, "    \"{name}\"{style};");
+        }
+        for (from, references) in &self.edges {
+            for to in references {
+                let style = if !self.edges.contains_key(to) {
+                    " [style=dashed]"
+                } else if Self::in_cycle(&cycles, from, to) {
+                    " [color=red]"
+                } else {
+                    ""
+                };
+                let _ = writeln!(out, "    \"{from}\" -> \"{to}\"{style};");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart, with rules in cycles styled
+    /// red and references to missing rules dotted.
+    pub fn to_mermaid(&self) -> String {
+        let cycles = self.cycles();
+        let mut out = String::from("flowchart TD\n");
+        for (from, references) in &self.edges {
+            if references.is_empty() {
+                let _ = writeln!(out, "    {from}");
+            }
+            for to in references {
+                let arrow = if self.edges.contains_key(to) {
+                    "-->"
+                } else {
+                    "-.->"
+                };
+                let _ = writeln!(out, "    {from} {arrow} {to}");
+            }
+        }
+        for name in cycles.iter().flatten().collect::<BTreeSet<_>>() {
+            let _ = writeln!(out, "    style {name} stroke:red");
+        }
+        out
+    }
+}
+
+/// Tarjan's strongly connected components over the rule graph.
+#[derive(Default)]
+struct Tarjan<'a> {
+    index: HashMap<&'a str, usize>,
+    low: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, graph: &'a RuleGraph, name: &'a str) {
+        let index = self.index.len();
+        self.index.insert(name, index);
+        self.low.insert(name, index);
+        self.stack.push(name);
+        self.on_stack.insert(name);
+        for to in graph.edges.get(name).into_iter().flatten() {
+            if !graph.edges.contains_key(to) {
+                continue;
+            }
+            if !self.index.contains_key(to.as_str()) {
+                self.visit(graph, to);
+                let low = self.low[name].min(self.low[to.as_str()]);
+                self.low.insert(name, low);
+            } else if self.on_stack.contains(to.as_str()) {
+                let low = self.low[name].min(self.index[to.as_str()]);
+                self.low.insert(name, low);
+            }
+        }
+        if self.low[name] == self.index[name] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == name {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::BTreeSet;
+
+use serde_json::json;
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, TokenPattern, RESERVED_NAMES,
+};
+
+/// Literal tokens of a grammar, split by how editors should color them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Vocabulary {
+    /// Word literals like `let` or `kw[fn]`.
+    pub keywords: BTreeSet<String>,
+    /// Symbol literals that read as operators, like `=` or `->`.
+    pub operators: BTreeSet<String>,
+    /// Brackets and separators.
+    pub punctuation: BTreeSet<String>,
+    /// Which builtin patterns the grammar uses.
+    pub strings: bool,
+    pub numbers: bool,
+    pub booleans: bool,
+}
+
+impl Vocabulary {
+    pub fn of(definition: &ParserDefinition) -> Self {
+        let mut vocabulary = Self::default();
+        for (_, patterns) in definition.all_rules() {
+            for alternative in patterns.iter().flat_map(|p| p.alternatives()) {
+                vocabulary.sequence(alternative);
+            }
+        }
+        vocabulary
+    }
+
+    fn sequence(&mut self, sequence: &[TokenPattern]) {
+        for token in sequence {
+            if let Some(separator) = &token.separator {
+                self.literal(separator);
+            }
+            match &token.pattern {
+                InternalPattern::Named { kind, .. } => match kind {
+                    InternalPatternKind::Int | InternalPatternKind::Float => self.numbers = true,
+                    InternalPatternKind::String => self.strings = true,
+                    InternalPatternKind::Bool => self.booleans = true,
+                    InternalPatternKind::Keyword(text) | InternalPatternKind::Symbol(text) => {
+                        self.literal(text)
+                    }
+                    InternalPatternKind::OneOf(options) => {
+                        for option in options {
+                            self.literal(option);
+                        }
+                    }
+                    InternalPatternKind::Balanced { open, close } => {
+                        self.literal(open);
+                        self.literal(close);
+                    }
+                    InternalPatternKind::Ident
+                    | InternalPatternKind::Regex(_)
+                    | InternalPatternKind::Custom(_) => {}
+                },
+                InternalPattern::Raw { value } => self.literal(value),
+                InternalPattern::Exact { pattern } => self.sequence(pattern),
+            }
+        }
+    }
+
+    fn literal(&mut self, text: &str) {
+        if text.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+            self.keywords.insert(text.to_string());
+        } else if text.chars().all(|c| "()[]{},;.:".contains(c)) {
+            self.punctuation.insert(text.to_string());
+        } else {
+            self.operators.insert(text.to_string());
+        }
+    }
+}
+
+/// Builds a TextMate grammar (as JSON) for the language `definition` describes.
+///
+/// The input lexer has no comment syntax, so no comment scope is emitted.
+pub fn to_textmate(definition: &ParserDefinition, name: &str) -> String {
+    let vocabulary = Vocabulary::of(definition);
+    let scope = name.to_lowercase();
+    let alternation = |words: &BTreeSet<String>| {
+        words
+            .iter()
+            .map(|w| regex::escape(w))
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+    let mut patterns = Vec::new();
+    if vocabulary.strings {
+        patterns.push(json!({
+            "name": format!("string.quoted.double.{scope}"),
+            "match": r#""(?:[^"\\]|\\.)*""#,
+        }));
+    }
+    if vocabulary.booleans {
+        patterns.push(json!({
+            "name": format!("constant.language.boolean.{scope}"),
+            "match": r"\b(?:true|false)\b",
+        }));
+    }
+    if !vocabulary.keywords.is_empty() {
+        patterns.push(json!({
+            "name": format!("keyword.control.{scope}"),
+            "match": format!(r"\b(?:{})\b", alternation(&vocabulary.keywords)),
+        }));
+    }
+    if vocabulary.numbers {
+        patterns.push(json!({
+            "name": format!("constant.numeric.{scope}"),
+            "match": r"\b[0-9]+(?:\.[0-9]*)?",
+        }));
+    }
+    if !vocabulary.operators.is_empty() {
+        // longest first, so `->` wins over `-`
+        let mut operators: Vec<_> = vocabulary.operators.iter().collect();
+        operators.sort_by_key(|o| std::cmp::Reverse(o.len()));
+        let operators: Vec<_> = operators.into_iter().map(|o| regex::escape(o)).collect();
+        patterns.push(json!({
+            "name": format!("keyword.operator.{scope}"),
+            "match": operators.join("|"),
+        }));
+    }
+    if !vocabulary.punctuation.is_empty() {
+        patterns.push(json!({
+            "name": format!("punctuation.{scope}"),
+            "match": alternation(&vocabulary.punctuation),
+        }));
+    }
+    let grammar = json!({
+        "name": name,
+        "scopeName": format!("source.{scope}"),
+        "patterns": patterns,
+    });
+    serde_json::to_string_pretty(&grammar).expect("json values always serialize") + "\n"
+}
+
+/// Builds a tree-sitter `highlights.scm` query for the language `definition`
+/// describes.
+///
+/// Literals are matched as anonymous nodes. The builtin patterns are expected
+/// as named nodes called like the pattern, e.g. `(string)` and `(int)`.
+pub fn to_tree_sitter_query(definition: &ParserDefinition) -> String {
+    let vocabulary = Vocabulary::of(definition);
+    let list = |words: &BTreeSet<String>| {
+        words
+            .iter()
+            .map(|w| format!("{w:?}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let mut out = String::new();
+    if !vocabulary.keywords.is_empty() {
+        out.push_str(&format!("[{}] @keyword\n", list(&vocabulary.keywords)));
+    }
+    if !vocabulary.operators.is_empty() {
+        out.push_str(&format!("[{}] @operator\n", list(&vocabulary.operators)));
+    }
+    if !vocabulary.punctuation.is_empty() {
+        out.push_str(&format!(
+            "[{}] @punctuation.delimiter\n",
+            list(&vocabulary.punctuation)
+        ));
+    }
+    if vocabulary.strings {
+        out.push_str("(string) @string\n");
+    }
+    if vocabulary.numbers {
+        out.push_str("[(int) (float)] @number\n");
+    }
+    if vocabulary.booleans {
+        out.push_str("(bool) @boolean\n");
+    }
+    out
+}
+
+/// Words starting declarations in grammar files, besides the ones in
+/// [`RESERVED_NAMES`]: `emit` after a rule's `~~~` and `test` blocks.
+const DECLARATION_WORDS: &[&str] = &["define", "override", "tmpl_version", "emit", "test"];
+
+/// Fields of `test` blocks.
+const TEST_FIELDS: &[&str] = &["input", "rule", "expect"];
+
+/// Constants of define values and test expectations.
+const CONSTANTS: &[&str] = &["true", "false", "Ok", "Error"];
+
+/// Words of the grammar syntax, split into declaration keywords, test
+/// fields and the built-in pattern kinds like `int` or the `kw` of
+/// `<kw[..]>`, which are all other [`RESERVED_NAMES`].
+fn grammar_words() -> (Vec<&'static str>, Vec<&'static str>, Vec<&'static str>) {
+    let keywords = DECLARATION_WORDS.to_vec();
+    let kinds = (RESERVED_NAMES.iter().copied())
+        .filter(|name| !DECLARATION_WORDS.contains(name))
+        .collect();
+    (keywords, TEST_FIELDS.to_vec(), kinds)
+}
+
+/// Builds a TextMate grammar (as JSON) for `.tmpl` grammar files
+/// themselves, following the tokens of the definition parser.
+pub fn grammar_textmate() -> String {
+    let (keywords, fields, kinds) = grammar_words();
+    let words = |words: &[&str]| format!(r"\b(?:{})\b", words.join("|"));
+    let grammar = json!({
+        "name": "tmpl",
+        "scopeName": "source.tmpl",
+        "fileTypes": ["tmpl"],
+        "patterns": [
+            { "name": "string.quoted.double.tmpl", "match": r#""(?:[^"\\]|\\.)*""# },
+            { "name": "string.regexp.tmpl", "match": r"\bs/(?:\\/|[^/\n])*/" },
+            {
+                "match": format!(r"^(?:(override)\s+)?(?!{})([A-Za-z_]\w*)\s*(?=:)", words(&fields)),
+                "captures": {
+                    "1": { "name": "keyword.control.tmpl" },
+                    "2": { "name": "entity.name.function.tmpl" },
+                },
+            },
+            { "name": "punctuation.section.rule.end.tmpl", "match": "^~~~" },
+            { "name": "keyword.control.tmpl", "match": words(&keywords) },
+            { "name": "variable.other.member.tmpl", "match": format!(r"{}(?=\s*:)", words(&fields)) },
+            { "name": "variable.parameter.tmpl", "match": r"(?<=<)\s*[A-Za-z_]\w*(?=\s*:)" },
+            { "name": "support.type.tmpl", "match": words(&kinds) },
+            { "name": "constant.language.tmpl", "match": words(CONSTANTS) },
+            { "name": "constant.numeric.tmpl", "match": r"\b[0-9]+(?:\.[0-9]*)?" },
+            { "name": "keyword.operator.tmpl", "match": r"\*\*|\+\+|[|*+?]" },
+        ],
+    });
+    serde_json::to_string_pretty(&grammar).expect("json values always serialize") + "\n"
+}
+
+/// Builds a Vim syntax file for `.tmpl` grammar files, to be saved as
+/// `syntax/tmpl.vim`.
+pub fn grammar_vim() -> String {
+    let (keywords, fields, kinds) = grammar_words();
+    let mut out = String::from("\" Vim syntax file for tmpl grammars\n");
+    out.push_str("if exists(\"b:current_syntax\")\n  finish\nendif\n\n");
+    out.push_str(&format!(
+        "syntax keyword tmplKeyword {}\n",
+        keywords.join(" ")
+    ));
+    out.push_str(&format!("syntax keyword tmplField {}\n", fields.join(" ")));
+    out.push_str(&format!("syntax keyword tmplType {}\n", kinds.join(" ")));
+    out.push_str(&format!(
+        "syntax keyword tmplConstant {}\n",
+        CONSTANTS.join(" ")
+    ));
+    out.push_str(concat!(
+        "syntax match tmplNumber /\\<\\d\\+\\(\\.\\d*\\)\\?/\n",
+        "syntax match tmplOperator /\\*\\*\\|++\\|[|*+?]/\n",
+        "syntax match tmplRule /^\\(override\\s\\+\\)\\?\\zs\\h\\w*\\ze\\s*:/\n",
+        "syntax match tmplCapture /<\\s*\\zs\\h\\w*\\ze\\s*:/\n",
+        "syntax match tmplDelimiter /^\\~\\~\\~/\n",
+        "syntax match tmplRegex +\\<s/\\(\\\\/\\|[^/]\\)*/+\n",
+        "syntax region tmplString start=/\"/ skip=/\\\\./ end=/\"/\n",
+        "\n",
+        "highlight default link tmplKeyword Keyword\n",
+        "highlight default link tmplField Label\n",
+        "highlight default link tmplType Type\n",
+        "highlight default link tmplConstant Constant\n",
+        "highlight default link tmplNumber Number\n",
+        "highlight default link tmplOperator Operator\n",
+        "highlight default link tmplRule Function\n",
+        "highlight default link tmplCapture Identifier\n",
+        "highlight default link tmplDelimiter Delimiter\n",
+        "highlight default link tmplRegex SpecialChar\n",
+        "highlight default link tmplString String\n",
+        "\n",
+        "let b:current_syntax = \"tmpl\"\n",
+    ));
+    out
+}
+
+/// Builds an Emacs major mode for `.tmpl` grammar files, to be saved as
+/// `tmpl-mode.el`.
+pub fn grammar_emacs() -> String {
+    let (keywords, fields, kinds) = grammar_words();
+    let words = |words: &[&str]| {
+        let quoted: Vec<_> = words.iter().map(|word| format!("\"{word}\"")).collect();
+        format!("(regexp-opt '({}) 'symbols)", quoted.join(" "))
+    };
+    let mut out = String::from(
+        ";;; tmpl-mode.el --- Major mode for tmpl grammars -*- lexical-binding: t -*-\n\n",
+    );
+    out.push_str("(defvar tmpl-font-lock-keywords\n");
+    out.push_str(&format!(
+        "  `((,{} . font-lock-keyword-face)\n",
+        words(&keywords)
+    ));
+    out.push_str(&format!(
+        "    (,{} . font-lock-builtin-face)\n",
+        words(&fields)
+    ));
+    out.push_str(&format!("    (,{} . font-lock-type-face)\n", words(&kinds)));
+    out.push_str(&format!(
+        "    (,{} . font-lock-constant-face)\n",
+        words(CONSTANTS)
+    ));
+    out.push_str(concat!(
+        "    (\"^\\\\(?:override[ \\t]+\\\\)?\\\\([A-Za-z_][A-Za-z0-9_]*\\\\)[ \\t]*:\" 1 font-lock-function-name-face)\n",
+        "    (\"<[ \\t]*\\\\([A-Za-z_][A-Za-z0-9_]*\\\\)[ \\t]*:\" 1 font-lock-variable-name-face)\n",
+        "    (\"\\\\_<s/\\\\(?:\\\\\\\\/\\\\|[^/\\n]\\\\)*/\" . font-lock-string-face)\n",
+        "    (\"^~~~\" . font-lock-preprocessor-face)\n",
+        "    (\"\\\\_<[0-9]+\\\\(?:\\\\.[0-9]*\\\\)?\" . font-lock-constant-face))\n",
+        "  \"Highlighting of `tmpl-mode'.\")\n\n",
+        "(defvar tmpl-mode-syntax-table\n",
+        "  (let ((table (make-syntax-table)))\n",
+        "    (modify-syntax-entry ?_ \"_\" table)\n",
+        "    (modify-syntax-entry ?\\\" \"\\\"\" table)\n",
+        "    (modify-syntax-entry ?\\\\ \"\\\\\" table)\n",
+        "    table)\n",
+        "  \"Syntax table of `tmpl-mode'.\")\n\n",
+        ";;;###autoload\n",
+        "(define-derived-mode tmpl-mode prog-mode \"tmpl\"\n",
+        "  \"Major mode for tmpl grammar files.\"\n",
+        "  (setq-local font-lock-defaults '(tmpl-font-lock-keywords)))\n\n",
+        ";;;###autoload\n",
+        "(add-to-list 'auto-mode-alist '(\"\\\\.tmpl\\\\'\" . tmpl-mode))\n\n",
+        "(provide 'tmpl-mode)\n",
+        ";;; tmpl-mode.el ends here\n",
+    ));
+    out
+}
@@ -0,0 +1,8 @@
+mod ast;
+mod ebnf;
+mod lower;
+mod pest;
+
+pub use ast::{ImportError, Imported};
+pub use ebnf::from_ebnf;
+pub use pest::from_pest;
@@ -0,0 +1,106 @@
+use thiserror::Error;
+
+use crate::definition::ParserDefinition;
+
+pub type Result<T> = std::result::Result<T, ImportError>;
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("Syntax error: {0}")]
+    Syntax(#[from] peg::error::ParseError<peg::str::LineCol>),
+    #[error("Grammar contains no rules")]
+    Empty,
+}
+
+/// A grammar converted from another notation.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub definition: ParserDefinition,
+    /// Constructs that could not be translated exactly.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Repetition {
+    Optional,
+    ZeroOrMore,
+    OneOrMore,
+    Bounded { min: usize },
+}
+
+/// Grammar expression shared by all foreign notations.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Choice(Vec<Expr>),
+    Sequence(Vec<Expr>),
+    Repeat(Box<Expr>, Repetition),
+    Literal(String),
+    Reference(String),
+    /// A regex fragment matching a single character, e.g. `[a-z]`.
+    Class(String),
+    /// A lookahead predicate or exclusion, which has no tmpl equivalent.
+    Predicate,
+    /// Matches nothing, like pest's `SOI` and `EOI`.
+    Empty,
+}
+
+/// Character level rules built into pest.
+const CHAR_LEVEL_BUILTINS: [&str; 9] = [
+    "ANY",
+    "NEWLINE",
+    "ASCII_DIGIT",
+    "ASCII_NONZERO_DIGIT",
+    "ASCII_ALPHA",
+    "ASCII_ALPHA_LOWER",
+    "ASCII_ALPHA_UPPER",
+    "ASCII_ALPHANUMERIC",
+    "ASCII_HEX_DIGIT",
+];
+
+impl Expr {
+    /// Whether the expression works on single characters instead of tokens.
+    pub fn is_char_level(&self) -> bool {
+        match self {
+            Expr::Choice(exprs) | Expr::Sequence(exprs) => exprs.iter().any(Expr::is_char_level),
+            Expr::Repeat(inner, _) => inner.is_char_level(),
+            Expr::Class(_) => true,
+            Expr::Reference(name) => CHAR_LEVEL_BUILTINS.contains(&name.as_str()),
+            Expr::Literal(_) | Expr::Predicate | Expr::Empty => false,
+        }
+    }
+
+    pub fn contains_predicate(&self) -> bool {
+        match self {
+            Expr::Choice(exprs) | Expr::Sequence(exprs) => {
+                exprs.iter().any(Expr::contains_predicate)
+            }
+            Expr::Repeat(inner, _) => inner.contains_predicate(),
+            Expr::Predicate => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForeignRule {
+    pub name: String,
+    /// Whether the rule describes a token instead of a syntactic structure.
+    pub lexical: bool,
+    pub expr: Expr,
+}
+
+pub fn choice(first: Expr, rest: Vec<Expr>) -> Expr {
+    if rest.is_empty() {
+        first
+    } else {
+        Expr::Choice(std::iter::once(first).chain(rest).collect())
+    }
+}
+
+pub fn sequence(first: Expr, rest: Vec<Expr>) -> Expr {
+    if rest.is_empty() {
+        first
+    } else {
+        Expr::Sequence(std::iter::once(first).chain(rest).collect())
+    }
+}
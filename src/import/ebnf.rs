@@ -0,0 +1,108 @@
+use crate::import::ast::*;
+use crate::import::lower::lower;
+
+peg::parser! {
+    grammar parser() for str {
+        pub rule grammar() -> Vec<(String, Expr)>
+            = _ rules:(r:rule_def() _ { r })* { rules }
+
+        rule rule_def() -> (String, Expr)
+            = name:ident() _ "::=" _ e:expr() { (name, e) }
+
+        rule expr() -> Expr
+            = first:seq() rest:(_ "|" _ s:seq() { s })* { choice(first, rest) }
+
+        rule seq() -> Expr
+            = first:term() rest:(_ t:term() { t })* { sequence(first, rest) }
+
+        rule term() -> Expr
+            = !(ident() _ "::=") e:primary() post:postfix()* exclusion:(_ "-" _ primary())? {
+                let e = post.into_iter().fold(e, |e, r| Expr::Repeat(Box::new(e), r));
+                match exclusion {
+                    Some(_) => Expr::Sequence(vec![Expr::Predicate, e]),
+                    None => e,
+                }
+            }
+
+        rule primary() -> Expr
+            = "(" _ e:expr() _ ")" { e }
+            / "'" s:$([^'\'']*) "'" { Expr::Literal(s.to_string()) }
+            / "\"" s:$([^'"']*) "\"" { Expr::Literal(s.to_string()) }
+            / "[" s:$([^']']*) "]" { Expr::Class(format!("[{}]", class(s))) }
+            / "#x" h:$(['0'..='9' | 'a'..='f' | 'A'..='F']+) { Expr::Class(format!("\\x{{{h}}}")) }
+            / "." { Expr::Class(".".to_string()) }
+            / n:ident() { Expr::Reference(n) }
+
+        rule postfix() -> Repetition
+            = "?" { Repetition::Optional }
+            / "*" { Repetition::ZeroOrMore }
+            / "+" { Repetition::OneOrMore }
+
+        rule ident() -> String
+            = s:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) {
+                s.to_string()
+            }
+
+        rule _() = quiet!{([' ' | '\t' | '\r' | '\n'] / "/*" (!"*/" [_])* "*/")*}
+    }
+}
+
+/// Converts the content of an EBNF character class into regex syntax.
+fn class(content: &str) -> String {
+    let mut out = String::new();
+    let mut rest = content;
+    while let Some(c) = rest.chars().next() {
+        if let Some(hex) = rest.strip_prefix("#x") {
+            let digits = hex.chars().take_while(char::is_ascii_hexdigit).count();
+            out.push_str(&format!("\\x{{{}}}", &hex[..digits]));
+            rest = &hex[digits..];
+            continue;
+        }
+        if matches!(c, '\\' | '[') {
+            out.push('\\');
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Converts a W3C style EBNF grammar into a tmpl grammar.
+///
+/// Rules using character classes and rules with upper case names describe
+/// tokens and are turned into regex patterns.
+///
+/// Exporting the result with [`to_ebnf`](crate::export::to_ebnf) gives EBNF
+/// that imports to the same definition:
+///
+/// ```
+/// use tmpl::export::to_ebnf;
+/// use tmpl::import::from_ebnf;
+///
+/// let src = "
+/// Main ::= pair ( ';' pair )*
+/// pair ::= sign '(' sign? ')'
+/// sign ::= '+' | '-'
+/// ";
+/// let imported = from_ebnf(src)?;
+/// assert!(imported.warnings.is_empty());
+/// let exported = to_ebnf(&imported.definition);
+/// assert!(exported.text.contains("pair ::= sign '(' sign? ')'"));
+/// assert_eq!(from_ebnf(&exported.text)?.definition, imported.definition);
+/// # Ok::<(), tmpl::import::ImportError>(())
+/// ```
+pub fn from_ebnf(src: &str) -> Result<Imported> {
+    let rules = parser::grammar(src)?
+        .into_iter()
+        .map(|(name, expr)| ForeignRule {
+            lexical: expr.is_char_level() || is_upper_case(&name),
+            name,
+            expr,
+        })
+        .collect();
+    lower(rules)
+}
+
+fn is_upper_case(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_alphabetic()) && !name.chars().any(|c| c.is_lowercase())
+}
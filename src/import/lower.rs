@@ -0,0 +1,300 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+
+use crate::definition::{
+    bool, custom, float, ident, int, keyword, raw, regex, string, symbol, InternalPattern,
+    ParserDefinition, Pattern, RepeatMode, RuleMap, TokenPattern,
+};
+use crate::import::ast::{Expr, ForeignRule, ImportError, Imported, Repetition, Result};
+
+/// Limits how deep lexical rules referencing each other are inlined.
+const MAX_INLINE_DEPTH: usize = 32;
+
+/// Builds a tmpl grammar from rules of a foreign notation.
+///
+/// The entry rule is `Main` if it exists and the first rule otherwise.
+/// Groups that tmpl can't express inline become rules of their own, named
+/// after the rule they appear in.
+pub fn lower(rules: Vec<ForeignRule>) -> Result<Imported> {
+    let main = rules
+        .iter()
+        .find(|r| r.name == "Main")
+        .or(rules.first())
+        .ok_or(ImportError::Empty)?
+        .name
+        .clone();
+    let mut lowerer = Lowerer {
+        lexical: rules
+            .iter()
+            .filter(|r| r.lexical)
+            .map(|r| (r.name.clone(), r.expr.clone()))
+            .collect(),
+        rules: RuleMap::default(),
+        warnings: Vec::new(),
+        rule: String::new(),
+        synthetic: 0,
+    };
+    for rule in rules.iter().filter(|r| !r.lexical) {
+        lowerer.rule = rule.name.clone();
+        lowerer.synthetic = 0;
+        let patterns = lowerer.lower_rule(&rule.expr);
+        lowerer.rules.insert(rule.name.clone(), patterns);
+    }
+    lowerer.rule = main.clone();
+    let entry = match lowerer.rules.shift_remove(&main) {
+        Some(patterns) if main == "Main" => patterns,
+        Some(patterns) => {
+            lowerer.rules.insert(main.clone(), patterns);
+            vec![Pattern::from(single(lowerer.reference(&main)))]
+        }
+        None => vec![Pattern::from(single(lowerer.reference(&main)))],
+    };
+    Ok(Imported {
+        definition: ParserDefinition {
+            version: None,
+            entry,
+            rules: lowerer.rules,
+            defines: Vec::new(),
+            emits: Default::default(),
+            docs: Default::default(),
+            tests: Vec::new(),
+            symbols: Default::default(),
+        },
+        warnings: lowerer.warnings,
+    })
+}
+
+struct Lowerer {
+    lexical: HashMap<String, Expr>,
+    rules: RuleMap,
+    warnings: Vec<String>,
+    /// Rule currently being lowered.
+    rule: String,
+    /// Number of rules extracted from the current rule so far.
+    synthetic: usize,
+}
+
+impl Lowerer {
+    fn warn(&mut self, message: impl Display) {
+        self.warnings.push(format!("{}: {message}", self.rule));
+    }
+
+    fn lower_rule(&mut self, expr: &Expr) -> Vec<Pattern> {
+        let alternatives: Vec<&Expr> = match expr {
+            Expr::Choice(alternatives) => alternatives.iter().collect(),
+            other => vec![other],
+        };
+        let sequences = alternatives
+            .into_iter()
+            .map(|alternative| self.lower_sequence(alternative))
+            .collect();
+        vec![Pattern::from_alternatives(sequences)]
+    }
+
+    fn lower_sequence(&mut self, expr: &Expr) -> Vec<TokenPattern> {
+        let mut items = Vec::new();
+        flatten_sequence(expr, &mut items);
+        let mut tokens: Vec<TokenPattern> = items
+            .into_iter()
+            .filter_map(|item| self.lower_term(item))
+            .collect();
+        dedupe_names(&mut tokens);
+        tokens
+    }
+
+    fn lower_term(&mut self, expr: &Expr) -> Option<TokenPattern> {
+        let (inner, repetition) = match expr {
+            Expr::Repeat(inner, repetition) => (&**inner, Some(repetition)),
+            other => (other, None),
+        };
+        let pattern = self.lower_atom(inner)?;
+        let (is_optional, repeat_mode) = match repetition {
+            None => (false, None),
+            Some(Repetition::Optional) => (true, None),
+            Some(Repetition::ZeroOrMore) => (false, Some(RepeatMode::ZeroOrMore)),
+            Some(Repetition::OneOrMore) => (false, Some(RepeatMode::OneOrMore)),
+            Some(Repetition::Bounded { min }) => {
+                self.warn("bounded repetition is approximated with `*` or `+`");
+                let mode = if *min == 0 {
+                    RepeatMode::ZeroOrMore
+                } else {
+                    RepeatMode::OneOrMore
+                };
+                (false, Some(mode))
+            }
+        };
+        Some(TokenPattern {
+            pattern,
+            is_optional,
+            repeat_mode,
+            separator: None,
+        })
+    }
+
+    fn lower_atom(&mut self, expr: &Expr) -> Option<InternalPattern> {
+        match expr {
+            Expr::Literal(text) if text.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                Some(raw(text))
+            }
+            Expr::Literal(text) if text.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.regex(None, &::regex::escape(text))
+            }
+            Expr::Literal(text) => Some(symbol(None, text)),
+            Expr::Reference(name) => Some(self.reference(name)),
+            Expr::Class(class) => self.regex(None, class),
+            Expr::Predicate => {
+                self.warn("predicates and exclusions are not supported and were dropped");
+                None
+            }
+            Expr::Empty => None,
+            Expr::Choice(_) | Expr::Sequence(_) | Expr::Repeat(..) => {
+                self.synthetic += 1;
+                let name = format!("{}_{}", self.rule, self.synthetic);
+                let patterns = self.lower_rule(expr);
+                self.rules.insert(name.clone(), patterns);
+                Some(custom(Some(snake_case(&name)), &name))
+            }
+        }
+    }
+
+    /// Resolves a rule reference, mapping the token rules written by
+    /// `tmpl export` back to the builtin patterns.
+    fn reference(&mut self, name: &str) -> InternalPattern {
+        let capture = Some(snake_case(name));
+        match name {
+            "IDENT" => return ident(capture),
+            "INT" => return int(capture),
+            "FLOAT" => return float(capture),
+            "STRING" => return string(capture),
+            "BOOL" => return bool(capture),
+            _ => {}
+        }
+        if let Some(word) = name.strip_prefix("KW_") {
+            return keyword(None, word);
+        }
+        let Some(expr) = self.lexical.get(name).cloned() else {
+            return custom(capture, name);
+        };
+        if expr.contains_predicate() {
+            self.warn(format!("predicates in token `{name}` were dropped"));
+        }
+        match self.to_regex(&expr, 0) {
+            Ok(re) => match self.regex(capture.clone(), &re) {
+                Some(pattern) => pattern,
+                None => ident(capture),
+            },
+            Err(e) => {
+                self.warn(format!("{e}, `{name}` is replaced by an identifier"));
+                ident(capture)
+            }
+        }
+    }
+
+    fn regex(&mut self, name: Option<String>, re: &str) -> Option<InternalPattern> {
+        match regex(name, re) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                self.warn(e);
+                None
+            }
+        }
+    }
+
+    /// Translates a lexical rule into a regex, inlining the lexical rules it uses.
+    fn to_regex(&self, expr: &Expr, depth: usize) -> std::result::Result<String, String> {
+        if depth > MAX_INLINE_DEPTH {
+            return Err("tokens are nested too deeply".to_string());
+        }
+        Ok(match expr {
+            Expr::Choice(alternatives) => {
+                let alternatives = alternatives
+                    .iter()
+                    .map(|alternative| self.to_regex(alternative, depth))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                format!("(?:{})", alternatives.join("|"))
+            }
+            Expr::Sequence(parts) => parts
+                .iter()
+                .map(|part| self.to_regex(part, depth))
+                .collect::<std::result::Result<String, _>>()?,
+            Expr::Repeat(inner, repetition) => {
+                let operator = match repetition {
+                    Repetition::Optional => "?".to_string(),
+                    Repetition::ZeroOrMore => "*".to_string(),
+                    Repetition::OneOrMore => "+".to_string(),
+                    Repetition::Bounded { min } => format!("{{{min},}}"),
+                };
+                format!("(?:{}){operator}", self.to_regex(inner, depth)?)
+            }
+            Expr::Literal(text) => ::regex::escape(text),
+            Expr::Class(class) => class.clone(),
+            Expr::Reference(name) => match self.lexical.get(name) {
+                Some(expr) => self.to_regex(expr, depth + 1)?,
+                None => match name.as_str() {
+                    "ANY" => ".",
+                    "NEWLINE" => r"(?:\n|\r\n|\r)",
+                    "ASCII_DIGIT" => "[0-9]",
+                    "ASCII_NONZERO_DIGIT" => "[1-9]",
+                    "ASCII_ALPHA" => "[a-zA-Z]",
+                    "ASCII_ALPHA_LOWER" => "[a-z]",
+                    "ASCII_ALPHA_UPPER" => "[A-Z]",
+                    "ASCII_ALPHANUMERIC" => "[a-zA-Z0-9]",
+                    "ASCII_HEX_DIGIT" => "[0-9a-fA-F]",
+                    _ => return Err(format!("`{name}` can't be used inside a token")),
+                }
+                .to_string(),
+            },
+            Expr::Predicate | Expr::Empty => String::new(),
+        })
+    }
+}
+
+fn single(pattern: InternalPattern) -> TokenPattern {
+    TokenPattern {
+        pattern,
+        is_optional: false,
+        repeat_mode: None,
+        separator: None,
+    }
+}
+
+fn flatten_sequence<'e>(expr: &'e Expr, items: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Sequence(parts) => {
+            for part in parts {
+                flatten_sequence(part, items);
+            }
+        }
+        other => items.push(other),
+    }
+}
+
+/// Numbers repeated captures, so every field of the resulting node is kept.
+fn dedupe_names(tokens: &mut [TokenPattern]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for token in tokens {
+        if let InternalPattern::Named {
+            name: Some(name), ..
+        } = &mut token.pattern
+        {
+            let count = seen.entry(name.clone()).or_default();
+            *count += 1;
+            if *count > 1 {
+                *name = format!("{name}_{count}");
+            }
+        }
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lower {
+            out.push('_');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        out.extend(c.to_lowercase());
+    }
+    out
+}
@@ -0,0 +1,126 @@
+use crate::import::ast::*;
+use crate::import::lower::lower;
+
+peg::parser! {
+    grammar parser() for str {
+        pub rule grammar() -> Vec<(String, Option<char>, Expr)>
+            = _ rules:(r:rule_def() _ { r })* { rules }
+
+        rule rule_def() -> (String, Option<char>, Expr)
+            = name:ident() _ "=" _ m:modifier()? _ "{" _ e:expr() _ "}" { (name, m, e) }
+
+        rule modifier() -> char
+            = ['_' | '@' | '$' | '!']
+
+        rule expr() -> Expr
+            = ("|" _)? first:seq() rest:(_ "|" _ s:seq() { s })* { choice(first, rest) }
+
+        rule seq() -> Expr
+            = first:term() rest:(_ "~" _ t:term() { t })* { sequence(first, rest) }
+
+        rule term() -> Expr
+            = tag()? p:(prefix() { })* e:primary() post:(_ r:postfix() { r })* {
+                let e = post.into_iter().fold(e, |e, r| Expr::Repeat(Box::new(e), r));
+                if p.is_empty() { e } else { Expr::Predicate }
+            }
+
+        rule tag()
+            = "#" ident() _ "=" _
+
+        rule prefix()
+            = ("&" / "!") _
+
+        rule primary() -> Expr
+            = "(" _ e:expr() _ ")" { e }
+            / "^"? s:string() { Expr::Literal(s) }
+            / a:character() _ ".." _ b:character() {
+                Expr::Class(format!("[{}-{}]", escape_class(a), escape_class(b)))
+            }
+            / c:character() { Expr::Literal(c.to_string()) }
+            / n:ident() {
+                match n.as_str() {
+                    "SOI" | "EOI" => Expr::Empty,
+                    _ => Expr::Reference(n),
+                }
+            }
+
+        rule postfix() -> Repetition
+            = "?" { Repetition::Optional }
+            / "*" { Repetition::ZeroOrMore }
+            / "+" { Repetition::OneOrMore }
+            / "{" _ min:$(['0'..='9']*) _ ("," _ ['0'..='9']* _)? "}" {
+                Repetition::Bounded { min: min.parse().unwrap_or(0) }
+            }
+
+        rule string() -> String
+            = "\"" s:(escaped() / [^'"' | '\\'])* "\"" { s.into_iter().collect() }
+
+        rule character() -> char
+            = "'" c:(escaped() / [^'\'' | '\\']) "'" { c }
+
+        rule escaped() -> char
+            = "\\" c:[_] {
+                match c {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    c => c,
+                }
+            }
+
+        rule ident() -> String
+            = s:$(['a'..='z' | 'A'..='Z' | '_']['a'..='z' | 'A'..='Z' | '0'..='9' | '_']*) {
+                s.to_string()
+            }
+
+        rule _() = quiet!{([' ' | '\t' | '\r' | '\n'] / "//" [^'\n']*)*}
+    }
+}
+
+fn escape_class(c: char) -> String {
+    match c {
+        '\\' | ']' | '[' | '^' | '-' => format!("\\{c}"),
+        c => c.to_string(),
+    }
+}
+
+/// Converts a pest grammar into a tmpl grammar.
+///
+/// Atomic rules and rules working on single characters describe tokens and
+/// are turned into regex patterns. `WHITESPACE` and `COMMENT` are dropped,
+/// since tmpl skips whitespace on its own.
+///
+/// Exporting the result with [`to_pest`](crate::export::to_pest) gives a pest
+/// grammar that imports to the same definition:
+///
+/// ```
+/// use tmpl::export::to_pest;
+/// use tmpl::import::from_pest;
+///
+/// let src = r#"
+/// Main = { SOI ~ pair ~ (";" ~ pair)* ~ EOI }
+/// pair = { sign ~ "(" ~ sign? ~ ")" }
+/// sign = { "+" | "-" }
+/// "#;
+/// let imported = from_pest(src)?;
+/// assert!(imported.warnings.is_empty());
+/// let exported = to_pest(&imported.definition);
+/// assert!(exported.text.contains(r#"pair = { sign ~ "(" ~ sign? ~ ")" }"#));
+/// assert_eq!(from_pest(&exported.text)?.definition, imported.definition);
+/// # Ok::<(), tmpl::import::ImportError>(())
+/// ```
+pub fn from_pest(src: &str) -> Result<Imported> {
+    let rules = parser::grammar(src)?
+        .into_iter()
+        .filter(|(name, modifier, _)| {
+            !(*modifier == Some('_') && (name == "WHITESPACE" || name == "COMMENT"))
+        })
+        .map(|(name, modifier, expr)| ForeignRule {
+            lexical: matches!(modifier, Some('@' | '$')) || expr.is_char_level(),
+            name,
+            expr,
+        })
+        .collect();
+    lower(rules)
+}
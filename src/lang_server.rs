@@ -0,0 +1,182 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use lsp_server::{Request, Response};
+use lsp_types as lsp;
+
+use tmpl::custom::{ParseSession, Parser, RuleSpan};
+use tmpl::definition::ParserDefinition;
+
+use crate::diagnostics::Diagnostic;
+use crate::lsp::{reply, unsupported, Documents};
+
+/// Runs a language server on stdin/stdout for files written in the language
+/// `definition` describes, parsing them from `entry`.
+pub fn run(definition: Arc<ParserDefinition>, entry: String) -> anyhow::Result<()> {
+    let capabilities = lsp::ServerCapabilities {
+        document_symbol_provider: Some(lsp::OneOf::Left(true)),
+        folding_range_provider: Some(lsp::FoldingRangeProviderCapability::Simple(true)),
+        ..Default::default()
+    };
+    let diagnostics = |text: &str| diagnostics(&definition, &entry, text);
+    let respond =
+        |documents: &Documents, request: Request| respond(&definition, &entry, documents, request);
+    crate::lsp::serve(capabilities, diagnostics, respond)
+}
+
+fn respond(
+    definition: &Arc<ParserDefinition>,
+    entry: &str,
+    documents: &Documents,
+    request: Request,
+) -> Response {
+    use lsp::request::{DocumentSymbolRequest, FoldingRangeRequest};
+    let outline = |uri: &lsp::Url| {
+        let text = documents.get(uri)?;
+        Some((text, rule_spans(definition, entry, text)?))
+    };
+    reply::<DocumentSymbolRequest>(&request, |params| {
+        let (text, spans) = outline(&params.text_document.uri)?;
+        Some(lsp::DocumentSymbolResponse::Nested(symbols(text, &spans)))
+    })
+    .or_else(|| {
+        reply::<FoldingRangeRequest>(&request, |params| {
+            let (text, spans) = outline(&params.text_document.uri)?;
+            Some(folding_ranges(text, &spans))
+        })
+    })
+    .unwrap_or_else(|| unsupported(&request))
+}
+
+/// The first syntax error in `text`. The parser stops at the first error, so
+/// there is at most one.
+fn diagnostics(
+    definition: &Arc<ParserDefinition>,
+    entry: &str,
+    text: &str,
+) -> Vec<lsp::Diagnostic> {
+    let Err(e) = crate::parse_text(
+        &mut ParseSession::new(Arc::clone(definition)),
+        None,
+        text,
+        entry,
+    ) else {
+        return Vec::new();
+    };
+    let (offset, message) = match e.downcast_ref::<Diagnostic>() {
+        Some(diagnostic) => {
+            let offset = diagnostic.span.as_ref().map_or(0, |span| span.offset);
+            (offset, diagnostic.message.clone())
+        }
+        None => (0, format!("{e:#}")),
+    };
+    let start = position(text, offset);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(text.len(), |(i, _)| offset + i);
+    vec![lsp::Diagnostic {
+        range: lsp::Range::new(start, position(text, end)),
+        severity: Some(lsp::DiagnosticSeverity::ERROR),
+        source: Some("tmpl".to_string()),
+        message,
+        ..Default::default()
+    }]
+}
+
+/// Byte ranges of every rule in a successful parse of `text`, outermost first.
+fn rule_spans(
+    definition: &Arc<ParserDefinition>,
+    entry: &str,
+    text: &str,
+) -> Option<Vec<(String, Range<usize>)>> {
+    let (tokens, offsets): (Vec<_>, Vec<_>) =
+        tmpl::lexer::lex_spanned(text).ok()?.into_iter().unzip();
+    let parser = Parser::new(Arc::clone(definition), tokens).with_spans(true);
+    parser.parse_entry(entry).ok()?;
+    let mut spans: Vec<_> = parser
+        .spans()?
+        .into_iter()
+        .filter(|span| span.start < span.end)
+        .map(|RuleSpan { rule, start, end }| (rule, offsets[start].start..offsets[end - 1].end))
+        .collect();
+    // Rules finish inside out, so reversing first keeps a rule ahead of any
+    // rule it wraps with the exact same range.
+    spans.reverse();
+    spans.sort_by_key(|(_, range)| (range.start, std::cmp::Reverse(range.end)));
+    Some(spans)
+}
+
+/// Nests the rule spans into document symbols named after their rule, with the
+/// first line of the matched text as detail.
+fn symbols(text: &str, spans: &[(String, Range<usize>)]) -> Vec<lsp::DocumentSymbol> {
+    let mut roots: Vec<lsp::DocumentSymbol> = Vec::new();
+    let mut open: Vec<(Range<usize>, lsp::DocumentSymbol)> = Vec::new();
+    let close = |open: &mut Vec<(Range<usize>, lsp::DocumentSymbol)>,
+                 roots: &mut Vec<lsp::DocumentSymbol>| {
+        let (_, symbol) = open.pop().expect("an open symbol");
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+            None => roots.push(symbol),
+        }
+    };
+    for (rule, range) in spans {
+        while open
+            .last()
+            .is_some_and(|(parent, _)| range.start >= parent.end)
+        {
+            close(&mut open, &mut roots);
+        }
+        let lsp_range = lsp::Range::new(position(text, range.start), position(text, range.end));
+        let detail = text[range.clone()]
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim();
+        #[allow(deprecated)]
+        let symbol = lsp::DocumentSymbol {
+            name: rule.clone(),
+            detail: Some(detail.to_string()),
+            kind: lsp::SymbolKind::OBJECT,
+            tags: None,
+            deprecated: None,
+            range: lsp_range,
+            selection_range: lsp_range,
+            children: None,
+        };
+        open.push((range.clone(), symbol));
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+/// A folding range for every rule that spans more than one line.
+fn folding_ranges(text: &str, spans: &[(String, Range<usize>)]) -> Vec<lsp::FoldingRange> {
+    let mut ranges: Vec<lsp::FoldingRange> = spans
+        .iter()
+        .map(|(_, range)| {
+            (
+                position(text, range.start).line,
+                position(text, range.end).line,
+            )
+        })
+        .filter(|(start, end)| start < end)
+        .map(|(start_line, end_line)| lsp::FoldingRange {
+            start_line,
+            end_line,
+            ..Default::default()
+        })
+        .collect();
+    ranges.dedup_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+/// Position of the byte `offset` in `text`, with the column counted in UTF-16 units.
+fn position(text: &str, offset: usize) -> lsp::Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() as u32;
+    lsp::Position::new(line, before[line_start..].encode_utf16().count() as u32)
+}
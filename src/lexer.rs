@@ -1,19 +1,27 @@
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
 use logos::Logos;
+use serde::Serialize;
 use thiserror::Error;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Default, Debug, Clone, PartialEq, Error)]
 pub enum LexingError {
     #[error("Invalid integer: {0}")]
-    InvalidInteger(#[from] std::num::ParseIntError),
+    InvalidInteger(#[from] core::num::ParseIntError),
     #[error("Invalid float: {0}")]
-    InvalidFloat(#[from] std::num::ParseFloatError),
+    InvalidFloat(#[from] core::num::ParseFloatError),
     #[error("Invalid lexeme")]
     #[default]
     InvalidLexeme,
 }
 
-#[derive(Debug, Logos, PartialEq)]
+#[derive(Debug, Clone, Logos, PartialEq, Serialize)]
 #[logos(error = LexingError)]
 pub enum Token {
     #[regex(r"([ \t]|\r\n|\n)+")]
@@ -22,12 +30,87 @@ pub enum Token {
     True,
     #[token("false")]
     False,
-    #[regex(r"[-+*/=>\\_.:,;<>!$%&?@]+", |lex| lex.slice().to_owned())]
+    #[regex(r#""([^"\\]|\\.)*""#, |lex| unescape(lex.slice()))]
+    String(String),
+    #[regex(r"[-+*/=>\\.:,;<>!$%&?@|()\[\]{}]", |lex| lex.slice().to_owned())]
     Symbol(String),
-    #[regex(r"[a-zA-Z_][a-zA-Z_0-9]+", |lex| lex.slice().to_owned())]
+    #[regex(r"[a-zA-Z_][a-zA-Z_0-9]*", |lex| lex.slice().to_owned())]
     Ident(String),
     #[regex(r"[0-9]+\.[0-9]*", |lex| lex.slice().parse())]
     Float(f64),
     #[regex(r"[0-9]+", |lex| lex.slice().parse())]
     Integer(i64),
 }
+
+impl Token {
+    /// Name of the token's variant, e.g. `ident` or `symbol`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Token::Ws => "ws",
+            Token::True | Token::False => "bool",
+            Token::String(_) => "string",
+            Token::Symbol(_) => "symbol",
+            Token::Ident(_) => "ident",
+            Token::Float(_) => "float",
+            Token::Integer(_) => "int",
+        }
+    }
+}
+
+pub type Span = core::ops::Range<usize>;
+
+/// Lexes `src`, keeping the byte range of every token.
+#[tracing::instrument(level = "debug", skip_all, fields(bytes = src.len()))]
+pub fn lex_spanned(src: &str) -> Result<Vec<(Token, Span)>, (LexingError, Span)> {
+    let tokens = Token::lexer(src)
+        .spanned()
+        .map(|(token, span)| match token {
+            Ok(token) => Ok((token, span)),
+            Err(e) => Err((e, span)),
+        })
+        .collect::<Result<Vec<_>, _>>();
+    if let Ok(tokens) = &tokens {
+        tracing::debug!(tokens = tokens.len(), "lexed input");
+    }
+    tokens
+}
+
+/// Lexes `src` like [`lex_spanned`] into `tokens` and `spans`, replacing
+/// what they held but keeping their capacity, to lex many inputs without
+/// allocating new buffers for each.
+#[tracing::instrument(level = "debug", skip_all, fields(bytes = src.len()))]
+pub fn lex_into(
+    src: &str,
+    tokens: &mut Vec<Token>,
+    spans: &mut Vec<Span>,
+) -> Result<(), (LexingError, Span)> {
+    tokens.clear();
+    spans.clear();
+    for (token, span) in Token::lexer(src).spanned() {
+        tokens.push(token.map_err(|e| (e, span.clone()))?);
+        spans.push(span);
+    }
+    tracing::debug!(tokens = tokens.len(), "lexed input");
+    Ok(())
+}
+
+fn unescape(quoted: &str) -> String {
+    quoted[1..quoted.len() - 1]
+        .replace("\\\"", "\"")
+        .replace("\\\\", "\\")
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Token::Ws => write!(f, " "),
+            Token::True => write!(f, "true"),
+            Token::False => write!(f, "false"),
+            Token::String(s) => write!(f, "\"{}\"", s),
+            Token::Symbol(s) => write!(f, "{}", s),
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::Float(fl) => write!(f, "{}", fl),
+            Token::Integer(i) => write!(f, "{}", i),
+        }
+    }
+}
@@ -1,6 +1,65 @@
+//! Without the default `std` feature only the lexer, the runtime parser in
+//! [`custom`] and the definition types are available, which is enough to
+//! parse input with a definition built in code or deserialized. The `diagram`
+//! feature adds the `diagram` module, the `miette` feature implements
+//! `miette::Diagnostic` for grammar text errors, and the `tmpl` binary needs
+//! the `cli` feature.
+
 // TODO: Remove the following line once the majority of the code has been implemented
 #![allow(dead_code, unused_imports, unused_variables)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod coverage;
 pub mod custom;
 pub mod definition;
+#[cfg(feature = "diagram")]
+pub mod diagram;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fuzz;
+#[cfg(feature = "std")]
+pub mod generate;
+#[cfg(feature = "std")]
+pub mod grammar;
+#[cfg(feature = "std")]
+pub mod graph;
+#[cfg(feature = "std")]
+pub mod highlight;
+#[cfg(feature = "std")]
+pub mod import;
 pub mod lexer;
+#[cfg(feature = "std")]
+pub mod lint;
+pub mod macros;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod rewrite;
+#[cfg(feature = "std")]
+pub mod scaffold;
+#[cfg(feature = "std")]
+pub mod source_format;
+#[cfg(feature = "std")]
+pub mod stats;
+pub mod style;
+pub mod template;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use grammar::{Grammar, GrammarOptions};
@@ -0,0 +1,346 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use regex_syntax::ast::{Ast, RepetitionKind, RepetitionRange};
+use stringlit::s;
+
+use crate::definition::{
+    walk_token, InternalPattern, InternalPatternKind, ParserDefinition, PatternVisitor, Regex,
+    RepeatMode, TokenPattern,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintId {
+    /// A rule none of the entry points reach.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse("Main:\n<n:int>\n~~~\n\nUnused:\n<b:bool>\n~~~\n")?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::UnusedRule]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    UnusedRule,
+    /// An alternative that never matches, since an earlier one always matches or
+    /// starts the same way.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse("Main:\n| <n:int>\n| <n:int> + <m:int>\n~~~\n")?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::UnreachableAlternative]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    UnreachableAlternative,
+    /// An optional or repeated pattern that already matches empty input.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse("Main:\n<a:A>?\n~~~\n\nA:\n<n:int>?\n~~~\n")?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::EmptyOptional]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    EmptyOptional,
+    /// An alternative starting with a keyword after one starting with an
+    /// identifier, which already matches the keyword.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse("Main:\n| <name:ident>\n| let <name:ident>\n~~~\n")?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::KeywordIdentConflict]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    KeywordIdentConflict,
+    /// A regex that matches empty input or whitespace, is anchored or can take
+    /// exponential time in a backtracking engine.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse("Main:\n<word:s/[a-z]*/>\n~~~\n")?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::SuspiciousRegex]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    SuspiciousRegex,
+    /// A rule only reachable through the rules of the `deprecated` define.
+    ///
+    /// ```
+    /// use tmpl::definition::parse;
+    /// use tmpl::lint::{lint, LintId};
+    ///
+    /// let definition = parse(
+    ///     "define deprecated: [\"Old\"];\n\nMain:\n<o:Old>\n~~~\n\n\
+    ///      Old:\n<h:Helper>\n~~~\n\nHelper:\n<i:ident>\n~~~\n",
+    /// )?;
+    /// let ids: Vec<_> = lint(&definition).into_iter().map(|lint| lint.id).collect();
+    /// assert_eq!(ids, [LintId::OnlyDeprecated]);
+    /// # Ok::<(), tmpl::Error>(())
+    /// ```
+    OnlyDeprecated,
+}
+
+impl LintId {
+    pub const ALL: [LintId; 6] = [
+        LintId::UnusedRule,
+        LintId::UnreachableAlternative,
+        LintId::EmptyOptional,
+        LintId::KeywordIdentConflict,
+        LintId::SuspiciousRegex,
+        LintId::OnlyDeprecated,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LintId::UnusedRule => "unused-rule",
+            LintId::UnreachableAlternative => "unreachable-alternative",
+            LintId::EmptyOptional => "empty-optional",
+            LintId::KeywordIdentConflict => "keyword-ident-conflict",
+            LintId::SuspiciousRegex => "suspicious-regex",
+            LintId::OnlyDeprecated => "only-deprecated",
+        }
+    }
+}
+
+impl Display for LintId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+impl FromStr for LintId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LintId::ALL
+            .into_iter()
+            .find(|id| id.name() == s)
+            .ok_or_else(|| format!("unknown lint: {s}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lint {
+    pub id: LintId,
+    pub rule: String,
+    pub message: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.id, self.rule, self.message)
+    }
+}
+
+/// Runs every lint over `definition`. Rules count as used if one of the
+/// [`ParserDefinition::entry_points`] reaches them.
+pub fn lint(definition: &ParserDefinition) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let reachability = definition.reachability();
+    let reachable = reachability.reachable;
+    let nullable = nullable_rules(definition);
+    for (name, patterns) in definition.all_rules() {
+        let mut push = |id, message: String| {
+            lints.push(Lint {
+                id,
+                rule: name.to_string(),
+                message,
+            })
+        };
+        if !reachable.contains(name) {
+            push(LintId::UnusedRule, s!("rule is never used"));
+        }
+        if reachability.only_deprecated.contains(&name) {
+            push(
+                LintId::OnlyDeprecated,
+                s!("rule is only used through deprecated rules"),
+            );
+        }
+        let alternatives: Vec<_> = patterns.iter().flat_map(|p| p.alternatives()).collect();
+        for (i, alternative) in alternatives.iter().enumerate() {
+            let earlier = &alternatives[..i];
+            if let Some(j) = earlier
+                .iter()
+                .position(|e| e.iter().all(|t| token_nullable(t, &nullable)))
+            {
+                push(
+                    LintId::UnreachableAlternative,
+                    format!(
+                        "alternative {} can never match, alternative {} always matches",
+                        i + 1,
+                        j + 1
+                    ),
+                );
+            } else if let Some(j) = earlier.iter().position(|e| is_prefix(e, alternative)) {
+                push(
+                    LintId::UnreachableAlternative,
+                    format!("alternative {} is shadowed by alternative {}", i + 1, j + 1),
+                );
+            }
+            if let Some(word) = alternative.first().and_then(leading_keyword) {
+                if let Some(j) = earlier.iter().position(|e| e.first().is_some_and(is_ident)) {
+                    push(
+                        LintId::KeywordIdentConflict,
+                        format!(
+                            "alternative {} starts with keyword `{word}`, but alternative {} already matches it as an identifier",
+                            i + 1,
+                            j + 1
+                        ),
+                    );
+                }
+            }
+        }
+        for token in alternatives.iter().flat_map(|a| flatten(a)) {
+            let repeats = token.is_optional || token.repeat_mode.is_some();
+            if repeats && pattern_nullable(&token.pattern, &nullable) {
+                push(
+                    LintId::EmptyOptional,
+                    format!("`{token}` wraps a pattern that already matches empty input"),
+                );
+            }
+            if let InternalPattern::Named {
+                kind: InternalPatternKind::Regex(re),
+                ..
+            } = &token.pattern
+            {
+                for problem in regex_problems(re) {
+                    push(LintId::SuspiciousRegex, format!("`{token}` {problem}"));
+                }
+            }
+        }
+    }
+    lints
+}
+
+/// Names of the rules that can match without consuming any input.
+pub(crate) fn nullable_rules(definition: &ParserDefinition) -> HashSet<String> {
+    definition
+        .first_sets()
+        .into_iter()
+        .filter(|(_, first)| first.nullable)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+pub(crate) fn token_nullable(token: &TokenPattern, nullable: &HashSet<String>) -> bool {
+    token.is_optional
+        || matches!(token.repeat_mode, Some(RepeatMode::ZeroOrMore))
+        || pattern_nullable(&token.pattern, nullable)
+}
+
+fn pattern_nullable(pattern: &InternalPattern, nullable: &HashSet<String>) -> bool {
+    match pattern {
+        InternalPattern::Named {
+            kind: InternalPatternKind::Custom(name),
+            ..
+        } => nullable.contains(name),
+        InternalPattern::Exact { pattern } => pattern.iter().all(|t| token_nullable(t, nullable)),
+        _ => false,
+    }
+}
+
+/// All token patterns of a sequence, including the ones nested in exact patterns.
+fn flatten(sequence: &[TokenPattern]) -> Vec<&TokenPattern> {
+    struct Tokens<'a>(Vec<&'a TokenPattern>);
+
+    impl<'a> PatternVisitor<'a> for Tokens<'a> {
+        fn visit_token(&mut self, token: &'a TokenPattern) {
+            self.0.push(token);
+            walk_token(self, token);
+        }
+    }
+
+    let mut tokens = Tokens(Vec::new());
+    tokens.visit_sequence(sequence);
+    tokens.0
+}
+
+fn is_prefix(prefix: &[TokenPattern], sequence: &[TokenPattern]) -> bool {
+    prefix.len() <= sequence.len()
+        && prefix
+            .iter()
+            .zip(sequence)
+            .all(|(a, b)| a.to_string() == b.to_string())
+}
+
+fn is_ident(token: &TokenPattern) -> bool {
+    matches!(
+        token.pattern,
+        InternalPattern::Named {
+            kind: InternalPatternKind::Ident,
+            ..
+        }
+    )
+}
+
+fn leading_keyword(token: &TokenPattern) -> Option<&str> {
+    match &token.pattern {
+        InternalPattern::Named {
+            kind: InternalPatternKind::Keyword(word),
+            ..
+        }
+        | InternalPattern::Raw { value: word } => Some(word.as_str()),
+        _ => None,
+    }
+}
+
+fn regex_problems(re: &Regex) -> Vec<&'static str> {
+    let source = re.as_str();
+    let mut problems = Vec::new();
+    if re.is_match("") {
+        problems.push("can match the empty string");
+    }
+    if source.contains("\\s") || source.contains(' ') {
+        problems.push("can match whitespace, which is never part of a token");
+    }
+    if source.starts_with('^') || source.ends_with('$') {
+        problems.push("is anchored, but regexes always have to match a whole token");
+    }
+    if let Ok(ast) = regex_syntax::ast::parse::Parser::new().parse(source) {
+        if backtracks(&ast, false) {
+            problems.push(
+                "nests unbounded repetitions or repeats equal alternatives, which backtracking engines, like the ones running exported highlighting grammars, can take exponential time on",
+            );
+        }
+    }
+    problems
+}
+
+/// Whether `ast` has an unbounded repetition of something that is itself
+/// repeated without bound or is an alternation with two equal branches,
+/// like `(a+)*` or `(a|a)+`. `repeated` is whether `ast` is inside such a
+/// repetition already.
+fn backtracks(ast: &Ast, repeated: bool) -> bool {
+    match ast {
+        Ast::Repetition(repetition) => {
+            let unbounded = matches!(
+                repetition.op.kind,
+                RepetitionKind::ZeroOrMore
+                    | RepetitionKind::OneOrMore
+                    | RepetitionKind::Range(RepetitionRange::AtLeast(_))
+            );
+            (unbounded && repeated) || backtracks(&repetition.ast, repeated || unbounded)
+        }
+        Ast::Group(group) => backtracks(&group.ast, repeated),
+        Ast::Concat(concat) => concat.asts.iter().any(|ast| backtracks(ast, repeated)),
+        Ast::Alternation(alternation) => {
+            let branches: Vec<_> = alternation.asts.iter().map(ToString::to_string).collect();
+            let overlapping = (1..branches.len()).any(|i| branches[..i].contains(&branches[i]));
+            (repeated && overlapping)
+                || alternation.asts.iter().any(|ast| backtracks(ast, repeated))
+        }
+        _ => false,
+    }
+}
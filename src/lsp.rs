@@ -0,0 +1,591 @@
+use std::collections::HashMap;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types as lsp;
+use lsp_types::notification::Notification as _;
+use lsp_types::request::Request as _;
+use lsp_types::Url;
+use regex::Regex;
+use tmpl::definition::{DefinitionParseError, ParserDefinition};
+
+/// Pattern kinds offered as completions inside `<...>`.
+const PATTERN_KINDS: [&str; 10] = [
+    "ident",
+    "int",
+    "float",
+    "string",
+    "bool",
+    "s//",
+    "kw[]",
+    "sym[]",
+    "oneof[]",
+    "balanced()",
+];
+
+/// Open documents by URI, kept in sync with the client.
+pub type Documents = HashMap<Url, String>;
+
+/// Runs a language server for `.tmpl` grammar files on stdin/stdout.
+pub fn run() -> anyhow::Result<()> {
+    let capabilities = lsp::ServerCapabilities {
+        definition_provider: Some(lsp::OneOf::Left(true)),
+        rename_provider: Some(lsp::OneOf::Left(true)),
+        references_provider: Some(lsp::OneOf::Left(true)),
+        hover_provider: Some(lsp::HoverProviderCapability::Simple(true)),
+        completion_provider: Some(lsp::CompletionOptions {
+            trigger_characters: Some(vec!["<".to_string(), ":".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    serve(capabilities, diagnostics, respond)
+}
+
+/// Runs a language server on stdin/stdout that publishes `diagnostics` for
+/// every changed document and answers requests with `respond`.
+pub fn serve(
+    capabilities: lsp::ServerCapabilities,
+    diagnostics: impl Fn(&str) -> Vec<lsp::Diagnostic>,
+    respond: impl Fn(&Documents, Request) -> Response,
+) -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = lsp::ServerCapabilities {
+        text_document_sync: Some(lsp::TextDocumentSyncCapability::Kind(
+            lsp::TextDocumentSyncKind::FULL,
+        )),
+        ..capabilities
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut documents = Documents::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = respond(&documents, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = update(&mut documents, notification)? {
+                    let params = lsp::PublishDiagnosticsParams {
+                        diagnostics: documents
+                            .get(&uri)
+                            .map(|t| diagnostics(t))
+                            .unwrap_or_default(),
+                        uri,
+                        version: None,
+                    };
+                    connection
+                        .sender
+                        .send(Message::Notification(Notification::new(
+                            lsp::notification::PublishDiagnostics::METHOD.to_string(),
+                            params,
+                        )))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Answers `request` with `f` if it is an `R` request.
+pub fn reply<R: lsp::request::Request>(
+    request: &Request,
+    f: impl FnOnce(R::Params) -> R::Result,
+) -> Option<Response> {
+    if request.method != R::METHOD {
+        return None;
+    }
+    let id = request.id.clone();
+    let response = match serde_json::from_value(request.params.clone()) {
+        Ok(params) => match serde_json::to_value(f(params)) {
+            Ok(value) => Response::new_ok(id, value),
+            Err(e) => Response::new_err(id, ErrorCode::InternalError as i32, e.to_string()),
+        },
+        Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string()),
+    };
+    Some(response)
+}
+
+/// Response for a request no handler took.
+pub fn unsupported(request: &Request) -> Response {
+    Response::new_err(
+        request.id.clone(),
+        ErrorCode::MethodNotFound as i32,
+        format!("unsupported request `{}`", request.method),
+    )
+}
+
+/// Applies document notifications, returning the document that changed.
+fn update(documents: &mut Documents, notification: Notification) -> anyhow::Result<Option<Url>> {
+    use lsp::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument};
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: lsp::DidOpenTextDocumentParams =
+                serde_json::from_value(notification.params)?;
+            let uri = params.text_document.uri;
+            documents.insert(uri.clone(), params.text_document.text);
+            Ok(Some(uri))
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: lsp::DidChangeTextDocumentParams =
+                serde_json::from_value(notification.params)?;
+            let uri = params.text_document.uri;
+            if let Some(change) = params.content_changes.into_iter().last() {
+                documents.insert(uri.clone(), change.text);
+            }
+            Ok(Some(uri))
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: lsp::DidCloseTextDocumentParams =
+                serde_json::from_value(notification.params)?;
+            documents.remove(&params.text_document.uri);
+            Ok(None)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn respond(documents: &Documents, request: Request) -> Response {
+    use lsp::request::{Completion, GotoDefinition, HoverRequest, References, Rename};
+    reply::<GotoDefinition>(&request, |params| definition(documents, params))
+        .or_else(|| reply::<Rename>(&request, |params| rename(documents, params)))
+        .or_else(|| reply::<References>(&request, |params| references(documents, params)))
+        .or_else(|| reply::<HoverRequest>(&request, |params| hover(documents, params)))
+        .or_else(|| reply::<Completion>(&request, |params| completion(documents, params)))
+        .unwrap_or_else(|| unsupported(&request))
+}
+
+/// A rule name as it appears in the grammar text.
+#[derive(Debug, Clone)]
+struct Occurrence {
+    name: String,
+    range: lsp::Range,
+}
+
+/// Rule definitions and references found by scanning the grammar text.
+#[derive(Debug, Default)]
+struct Outline {
+    /// Definitions with the line of their closing `~~~`.
+    definitions: Vec<(Occurrence, u32)>,
+    references: Vec<Occurrence>,
+}
+
+impl Outline {
+    fn scan(text: &str) -> Self {
+        let rule_start =
+            Regex::new(r"^\s*(?:override\s+)?([A-Za-z_][A-Za-z_0-9]*)\s*:").expect("valid regex");
+        let reference =
+            Regex::new(r"<\s*(?:[A-Za-z_][A-Za-z_0-9]*\s*:\s*)?([A-Za-z_][A-Za-z_0-9]*)\s*>")
+                .expect("valid regex");
+        let mut outline = Outline::default();
+        let mut in_rule = false;
+        for (number, line) in text.lines().enumerate() {
+            let number = number as u32;
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if in_rule && trimmed.starts_with("~~~") {
+                in_rule = false;
+                if let Some((_, end)) = outline.definitions.last_mut() {
+                    *end = number;
+                }
+                continue;
+            }
+            let mut body_start = 0;
+            if !in_rule {
+                if trimmed.starts_with("define") || trimmed.starts_with("///") {
+                    continue;
+                }
+                if let Some(name) = rule_start.captures(line).and_then(|c| c.get(1)) {
+                    let occurrence = occurrence(line, number, name);
+                    outline.definitions.push((occurrence, number));
+                    body_start = name.end();
+                    in_rule = true;
+                }
+            }
+            for captures in reference.captures_iter(&line[body_start..]) {
+                let Some(name) = captures.get(1) else {
+                    continue;
+                };
+                if ["ident", "int", "float", "string", "bool"].contains(&name.as_str()) {
+                    continue;
+                }
+                let start = body_start + name.start();
+                let range = utf16_range(line, number, start, start + name.len());
+                outline.references.push(Occurrence {
+                    name: name.as_str().to_string(),
+                    range,
+                });
+            }
+        }
+        outline
+    }
+
+    fn definition(&self, name: &str) -> Option<&(Occurrence, u32)> {
+        self.definitions.iter().find(|(d, _)| d.name == name)
+    }
+
+    /// The rule name under `position`, whether defined or referenced there.
+    fn name_at(&self, position: lsp::Position) -> Option<&str> {
+        let contains = |range: &lsp::Range| range.start <= position && position <= range.end;
+        self.definitions
+            .iter()
+            .map(|(d, _)| d)
+            .chain(&self.references)
+            .find(|o| contains(&o.range))
+            .map(|o| o.name.as_str())
+    }
+}
+
+fn occurrence(line: &str, number: u32, name: regex::Match) -> Occurrence {
+    Occurrence {
+        name: name.as_str().to_string(),
+        range: utf16_range(line, number, name.start(), name.end()),
+    }
+}
+
+/// Converts byte offsets within `line` to an LSP range, which counts UTF-16 units.
+fn utf16_range(line: &str, number: u32, start: usize, end: usize) -> lsp::Range {
+    let column = |offset: usize| line[..offset].encode_utf16().count() as u32;
+    lsp::Range::new(
+        lsp::Position::new(number, column(start)),
+        lsp::Position::new(number, column(end)),
+    )
+}
+
+/// Converts a byte range of `text` to an LSP range, cut off at the end of
+/// the line it starts on.
+fn span_range(text: &str, span: std::ops::Range<usize>) -> lsp::Range {
+    let start = text[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[start..].lines().next().unwrap_or_default();
+    let number = text[..start].matches('\n').count() as u32;
+    let end = (span.end - start).min(line.len());
+    utf16_range(line, number, span.start - start, end)
+}
+
+fn diagnostics(text: &str) -> Vec<lsp::Diagnostic> {
+    let error = |range: lsp::Range, message: String| lsp::Diagnostic {
+        range,
+        severity: Some(lsp::DiagnosticSeverity::ERROR),
+        source: Some("tmpl".to_string()),
+        message,
+        ..Default::default()
+    };
+    let outline = Outline::scan(text);
+    let mut diagnostics = Vec::new();
+    match tmpl::definition::parse(text) {
+        Err(tmpl::Error::Syntax(e)) => {
+            let line = e.location.line.saturating_sub(1) as u32;
+            let column = e.location.column.saturating_sub(1) as u32;
+            let position = lsp::Position::new(line, column);
+            let range = lsp::Range::new(position, lsp::Position::new(line, column + 1));
+            diagnostics.push(error(range, e.to_string()));
+        }
+        // Unknown references are reported for every occurrence below.
+        Err(tmpl::Error::Definition(DefinitionParseError::At { error, .. }))
+            if matches!(*error, DefinitionParseError::UnknownRule { .. }) => {}
+        Err(tmpl::Error::Definition(e)) if e.span().is_some() => {
+            let range = span_range(text, e.span().unwrap_or_default());
+            diagnostics.push(error(range, e.to_string()));
+        }
+        Err(e) => diagnostics.push(error(lsp::Range::default(), e.to_string())),
+        Ok(definition) => {
+            for lint in tmpl::lint::lint(&definition) {
+                let range = outline
+                    .definition(&lint.rule)
+                    .map(|(d, _)| d.range)
+                    .unwrap_or_default();
+                diagnostics.push(lsp::Diagnostic {
+                    range,
+                    severity: Some(lsp::DiagnosticSeverity::WARNING),
+                    code: Some(lsp::NumberOrString::String(lint.id.name().to_string())),
+                    source: Some("tmpl".to_string()),
+                    message: lint.message,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    for reference in &outline.references {
+        if outline.definition(&reference.name).is_none() {
+            let message = format!("unknown rule `{}`", reference.name);
+            diagnostics.push(error(reference.range, message));
+        }
+    }
+    diagnostics
+}
+
+fn definition(
+    documents: &Documents,
+    params: lsp::GotoDefinitionParams,
+) -> Option<lsp::GotoDefinitionResponse> {
+    let position = params.text_document_position_params;
+    let uri = position.text_document.uri;
+    let outline = Outline::scan(documents.get(&uri)?);
+    let name = outline.name_at(position.position)?;
+    let (definition, _) = outline.definition(name)?;
+    Some(lsp::GotoDefinitionResponse::Scalar(lsp::Location::new(
+        uri.clone(),
+        definition.range,
+    )))
+}
+
+/// Ranges of the declarations and references of the rule under `position`,
+/// taken from the symbol index of the grammar if it parses and from the
+/// scanned outline otherwise.
+fn occurrences(
+    text: &str,
+    position: lsp::Position,
+    include_declaration: bool,
+) -> Option<Vec<lsp::Range>> {
+    let outline = Outline::scan(text);
+    let name = outline.name_at(position)?;
+    let ranges = match tmpl::definition::parse(text) {
+        Ok(definition) => {
+            let declarations = match include_declaration {
+                true => definition.declarations(name),
+                false => Vec::new(),
+            };
+            (declarations.into_iter())
+                .chain(definition.references(name))
+                .map(|location| span_range(text, location.span))
+                .collect()
+        }
+        Err(_) => {
+            let declarations = outline.definitions.iter().map(|(d, _)| d);
+            (declarations.filter(|_| include_declaration))
+                .chain(&outline.references)
+                .filter(|o| o.name == name)
+                .map(|o| o.range)
+                .collect()
+        }
+    };
+    Some(ranges)
+}
+
+fn rename(documents: &Documents, params: lsp::RenameParams) -> Option<lsp::WorkspaceEdit> {
+    let position = params.text_document_position;
+    let uri = position.text_document.uri;
+    let edits = occurrences(documents.get(&uri)?, position.position, true)?
+        .into_iter()
+        .map(|range| lsp::TextEdit::new(range, params.new_name.clone()))
+        .collect();
+    Some(lsp::WorkspaceEdit::new(HashMap::from([(uri, edits)])))
+}
+
+fn references(documents: &Documents, params: lsp::ReferenceParams) -> Option<Vec<lsp::Location>> {
+    let position = params.text_document_position;
+    let uri = position.text_document.uri;
+    let include_declaration = params.context.include_declaration;
+    let ranges = occurrences(documents.get(&uri)?, position.position, include_declaration)?;
+    let locations = ranges
+        .into_iter()
+        .map(|range| lsp::Location::new(uri.clone(), range));
+    Some(locations.collect())
+}
+
+fn hover(documents: &Documents, params: lsp::HoverParams) -> Option<lsp::Hover> {
+    let position = params.text_document_position_params;
+    let text = documents.get(&position.text_document.uri)?;
+    let outline = Outline::scan(text);
+    let name = outline.name_at(position.position)?;
+    let (definition, end) = outline.definition(name)?;
+    let value = match tmpl::definition::parse(text) {
+        Ok(parsed) => describe_rule(&parsed, name)?,
+        // Grammars with errors are shown as they are written.
+        Err(_) => {
+            let start = definition.range.start.line as usize;
+            let body = text
+                .lines()
+                .skip(start)
+                .take(*end as usize + 1 - start)
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("```tmpl\n{body}\n```")
+        }
+    };
+    Some(lsp::Hover {
+        contents: lsp::HoverContents::Markup(lsp::MarkupContent {
+            kind: lsp::MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    })
+}
+
+/// Markdown with the rule `name` pretty-printed, its doc comment and the
+/// fields of its trees.
+fn describe_rule(definition: &ParserDefinition, name: &str) -> Option<String> {
+    let mut value = format!("```tmpl\n{}```", definition.rule_text(name)?);
+    if let Some(doc) = definition.doc(name) {
+        value.push_str("\n\n");
+        value.push_str(doc);
+    }
+    if let Some(captures) = describe_captures(definition, name) {
+        value.push_str("\n\n");
+        value.push_str(&captures);
+    }
+    Some(value)
+}
+
+fn describe_captures(definition: &ParserDefinition, name: &str) -> Option<String> {
+    let captures = definition.captures(name);
+    if captures.is_empty() {
+        return None;
+    }
+    let captures: Vec<_> = captures.iter().map(|c| format!("`{c}`")).collect();
+    Some(format!("Captures: {}", captures.join(", ")))
+}
+
+fn completion(
+    documents: &Documents,
+    params: lsp::CompletionParams,
+) -> Option<lsp::CompletionResponse> {
+    let uri = params.text_document_position.text_document.uri;
+    let text = documents.get(&uri)?;
+    let outline = Outline::scan(text);
+    let parsed = tmpl::definition::parse(text).ok();
+    let kinds = PATTERN_KINDS.iter().map(|kind| lsp::CompletionItem {
+        label: kind.to_string(),
+        kind: Some(lsp::CompletionItemKind::KEYWORD),
+        ..Default::default()
+    });
+    let rules = outline
+        .definitions
+        .iter()
+        .map(|(d, _)| lsp::CompletionItem {
+            label: d.name.clone(),
+            kind: Some(lsp::CompletionItemKind::CLASS),
+            detail: (parsed.as_ref()).and_then(|parsed| describe_captures(parsed, &d.name)),
+            documentation: (parsed.as_ref())
+                .and_then(|parsed| parsed.doc(&d.name))
+                .map(|doc| lsp::Documentation::String(doc.to_string())),
+            ..Default::default()
+        });
+    Some(lsp::CompletionResponse::Array(kinds.chain(rules).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = "Main:\n<items:Item>*\n~~~\n\n/// One item.\nItem:\n<n:int>\n~~~\n";
+
+    fn uri() -> Url {
+        Url::parse("file:///grammar.tmpl").unwrap()
+    }
+
+    /// Sends an `R` request about [`GRAMMAR`] through [`respond`].
+    fn request<R: lsp::request::Request>(params: R::Params) -> R::Result {
+        let documents = Documents::from([(uri(), GRAMMAR.to_string())]);
+        let response = respond(&documents, Request::new(1.into(), R::METHOD.into(), params));
+        serde_json::from_value(response.result.expect("request succeeds")).unwrap()
+    }
+
+    /// Where `line` and `character` are in [`GRAMMAR`].
+    fn position(line: u32, character: u32) -> lsp::TextDocumentPositionParams {
+        let document = lsp::TextDocumentIdentifier::new(uri());
+        lsp::TextDocumentPositionParams::new(document, lsp::Position::new(line, character))
+    }
+
+    fn range(line: u32, start: u32, end: u32) -> lsp::Range {
+        lsp::Range::new(
+            lsp::Position::new(line, start),
+            lsp::Position::new(line, end),
+        )
+    }
+
+    #[test]
+    fn diagnostics_point_at_unknown_rules() {
+        assert!(diagnostics(GRAMMAR).is_empty());
+        let found = diagnostics("Main:\n<a:Missing>\n~~~\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].range, range(1, 3, 10));
+        assert_eq!(found[0].message, "unknown rule `Missing`");
+    }
+
+    #[test]
+    fn diagnostics_report_lints_on_the_rule() {
+        let found = diagnostics("Main:\n<n:int>\n~~~\n\nUnused:\n<b:bool>\n~~~\n");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].range, range(4, 0, 6));
+        assert_eq!(found[0].severity, Some(lsp::DiagnosticSeverity::WARNING));
+        let code = lsp::NumberOrString::String("unused-rule".to_string());
+        assert_eq!(found[0].code, Some(code));
+    }
+
+    #[test]
+    fn goes_to_the_rule_definition() {
+        let response = request::<lsp::request::GotoDefinition>(lsp::GotoDefinitionParams {
+            text_document_position_params: position(1, 8),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let location = lsp::Location::new(uri(), range(5, 0, 4));
+        assert_eq!(
+            response,
+            Some(lsp::GotoDefinitionResponse::Scalar(location))
+        );
+    }
+
+    #[test]
+    fn renames_declarations_and_references() {
+        let edit = request::<lsp::request::Rename>(lsp::RenameParams {
+            text_document_position: position(5, 2),
+            new_name: "Entry".to_string(),
+            work_done_progress_params: Default::default(),
+        });
+        let mut ranges: Vec<_> = edit.unwrap().changes.unwrap()[&uri()]
+            .iter()
+            .map(|edit| edit.range)
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        assert_eq!(ranges, [range(1, 7, 11), range(5, 0, 4)]);
+    }
+
+    #[test]
+    fn hover_shows_docs_and_captures() {
+        let hover = request::<lsp::request::HoverRequest>(lsp::HoverParams {
+            text_document_position_params: position(1, 8),
+            work_done_progress_params: Default::default(),
+        });
+        let lsp::HoverContents::Markup(content) = hover.unwrap().contents else {
+            panic!("hover is markdown");
+        };
+        assert!(content.value.contains("One item."));
+        assert!(content.value.ends_with("Captures: `n`"));
+    }
+
+    #[test]
+    fn completes_pattern_kinds_and_rules() {
+        let completion = request::<lsp::request::Completion>(lsp::CompletionParams {
+            text_document_position: position(6, 1),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+        let Some(lsp::CompletionResponse::Array(items)) = completion else {
+            panic!("completions are a list");
+        };
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert!(labels.contains(&"int"));
+        assert!(labels.contains(&"Item"));
+    }
+
+    #[test]
+    fn rejects_unsupported_requests() {
+        let request = Request::new(1.into(), "textDocument/formatting".into(), ());
+        let response = respond(&Documents::new(), request);
+        assert_eq!(
+            response.error.map(|e| e.code),
+            Some(ErrorCode::MethodNotFound as i32)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use thiserror::Error;
+
+use crate::custom::{Ast, Node};
+use crate::definition::ParserDefinition;
+
+/// Expands the macros of languages that have them, after parsing. A
+/// grammar lists the rules whose trees define macros in a
+/// `macro_definitions` define and those whose trees invoke them in a
+/// `macro_invocations` define:
+///
+/// ```text
+/// define macro_definitions: "Macro";
+/// define macro_invocations: "Call";
+/// ```
+///
+/// - A definition has a `name` field with an identifier, a `params` field
+///   with a list of identifiers, which can be missing for macros without
+///   parameters, and a `body` field. Definitions are taken out of the tree,
+///   dropped from lists and left as unmatched fields otherwise. All of them
+///   are collected before expanding, so invocations can come first.
+/// - An invocation has a `name` field and an `args` field with a list of
+///   arguments, one per parameter. It is replaced by the body of the macro
+///   with the parameters substituted, which is then expanded in turn. An
+///   invocation in a list that expands to a list is spliced into it.
+///
+/// A parameter is substituted where the body has the identifier itself, or
+/// a tree whose only field is the identifier, like `Var(name: x)`. Macros
+/// aren't hygienic: any such identifier in the body is substituted.
+///
+/// ```
+/// use tmpl::custom::Node;
+/// use tmpl::macros::Macros;
+///
+/// let grammar = tmpl::Grammar::parse(concat!(
+///     "define macro_definitions: \"Macro\";\n",
+///     "define macro_invocations: \"Call\";\n\n",
+///     "Main:\n<macros:Macro>* <calls:Call>*\n~~~\n\n",
+///     "Macro:\nmacro <name:ident> ( <params:ident> ** \",\" ) { <body:Print>* }\n~~~\n\n",
+///     "Call:\n<name:ident> ( <args:Value> ** \",\" ) ;\n~~~\n\n",
+///     "Print:\nprint <value:Value> ;\n~~~\n\n",
+///     "Value:\n| <int:int>\n| <var:ident>\n~~~\n",
+/// ))?;
+/// let ast = grammar.parse_str("macro twice(x) { print x; print x; } twice(1); twice(2);")?;
+/// let macros = Macros::new(grammar.definition());
+/// let Node::Ast(expanded) = macros.expand(Node::Ast(ast))? else {
+///     unreachable!("`Main` is neither a definition nor an invocation")
+/// };
+/// assert_eq!(expanded.fields["macros"], Node::List(Vec::new()));
+/// let Node::List(prints) = &expanded.fields["calls"] else {
+///     unreachable!("`calls` is repeated")
+/// };
+/// assert_eq!(prints.len(), 4);
+/// let expected = serde_json::json!({
+///     "rule": "Print",
+///     "fields": { "value": { "rule": "Value", "fields": { "int": 2 } } },
+/// });
+/// assert_eq!(prints[3].to_json_value(), expected);
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Macros {
+    definitions: Vec<String>,
+    invocations: Vec<String>,
+}
+
+/// How deep invocations can expand to further invocations before
+/// [`Macros::expand`] gives up, as a macro that invokes itself never ends.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum MacroError {
+    #[error("`{rule}` tree has no `{field}` field, which macros need")]
+    MissingField { rule: String, field: &'static str },
+    #[error("Parameters of macro `{0}` have to be identifiers")]
+    Parameter(String),
+    #[error("Macro `{0}` is defined more than once")]
+    Redefined(String),
+    #[error("Macro `{0}` isn't defined")]
+    Unknown(String),
+    #[error("Macro `{name}` takes {expected} argument(s), but got {found}")]
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Expanding macro `{0}` nested more than {MAX_EXPANSION_DEPTH} deep, it keeps invoking itself")]
+    TooDeep(String),
+    #[error("The whole tree is a macro definition or expands to a value")]
+    Root,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Macro {
+    params: Vec<String>,
+    body: Node,
+}
+
+impl Macros {
+    /// The macro rules `definition` lists in its `macro_definitions` and
+    /// `macro_invocations` defines.
+    pub fn new(definition: &ParserDefinition) -> Self {
+        let names = |define| {
+            (definition.define_names(define).into_iter())
+                .map(ToString::to_string)
+                .collect()
+        };
+        Self {
+            definitions: names("macro_definitions"),
+            invocations: names("macro_invocations"),
+        }
+    }
+
+    /// Whether the grammar has no macros, so expanding changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty() && self.invocations.is_empty()
+    }
+
+    /// Takes the macro definitions out of `node` and expands every
+    /// invocation in it.
+    pub fn expand(&self, node: Node) -> Result<Node, MacroError> {
+        let mut macros = BTreeMap::new();
+        let node = self.collect(node, &mut macros)?.unwrap_or(Node::None);
+        self.expand_node(node, &macros, 0)
+    }
+
+    /// Removes the definitions from `node` into `macros`, giving `None` if
+    /// `node` is one itself.
+    fn collect(
+        &self,
+        node: Node,
+        macros: &mut BTreeMap<String, Macro>,
+    ) -> Result<Option<Node>, MacroError> {
+        Ok(Some(match node {
+            Node::Ast(mut ast) if self.definitions.contains(&ast.rule) => {
+                let name = name(&ast)?;
+                let params = match ast.fields.remove("params") {
+                    None | Some(Node::None) => Vec::new(),
+                    Some(Node::List(params)) => params
+                        .iter()
+                        .map(|param| text(param).map(ToString::to_string))
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| MacroError::Parameter(name.clone()))?,
+                    Some(param) => Vec::from([text(&param)
+                        .ok_or_else(|| MacroError::Parameter(name.clone()))?
+                        .to_string()]),
+                };
+                let body = (ast.fields.remove("body")).ok_or_else(|| missing(&ast, "body"))?;
+                if macros
+                    .insert(name.clone(), Macro { params, body })
+                    .is_some()
+                {
+                    return Err(MacroError::Redefined(name));
+                }
+                return Ok(None);
+            }
+            Node::Ast(ast) => Node::Ast(Ast {
+                rule: ast.rule,
+                fields: (ast.fields.into_iter())
+                    .map(|(field, node)| {
+                        Ok((field, self.collect(node, macros)?.unwrap_or(Node::None)))
+                    })
+                    .collect::<Result<_, MacroError>>()?,
+            }),
+            Node::List(items) => Node::List(
+                items
+                    .into_iter()
+                    .filter_map(|item| self.collect(item, macros).transpose())
+                    .collect::<Result<_, _>>()?,
+            ),
+            node => node,
+        }))
+    }
+
+    fn expand_node(
+        &self,
+        node: Node,
+        macros: &BTreeMap<String, Macro>,
+        depth: usize,
+    ) -> Result<Node, MacroError> {
+        Ok(match node {
+            Node::Ast(ast) if self.invocations.contains(&ast.rule) => {
+                let name = name(&ast)?;
+                let args = match ast.fields.get("args") {
+                    None | Some(Node::None) => &[][..],
+                    Some(Node::List(args)) => args.as_slice(),
+                    Some(arg) => core::slice::from_ref(arg),
+                };
+                let Some(definition) = macros.get(&name) else {
+                    return Err(MacroError::Unknown(name));
+                };
+                if args.len() != definition.params.len() {
+                    return Err(MacroError::Arity {
+                        name,
+                        expected: definition.params.len(),
+                        found: args.len(),
+                    });
+                }
+                if depth == MAX_EXPANSION_DEPTH {
+                    return Err(MacroError::TooDeep(name));
+                }
+                let bindings = (definition.params.iter())
+                    .map(String::as_str)
+                    .zip(args)
+                    .collect();
+                let body = substitute(definition.body.clone(), &bindings);
+                self.expand_node(body, macros, depth + 1)?
+            }
+            Node::Ast(ast) => Node::Ast(Ast {
+                rule: ast.rule,
+                fields: (ast.fields.into_iter())
+                    .map(|(field, node)| Ok((field, self.expand_node(node, macros, depth)?)))
+                    .collect::<Result<_, MacroError>>()?,
+            }),
+            Node::List(items) => {
+                let mut expanded = Vec::with_capacity(items.len());
+                for item in items {
+                    let invocation =
+                        matches!(&item, Node::Ast(ast) if self.invocations.contains(&ast.rule));
+                    match self.expand_node(item, macros, depth)? {
+                        Node::List(spliced) if invocation => expanded.extend(spliced),
+                        node => expanded.push(node),
+                    }
+                }
+                Node::List(expanded)
+            }
+            node => node,
+        })
+    }
+}
+
+/// Replaces the parameters in `node` by their arguments. Arguments aren't
+/// substituted again, so they can mention parameters themselves.
+fn substitute(node: Node, bindings: &BTreeMap<&str, &Node>) -> Node {
+    if let Some(arg) = parameter(&node).and_then(|name| bindings.get(name)) {
+        return (*arg).clone();
+    }
+    match node {
+        Node::Ast(ast) => Node::Ast(Ast {
+            rule: ast.rule,
+            fields: (ast.fields.into_iter())
+                .map(|(field, node)| (field, substitute(node, bindings)))
+                .collect(),
+        }),
+        Node::List(items) => Node::List(
+            items
+                .into_iter()
+                .map(|item| substitute(item, bindings))
+                .collect(),
+        ),
+        node => node,
+    }
+}
+
+/// The identifier that `node` is, or that a tree like `Var(name: x)` with
+/// only one field consists of.
+fn parameter(node: &Node) -> Option<&str> {
+    match node {
+        Node::Ident(name) => Some(name.as_str()),
+        Node::Ast(ast) if ast.fields.len() == 1 => match ast.fields.values().next() {
+            Some(Node::Ident(name)) => Some(name.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn text(node: &Node) -> Option<&str> {
+    match node {
+        Node::Ident(text) | Node::Text(text) | Node::String(text) => Some(text.as_str()),
+        _ => None,
+    }
+}
+
+/// The `name` of a definition or invocation.
+fn name(ast: &Ast) -> Result<String, MacroError> {
+    (ast.fields.get("name").and_then(text))
+        .map(ToString::to_string)
+        .ok_or_else(|| missing(ast, "name"))
+}
+
+fn missing(ast: &Ast, field: &'static str) -> MacroError {
+    MacroError::MissingField {
+        rule: ast.rule.clone(),
+        field,
+    }
+}
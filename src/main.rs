@@ -1,29 +1,1264 @@
 #![allow(dead_code, unused_imports)]
 
-use std::path::PathBuf;
+mod batch;
+mod bench;
+mod diagnostics;
+mod fuzz_runner;
+mod lang_server;
+mod lsp;
+mod output;
+mod repl;
+mod serve;
+mod test_runner;
+mod tokens;
 
-use clap::Parser;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use clap::{ColorChoice, CommandFactory, Parser, Subcommand, ValueEnum};
+use diagnostics::{Diagnostic, ErrorFormat, ErrorKind, Span};
 use logos::Logos;
+use output::Format;
 use serde::Serialize;
+use tmpl::custom::{Ast, Node, ParseError, ParseSession};
+use tmpl::definition::{ParserDefinition, Severity, ValidationIssue};
+use tmpl::lexer::Token;
+use tmpl::lint::LintId;
+use tmpl::rewrite::{RewriteError, Rewrites};
+use tmpl::style::OutputStyle;
+use tmpl::template::{Template, TemplateError};
 
+/// Describe languages with template-like grammars and parse sources with them
 #[derive(Parser)]
+#[command(name = "tmpl", version)]
 struct Opts {
-    grammar: PathBuf,
-    src: Option<PathBuf>,
+    /// Output format for serialized results
+    #[arg(long, value_enum, default_value_t = Format::Yaml, global = true)]
+    format: Format,
+    /// Format used to report errors
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Human, global = true)]
+    error_format: ErrorFormat,
+    /// Trace the grammar and source parsers on stderr
+    #[arg(long, global = true)]
+    trace: bool,
+    /// Treat grammar warnings, like unused rules, as errors
+    #[arg(long, global = true)]
+    strict: bool,
+    /// When to color error messages
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto, global = true)]
+    color: ColorChoice,
+    /// Log progress on stderr, repeat for more detail (-v, -vv, -vvv)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a starter grammar guessed from a sample source file
+    Init {
+        /// Sample written in the language the grammar should describe
+        #[arg(long)]
+        from: PathBuf,
+        /// File to write the grammar to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Draft a grammar from the statements of example source files
+    Infer {
+        /// Example files or glob patterns
+        #[arg(required = true)]
+        samples: Vec<String>,
+        /// File to write the grammar to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Validate a grammar file
+    Check { grammar: PathBuf },
+    /// Print the grammar exactly as the parser will use it
+    Expand {
+        grammar: PathBuf,
+        /// Print the definition's data structure instead of grammar text
+        #[arg(long)]
+        tree: bool,
+        /// Print the grammar after optimizing it, as `compile --optimize` stores it
+        #[arg(long)]
+        optimize: bool,
+        /// Print the grammar after minimizing it, as `compile --minimize` stores it
+        #[arg(long)]
+        minimize: bool,
+    },
+    /// Validate a grammar and store it in a binary file that loads faster
+    Compile {
+        grammar: PathBuf,
+        /// File to write the compiled grammar to
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Rewrite the grammar to parse faster, without changing the syntax trees
+        #[arg(long)]
+        optimize: bool,
+        /// Remove unused rules and inline those used once, without changing the
+        /// syntax trees of the entry rules
+        #[arg(long)]
+        minimize: bool,
+    },
+    /// Parse source files using a grammar or compiled grammar (either path may be `-` for stdin)
+    Parse {
+        grammar: PathBuf,
+        /// Source files or glob patterns
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// Number of files parsed at once, one per CPU by default
+        #[arg(short = 'j', long)]
+        jobs: Option<usize>,
+        /// Rewrite the grammar to parse faster before parsing, without changing the syntax trees
+        #[arg(long)]
+        optimize: bool,
+        /// Rewrite the printed syntax tree with the `match .. => ..` rules in this file
+        #[arg(long)]
+        rewrite: Option<PathBuf>,
+    },
+    /// Parse a source file and fill in an output template from its syntax tree
+    Render {
+        grammar: PathBuf,
+        src: PathBuf,
+        /// Template with placeholders like `{name}` for the captures of the tree,
+        /// defaults to the `emit` templates of the grammar's rules
+        #[arg(short, long)]
+        template: Option<PathBuf>,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// Rewrite the syntax tree with the `match .. => ..` rules in this file first
+        #[arg(long)]
+        rewrite: Option<PathBuf>,
+        /// File to write the output to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        style: StyleArgs,
+    },
+    /// Translate a source file into the language of another grammar
+    Translate {
+        /// Grammar of the source file
+        #[arg(long)]
+        from: PathBuf,
+        /// Grammar whose `emit` templates write the output
+        #[arg(long)]
+        to: PathBuf,
+        /// `match .. => ..` rules turning trees of the first grammar into trees of
+        /// the second, needed unless both name their rules alike
+        #[arg(long)]
+        map: Option<PathBuf>,
+        src: PathBuf,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// File to write the output to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Measure lexing and parsing throughput of a grammar
+    Bench {
+        grammar: PathBuf,
+        /// Source files or glob patterns
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// How often every file is lexed and parsed
+        #[arg(short = 'n', long, default_value_t = 100)]
+        iterations: u32,
+    },
+    /// Report which alternatives of a grammar's rules parsing a corpus uses and which it never tests
+    Coverage {
+        grammar: PathBuf,
+        /// Source files or glob patterns
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// Write the report as an HTML page to this file instead of printing it
+        #[arg(long)]
+        html: Option<PathBuf>,
+    },
+    /// Report size and shape figures of a grammar
+    Stats { grammar: PathBuf },
+    /// Check that mutated inputs never crash the lexer or parser
+    Fuzz {
+        grammar: PathBuf,
+        /// Sample inputs to mutate, random tokens are generated without any
+        samples: Vec<String>,
+        /// Number of inputs to try
+        #[arg(short = 'n', long, default_value_t = 10_000)]
+        iterations: u32,
+        /// Seed for the random number generator, to reproduce a run
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Dump the tokens of a source file with their kinds and positions
+    Tokens {
+        src: PathBuf,
+        /// Print an aligned table instead of serialized records
+        #[arg(long)]
+        table: bool,
+    },
+    /// Reformat a grammar file in place (`-` prints the result instead)
+    Fmt {
+        grammar: PathBuf,
+        /// Only check whether the file is formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Reformat a source file in the layout its grammar implies, in place (`-` prints the result instead)
+    FmtSrc {
+        grammar: PathBuf,
+        src: PathBuf,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// Only check whether the file is formatted
+        #[arg(long)]
+        check: bool,
+        #[command(flatten)]
+        style: StyleArgs,
+    },
+    /// Report likely mistakes in a grammar file
+    Lint {
+        grammar: PathBuf,
+        /// Treat the given lints (or `all`) as errors
+        #[arg(long)]
+        deny: Vec<String>,
+    },
+    /// Render the rules of a grammar as railroad diagrams in an HTML page
+    Diagram {
+        grammar: PathBuf,
+        /// File to write the HTML to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print which rules reference which, with cycles highlighted
+    Graph {
+        grammar: PathBuf,
+        /// Notation to render the graph in
+        #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+        to: GraphFormat,
+    },
+    /// Compare two grammars rule by rule
+    Diff { old: PathBuf, new: PathBuf },
+    /// Compare the syntax trees of two source files parsed with the same grammar
+    DiffAst {
+        grammar: PathBuf,
+        old: PathBuf,
+        new: PathBuf,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+    },
+    /// Convert a grammar into another grammar notation
+    Export {
+        grammar: PathBuf,
+        /// Notation to convert to
+        #[arg(long, value_enum)]
+        to: ExportFormat,
+        /// File to write the result to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate a standalone Rust parser with typed AST structs
+    Codegen {
+        grammar: PathBuf,
+        /// File to write the Rust source to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave out the file level attributes so the output can be `include!`d
+        #[arg(long)]
+        embedded: bool,
+        /// Derive serde's Serialize and Deserialize for the AST types
+        #[arg(long)]
+        serde: bool,
+    },
+    /// Print random inputs matching a grammar
+    Generate {
+        grammar: PathBuf,
+        /// Number of inputs to generate
+        #[arg(long, default_value_t = 10)]
+        count: usize,
+        /// Nesting depth after which rules take their shortest alternative
+        #[arg(long, default_value_t = 5)]
+        max_depth: usize,
+        /// Seed for the random number generator, to reproduce a run
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Parse every input and report the ones the grammar rejects
+        #[arg(long)]
+        check: bool,
+    },
+    /// Generate editor syntax highlighting for the language a grammar describes
+    Highlight {
+        grammar: PathBuf,
+        /// Highlighting format to generate
+        #[arg(long, value_enum)]
+        to: HighlightFormat,
+        /// File to write the result to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Generate highlighting for tmpl grammar files themselves in an editor's format
+    SelfHighlight {
+        /// Editor format to generate
+        #[arg(long, value_enum)]
+        to: SelfHighlightFormat,
+        /// File to write the result to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Convert a grammar from another notation into a tmpl grammar
+    Import {
+        file: PathBuf,
+        /// Notation to convert from
+        #[arg(long, value_enum)]
+        from: ImportFormat,
+        /// File to write the result to, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Run the grammar tests in a directory of YAML test specs
+    Test {
+        grammar: PathBuf,
+        /// Directory containing the tests, defaults to `tests` next to the grammar.
+        /// The `test` blocks of the grammar run as well
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Replace the AST and error snapshots with the current results. Tests that
+        /// expect success but fail, or the other way around, still fail
+        #[arg(long)]
+        update: bool,
+    },
+    /// Run a language server for grammar files on stdin/stdout
+    Lsp,
+    /// Run a language server for sources written in the language a grammar describes
+    LangServer {
+        grammar: PathBuf,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        entry: String,
+    },
+    /// Serve a JSON API for parsing sources with a grammar over HTTP
+    Serve {
+        grammar: PathBuf,
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// Address to bind to
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Rule to start parsing from when a request doesn't name one
+        #[arg(long, default_value = "Main")]
+        entry: String,
+        /// Number of requests handled at once, one per CPU by default
+        #[arg(short = 'j', long)]
+        workers: Option<usize>,
+        /// Seconds a parse may take before the request is answered with 504
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Print a shell completion script
+    Completions { shell: clap_complete::Shell },
+    /// Print the man page
+    Man,
+    /// Print a JSON Schema for grammars serialized as JSON, like `expand --tree --format json` prints
+    Schema,
+    /// Interactively parse inputs against a grammar
+    Repl {
+        grammar: PathBuf,
+        /// Rule to start parsing from
+        #[arg(long, default_value = "Main")]
+        rule: String,
+    },
+}
+
+/// Overrides of the output style a grammar sets with `output_*` defines
+#[derive(clap::Args)]
+struct StyleArgs {
+    /// Columns per indentation level
+    #[arg(long)]
+    indent_width: Option<usize>,
+    /// Indent with tabs instead of spaces
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    tabs: Option<bool>,
+    /// Break lines that get longer than this after a separator
+    #[arg(long)]
+    line_width: Option<usize>,
+    /// Put a space after separators like `,`, or not with `false`
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    separator_space: Option<bool>,
+}
+
+impl StyleArgs {
+    /// The style of `definition` with the flags that were given applied.
+    fn style(&self, definition: &ParserDefinition) -> OutputStyle {
+        let mut style = OutputStyle::from_definition(definition);
+        if let Some(indent_width) = self.indent_width {
+            style.indent_width = indent_width;
+        }
+        if let Some(tabs) = self.tabs {
+            style.tabs = tabs;
+        }
+        if let Some(line_width) = self.line_width {
+            style.line_width = Some(line_width);
+        }
+        if let Some(separator_space) = self.separator_space {
+            style.separator_space = Some(separator_space);
+        }
+        style
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Ebnf,
+    Pest,
+    Antlr,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HighlightFormat {
+    Textmate,
+    TreeSitterQuery,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SelfHighlightFormat {
+    Textmate,
+    Vim,
+    Emacs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Ebnf,
+    Pest,
+}
+
+fn print<T: Serialize>(format: Format, t: &T) -> anyhow::Result<()> {
+    println!("{}", format.render(t)?);
+    Ok(())
+}
+
+/// Reads the file at `path`, or stdin if the path is `-`.
+fn read_input(path: &Path) -> anyhow::Result<String> {
+    Ok(String::from_utf8(read_bytes(path)?)?)
+}
+
+/// The text of an input file, see [`map_input`].
+enum Input {
+    Mapped(memmap2::Mmap),
+    Read(String),
+}
+
+impl Input {
+    /// The input as text. A mapped file is checked on every call, so a file
+    /// that changed while it was mapped is reported instead of being read as
+    /// invalid UTF-8.
+    fn text(&self) -> anyhow::Result<&str> {
+        match self {
+            Input::Mapped(map) => Ok(std::str::from_utf8(map)?),
+            Input::Read(text) => Ok(text),
+        }
+    }
+}
+
+/// Memory-maps the file at `path`, or reads stdin if the path is `-`. Large
+/// inputs are lexed straight from the mapping instead of being copied into a
+/// `String` first.
+fn map_input(path: &Path) -> anyhow::Result<Input> {
+    if path == Path::new("-") {
+        return Ok(Input::Read(read_input(path)?));
+    }
+    let file = std::fs::File::open(path)?;
+    // SAFETY: the mapping is only read, and `Input::text` checks it before
+    // handing out a `&str`. Like with any other way of reading it, the file
+    // must not be truncated or written while it is parsed.
+    let map = unsafe { memmap2::Mmap::map(&file)? };
+    Ok(Input::Mapped(map))
+}
+
+fn read_bytes(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut buf = Vec::new();
+        std::io::stdin().read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        Ok(std::fs::read(path)?)
+    }
+}
+
+/// Whether loading a grammar with warnings fails, see `--strict`.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Whether grammar text is loaded with [`tmpl::definition::parse_lazy`],
+/// which the interactive commands use to start quickly.
+static LAZY: AtomicBool = AtomicBool::new(false);
+
+fn load_grammar(path: &Path) -> anyhow::Result<ParserDefinition> {
+    Ok(load_grammar_with_source(path)?.0)
+}
+
+/// Like [`load_grammar`], also returning the grammar text if the grammar was
+/// parsed from text.
+fn load_grammar_with_source(path: &Path) -> anyhow::Result<(ParserDefinition, Option<String>)> {
+    let _span = tracing::info_span!("load_grammar", path = %path.display()).entered();
+    let bytes = read_bytes(path)?;
+    let to_diagnostic = |e: &dyn std::fmt::Display| {
+        Diagnostic::new(ErrorKind::Grammar, Some(path), None, e.to_string())
+    };
+    let loaded = if ParserDefinition::is_compiled(&bytes) {
+        let definition = ParserDefinition::from_bytes(&bytes).map_err(|e| to_diagnostic(&e))?;
+        (definition, None)
+    } else {
+        let src = String::from_utf8(bytes)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => {
+                let definition =
+                    ParserDefinition::from_json(&src).map_err(|e| to_diagnostic(&e))?;
+                (definition, None)
+            }
+            Some("yaml" | "yml") => {
+                let definition =
+                    ParserDefinition::from_yaml(&src).map_err(|e| to_diagnostic(&e))?;
+                (definition, None)
+            }
+            _ => (parse_grammar(path, &src)?, Some(src)),
+        }
+    };
+    tracing::info!("loaded grammar");
+    if STRICT.load(Ordering::Relaxed) {
+        let warnings = match &loaded.1 {
+            Some(src) => tmpl::definition::parse_with_diagnostics(src)?
+                .1
+                .into_iter()
+                .collect(),
+            None => loaded.0.validate(),
+        };
+        let warnings: Vec<_> = warnings
+            .into_iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+            .collect();
+        for warning in &warnings {
+            let diagnostic = issue_diagnostic(path, loaded.1.as_deref(), warning);
+            eprintln!("{}", diagnostic.render("error"));
+        }
+        if !warnings.is_empty() {
+            let message = format!(
+                "grammar has {} warning(s), which --strict treats as errors",
+                warnings.len()
+            );
+            return Err(Diagnostic::new(ErrorKind::Grammar, Some(path), None, message).into());
+        }
+    }
+    Ok(loaded)
+}
+
+/// Reports a validation issue of the grammar at `path`, pointing into the
+/// grammar text `src` if the issue has a span.
+fn issue_diagnostic(path: &Path, src: Option<&str>, issue: &ValidationIssue) -> Diagnostic {
+    let range = issue.span.clone().unwrap_or_default();
+    let src = src.filter(|_| issue.span.is_some());
+    let span = src.map(|src| Span::at(src, range.start));
+    let message = issue.to_string();
+    let message = message
+        .split_once(": ")
+        .map_or(&*message, |(_, message)| message);
+    Diagnostic::new(ErrorKind::Grammar, Some(path), span, message.to_string())
+        .with_snippet(src.unwrap_or_default(), range.len())
+}
+
+/// Parses the grammar `src` read from `path`, reporting failures as grammar diagnostics.
+fn parse_grammar(path: &Path, src: &str) -> anyhow::Result<ParserDefinition> {
+    let parsed = match LAZY.load(Ordering::Relaxed) {
+        true => tmpl::definition::parse_lazy(src),
+        false => tmpl::definition::parse(src),
+    };
+    match parsed {
+        Ok(definition) => Ok(definition),
+        Err(tmpl::Error::Syntax(e)) => {
+            let span = Span::at(src, e.location.offset);
+            Err(
+                Diagnostic::new(ErrorKind::Grammar, Some(path), Some(span), e.to_string())
+                    .with_expected(e.expected.clone())
+                    .with_snippet(src, 1)
+                    .into(),
+            )
+        }
+        Err(tmpl::Error::Definition(e)) => {
+            let range = e.span().unwrap_or_default();
+            let span = e.span().map(|_| Span::at(src, range.start));
+            Err(
+                Diagnostic::new(ErrorKind::Grammar, Some(path), span, e.to_string())
+                    .with_snippet(src, range.len())
+                    .into(),
+            )
+        }
+        Err(e) => Err(Diagnostic::new(ErrorKind::Grammar, Some(path), None, e.to_string()).into()),
+    }
+}
+
+/// Reads the output template at `path`, reporting syntax errors as diagnostics.
+fn load_template(path: &Path) -> anyhow::Result<Template> {
+    let text = std::fs::read_to_string(path)?;
+    Template::parse(&text).map_err(|e| {
+        let diagnostic = match &e {
+            TemplateError::Syntax { offset, message } => {
+                let span = Span::at(&text, *offset);
+                Diagnostic::new(ErrorKind::Other, Some(path), Some(span), message.clone())
+                    .with_snippet(&text, 1)
+            }
+            _ => Diagnostic::new(ErrorKind::Other, Some(path), None, e.to_string()),
+        };
+        diagnostic.into()
+    })
+}
+
+/// Reads the rewrites at `path`, reporting syntax errors as diagnostics.
+fn load_rewrites(path: &Path) -> anyhow::Result<Rewrites> {
+    let text = std::fs::read_to_string(path)?;
+    Rewrites::parse(&text).map_err(|e| {
+        let diagnostic = match &e {
+            RewriteError::Syntax { offset, message } => {
+                let span = Span::at(&text, *offset);
+                Diagnostic::new(ErrorKind::Other, Some(path), Some(span), message.clone())
+                    .with_snippet(&text, 1)
+            }
+            _ => Diagnostic::new(ErrorKind::Other, Some(path), None, e.to_string()),
+        };
+        diagnostic.into()
+    })
+}
+
+/// Lexes and parses the file at `path`, reporting failures as input diagnostics.
+fn parse_source(session: &mut ParseSession, path: &Path, entry: &str) -> anyhow::Result<Ast> {
+    let _span = tracing::info_span!("parse_source", path = %path.display()).entered();
+    let src = map_input(path)?;
+    parse_text(session, Some(path), src.text()?, entry)
+}
+
+/// Lexes and parses `src` in `session`, reporting failures as input
+/// diagnostics against `path`.
+fn parse_text(
+    session: &mut ParseSession,
+    path: Option<&Path>,
+    src: &str,
+    entry: &str,
+) -> anyhow::Result<Ast> {
+    session.lex(src).map_err(|(e, span)| {
+        let len = span.len();
+        let span = Span::at(src, span.start);
+        Diagnostic::new(ErrorKind::Input, path, Some(span), e.to_string()).with_snippet(src, len)
+    })?;
+    session.parse_entry(entry).map_err(|e| {
+        let token = e.context().and_then(|context| context.span.clone());
+        let offset = token.as_ref().map_or(src.len(), |span| span.start);
+        let len = token.map_or(0, |span| span.len());
+        let expected = match e.kind() {
+            ParseError::UnexpectedToken { expected, .. } | ParseError::UnexpectedEof(expected) => {
+                vec![expected.clone()]
+            }
+            _ => Vec::new(),
+        };
+        let span = Span::at(src, offset);
+        Diagnostic::new(ErrorKind::Input, path, Some(span), e.to_string())
+            .with_expected(expected)
+            .with_snippet(src, len)
+            .into()
+    })
+}
+
+/// Name of the language a grammar file describes, taken from the file name.
+fn grammar_name(path: &Path) -> String {
+    path.file_stem()
+        .filter(|_| path != Path::new("-"))
+        .map_or("Grammar".to_string(), |s| s.to_string_lossy().into_owned())
+}
+
+/// Sends log output to stderr, at a level depending on how often `-v` was given.
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
 }
 
-fn print<T: Serialize>(t: &T) {
-    println!("{}", serde_yaml::to_string(t).unwrap());
+fn main() {
+    let opts = Opts::parse();
+    init_logging(opts.verbose);
+    diagnostics::set_color(opts.color);
+    let error_format = opts.error_format;
+    if let Err(e) = run(opts) {
+        std::process::exit(diagnostics::emit(&e, error_format));
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let Opts { grammar, src: _ } = Opts::parse();
-    let parsed = tmpl::definition::parse(std::fs::read_to_string(grammar)?.as_str())??;
-    print(&parsed);
-    // let src = std::fs::read_to_string(src)?;
-    // let lexer = tmpl::lexer::Token::lexer(&src);
-    // let parser = tmpl::custom::Parser::new(parsed, lexer.collect::<Result<Vec<_>, _>>()?);
-    // let ast = parser.parse()?;
-    // println!("{}", rsn::to_string_pretty(&ast)?);
+fn run(opts: Opts) -> anyhow::Result<()> {
+    let Opts {
+        format,
+        error_format,
+        trace,
+        strict,
+        color: _,
+        verbose: _,
+        command,
+    } = opts;
+    tmpl::definition::set_trace(trace);
+    STRICT.store(strict, Ordering::Relaxed);
+    match command {
+        Command::Check { grammar } => {
+            let (parsed, src) = load_grammar_with_source(&grammar)?;
+            let issues = match &src {
+                Some(src) => {
+                    let (_, diagnostics) = tmpl::definition::parse_with_diagnostics(src)?;
+                    let errors = parsed.validate().into_iter();
+                    errors
+                        .filter(|issue| issue.severity == Severity::Error)
+                        .chain(diagnostics)
+                        .collect()
+                }
+                None => parsed.validate(),
+            };
+            for issue in &issues {
+                let label = match issue.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                let diagnostic = issue_diagnostic(&grammar, src.as_deref(), issue);
+                eprintln!("{}", diagnostic.render(label));
+            }
+            let errors = issues
+                .iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .count();
+            if errors > 0 {
+                let message = format!("grammar has {errors} error(s)");
+                return Err(
+                    Diagnostic::new(ErrorKind::Grammar, Some(&grammar), None, message).into(),
+                );
+            }
+            println!(
+                "{}: ok ({} rules, {} defines)",
+                grammar.display(),
+                parsed.rules.len() + 1,
+                parsed.defines.len()
+            );
+        }
+        Command::Expand {
+            grammar,
+            tree,
+            optimize,
+            minimize,
+        } => {
+            let mut definition = load_grammar(&grammar)?;
+            if minimize {
+                definition.minimize();
+            }
+            if optimize {
+                definition.optimize();
+            }
+            if tree {
+                print(format, &definition)?;
+            } else {
+                print!("{definition}");
+            }
+        }
+        Command::Compile {
+            grammar,
+            output,
+            optimize,
+            minimize,
+        } => {
+            let mut definition = load_grammar(&grammar)?;
+            if minimize {
+                definition.minimize();
+            }
+            if optimize {
+                definition.optimize();
+            }
+            std::fs::write(output, definition.to_bytes()?)?;
+        }
+        Command::Parse {
+            grammar,
+            sources,
+            entry,
+            jobs,
+            optimize,
+            rewrite,
+        } => {
+            let rewrites = rewrite.as_deref().map(load_rewrites).transpose()?;
+            let sources = batch::expand(&sources)?;
+            let from_stdin = |p: &PathBuf| p == Path::new("-");
+            if from_stdin(&grammar) && sources.iter().any(from_stdin) {
+                anyhow::bail!("grammar and source can't both be read from stdin");
+            }
+            let mut parsed = load_grammar(&grammar)?;
+            if optimize {
+                parsed.optimize();
+            }
+            let parsed = Arc::new(parsed);
+            if parsed.rule(&entry).is_none() {
+                let message = format!("unknown entry rule `{entry}`");
+                return Err(
+                    Diagnostic::new(ErrorKind::Grammar, Some(&grammar), None, message).into(),
+                );
+            }
+            if let [src] = &sources[..] {
+                let mut session = ParseSession::new(Arc::clone(&parsed)).with_trace(trace);
+                let ast = parse_source(&mut session, src, &entry)?;
+                let ast = tmpl::Grammar::from(parsed).expand_macros(ast)?;
+                match rewrites {
+                    Some(rewrites) => match rewrites.apply(Node::Ast(ast))? {
+                        Node::Ast(ast) => print(format, &ast)?,
+                        node => print(format, &node)?,
+                    },
+                    None => print(format, &ast)?,
+                }
+            } else {
+                let jobs = jobs.unwrap_or_else(batch::default_jobs);
+                batch::run(&parsed, &sources, &entry, jobs, error_format, trace)?;
+            }
+        }
+        Command::Render {
+            grammar,
+            src,
+            template: path,
+            entry,
+            rewrite,
+            output,
+            style,
+        } => {
+            let template = path.as_deref().map(load_template).transpose()?;
+            let rewrites = rewrite.as_deref().map(load_rewrites).transpose()?;
+            let definition = Arc::new(load_grammar(&grammar)?);
+            let style = style.style(&definition);
+            let mut session = ParseSession::new(Arc::clone(&definition)).with_trace(trace);
+            let ast = parse_source(&mut session, &src, &entry)?;
+            let parsed = tmpl::Grammar::from(definition);
+            let mut ast = parsed.expand_macros(ast)?;
+            if let Some(rewrites) = rewrites {
+                ast = match rewrites.apply(Node::Ast(ast))? {
+                    Node::Ast(ast) => ast,
+                    _ => anyhow::bail!("the rewrites replaced the whole tree by a value"),
+                };
+            }
+            let rendered = match template {
+                Some(template) => template.with_style(style).render(&ast),
+                None => parsed.emit_with_style(&ast, &style),
+            };
+            let rendered = rendered.map_err(|e| {
+                let path = path.as_deref().unwrap_or(&grammar);
+                Diagnostic::new(ErrorKind::Other, Some(path), None, e.to_string())
+            })?;
+            match output {
+                Some(path) => std::fs::write(path, rendered)?,
+                None => print!("{rendered}"),
+            }
+        }
+        Command::Translate {
+            from,
+            to,
+            map,
+            src,
+            entry,
+            output,
+        } => {
+            let mapping = map.as_deref().map(load_rewrites).transpose()?;
+            let definition = Arc::new(load_grammar(&from)?);
+            let target = tmpl::Grammar::from(load_grammar(&to)?);
+            let mut session = ParseSession::new(Arc::clone(&definition)).with_trace(trace);
+            let ast = parse_source(&mut session, &src, &entry)?;
+            let mut ast = tmpl::Grammar::from(definition).expand_macros(ast)?;
+            if let Some(mapping) = mapping {
+                ast = match mapping.apply(Node::Ast(ast))? {
+                    Node::Ast(ast) => ast,
+                    _ => return Err(RewriteError::Root.into()),
+                };
+            }
+            let translated = target
+                .emit(&ast)
+                .map_err(|e| Diagnostic::new(ErrorKind::Other, Some(&to), None, e.to_string()))?;
+            match output {
+                Some(path) => std::fs::write(path, translated)?,
+                None => print!("{translated}"),
+            }
+        }
+        Command::Bench {
+            grammar,
+            sources,
+            iterations,
+        } => bench::run(
+            &Arc::new(load_grammar(&grammar)?),
+            &batch::expand(&sources)?,
+            iterations,
+        )?,
+        Command::Coverage {
+            grammar,
+            sources,
+            entry,
+            html,
+        } => {
+            let mut session = ParseSession::new(load_grammar(&grammar)?)
+                .with_trace(trace)
+                .with_coverage(true);
+            // Files that don't parse are reported, but what they matched
+            // before failing still counts.
+            for src in batch::expand(&sources)? {
+                if let Err(e) = parse_source(&mut session, &src, &entry) {
+                    diagnostics::emit(&e, error_format);
+                }
+            }
+            let coverage = session.coverage().cloned().unwrap_or_default();
+            let report = tmpl::coverage::CoverageReport::new(session.definition(), &coverage);
+            match html {
+                Some(path) => std::fs::write(path, report.to_html())?,
+                None => print!("{report}"),
+            }
+        }
+        Command::Stats { grammar } => print(format, &tmpl::stats::stats(&load_grammar(&grammar)?))?,
+        Command::Fuzz {
+            grammar,
+            samples,
+            iterations,
+            seed,
+        } => fuzz_runner::run(
+            &Arc::new(load_grammar(&grammar)?),
+            &batch::expand(&samples)?,
+            iterations,
+            seed,
+        )?,
+        Command::Tokens { src, table } => {
+            let text = read_input(&src)?;
+            let lexed = tmpl::lexer::lex_spanned(&text).map_err(|(e, span)| {
+                let span = Span::at(&text, span.start);
+                Diagnostic::new(ErrorKind::Input, Some(&src), Some(span), e.to_string())
+            })?;
+            let tokens = tokens::describe(&text, lexed);
+            if table {
+                print!("{}", tokens::table(&tokens));
+            } else {
+                print(format, &tokens)?;
+            }
+        }
+        Command::Fmt { grammar, check } => {
+            let original = read_input(&grammar)?;
+            let definition = parse_grammar(&grammar, &original)?;
+            if !definition.round_trips() {
+                anyhow::bail!(
+                    "formatting {} would change its meaning, leaving it as is",
+                    grammar.display()
+                );
+            }
+            let formatted = definition.to_string();
+            if check {
+                if original != formatted {
+                    anyhow::bail!("{} is not formatted", grammar.display());
+                }
+            } else if grammar == Path::new("-") {
+                print!("{formatted}");
+            } else if original != formatted {
+                std::fs::write(&grammar, formatted)?;
+            }
+        }
+        Command::FmtSrc {
+            grammar,
+            src,
+            entry,
+            check,
+            style,
+        } => {
+            let definition = Arc::new(load_grammar(&grammar)?);
+            let style = style.style(&definition);
+            let original = read_input(&src)?;
+            let formatted =
+                tmpl::source_format::format_source_with(&definition, &original, &entry, &style)
+                    .map_err(|e| {
+                        Diagnostic::new(ErrorKind::Input, Some(&src), None, e.to_string())
+                    })?;
+            if check {
+                if original != formatted {
+                    anyhow::bail!("{} is not formatted", src.display());
+                }
+            } else if src == Path::new("-") {
+                print!("{formatted}");
+            } else if original != formatted {
+                std::fs::write(&src, formatted)?;
+            }
+        }
+        Command::Lint { grammar, deny } => {
+            let denied = if deny.iter().any(|d| d == "all") {
+                LintId::ALL.to_vec()
+            } else {
+                deny.iter()
+                    .map(|d| d.parse())
+                    .collect::<Result<Vec<LintId>, _>>()
+                    .map_err(anyhow::Error::msg)?
+            };
+            let lints = tmpl::lint::lint(&load_grammar(&grammar)?);
+            let mut errors = 0;
+            for lint in &lints {
+                if denied.contains(&lint.id) {
+                    errors += 1;
+                    eprintln!("error{lint}");
+                } else {
+                    eprintln!("warning{lint}");
+                }
+            }
+            if errors > 0 {
+                let message = format!("{errors} denied lint(s) found");
+                return Err(
+                    Diagnostic::new(ErrorKind::Grammar, Some(&grammar), None, message).into(),
+                );
+            }
+        }
+        Command::Init { from, output } => {
+            let src = read_input(&from)?;
+            let definition = tmpl::scaffold::scaffold(&src).map_err(|(e, span)| {
+                let span = Span::at(&src, span.start);
+                Diagnostic::new(ErrorKind::Input, Some(&from), Some(span), e.to_string())
+            })?;
+            match output {
+                Some(path) => std::fs::write(path, definition.to_string())?,
+                None => print!("{definition}"),
+            }
+        }
+        Command::Infer { samples, output } => {
+            let paths = batch::expand(&samples)?;
+            let texts = (paths.iter())
+                .map(|path| read_input(path))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let samples: Vec<_> = texts.iter().map(String::as_str).collect();
+            let definition = tmpl::scaffold::infer(&samples).map_err(|e| {
+                let span = Span::at(samples[e.sample], e.span.start);
+                let path = &paths[e.sample];
+                Diagnostic::new(
+                    ErrorKind::Input,
+                    Some(path),
+                    Some(span),
+                    e.error.to_string(),
+                )
+            })?;
+            match output {
+                Some(path) => std::fs::write(path, definition.to_string())?,
+                None => print!("{definition}"),
+            }
+        }
+        Command::Diagram { grammar, output } => {
+            let html = tmpl::diagram::render_html(&load_grammar(&grammar)?);
+            match output {
+                Some(path) => std::fs::write(path, html)?,
+                None => print!("{html}"),
+            }
+        }
+        Command::DiffAst {
+            grammar,
+            old,
+            new,
+            entry,
+        } => {
+            let mut session = ParseSession::new(load_grammar(&grammar)?).with_trace(trace);
+            let old = parse_source(&mut session, &old, &entry)?;
+            let new = parse_source(&mut session, &new, &entry)?;
+            for change in tmpl::custom::diff(&old, &new) {
+                println!("{change}");
+            }
+        }
+        Command::Graph { grammar, to } => {
+            let graph = tmpl::graph::RuleGraph::of(&load_grammar(&grammar)?);
+            match to {
+                GraphFormat::Dot => print!("{}", graph.to_dot()),
+                GraphFormat::Mermaid => print!("{}", graph.to_mermaid()),
+            }
+        }
+        Command::Diff { old, new } => {
+            let changes = tmpl::diff::diff(&load_grammar(&old)?, &load_grammar(&new)?);
+            for change in &changes {
+                println!("{change}");
+            }
+        }
+        Command::Export {
+            grammar,
+            to,
+            output,
+        } => {
+            let definition = load_grammar(&grammar)?;
+            let exported = match to {
+                ExportFormat::Ebnf => tmpl::export::to_ebnf(&definition),
+                ExportFormat::Pest => tmpl::export::to_pest(&definition),
+                ExportFormat::Antlr => tmpl::export::to_antlr(&definition, &grammar_name(&grammar)),
+            };
+            for warning in &exported.warnings {
+                eprintln!("warning: {warning}");
+            }
+            match output {
+                Some(path) => std::fs::write(path, exported.text)?,
+                None => print!("{}", exported.text),
+            }
+        }
+        Command::Codegen {
+            grammar,
+            output,
+            embedded,
+            serde,
+        } => {
+            let options = tmpl::codegen::Options { embedded, serde };
+            let definition = load_grammar(&grammar)?;
+            let code = tmpl::codegen::generate_with(&definition, &options).map_err(|e| {
+                Diagnostic::new(ErrorKind::Grammar, Some(&grammar), None, e.to_string())
+            })?;
+            match output {
+                Some(path) => std::fs::write(path, code)?,
+                None => print!("{code}"),
+            }
+        }
+        Command::Generate {
+            grammar,
+            count,
+            max_depth,
+            seed,
+            check,
+        } => {
+            let definition = Arc::new(load_grammar(&grammar)?);
+            let mut generator = tmpl::generate::Generator::new(&definition, max_depth);
+            if let Some(seed) = seed {
+                generator = generator.with_seed(seed);
+            }
+            let mut rejected = 0;
+            for _ in 0..count {
+                let input = generator.generate()?;
+                println!("{input}");
+                if check {
+                    let tokens = Token::lexer(&input).collect::<Result<Vec<_>, _>>()?;
+                    let parser = tmpl::custom::Parser::new(Arc::clone(&definition), tokens);
+                    if let Err(e) = parser.parse() {
+                        rejected += 1;
+                        eprintln!("rejected: {e}");
+                    }
+                }
+            }
+            if rejected > 0 {
+                anyhow::bail!("the grammar rejects {rejected} of {count} generated inputs");
+            }
+        }
+        Command::Highlight {
+            grammar,
+            to,
+            output,
+        } => {
+            let definition = load_grammar(&grammar)?;
+            let text = match to {
+                HighlightFormat::Textmate => {
+                    tmpl::highlight::to_textmate(&definition, &grammar_name(&grammar))
+                }
+                HighlightFormat::TreeSitterQuery => {
+                    tmpl::highlight::to_tree_sitter_query(&definition)
+                }
+            };
+            match output {
+                Some(path) => std::fs::write(path, text)?,
+                None => print!("{text}"),
+            }
+        }
+        Command::SelfHighlight { to, output } => {
+            let text = match to {
+                SelfHighlightFormat::Textmate => tmpl::highlight::grammar_textmate(),
+                SelfHighlightFormat::Vim => tmpl::highlight::grammar_vim(),
+                SelfHighlightFormat::Emacs => tmpl::highlight::grammar_emacs(),
+            };
+            match output {
+                Some(path) => std::fs::write(path, text)?,
+                None => print!("{text}"),
+            }
+        }
+        Command::Import { file, from, output } => {
+            let src = read_input(&file)?;
+            let imported = match from {
+                ImportFormat::Ebnf => tmpl::import::from_ebnf(&src),
+                ImportFormat::Pest => tmpl::import::from_pest(&src),
+            }
+            .map_err(|e| Diagnostic::new(ErrorKind::Grammar, Some(&file), None, e.to_string()))?;
+            for warning in &imported.warnings {
+                eprintln!("warning: {warning}");
+            }
+            let text = imported.definition.to_string();
+            match output {
+                Some(path) => std::fs::write(path, text)?,
+                None => print!("{text}"),
+            }
+        }
+        Command::Test {
+            grammar,
+            dir,
+            update,
+        } => {
+            let definition = Arc::new(load_grammar(&grammar)?);
+            let files = match dir {
+                Some(dir) => test_runner::discover(&dir)?,
+                None => {
+                    let dir = grammar.parent().unwrap_or(Path::new(".")).join("tests");
+                    // A grammar with `test` blocks doesn't need a test directory.
+                    match dir.exists() || definition.tests.is_empty() {
+                        true => test_runner::discover(&dir)?,
+                        false => Vec::new(),
+                    }
+                }
+            };
+            test_runner::run(&definition, &grammar, &files, update)?;
+        }
+        Command::Lsp => lsp::run()?,
+        Command::LangServer { grammar, entry } => {
+            LAZY.store(true, Ordering::Relaxed);
+            lang_server::run(Arc::new(load_grammar(&grammar)?), entry)?
+        }
+        Command::Serve {
+            grammar,
+            port,
+            host,
+            entry,
+            workers,
+            timeout,
+        } => serve::run(
+            &Arc::new(load_grammar(&grammar)?),
+            &format!("{host}:{port}"),
+            &entry,
+            workers.unwrap_or_else(batch::default_jobs),
+            std::time::Duration::from_secs(timeout),
+        )?,
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Opts::command(), "tmpl", &mut std::io::stdout());
+        }
+        Command::Man => clap_mangen::Man::new(Opts::command()).render(&mut std::io::stdout())?,
+        Command::Schema => println!(
+            "{}",
+            serde_json::to_string_pretty(&ParserDefinition::json_schema())?
+        ),
+        Command::Repl { grammar, rule } => {
+            LAZY.store(true, Ordering::Relaxed);
+            repl::run(Arc::new(load_grammar(&grammar)?), rule, format, trace)?
+        }
+    }
     Ok(())
 }
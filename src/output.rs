@@ -0,0 +1,24 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    #[default]
+    Yaml,
+    Json,
+    Ron,
+    Rsn,
+    Sexpr,
+}
+
+impl Format {
+    pub fn render<T: Serialize>(self, value: &T) -> anyhow::Result<String> {
+        Ok(match self {
+            Format::Yaml => serde_yaml::to_string(value)?,
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?,
+            Format::Rsn => rsn::to_string_pretty(value)?,
+            Format::Sexpr => serde_lexpr::to_string(value)?,
+        })
+    }
+}
@@ -0,0 +1,61 @@
+//! Python bindings, enabled with the `python` feature.
+//!
+//! Build with `maturin build` and use it from Python:
+//!
+//! ```python
+//! import tmpl
+//! grammar = tmpl.Grammar("config.tmpl")
+//! ast = grammar.parse(text)
+//! ```
+//!
+//! Syntax trees are returned as plain dicts and lists, in the shape
+//! `tmpl parse --format json` prints.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+use crate::Error;
+
+create_exception!(
+    tmpl,
+    TmplError,
+    PyException,
+    "Raised for invalid grammars and input."
+);
+
+fn to_py_err(e: Error) -> PyErr {
+    TmplError::new_err(e.to_string())
+}
+
+/// A grammar, loaded from a grammar file or a compiled grammar.
+#[pyclass(name = "Grammar", module = "tmpl", frozen)]
+pub struct Grammar(crate::Grammar);
+
+#[pymethods]
+impl Grammar {
+    #[new]
+    fn new(path: std::path::PathBuf) -> PyResult<Self> {
+        crate::Grammar::from_file(path).map(Self).map_err(to_py_err)
+    }
+
+    /// Builds a grammar from grammar text.
+    #[staticmethod]
+    fn from_source(src: &str) -> PyResult<Self> {
+        crate::Grammar::parse(src).map(Self).map_err(to_py_err)
+    }
+
+    /// Parses `text`, starting at `entry` or at `Main`.
+    #[pyo3(signature = (text, entry = "Main"))]
+    fn parse<'py>(&self, py: Python<'py>, text: &str, entry: &str) -> PyResult<Bound<'py, PyAny>> {
+        let ast = self.0.parse_entry(entry, text).map_err(to_py_err)?;
+        Ok(pythonize::pythonize(py, &ast)?)
+    }
+}
+
+#[pymodule]
+fn tmpl(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Grammar>()?;
+    m.add("TmplError", m.py().get_type::<TmplError>())?;
+    Ok(())
+}
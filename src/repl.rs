@@ -0,0 +1,71 @@
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use logos::Logos;
+use tmpl::custom::{Ast, Parser};
+use tmpl::definition::ParserDefinition;
+use tmpl::lexer::Token;
+
+use crate::output::Format;
+
+/// Reads inputs from stdin and parses each of them starting at `rule`.
+///
+/// A line ending in `\` continues the input on the next line. Lines starting
+/// with `:` are commands: `:rule <Name>` switches the start rule, `:quit` exits.
+pub fn run(
+    definition: Arc<ParserDefinition>,
+    mut rule: String,
+    format: Format,
+    trace: bool,
+) -> anyhow::Result<()> {
+    let mut lines = std::io::stdin().lock().lines();
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() {
+            format!("{rule}> ")
+        } else {
+            "... ".to_string()
+        };
+        print!("{prompt}");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        if let Some(continued) = line.strip_suffix('\\') {
+            input.push_str(continued);
+            input.push('\n');
+            continue;
+        }
+        input.push_str(&line);
+        let current = std::mem::take(&mut input);
+        match current.trim() {
+            "" => {}
+            ":quit" | ":q" => break,
+            command if command.starts_with(":rule") => {
+                let name = command[":rule".len()..].trim();
+                if definition.rule(name).is_some() {
+                    rule = name.to_string();
+                } else {
+                    eprintln!("unknown rule: {name}");
+                }
+            }
+            source => match parse(&definition, &rule, source, trace) {
+                Ok(ast) => println!("{}", format.render(&ast)?),
+                Err(e) => eprintln!("error: {e}"),
+            },
+        }
+    }
+    Ok(())
+}
+
+fn parse(
+    definition: &Arc<ParserDefinition>,
+    rule: &str,
+    source: &str,
+    trace: bool,
+) -> anyhow::Result<Ast> {
+    let tokens = Token::lexer(source).collect::<Result<Vec<_>, _>>()?;
+    let parser = Parser::new(Arc::clone(definition), tokens).with_trace(trace);
+    Ok(parser.parse_entry(rule)?)
+}
@@ -0,0 +1,392 @@
+use alloc::{
+    borrow::ToOwned,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use crate::custom::{Ast, Node};
+
+/// Rewrites applied to syntax trees, for desugarings and simplifications
+/// that would otherwise take a visitor. Every rewrite replaces trees
+/// matching a pattern:
+///
+/// ```text
+/// // Adding zero changes nothing.
+/// match Add(lhs, rhs: Int(value: 0)) => lhs
+/// match Neg(inner: Neg(inner: x)) => x
+/// ```
+///
+/// - `Rule(field: pattern, ..)` matches a tree of the rule `Rule` whose
+///   fields match their patterns, other fields are ignored. `Rule(field)`
+///   is short for `Rule(field: field)`.
+/// - A name matches anything and binds it, a name bound twice has to match
+///   the same both times. `_` matches anything without binding it.
+/// - Numbers, strings, `true`, `false` and `none` match nodes with that
+///   value, strings match identifiers and matched text too.
+///
+/// The replacement after `=>` is built the same way, with names giving
+/// what they were bound to. Trees are rewritten bottom-up, with the first
+/// matching rewrite, until none matches anymore.
+///
+/// ```
+/// use tmpl::custom::{Ast, Node};
+/// use tmpl::rewrite::Rewrites;
+///
+/// let grammar = tmpl::Grammar::parse(concat!(
+///     "Main:\n<expr:Add>\n~~~\n\n",
+///     "Add:\n<lhs:Int> + <rhs:Int>\n~~~\n\n",
+///     "Int:\n<value:int>\n~~~\n",
+/// ))?;
+/// let rewrites: Rewrites = "match Add(lhs, rhs: Int(value: 0)) => lhs".parse()?;
+/// let ast = grammar.parse_str("7 + 0")?;
+/// let Node::Ast(rewritten) = rewrites.apply(Node::Ast(ast))? else {
+///     unreachable!("only trees are rewritten")
+/// };
+/// let mut seven = Ast::new("Int");
+/// seven.fields.insert("value".to_string(), Node::Int(7));
+/// assert_eq!(rewritten.fields["expr"], Node::Ast(seven));
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Rewrites {
+    rules: Vec<Rewrite>,
+}
+
+/// Rewrites applied to one tree before [`Rewrites::apply`] gives up, as a
+/// rewrite that recreates what it matched never ends.
+pub const MAX_REWRITES: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum RewriteError {
+    #[error("{message} at byte {offset}")]
+    Syntax { offset: usize, message: String },
+    #[error("Rewrites didn't finish after {MAX_REWRITES} steps, a rewrite keeps matching its own result")]
+    Diverged,
+    #[error("The rewrites replaced the whole tree by a value")]
+    Root,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rewrite {
+    pattern: Term,
+    replacement: Term,
+}
+
+/// A pattern or replacement.
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Tree(String, Vec<(String, Term)>),
+    Name(String),
+    Wildcard,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bool(bool),
+    None,
+}
+
+impl Rewrites {
+    pub fn parse(src: &str) -> Result<Self, RewriteError> {
+        let mut parser = TermParser { src, offset: 0 };
+        let mut rules = Vec::new();
+        while !parser.at_end() {
+            parser.keyword("match")?;
+            let pattern = parser.term()?;
+            parser.expect("=>")?;
+            let start = parser.offset;
+            let replacement = parser.term()?;
+            let mut bound = Vec::new();
+            pattern.names(&mut bound);
+            let mut used = Vec::new();
+            replacement.names(&mut used);
+            if let Some(name) = used.iter().find(|name| !bound.contains(name)) {
+                return Err(syntax(
+                    start,
+                    format!("`{name}` isn't bound by the pattern"),
+                ));
+            }
+            if replacement == Term::Wildcard {
+                return Err(syntax(start, "`_` can't be a replacement"));
+            }
+            rules.push(Rewrite {
+                pattern,
+                replacement,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Rewrites `node` and everything in it.
+    pub fn apply(&self, node: Node) -> Result<Node, RewriteError> {
+        let mut budget = MAX_REWRITES;
+        self.rewrite(node, &mut budget)
+    }
+
+    fn rewrite(&self, mut node: Node, budget: &mut usize) -> Result<Node, RewriteError> {
+        loop {
+            node = self.rewrite_children(node, budget)?;
+            let mut bindings = BTreeMap::new();
+            let Some(rule) = (self.rules.iter()).find(|rule| {
+                bindings.clear();
+                rule.pattern.matches(&node, &mut bindings)
+            }) else {
+                return Ok(node);
+            };
+            *budget = budget.checked_sub(1).ok_or(RewriteError::Diverged)?;
+            node = rule.replacement.build(&bindings);
+        }
+    }
+
+    fn rewrite_children(&self, node: Node, budget: &mut usize) -> Result<Node, RewriteError> {
+        Ok(match node {
+            Node::Ast(ast) => {
+                let fields = ast
+                    .fields
+                    .into_iter()
+                    .map(|(name, node)| Ok((name, self.rewrite(node, budget)?)))
+                    .collect::<Result<_, RewriteError>>()?;
+                Node::Ast(Ast {
+                    rule: ast.rule,
+                    fields,
+                })
+            }
+            Node::List(items) => Node::List(
+                items
+                    .into_iter()
+                    .map(|item| self.rewrite(item, budget))
+                    .collect::<Result<_, _>>()?,
+            ),
+            node => node,
+        })
+    }
+}
+
+impl FromStr for Rewrites {
+    type Err = RewriteError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Self::parse(src)
+    }
+}
+
+impl Term {
+    fn matches<'a>(&'a self, node: &'a Node, bindings: &mut BTreeMap<&'a str, &'a Node>) -> bool {
+        match (self, node) {
+            (Term::Wildcard, _) => true,
+            (Term::Name(name), node) => match bindings.get(name.as_str()) {
+                Some(bound) => *bound == node,
+                None => {
+                    bindings.insert(name, node);
+                    true
+                }
+            },
+            (Term::Tree(rule, fields), Node::Ast(ast)) => {
+                *rule == ast.rule
+                    && fields.iter().all(|(field, term)| {
+                        ast.fields
+                            .get(field)
+                            .is_some_and(|node| term.matches(node, bindings))
+                    })
+            }
+            (Term::Int(a), Node::Int(b)) => a == b,
+            (Term::Float(a), Node::Float(b)) => a == b,
+            (Term::String(a), Node::Ident(b) | Node::String(b) | Node::Text(b)) => a == b,
+            (Term::Bool(a), Node::Bool(b)) => a == b,
+            (Term::None, Node::None) => true,
+            _ => false,
+        }
+    }
+
+    fn build(&self, bindings: &BTreeMap<&str, &Node>) -> Node {
+        match self {
+            Term::Tree(rule, fields) => Node::Ast(Ast {
+                rule: rule.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(field, term)| (field.clone(), term.build(bindings)))
+                    .collect(),
+            }),
+            Term::Name(name) => bindings[name.as_str()].clone(),
+            Term::Int(i) => Node::Int(*i),
+            Term::Float(f) => Node::Float(*f),
+            Term::String(s) => Node::Text(s.clone()),
+            Term::Bool(b) => Node::Bool(*b),
+            Term::None | Term::Wildcard => Node::None,
+        }
+    }
+
+    fn names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Term::Name(name) => names.push(name),
+            Term::Tree(_, fields) => fields.iter().for_each(|(_, term)| term.names(names)),
+            _ => {}
+        }
+    }
+}
+
+fn syntax(offset: usize, message: impl Into<String>) -> RewriteError {
+    RewriteError::Syntax {
+        offset,
+        message: message.into(),
+    }
+}
+
+struct TermParser<'a> {
+    src: &'a str,
+    offset: usize,
+}
+
+impl<'a> TermParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.offset..]
+    }
+
+    /// Skips whitespace and `//` comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.offset += rest.len() - trimmed.len();
+            if !trimmed.starts_with("//") {
+                return;
+            }
+            self.offset += trimmed.find('\n').unwrap_or(trimmed.len());
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_trivia();
+        self.rest().is_empty()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_trivia();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.offset += token.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), RewriteError> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(syntax(self.offset, format!("expected `{token}`"))),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), RewriteError> {
+        let offset = self.offset;
+        match self.name() {
+            Some(name) if name == keyword => Ok(()),
+            _ => Err(syntax(offset, format!("expected `{keyword}`"))),
+        }
+    }
+
+    fn name(&mut self) -> Option<String> {
+        self.skip_trivia();
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(rest.len());
+        self.offset += end;
+        Some(rest[..end].to_owned())
+    }
+
+    fn term(&mut self) -> Result<Term, RewriteError> {
+        self.skip_trivia();
+        let start = self.offset;
+        let rest = self.rest();
+        if rest.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            return self.number();
+        }
+        if rest.starts_with('"') {
+            return self.string();
+        }
+        let Some(name) = self.name() else {
+            return Err(syntax(start, "expected a pattern"));
+        };
+        if !self.eat("(") {
+            return Ok(match name.as_str() {
+                "_" => Term::Wildcard,
+                "true" => Term::Bool(true),
+                "false" => Term::Bool(false),
+                "none" => Term::None,
+                _ => Term::Name(name),
+            });
+        }
+        let mut fields = Vec::new();
+        while !self.eat(")") {
+            if !fields.is_empty() {
+                self.expect(",")?;
+                if self.eat(")") {
+                    break;
+                }
+            }
+            let offset = self.offset;
+            let field = self
+                .name()
+                .ok_or_else(|| syntax(offset, "expected a field name"))?;
+            let term = match self.eat(":") {
+                true => self.term()?,
+                false => Term::Name(field.clone()),
+            };
+            fields.push((field, term));
+        }
+        Ok(Term::Tree(name, fields))
+    }
+
+    fn number(&mut self) -> Result<Term, RewriteError> {
+        let start = self.offset;
+        let rest = self.rest();
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| !c.is_ascii_digit() && *c != '.')
+            .map_or(rest.len(), |(i, _)| i);
+        let text = &rest[..end];
+        self.offset += end;
+        let invalid = || syntax(start, format!("invalid number `{text}`"));
+        match text.contains('.') {
+            true => text.parse().map(Term::Float).map_err(|_| invalid()),
+            false => text.parse().map(Term::Int).map_err(|_| invalid()),
+        }
+    }
+
+    /// Parses a quoted string with `\"` and `\\` escapes.
+    fn string(&mut self) -> Result<Term, RewriteError> {
+        let start = self.offset;
+        let mut text = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.offset += i + 1;
+                    return Ok(Term::String(text));
+                }
+                '\\' => match chars.next() {
+                    Some((_, c)) => text.push(c),
+                    None => break,
+                },
+                c => text.push(c),
+            }
+        }
+        Err(syntax(start, "unclosed string"))
+    }
+}
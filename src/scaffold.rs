@@ -0,0 +1,600 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use thiserror::Error;
+
+use crate::definition::{
+    bool, custom, float, ident, int, keyword, raw, regex, string, symbol, Define, InternalPattern,
+    ParserDefinition, Pattern, RuleMap, TokenPattern, Value,
+};
+use crate::lexer::{LexingError, Span, Token};
+
+/// Most keywords a scaffolded grammar guesses.
+const MAX_KEYWORDS: usize = 12;
+/// Rules [`infer`] drafts itself, which rules for clusters can't be named.
+const RESERVED: &[&str] = &["Main", "Statement", "Value", "Item"];
+
+/// Builds a starter grammar that accepts `sample` as a flat list of items.
+///
+/// Lowercase identifiers longer than a letter that occur more than once are
+/// guessed to be keywords and get their own `Keyword` rule. `Item` matches a
+/// keyword, an identifier, a literal of a kind the sample uses or any of the
+/// sample's symbols. Both lists are also recorded as `keywords` and `symbols`
+/// defines, so they survive once the rules get rewritten.
+pub fn scaffold(sample: &str) -> Result<ParserDefinition, (LexingError, Span)> {
+    let tokens = crate::lexer::lex_spanned(sample)?;
+    let mut idents: HashMap<&str, usize> = HashMap::new();
+    let mut symbols = BTreeSet::new();
+    let mut kinds = BTreeSet::new();
+    for (token, _) in &tokens {
+        match token {
+            Token::Ident(name) => *idents.entry(name).or_default() += 1,
+            Token::Symbol(s) => {
+                symbols.insert(s.as_str());
+            }
+            Token::Ws => {}
+            other => {
+                kinds.insert(other.kind());
+            }
+        }
+    }
+
+    let mut keywords: Vec<_> = idents
+        .iter()
+        .filter(|(name, count)| {
+            **count > 1 && name.len() > 1 && name.chars().all(|c| c.is_ascii_lowercase())
+        })
+        .map(|(name, count)| (*name, *count))
+        .collect();
+    keywords.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+    keywords.truncate(MAX_KEYWORDS);
+    let mut keywords: Vec<_> = keywords.into_iter().map(|(name, _)| name).collect();
+    keywords.sort();
+
+    let mut item = Vec::new();
+    if !keywords.is_empty() {
+        item.push(single(custom(Some("keyword".to_string()), "Keyword")));
+    }
+    item.push(single(ident(Some("name".to_string()))));
+    for kind in kinds {
+        let name = Some(kind.to_string());
+        item.push(single(match kind {
+            "float" => float(name),
+            "int" => int(name),
+            "string" => string(name),
+            _ => bool(name),
+        }));
+    }
+    if !symbols.is_empty() {
+        // Symbols are lexed one character at a time, so a single class covers them.
+        let class: String = symbols.iter().map(|s| regex::escape(s)).collect();
+        let pattern = regex(Some("symbol".to_string()), &format!("[{class}]"))
+            .expect("escaped symbols form a valid character class");
+        item.push(single(pattern));
+    }
+
+    let mut rules = RuleMap::default();
+    rules.insert("Item".to_string(), vec![Pattern::from_alternatives(item)]);
+    if !keywords.is_empty() {
+        let word = || Some("word".to_string());
+        let alternatives = keywords
+            .iter()
+            .map(|kw| single(keyword(word(), kw)))
+            .collect();
+        rules.insert(
+            "Keyword".to_string(),
+            vec![Pattern::from_alternatives(alternatives)],
+        );
+    }
+    let list = |items: Vec<&str>| {
+        Value::List(
+            items
+                .into_iter()
+                .map(|s| Value::String(s.to_string()))
+                .collect(),
+        )
+    };
+    let items = TokenPattern::from(custom(Some("items".to_string()), "Item")).zero_or_more();
+    Ok(ParserDefinition {
+        version: None,
+        entry: vec![Pattern::from(items)],
+        rules,
+        defines: vec![
+            Define {
+                name: "keywords".to_string(),
+                value: list(keywords),
+            },
+            Define {
+                name: "symbols".to_string(),
+                value: list(symbols.into_iter().collect()),
+            },
+        ],
+        emits: Default::default(),
+        docs: Default::default(),
+        tests: Vec::new(),
+        symbols: Default::default(),
+    })
+}
+
+fn single(pattern: InternalPattern) -> Vec<TokenPattern> {
+    vec![pattern.into()]
+}
+
+/// A sample [`infer`] couldn't lex.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("Sample {sample}: {error} at byte {}", span.start)]
+pub struct SampleError {
+    /// Index of the sample in the samples passed to [`infer`].
+    pub sample: usize,
+    pub error: LexingError,
+    pub span: Span,
+}
+
+/// Drafts a grammar from example sources of a language, for its author to
+/// refine.
+///
+/// The samples are split into statements, which end after a `;` or a line
+/// break outside of parentheses and brackets. Braces hold nested
+/// statements. Statements of the same shape form a cluster, and every
+/// cluster becomes a rule that `Statement` lists as an alternative:
+///
+/// - Lowercase words longer than a letter that start two statements or
+///   more, and aren't assigned to with `=`, are keywords. So is a word all
+///   statements of a cluster have in the same place.
+/// - Identifiers and literals become fields, and runs of the same kind
+///   become a repeated field.
+/// - Parentheses and brackets with a comma separated list of identifiers
+///   and literals become a list of `Value`s, others a list of any `Item`.
+///
+/// ```
+/// let samples = [
+///     "let x = 1;\nlet y = 2;\nprint x;\n",
+///     "let z = 3;\nprint z;\nprint(z, 4);\n",
+/// ];
+/// let grammar = tmpl::Grammar::from(tmpl::scaffold::infer(&samples)?);
+/// for sample in samples {
+///     grammar.parse_str(sample)?;
+/// }
+/// assert!(grammar.definition().rule("Let").is_some());
+/// assert!(grammar.definition().rule("Print").is_some());
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub fn infer(samples: &[&str]) -> Result<ParserDefinition, SampleError> {
+    let mut statements = Vec::new();
+    for (sample, src) in samples.iter().enumerate() {
+        let tokens = crate::lexer::lex_spanned(src)
+            .map_err(|(error, span)| SampleError {
+                sample,
+                error,
+                span,
+            })?
+            .into_iter()
+            .filter(|(token, _)| *token != Token::Ws)
+            .collect::<Vec<_>>();
+        let mut splitter = Splitter {
+            src,
+            tokens: &tokens,
+            next: 0,
+        };
+        splitter.statements(None, &mut statements);
+    }
+    let mut nested = Vec::new();
+    flatten(&statements, &mut nested);
+    let statements = nested;
+
+    let mut leading: HashMap<&str, usize> = HashMap::new();
+    let mut assigned = BTreeSet::new();
+    for statement in statements.iter().copied() {
+        if let [Piece::Token(Token::Ident(word)), rest @ ..] = &statement[..] {
+            *leading.entry(word).or_default() += 1;
+            if let [Piece::Token(Token::Symbol(s)), ..] = rest {
+                if s == "=" {
+                    assigned.insert(word.as_str());
+                }
+            }
+        }
+    }
+    let leading_keywords: BTreeSet<&str> = leading
+        .into_iter()
+        .filter(|(word, count)| *count > 1 && is_word(word) && !assigned.contains(word))
+        .map(|(word, _)| word)
+        .collect();
+
+    let mut clusters: Vec<(Vec<Shape>, Vec<&Statement>)> = Vec::new();
+    for statement in statements.iter().copied() {
+        let shape = shape(statement, &leading_keywords);
+        match clusters.iter_mut().find(|(s, _)| *s == shape) {
+            Some((_, members)) => members.push(statement),
+            None => clusters.push((shape, vec![statement])),
+        }
+    }
+
+    let mut keywords: BTreeSet<String> = leading_keywords.iter().map(|w| w.to_string()).collect();
+    let mut drafts = Drafts::default();
+    let mut alternatives = Vec::new();
+    for (shape, members) in &clusters {
+        // Runs shift the pieces, so only clusters without any line up.
+        let aligned = members.len() > 1 && !shape.iter().any(|e| matches!(e, Shape::Run(_)));
+        let mut fields = Fields::default();
+        let mut sequence = Vec::new();
+        for (i, element) in shape.iter().enumerate() {
+            match element {
+                Shape::Keyword(word) => sequence.push(raw(word).into()),
+                Shape::Symbol(s) => sequence.push(symbol(None, s).into()),
+                Shape::Kind(kind) => match common_word(members, i).filter(|_| aligned) {
+                    Some(word) => {
+                        keywords.insert(word.to_string());
+                        sequence.push(raw(word).into());
+                    }
+                    None => sequence.push(kind_pattern(kind, fields.next(field_name(kind)))),
+                },
+                Shape::Run(kind) => {
+                    let name = fields.next(&format!("{}s", field_name(kind)));
+                    sequence.push(kind_pattern(kind, name).one_or_more());
+                }
+                Shape::Block => {
+                    sequence.push(symbol(None, "{").into());
+                    let body = custom(Some(fields.next("body")), "Statement");
+                    sequence.push(TokenPattern::from(body).zero_or_more());
+                    sequence.push(symbol(None, "}").into());
+                }
+                Shape::List(open, close) => {
+                    sequence.push(symbol(None, open).into());
+                    let args = custom(Some(fields.next("args")), "Value");
+                    sequence.push(TokenPattern::from(args).separated_by(","));
+                    sequence.push(symbol(None, close).into());
+                    drafts.value = true;
+                }
+                Shape::Group(open, close) => {
+                    sequence.push(symbol(None, open).into());
+                    let items = custom(Some(fields.next("items")), "Item");
+                    sequence.push(TokenPattern::from(items).zero_or_more());
+                    sequence.push(symbol(None, close).into());
+                    drafts.item = true;
+                }
+            }
+        }
+        let name = drafts.rule_name(shape);
+        let field = Some(name.to_lowercase());
+        alternatives.push((
+            sequence.len(),
+            vec![TokenPattern::from(custom(field, &name))],
+        ));
+        drafts.rules.insert(name, vec![Pattern::from(sequence)]);
+    }
+    // Longer statements first, so a shorter one with the same start doesn't
+    // shadow them.
+    alternatives.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut rules = drafts.rules;
+    let alternatives = alternatives.into_iter().map(|(_, a)| a).collect();
+    rules.insert(
+        "Statement".to_string(),
+        vec![Pattern::from_alternatives(alternatives)],
+    );
+    let mut kinds = BTreeSet::new();
+    let mut symbols = BTreeSet::new();
+    for token in statements.iter().flat_map(|s| tokens_of(s)) {
+        match token {
+            Token::Symbol(s) => {
+                symbols.insert(s.clone());
+            }
+            Token::Ident(_) => {}
+            other => {
+                kinds.insert(other.kind());
+            }
+        }
+    }
+    let values = || {
+        let mut values = vec![single(ident(Some("name".to_string())))];
+        values.extend(
+            kinds
+                .iter()
+                .map(|kind| vec![kind_pattern(kind, kind.to_string())]),
+        );
+        values
+    };
+    if drafts.value {
+        rules.insert(
+            "Value".to_string(),
+            vec![Pattern::from_alternatives(values())],
+        );
+    }
+    if drafts.item {
+        let mut item = values();
+        let plain: String = (symbols.iter())
+            .filter(|s| !"()[]{}".contains(s.as_str()))
+            .map(|s| regex::escape(s))
+            .collect();
+        if !plain.is_empty() {
+            let pattern = regex(Some("symbol".to_string()), &format!("[{plain}]"))
+                .expect("escaped symbols form a valid character class");
+            item.push(single(pattern));
+        }
+        for (open, close) in [("(", ")"), ("[", "]")] {
+            let inner = custom(Some("inner".to_string()), "Item");
+            item.push(vec![
+                symbol(None, open).into(),
+                TokenPattern::from(inner).zero_or_more(),
+                symbol(None, close).into(),
+            ]);
+        }
+        rules.insert("Item".to_string(), vec![Pattern::from_alternatives(item)]);
+    }
+
+    let list = |items: Vec<String>| Value::List(items.into_iter().map(Value::String).collect());
+    let statements =
+        TokenPattern::from(custom(Some("statements".to_string()), "Statement")).zero_or_more();
+    Ok(ParserDefinition {
+        version: None,
+        entry: vec![Pattern::from(statements)],
+        rules,
+        defines: vec![
+            Define {
+                name: "keywords".to_string(),
+                value: list(keywords.into_iter().collect()),
+            },
+            Define {
+                name: "symbols".to_string(),
+                value: list(symbols.into_iter().collect()),
+            },
+        ],
+        emits: Default::default(),
+        docs: Default::default(),
+        tests: Vec::new(),
+        symbols: Default::default(),
+    })
+}
+
+type Statement = Vec<Piece>;
+
+/// A token of a statement, or a bracketed part of it.
+#[derive(Debug)]
+enum Piece {
+    Token(Token),
+    /// Statements in braces.
+    Block(Vec<Statement>),
+    /// Tokens in parentheses or brackets.
+    Group(&'static str, &'static str, Vec<Piece>),
+}
+
+/// What the statements of a cluster have in common.
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Keyword(String),
+    Symbol(String),
+    /// An identifier or literal of this kind.
+    Kind(&'static str),
+    /// Two or more identifiers or literals of this kind in a row.
+    Run(&'static str),
+    Block,
+    /// A comma separated list of identifiers and literals.
+    List(&'static str, &'static str),
+    Group(&'static str, &'static str),
+}
+
+struct Splitter<'a> {
+    src: &'a str,
+    tokens: &'a [(Token, Span)],
+    next: usize,
+}
+
+impl Splitter<'_> {
+    /// Reads statements up to `close`, or the end of the sample without one.
+    fn statements(&mut self, close: Option<&str>, statements: &mut Vec<Statement>) {
+        let tokens = self.tokens;
+        let mut statement = Vec::new();
+        while let Some((token, _)) = tokens.get(self.next) {
+            if matches!((token, close), (Token::Symbol(s), Some(close)) if s == close) {
+                break;
+            }
+            let end = matches!(token, Token::Symbol(s) if s == ";");
+            statement.push(self.piece());
+            if end || self.line_break() {
+                statements.push(std::mem::take(&mut statement));
+            }
+        }
+        if !statement.is_empty() {
+            statements.push(statement);
+        }
+    }
+
+    /// Reads the next token, with everything up to its closing bracket if
+    /// it opens one.
+    fn piece(&mut self) -> Piece {
+        let tokens = self.tokens;
+        let (token, _) = &tokens[self.next];
+        self.next += 1;
+        let Token::Symbol(s) = token else {
+            return Piece::Token(token.clone());
+        };
+        let piece = match s.as_str() {
+            "{" => {
+                let mut block = Vec::new();
+                self.statements(Some("}"), &mut block);
+                Piece::Block(block)
+            }
+            "(" => Piece::Group("(", ")", self.group(")")),
+            "[" => Piece::Group("[", "]", self.group("]")),
+            _ => return Piece::Token(token.clone()),
+        };
+        // Skips the closing bracket, unless the sample ended before it.
+        self.next = (self.next + 1).min(self.tokens.len());
+        piece
+    }
+
+    fn group(&mut self, close: &str) -> Vec<Piece> {
+        let tokens = self.tokens;
+        let mut pieces = Vec::new();
+        while let Some((token, _)) = tokens.get(self.next) {
+            if matches!(token, Token::Symbol(s) if s == close) {
+                break;
+            }
+            pieces.push(self.piece());
+        }
+        pieces
+    }
+
+    /// Whether a line break separates the last token read from the next.
+    fn line_break(&self) -> bool {
+        match (self.tokens.get(self.next - 1), self.tokens.get(self.next)) {
+            (Some((_, before)), Some((_, after))) => {
+                self.src[before.end..after.start].contains('\n')
+            }
+            _ => false,
+        }
+    }
+}
+
+fn shape(statement: &[Piece], keywords: &BTreeSet<&str>) -> Vec<Shape> {
+    let mut shape = Vec::new();
+    for (i, piece) in statement.iter().enumerate() {
+        let element = match piece {
+            Piece::Token(Token::Ident(word)) if i == 0 && keywords.contains(word.as_str()) => {
+                Shape::Keyword(word.clone())
+            }
+            Piece::Token(Token::Symbol(s)) => Shape::Symbol(s.clone()),
+            Piece::Token(token) => Shape::Kind(token.kind()),
+            Piece::Block(_) => Shape::Block,
+            Piece::Group(open, close, pieces) if is_list(pieces) => Shape::List(open, close),
+            Piece::Group(open, close, _) => Shape::Group(open, close),
+        };
+        let last = match shape.last() {
+            Some(Shape::Kind(kind) | Shape::Run(kind)) => Some(*kind),
+            _ => None,
+        };
+        match (last, element) {
+            (Some(last), Shape::Kind(kind)) if last == kind => {
+                *shape.last_mut().expect("a kind came before") = Shape::Run(kind);
+            }
+            (_, element) => shape.push(element),
+        }
+    }
+    shape
+}
+
+/// Whether `pieces` are identifiers and literals separated by commas.
+fn is_list(pieces: &[Piece]) -> bool {
+    (pieces.is_empty() || pieces.len() % 2 == 1)
+        && pieces.iter().enumerate().all(|(i, piece)| match piece {
+            Piece::Token(Token::Symbol(s)) => i % 2 == 1 && s == ",",
+            Piece::Token(_) => i % 2 == 0,
+            _ => false,
+        })
+}
+
+/// Collects `statements` and the statements in their braces, depth first.
+fn flatten<'a>(statements: &'a [Statement], all: &mut Vec<&'a Statement>) {
+    for statement in statements {
+        all.push(statement);
+        flatten_pieces(statement, all);
+    }
+}
+
+fn flatten_pieces<'a>(pieces: &'a [Piece], all: &mut Vec<&'a Statement>) {
+    for piece in pieces {
+        match piece {
+            Piece::Token(_) => {}
+            Piece::Block(statements) => flatten(statements, all),
+            Piece::Group(_, _, pieces) => flatten_pieces(pieces, all),
+        }
+    }
+}
+
+/// The word all `members` have as their `index`th piece, if any.
+fn common_word<'a>(members: &[&'a Statement], index: usize) -> Option<&'a str> {
+    let mut words = members.iter().map(|&statement| match statement.get(index) {
+        Some(Piece::Token(Token::Ident(word))) if is_word(word) => Some(word.as_str()),
+        _ => None,
+    });
+    let first = words.next()??;
+    words.all(|word| word == Some(first)).then_some(first)
+}
+
+fn tokens_of(statement: &[Piece]) -> Vec<&Token> {
+    statement
+        .iter()
+        .flat_map(|piece| match piece {
+            Piece::Token(token) => vec![token],
+            // The statements in braces are flattened into the others.
+            Piece::Block(_) => Vec::new(),
+            Piece::Group(_, _, pieces) => tokens_of(pieces),
+        })
+        .collect()
+}
+
+/// A lowercase word longer than a letter, which could be a keyword.
+fn is_word(word: &str) -> bool {
+    word.len() > 1 && word.chars().all(|c| c.is_ascii_lowercase())
+}
+
+fn field_name(kind: &str) -> &str {
+    match kind {
+        "ident" => "name",
+        _ => "value",
+    }
+}
+
+fn kind_pattern(kind: &str, name: String) -> TokenPattern {
+    let name = Some(name);
+    match kind {
+        "ident" => ident(name),
+        "float" => float(name),
+        "int" => int(name),
+        "string" => string(name),
+        _ => bool(name),
+    }
+    .into()
+}
+
+/// Field names of one rule, numbered when they repeat.
+#[derive(Default)]
+struct Fields(HashMap<String, usize>);
+
+impl Fields {
+    fn next(&mut self, name: &str) -> String {
+        let count = self.0.entry(name.to_string()).or_default();
+        *count += 1;
+        match count {
+            1 => name.to_string(),
+            n => format!("{name}{n}"),
+        }
+    }
+}
+
+/// The rules [`infer`] drafted so far.
+#[derive(Default)]
+struct Drafts {
+    rules: RuleMap,
+    /// Whether some rule uses `Value`.
+    value: bool,
+    /// Whether some rule uses `Item`.
+    item: bool,
+}
+
+impl Drafts {
+    /// Names a rule after the keyword it starts with or what it looks like,
+    /// numbered when the name is taken.
+    fn rule_name(&self, shape: &[Shape]) -> String {
+        let base = match shape {
+            [Shape::Keyword(word), ..] => {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            }
+            [Shape::Kind("ident"), Shape::Symbol(s), ..] if s == "=" => "Assign".to_string(),
+            [Shape::Kind("ident"), Shape::List("(", _) | Shape::Group("(", _), ..] => {
+                "Call".to_string()
+            }
+            _ => "Statement".to_string(),
+        };
+        (1..)
+            .map(|n| match n {
+                1 => base.clone(),
+                n => format!("{base}{n}"),
+            })
+            .find(|name| !RESERVED.contains(&name.as_str()) && !self.rules.contains_key(name))
+            .expect("some number is free")
+    }
+}
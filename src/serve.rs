@@ -0,0 +1,152 @@
+use std::io::Read;
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use tmpl::custom::ParseSession;
+use tmpl::definition::ParserDefinition;
+
+use crate::diagnostics::{Diagnostic, ErrorKind};
+
+/// Largest request body accepted, larger ones are answered with 413.
+const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+struct ParseRequest {
+    source: String,
+    entry: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ParseResponse {
+    Ast(tmpl::custom::Ast),
+    Diagnostics(Vec<Diagnostic>),
+}
+
+/// Serves `POST /parse` on `address`, parsing JSON `{"source", "entry"}` bodies with `definition`.
+///
+/// Requests are handled by `workers` threads. A parse that takes longer than
+/// `timeout` is answered with 504, but keeps its worker busy until it ends,
+/// so at most `workers` parses run at once.
+pub fn run(
+    definition: &Arc<ParserDefinition>,
+    address: &str,
+    entry: &str,
+    workers: usize,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let server =
+        Server::http(address).map_err(|e| anyhow::anyhow!("can't listen on {address}: {e}"))?;
+    eprintln!("listening on http://{}", server.server_addr());
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| {
+                for request in server.incoming_requests() {
+                    serve(request, definition, entry, timeout);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn serve(mut request: Request, definition: &Arc<ParserDefinition>, entry: &str, timeout: Duration) {
+    tracing::info!(method = %request.method(), url = request.url(), "request");
+    let mut overrun = None;
+    let (status, body) = match (request.method(), request.url()) {
+        (Method::Post, "/parse") => match read_body(&mut request) {
+            Ok(Some(body)) => handle(definition, body, entry, timeout, &mut overrun),
+            Ok(None) => (413, error(format!("body exceeds {MAX_BODY_BYTES} bytes"))),
+            Err(e) => (400, error(e.to_string())),
+        },
+        (_, "/parse") => (405, error("only POST is supported".to_string())),
+        (_, url) => (404, error(format!("no such endpoint `{url}`"))),
+    };
+    let (status, json) = match serde_json::to_string(&body) {
+        Ok(json) => (status, json),
+        Err(e) => {
+            tracing::error!("failed to serialize response: {e}");
+            let body = error(format!("failed to serialize response: {e}"));
+            (500, serde_json::to_string(&body).unwrap_or_default())
+        }
+    };
+    let header = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+    let response = Response::from_string(json)
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        tracing::warn!("failed to send response: {e}");
+    }
+    if let Some(parse) = overrun {
+        let _ = parse.join();
+    }
+}
+
+/// Reads the body of `request` as UTF-8, or `None` if it is longer than
+/// [`MAX_BODY_BYTES`].
+fn read_body(request: &mut Request) -> std::io::Result<Option<String>> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Ok(None);
+    }
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Parses the request in `body` on its own thread. If that takes longer
+/// than `timeout`, the thread is left in `overrun` to be joined once the
+/// 504 is sent.
+fn handle(
+    definition: &Arc<ParserDefinition>,
+    body: String,
+    entry: &str,
+    timeout: Duration,
+    overrun: &mut Option<JoinHandle<()>>,
+) -> (u16, ParseResponse) {
+    let request: ParseRequest = match serde_json::from_str(&body) {
+        Ok(request) => request,
+        Err(e) => return (400, error(format!("invalid request body: {e}"))),
+    };
+    let entry = request.entry.unwrap_or_else(|| entry.to_string());
+    if definition.rule(&entry).is_none() {
+        let message = format!("unknown entry rule `{entry}`");
+        let diagnostic = Diagnostic::new(ErrorKind::Grammar, None, None, message);
+        return (422, ParseResponse::Diagnostics(vec![diagnostic]));
+    }
+    let (sender, receiver) = mpsc::channel();
+    let definition = Arc::clone(definition);
+    let parse = thread::spawn(move || {
+        let mut session = ParseSession::new(definition);
+        let result = crate::parse_text(&mut session, None, &request.source, &entry);
+        let _ = sender.send(result);
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(ast)) => (200, ParseResponse::Ast(ast)),
+        Ok(Err(e)) => {
+            let diagnostic = match e.downcast::<Diagnostic>() {
+                Ok(diagnostic) => diagnostic,
+                Err(e) => Diagnostic::new(ErrorKind::Other, None, None, format!("{e:#}")),
+            };
+            (422, ParseResponse::Diagnostics(vec![diagnostic]))
+        }
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            *overrun = Some(parse);
+            let message = format!("parsing took longer than {}s", timeout.as_secs_f64());
+            (504, error(message))
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => (500, error("parser panicked".to_string())),
+    }
+}
+
+fn error(message: String) -> ParseResponse {
+    ParseResponse::Diagnostics(vec![Diagnostic::new(ErrorKind::Other, None, None, message)])
+}
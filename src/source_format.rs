@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::custom::Parser;
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, PatternVisitor, TokenPattern,
+};
+use crate::lexer::Token;
+use crate::style::OutputStyle;
+use crate::Error;
+
+/// Symbols that aren't separated from the token before them, unless a
+/// `format_no_space_before` define lists others.
+const NO_SPACE_BEFORE: &[&str] = &[",", ";", ")", "]", "."];
+/// Symbols that aren't separated from the token after them, unless a
+/// `format_no_space_after` define lists others.
+const NO_SPACE_AFTER: &[&str] = &["(", "[", "."];
+
+/// Reprints `src`, a source of the language `definition` describes parsed
+/// from `entry`, in a canonical layout the grammar implies:
+///
+/// - Every match of a rule that is repeated without a separator, like the
+///   statements of `<stmts:Stmt>*`, starts on a line of its own, indented
+///   by how many such matches it is nested in. A blank line before one is
+///   kept. The tokens following one, like a closing brace, start a new
+///   line too.
+/// - Other tokens are separated by a space, except after the symbols of a
+///   `format_no_space_after` define and before those of a
+///   `format_no_space_before` define, which default to opening and closing
+///   brackets, `,`, `;` and `.`. An opening bracket right after an
+///   identifier isn't separated either, like in a call.
+///
+/// The output is laid out in the style the grammar sets, see
+/// [`OutputStyle::from_definition`]. A style with a line width breaks lines
+/// after the separators of repeated patterns, like the `,` of
+/// `<params:ident> ** ","`, which its separator spacing applies to too.
+///
+/// Fails if the source doesn't parse, or if the formatted source would
+/// parse to a different tree.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// let grammar = tmpl::Grammar::parse(concat!(
+///     "Main:\n<items:Fn>*\n~~~\n\n",
+///     "Fn:\nfn <name:ident> ( <params:ident> ** \",\" ) { <body:Stmt>* }\n~~~\n\n",
+///     "Stmt:\n<name:ident> = <value:int> ;\n~~~\n",
+/// ))?;
+/// let definition = grammar.shared_definition();
+/// let formatted = tmpl::source_format::format_source(&definition, "fn f(a,b){x=1;y=2;}", "Main")?;
+/// assert_eq!(formatted, "fn f(a, b) {\n    x = 1;\n    y = 2;\n}\n");
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+pub fn format_source(
+    definition: &Arc<ParserDefinition>,
+    src: &str,
+    entry: &str,
+) -> Result<String, Error> {
+    let style = OutputStyle::from_definition(definition);
+    format_source_with(definition, src, entry, &style)
+}
+
+/// Reprints `src` like [`format_source`], laid out in `style` instead.
+pub fn format_source_with(
+    definition: &Arc<ParserDefinition>,
+    src: &str,
+    entry: &str,
+    style: &OutputStyle,
+) -> Result<String, Error> {
+    let (tokens, spans): (Vec<_>, Vec<_>) = crate::lexer::lex_spanned(src)?.into_iter().unzip();
+    let parser = Parser::new(Arc::clone(definition), tokens)
+        .with_token_spans(spans)
+        .with_spans(true);
+    let ast = parser.parse_entry(entry)?;
+    let rule_spans = parser.spans().unwrap_or_default();
+    let (tokens, spans) = parser.into_tokens();
+
+    let lines = line_rules(definition);
+    let significant = |i: &usize| tokens[*i] != Token::Ws;
+    let mut starts = vec![false; tokens.len()];
+    let mut ends = vec![false; tokens.len()];
+    let mut depth = vec![0usize; tokens.len()];
+    for span in rule_spans.iter().filter(|span| lines.contains(&span.rule)) {
+        let Some(last) = (span.start..span.end).rev().find(significant) else {
+            continue;
+        };
+        starts[span.start] = true;
+        ends[last] = true;
+        depth[span.start..span.end].iter_mut().for_each(|d| *d += 1);
+    }
+
+    let no_space_before = hint(definition, "format_no_space_before", NO_SPACE_BEFORE);
+    let no_space_after = hint(definition, "format_no_space_after", NO_SPACE_AFTER);
+    let separators = separators(definition);
+    let mut out = String::new();
+    let mut previous: Option<usize> = None;
+    for i in (0..tokens.len()).filter(significant) {
+        let text = &src[spans[i].clone()];
+        if let Some(p) = previous {
+            let before = &src[spans[p].clone()];
+            if starts[i] || ends[p] {
+                let gap = &src[spans[p].end..spans[i].start];
+                out.push('\n');
+                if starts[i] && gap.matches('\n').count() > 1 {
+                    out.push('\n');
+                }
+                out.push_str(&style.indent(depth[i].saturating_sub(1)));
+            } else {
+                let separator = separators.contains(before);
+                let line = &out[out.rfind('\n').map_or(0, |i| i + 1)..];
+                let columns = line.chars().count() + 1 + text.chars().count();
+                if separator && style.overflows(columns) {
+                    out.push('\n');
+                    out.push_str(&style.indent(depth[i]));
+                } else {
+                    let call = matches!(tokens[p], Token::Ident(_)) && matches!(text, "(" | "[");
+                    let space = match style.separator_space {
+                        Some(space) if separator => space,
+                        _ => {
+                            !call
+                                && !no_space_after.contains(&before)
+                                && !no_space_before.contains(&text)
+                        }
+                    };
+                    if space {
+                        out.push(' ');
+                    }
+                }
+            }
+        } else {
+            out.push_str(&style.indent(depth[i].saturating_sub(1)));
+        }
+        out.push_str(text);
+        previous = Some(i);
+    }
+    if previous.is_some() {
+        out.push('\n');
+    }
+
+    let (tokens, spans): (Vec<_>, Vec<_>) = crate::lexer::lex_spanned(&out)?.into_iter().unzip();
+    let reparsed = Parser::new(Arc::clone(definition), tokens)
+        .with_token_spans(spans)
+        .parse_entry(entry);
+    match reparsed {
+        Ok(formatted) if formatted == ast => Ok(out),
+        _ => Err(Error::Unformattable),
+    }
+}
+
+/// Rules some pattern repeats without a separator.
+fn line_rules(definition: &ParserDefinition) -> BTreeSet<String> {
+    struct Lines(BTreeSet<String>);
+
+    impl<'a> PatternVisitor<'a> for Lines {
+        fn visit_token(&mut self, token: &'a TokenPattern) {
+            if let InternalPattern::Named {
+                kind: InternalPatternKind::Custom(rule),
+                ..
+            } = &token.pattern
+            {
+                if token.repeat_mode.is_some() && token.separator.is_none() {
+                    self.0.insert(rule.clone());
+                }
+            }
+            crate::definition::walk_token(self, token);
+        }
+    }
+
+    let mut lines = Lines(BTreeSet::new());
+    lines.visit_definition(definition);
+    lines.0
+}
+
+/// The separators of repeated patterns.
+fn separators(definition: &ParserDefinition) -> BTreeSet<&str> {
+    struct Separators<'a>(BTreeSet<&'a str>);
+
+    impl<'a> PatternVisitor<'a> for Separators<'a> {
+        fn visit_token(&mut self, token: &'a TokenPattern) {
+            if let Some(separator) = &token.separator {
+                self.0.insert(separator);
+            }
+            crate::definition::walk_token(self, token);
+        }
+    }
+
+    let mut separators = Separators(BTreeSet::new());
+    separators.visit_definition(definition);
+    separators.0
+}
+
+/// The symbols the define `name` lists, or `default` without one.
+fn hint<'a>(definition: &'a ParserDefinition, name: &str, default: &[&'a str]) -> Vec<&'a str> {
+    match definition.defines.iter().any(|define| define.name == name) {
+        true => definition.define_names(name),
+        false => default.to_vec(),
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::definition::{
+    InternalPattern, InternalPatternKind, ParserDefinition, Pattern, PatternVisitor, TokenPattern,
+};
+
+/// Size and shape figures of a grammar.
+#[derive(Debug, Clone, Serialize)]
+pub struct GrammarStats {
+    pub rules: usize,
+    pub defines: usize,
+    pub alternatives: usize,
+    pub max_alternatives: usize,
+    pub average_alternatives: f64,
+    /// Longest chain of rule references starting at `Main`, not counting
+    /// recursion.
+    pub max_depth: usize,
+    /// Whether any rule can end up referencing itself.
+    pub recursive: bool,
+    pub regexes: usize,
+    pub reachable_rules: usize,
+    /// Share of rules reachable from `Main`, between 0 and 1.
+    pub reachable_ratio: f64,
+    pub alternatives_per_rule: BTreeMap<String, usize>,
+}
+
+pub fn stats(definition: &ParserDefinition) -> GrammarStats {
+    let rules = definition.all_rules();
+    let alternatives_per_rule: BTreeMap<String, usize> = rules
+        .iter()
+        .map(|(name, patterns)| {
+            let count = patterns.iter().map(|p| p.alternatives().len()).sum();
+            (name.to_string(), count)
+        })
+        .collect();
+    let alternatives: usize = alternatives_per_rule.values().sum();
+    let mut regexes = RegexCount::default();
+    regexes.visit_definition(definition);
+    let reachable = definition
+        .reachability()
+        .reachable
+        .into_iter()
+        .filter(|name| definition.rule(name).is_some())
+        .count();
+
+    let mut depths = Depths::default();
+    let max_depth = depths.depth(definition, "Main");
+    GrammarStats {
+        rules: rules.len(),
+        defines: definition.defines.len(),
+        alternatives,
+        max_alternatives: alternatives_per_rule.values().copied().max().unwrap_or(0),
+        average_alternatives: alternatives as f64 / rules.len() as f64,
+        max_depth,
+        recursive: depths.recursive,
+        regexes: regexes.0,
+        reachable_rules: reachable,
+        reachable_ratio: reachable as f64 / rules.len() as f64,
+        alternatives_per_rule,
+    }
+}
+
+#[derive(Default)]
+struct RegexCount(usize);
+
+impl PatternVisitor<'_> for RegexCount {
+    fn visit_kind(&mut self, kind: &InternalPatternKind) {
+        if let InternalPatternKind::Regex(_) = kind {
+            self.0 += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Depths<'a> {
+    known: HashMap<&'a str, usize>,
+    active: HashSet<&'a str>,
+    recursive: bool,
+}
+
+impl<'a> Depths<'a> {
+    fn depth(&mut self, definition: &'a ParserDefinition, name: &'a str) -> usize {
+        if let Some(depth) = self.known.get(name) {
+            return *depth;
+        }
+        let Some(patterns) = definition.rule(name) else {
+            return 0;
+        };
+        self.active.insert(name);
+        let mut deepest = 0;
+        for alternative in patterns.iter().flat_map(Pattern::alternatives) {
+            for reference in alternative.iter().flat_map(|t| t.pattern.references()) {
+                if self.active.contains(reference) {
+                    self.recursive = true;
+                } else {
+                    deepest = deepest.max(self.depth(definition, reference));
+                }
+            }
+        }
+        self.active.remove(name);
+        self.known.insert(name, deepest + 1);
+        deepest + 1
+    }
+}
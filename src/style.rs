@@ -0,0 +1,137 @@
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+};
+
+use crate::definition::{ParserDefinition, Value};
+
+/// Indentation of one level in the output of templates and the source
+/// formatter before [`OutputStyle::reindent`].
+pub const INDENT: &str = "    ";
+
+/// How rendered output and formatted sources are laid out. Grammars set it
+/// with defines, which the CLI flags of the same names override:
+///
+/// ```text
+/// define output_indent_width: 2;
+/// define output_tabs: false;
+/// define output_line_width: 80;
+/// define output_separator_space: true;
+/// ```
+///
+/// The default changes nothing: four spaces per level, no line width and
+/// separators as written.
+///
+/// ```
+/// use tmpl::style::OutputStyle;
+///
+/// let grammar = tmpl::Grammar::parse(concat!(
+///     "define output_indent_width: 2;\n",
+///     "define output_separator_space: false;\n\n",
+///     "Main:\n<name:ident> ( <args:ident> ** \",\" )\n~~~ ",
+///     "emit \"fn {name}() {{\n    call({for a in args sep \\\", \\\"}{a}{end});\n}}\"\n",
+/// ))?;
+/// let style = OutputStyle::from_definition(grammar.definition());
+/// assert_eq!(style.indent_width, 2);
+/// assert_eq!(grammar.transpile("f(a, b)")?, "fn f() {\n  call(a,b);\n}");
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputStyle {
+    /// Columns per indentation level, when indenting with spaces.
+    pub indent_width: usize,
+    /// Indents with a tab per level instead of spaces.
+    pub tabs: bool,
+    /// Lines that would get longer than this many columns are broken after
+    /// a list separator instead, and continue one level deeper.
+    pub line_width: Option<usize>,
+    /// Whether list separators like `,` are followed by a space, or as they
+    /// are written if not set.
+    pub separator_space: Option<bool>,
+}
+
+impl Default for OutputStyle {
+    fn default() -> Self {
+        Self {
+            indent_width: INDENT.len(),
+            tabs: false,
+            line_width: None,
+            separator_space: None,
+        }
+    }
+}
+
+impl OutputStyle {
+    /// The style the `output_*` defines of `definition` set, with the
+    /// default for those it doesn't have or that have a value of the wrong
+    /// kind.
+    pub fn from_definition(definition: &ParserDefinition) -> Self {
+        let define = |name: &str| {
+            (definition.defines.iter())
+                .find(|define| define.name == name)
+                .map(|define| &define.value)
+        };
+        let int = |name| match define(name) {
+            Some(Value::Int(value)) => value.parse().ok(),
+            _ => None,
+        };
+        let bool = |name| match define(name) {
+            Some(Value::Bool(value)) => Some(*value),
+            _ => None,
+        };
+        let default = Self::default();
+        Self {
+            indent_width: int("output_indent_width").unwrap_or(default.indent_width),
+            tabs: bool("output_tabs").unwrap_or(default.tabs),
+            line_width: int("output_line_width"),
+            separator_space: bool("output_separator_space"),
+        }
+    }
+
+    /// Indentation of `levels` levels.
+    pub fn indent(&self, levels: usize) -> String {
+        match self.tabs {
+            true => "\t".repeat(levels),
+            false => " ".repeat(self.indent_width * levels),
+        }
+    }
+
+    /// `separator` with a space after it or without one, as the style
+    /// wants.
+    pub fn separator<'a>(&self, separator: &'a str) -> Cow<'a, str> {
+        match self.separator_space {
+            None => Cow::Borrowed(separator),
+            Some(true) => Cow::Owned(separator.trim_end().to_string() + " "),
+            Some(false) => Cow::Borrowed(separator.trim_end()),
+        }
+    }
+
+    /// Whether a line of `columns` columns is too long.
+    pub fn overflows(&self, columns: usize) -> bool {
+        self.line_width.is_some_and(|width| columns > width)
+    }
+
+    /// Replaces every [`INDENT`] at the start of the lines of `text` with
+    /// one level of this style's indentation.
+    ///
+    /// This runs on text that is already rendered, so it can't tell the
+    /// layout from the contents of literals: the leading spaces of lines
+    /// inside a multi-line string literal are replaced as well.
+    pub fn reindent(&self, text: &str) -> String {
+        if !self.tabs && self.indent_width == INDENT.len() {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let mut rest = line;
+            let mut levels = 0;
+            while let Some(inner) = rest.strip_prefix(INDENT) {
+                rest = inner;
+                levels += 1;
+            }
+            out.push_str(&self.indent(levels));
+            out.push_str(rest);
+        }
+        out
+    }
+}
@@ -0,0 +1,451 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use core::ops::Range;
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use crate::custom::{Ast, Node};
+use crate::style::{OutputStyle, INDENT};
+
+/// Text with placeholders filled in from a syntax tree, to turn parsed
+/// sources into other text.
+///
+/// - `{name}` is the text of the field `name`, `{call.name}` that of a
+///   field of the tree in `call`. Missing optional matches are empty.
+/// - `{for arg in args}..{end}` repeats its body for every item of a
+///   repeated field, with the item as `arg`. `{for arg in args sep ", "}`
+///   puts `, ` between the items.
+/// - `{if name}..{else}..{end}` renders the first part if `name` matched
+///   something that isn't `false` or an empty list, otherwise the optional
+///   second part. Fields of alternatives that didn't match count as unmatched.
+/// - `{{` and `}}` are literal braces.
+///
+/// The [`OutputStyle`] set with [`Template::with_style`] decides the
+/// indentation and how separators are written and where lines break.
+///
+/// ```
+/// use tmpl::template::Template;
+///
+/// let grammar = tmpl::Grammar::parse(
+///     "Main:\n<name:ident> ( <args:Arg>* )\n~~~\n\nArg:\n<name:ident> = <value:int>\n~~~\n",
+/// )?;
+/// let ast = grammar.parse_str("point(x = 1 y = 2)")?;
+/// let template: Template = "{name}({for arg in args sep \", \"}{arg.name}: {arg.value}{end})".parse()?;
+/// assert_eq!(template.render(&ast)?, "point(x: 1, y: 2)");
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+    style: OutputStyle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    #[error("{message} at byte {offset}")]
+    Syntax { offset: usize, message: String },
+    #[error("Unknown field `{0}`")]
+    UnknownField(String),
+    #[error("`{0}` is a list, render its items with `{{for item in {0}}}`")]
+    List(String),
+    #[error("`{0}` is a tree, render one of its fields like `{{{0}.field}}`")]
+    Tree(String),
+    #[error("`{0}` is not a list to loop over")]
+    NotAList(String),
+    #[error("Rule `{0}` has no emit template")]
+    NoEmit(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Value(Path),
+    For {
+        item: String,
+        list: Path,
+        separator: String,
+        body: Vec<Part>,
+    },
+    If {
+        condition: Path,
+        then: Vec<Part>,
+        otherwise: Vec<Part>,
+    },
+}
+
+/// A field, possibly of a tree in a field, like `call.name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Path(Vec<String>);
+
+impl core::fmt::Display for Path {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0.join("."))
+    }
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Self, TemplateError> {
+        let mut tags = Tags { src, offset: 0 };
+        let (parts, end) = parse_parts(&mut tags)?;
+        match end {
+            None => Ok(Self {
+                parts,
+                style: OutputStyle::default(),
+            }),
+            Some((offset, tag)) => Err(syntax(offset, format!("`{{{tag}}}` without a block"))),
+        }
+    }
+
+    /// Lays out the output in `style`.
+    pub fn with_style(mut self, style: OutputStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Fills in the template from the fields of `ast`. Placeholders for a
+    /// tree fail, use its fields or [`Template::render_with`].
+    pub fn render(&self, ast: &Ast) -> Result<String, TemplateError> {
+        Ok(self.style.reindent(&self.render_trees(ast, None)?))
+    }
+
+    /// Fills in the template from the fields of `ast`, with what `tree`
+    /// gives for placeholders of a tree, like the tree rendered with a
+    /// template of its own. As the output usually ends up in other output,
+    /// its indentation is left for [`OutputStyle::reindent`] to change.
+    pub fn render_with(
+        &self,
+        ast: &Ast,
+        mut tree: impl FnMut(&Ast) -> Result<String, TemplateError>,
+    ) -> Result<String, TemplateError> {
+        self.render_trees(ast, Some(&mut tree))
+    }
+
+    fn render_trees(&self, ast: &Ast, mut trees: Trees<'_>) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        let scope = Scope {
+            ast,
+            items: Vec::new(),
+        };
+        render_parts(&self.parts, &scope, &mut trees, &self.style, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Self::parse(src)
+    }
+}
+
+fn syntax(offset: usize, message: impl Into<String>) -> TemplateError {
+    TemplateError::Syntax {
+        offset,
+        message: message.into(),
+    }
+}
+
+enum Piece<'a> {
+    Text(String),
+    /// The text between the braces of a tag and the offset of its `{`.
+    Tag(usize, &'a str),
+}
+
+/// Splits a template into text and tags.
+struct Tags<'a> {
+    src: &'a str,
+    offset: usize,
+}
+
+impl<'a> Tags<'a> {
+    fn next(&mut self) -> Result<Option<Piece<'a>>, TemplateError> {
+        let rest = &self.src[self.offset..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        if rest.starts_with('{') && !rest.starts_with("{{") {
+            let start = self.offset;
+            let end = tag_end(rest).ok_or_else(|| syntax(start, "unclosed `{`"))?;
+            self.offset += end + 1;
+            return Ok(Some(Piece::Tag(start, &rest[1..end])));
+        }
+        let mut text = String::new();
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if rest[i..].starts_with("{{") => {
+                    chars.next();
+                    text.push('{');
+                }
+                '{' => {
+                    self.offset += i;
+                    return Ok(Some(Piece::Text(text)));
+                }
+                '}' if rest[i..].starts_with("}}") => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => {
+                    return Err(syntax(
+                        self.offset + i,
+                        "`}` without `{`, write `}}` for a brace",
+                    ))
+                }
+                c => text.push(c),
+            }
+        }
+        self.offset = self.src.len();
+        Ok(Some(Piece::Text(text)))
+    }
+}
+
+/// Index of the `}` closing the tag `rest` starts with, skipping quoted text.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '}' if !quoted => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A tag closing a block, like `end`, with the offset of its `{`.
+type Closing<'a> = Option<(usize, &'a str)>;
+
+/// Parses parts up to the end of the template or a tag closing a block.
+fn parse_parts<'a>(tags: &mut Tags<'a>) -> Result<(Vec<Part>, Closing<'a>), TemplateError> {
+    let mut parts = Vec::new();
+    while let Some(piece) = tags.next()? {
+        let (offset, tag) = match piece {
+            Piece::Text(text) => {
+                parts.push(Part::Text(text));
+                continue;
+            }
+            Piece::Tag(offset, tag) => (offset, tag.trim()),
+        };
+        let words: Vec<_> = tag.splitn(2, char::is_whitespace).collect();
+        match words[..] {
+            ["end" | "else"] => return Ok((parts, Some((offset, tag)))),
+            ["for", rest] => parts.push(parse_for(tags, offset, rest)?),
+            ["if", condition] => {
+                let condition = parse_path(offset, condition)?;
+                let (then, end) = parse_parts(tags)?;
+                let otherwise = match end {
+                    Some((_, "else")) => match parse_parts(tags)? {
+                        (otherwise, Some((_, "end"))) => otherwise,
+                        _ => return Err(syntax(offset, "`{else}` without `{end}`")),
+                    },
+                    Some((_, "end")) => Vec::new(),
+                    _ => return Err(syntax(offset, "`{if}` without `{end}`")),
+                };
+                parts.push(Part::If {
+                    condition,
+                    then,
+                    otherwise,
+                });
+            }
+            _ => parts.push(Part::Value(parse_path(offset, tag)?)),
+        }
+    }
+    Ok((parts, None))
+}
+
+/// Parses a loop from `item in list` and an optional `sep "separator"`.
+fn parse_for(tags: &mut Tags<'_>, offset: usize, rest: &str) -> Result<Part, TemplateError> {
+    let malformed = || syntax(offset, "expected `{for item in list}`");
+    let (item, rest) = rest
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(malformed)?;
+    let rest = (rest.trim_start().strip_prefix("in"))
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .ok_or_else(malformed)?;
+    let (list, separator) = match rest.split_once(" sep ") {
+        Some((list, separator)) => (list, parse_string(offset, separator.trim())?),
+        None => (rest, String::new()),
+    };
+    let item = parse_path(offset, item)?;
+    let [item] = &item.0[..] else {
+        return Err(malformed());
+    };
+    let list = parse_path(offset, list)?;
+    let body = match parse_parts(tags)? {
+        (body, Some((_, "end"))) => body,
+        _ => return Err(syntax(offset, "`{for}` without `{end}`")),
+    };
+    Ok(Part::For {
+        item: item.clone(),
+        list,
+        separator,
+        body,
+    })
+}
+
+fn parse_path(offset: usize, path: &str) -> Result<Path, TemplateError> {
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let segments: Vec<_> = path.trim().split('.').map(str::to_string).collect();
+    match segments.iter().all(|segment| is_name(segment)) {
+        true => Ok(Path(segments)),
+        false => Err(syntax(offset, format!("`{}` is not a field", path.trim()))),
+    }
+}
+
+/// Parses a quoted string with `\"`, `\\`, `\n` and `\t` escapes.
+fn parse_string(offset: usize, quoted: &str) -> Result<String, TemplateError> {
+    let malformed = || syntax(offset, "expected a quoted separator like `sep \", \"`");
+    let inner = quoted
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(malformed)?;
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c @ ('"' | '\\')) => c,
+                _ => return Err(malformed()),
+            },
+            c => c,
+        });
+    }
+    Ok(text)
+}
+
+/// The tree a template is rendered from and the loop items in scope,
+/// innermost last.
+struct Scope<'a> {
+    ast: &'a Ast,
+    items: Vec<(&'a str, &'a Node)>,
+}
+
+impl<'a> Scope<'a> {
+    fn resolve(&self, path: &Path) -> Result<&'a Node, TemplateError> {
+        let unknown = || TemplateError::UnknownField(path.to_string());
+        let (first, rest) = path.0.split_first().expect("paths aren't empty");
+        let item = self.items.iter().rev().find(|(name, _)| name == first);
+        let mut node = match item {
+            Some((_, node)) => *node,
+            None => self.ast.fields.get(first).ok_or_else(unknown)?,
+        };
+        for segment in rest {
+            node = match node {
+                Node::Ast(ast) => ast.fields.get(segment).ok_or_else(unknown)?,
+                _ => return Err(unknown()),
+            };
+        }
+        Ok(node)
+    }
+}
+
+/// Renders the trees placeholders refer to, see [`Template::render_with`].
+type Trees<'f> = Option<&'f mut dyn FnMut(&Ast) -> Result<String, TemplateError>>;
+
+fn render_parts<'a>(
+    parts: &'a [Part],
+    scope: &Scope<'a>,
+    trees: &mut Trees<'_>,
+    style: &OutputStyle,
+    out: &mut String,
+) -> Result<(), TemplateError> {
+    for part in parts {
+        match part {
+            Part::Text(text) => out.push_str(text),
+            Part::Value(path) => match scope.resolve(path)? {
+                Node::Ident(s) | Node::String(s) | Node::Text(s) => out.push_str(s),
+                Node::Int(i) => write!(out, "{i}").expect("writing to a string"),
+                Node::Float(f) => write!(out, "{f}").expect("writing to a string"),
+                Node::Bool(b) => write!(out, "{b}").expect("writing to a string"),
+                Node::None => {}
+                Node::List(_) => return Err(TemplateError::List(path.to_string())),
+                Node::Ast(tree) => match trees {
+                    Some(render) => out.push_str(&render(tree)?),
+                    None => return Err(TemplateError::Tree(path.to_string())),
+                },
+            },
+            Part::For {
+                item,
+                list,
+                separator,
+                body,
+            } => {
+                let items = match scope.resolve(list)? {
+                    Node::List(items) => &items[..],
+                    Node::None => &[],
+                    _ => return Err(TemplateError::NotAList(list.to_string())),
+                };
+                let separator = style.separator(separator);
+                for (i, node) in items.iter().enumerate() {
+                    let mut inner = Scope {
+                        ast: scope.ast,
+                        items: scope.items.clone(),
+                    };
+                    inner.items.push((item, node));
+                    let start = out.len();
+                    if i > 0 {
+                        out.push_str(&separator);
+                    }
+                    let item_start = out.len();
+                    render_parts(body, &inner, trees, style, out)?;
+                    if i > 0 {
+                        break_after_separator(out, start..item_start, style);
+                    }
+                }
+            }
+            Part::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // Fields of other alternatives of a rule are missing.
+                let matched = match scope.resolve(condition) {
+                    Ok(Node::None | Node::Bool(false)) | Err(TemplateError::UnknownField(_)) => {
+                        false
+                    }
+                    Ok(Node::List(items)) => !items.is_empty(),
+                    Ok(_) => true,
+                    Err(error) => return Err(error),
+                };
+                let parts = if matched { then } else { otherwise };
+                render_parts(parts, scope, trees, style, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Breaks the line after the separator at `separator` in `out` if the item
+/// after it doesn't fit on the line anymore. The next line is indented one
+/// level deeper than the line of the separator.
+fn break_after_separator(out: &mut String, separator: Range<usize>, style: &OutputStyle) {
+    let line_start = out[..separator.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = out[separator.end..]
+        .find('\n')
+        .map_or(out.len(), |i| separator.end + i);
+    if !style.overflows(out[line_start..line_end].chars().count()) {
+        return;
+    }
+    let line = &out[line_start..separator.start];
+    let indentation = &line[..line.len() - line.trim_start().len()];
+    let replacement = format!(
+        "{}\n{indentation}{INDENT}",
+        out[separator.clone()].trim_end()
+    );
+    out.replace_range(separator, &replacement);
+}
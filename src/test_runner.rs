@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use logos::Logos;
+use serde::{Deserialize, Serialize};
+use tmpl::custom::{Ast, Parser};
+use tmpl::definition::{Expectation, GrammarTest, ParserDefinition};
+use tmpl::lexer::Token;
+
+/// A single grammar test, stored in a YAML list inside the test directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    pub input: String,
+    /// Rule to start parsing from, defaults to `Main`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(default)]
+    pub expect: Expectation,
+    /// Snapshot of the expected AST, only checked when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ast: Option<Ast>,
+    /// Text the error message has to contain, only checked when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<&GrammarTest> for TestCase {
+    fn from(test: &GrammarTest) -> Self {
+        Self {
+            name: test.name.clone(),
+            input: test.input.clone(),
+            rule: test.rule.clone(),
+            expect: test.expect,
+            ast: None,
+            error: None,
+        }
+    }
+}
+
+/// Finds all `.yaml` and `.yml` files in `dir`, sorted by path.
+pub fn discover(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml")
+        ) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Runs the `test` blocks of the grammar at `grammar` and every test in
+/// `files`, printing one line per test and a summary.
+///
+/// With `update` the AST and error snapshots are replaced by the current
+/// results and written back. Tests whose result contradicts their `expect`
+/// still fail and are left as they are. The grammar's own tests have no
+/// snapshots, so they are only checked.
+pub fn run(
+    definition: &Arc<ParserDefinition>,
+    grammar: &Path,
+    files: &[PathBuf],
+    update: bool,
+) -> anyhow::Result<()> {
+    let (mut passed, mut failed) = (0, 0);
+    for test in &definition.tests {
+        let case = TestCase::from(test);
+        match check(&case, parse(definition, &case)) {
+            None => {
+                passed += 1;
+                println!("{}::{}: ok", grammar.display(), case.name);
+            }
+            Some(problem) => {
+                failed += 1;
+                println!(
+                    "{}::{}: FAILED\n    {problem}",
+                    grammar.display(),
+                    case.name
+                );
+            }
+        }
+    }
+    for file in files {
+        let mut cases: Vec<TestCase> = serde_yaml::from_str(&std::fs::read_to_string(file)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", file.display()))?;
+        for case in &mut cases {
+            let result = parse(definition, case);
+            let problem = if update {
+                bless(case, result)
+            } else {
+                check(case, result)
+            };
+            match problem {
+                None => {
+                    passed += 1;
+                    println!("{}::{}: ok", file.display(), case.name);
+                }
+                Some(problem) => {
+                    failed += 1;
+                    println!("{}::{}: FAILED\n    {problem}", file.display(), case.name);
+                }
+            }
+        }
+        if update {
+            std::fs::write(file, serde_yaml::to_string(&cases)?)?;
+        }
+    }
+    println!("{passed} passed / {failed} failed");
+    if failed > 0 {
+        anyhow::bail!("{failed} test(s) failed");
+    }
+    Ok(())
+}
+
+fn parse(definition: &Arc<ParserDefinition>, case: &TestCase) -> anyhow::Result<Ast> {
+    let tokens = Token::lexer(&case.input).collect::<Result<Vec<_>, _>>()?;
+    let parser = Parser::new(Arc::clone(definition), tokens);
+    Ok(parser.parse_entry(case.rule.as_deref().unwrap_or("Main"))?)
+}
+
+/// Describes how `result` differs from what `case` expects.
+fn check(case: &TestCase, result: anyhow::Result<Ast>) -> Option<String> {
+    match (case.expect, result) {
+        (Expectation::Ok, Ok(ast)) => match &case.ast {
+            Some(expected) if *expected != ast => Some(format!(
+                "AST differs from snapshot, got:\n{}",
+                indent(&serde_yaml::to_string(&ast).unwrap_or_default())
+            )),
+            _ => None,
+        },
+        (Expectation::Ok, Err(e)) => Some(format!("expected success, got error: {e}")),
+        (Expectation::Error, Ok(_)) => Some("expected an error, but parsing succeeded".into()),
+        (Expectation::Error, Err(e)) => match &case.error {
+            Some(expected) if !e.to_string().contains(expected.as_str()) => {
+                Some(format!("expected error containing `{expected}`, got: {e}"))
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Replaces the snapshot of `case` by `result`. A result that contradicts
+/// `case.expect` is reported like [`check`] does and isn't recorded.
+fn bless(case: &mut TestCase, result: anyhow::Result<Ast>) -> Option<String> {
+    match (case.expect, result) {
+        (Expectation::Ok, Ok(ast)) => {
+            case.ast = Some(ast);
+            None
+        }
+        (Expectation::Error, Err(e)) => {
+            case.error = Some(e.to_string());
+            None
+        }
+        (_, result) => check(case, result),
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
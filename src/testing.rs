@@ -0,0 +1,98 @@
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{Error, Grammar};
+
+/// Environment variable that makes [`assert_parse_snapshot`] store the
+/// current syntax trees instead of comparing them, when set to anything
+/// but `0`.
+pub const UPDATE_VAR: &str = "TMPL_UPDATE_SNAPSHOTS";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Input doesn't parse: {0}")]
+    Parse(#[from] Error),
+    #[error("Failed to access snapshot {}: {error}", .path.display())]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error(
+        "Syntax tree differs from snapshot {}, set {UPDATE_VAR}=1 to accept it\n\
+         --- expected\n{expected}\n+++ actual\n{actual}",
+        .path.display()
+    )]
+    Mismatch {
+        path: PathBuf,
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Parses `input` with `grammar` and compares its syntax tree, serialized as
+/// YAML like in `tmpl test` files, with the snapshot stored at `path`. For
+/// regression tests of grammars:
+///
+/// ```
+/// use tmpl::testing::assert_parse_snapshot;
+///
+/// let grammar = tmpl::Grammar::parse("Main:\n<name:ident> = <value:int> ;\n~~~\n")?;
+/// let path = std::env::temp_dir().join("tmpl-doc-assign.yaml");
+/// # let _ = std::fs::remove_file(&path);
+/// // The first run stores the snapshot, later ones compare against it.
+/// assert_parse_snapshot(&grammar, "x = 1;", &path);
+/// assert_parse_snapshot(&grammar, "x  =  1 ;", &path);
+/// # Ok::<(), tmpl::Error>(())
+/// ```
+///
+/// A missing snapshot is stored and the assertion passes. With the
+/// [`UPDATE_VAR`] environment variable set, every snapshot is replaced by
+/// the current tree, to accept intended changes of the grammar.
+///
+/// # Panics
+///
+/// If `input` doesn't parse, the tree differs from the snapshot or the
+/// snapshot can't be read or written, see [`check_parse_snapshot`].
+#[track_caller]
+pub fn assert_parse_snapshot(grammar: &Grammar, input: &str, path: impl AsRef<Path>) {
+    if let Err(error) = check_parse_snapshot(grammar, input, path) {
+        panic!("{error}");
+    }
+}
+
+/// Compares like [`assert_parse_snapshot`], returning what went wrong
+/// instead of panicking.
+pub fn check_parse_snapshot(
+    grammar: &Grammar,
+    input: &str,
+    path: impl AsRef<Path>,
+) -> Result<(), SnapshotError> {
+    let path = path.as_ref();
+    let io = |error| SnapshotError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    let ast = grammar.parse_str(input)?;
+    let actual = serde_yaml::to_string(&ast).expect("syntax trees serialize to YAML");
+    let update = std::env::var_os(UPDATE_VAR).is_some_and(|value| value != "0");
+    let expected = match std::fs::read_to_string(path) {
+        Ok(expected) if !update => expected,
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => return Err(io(error)),
+        _ => {
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent).map_err(io)?;
+            }
+            return std::fs::write(path, actual).map_err(io);
+        }
+    };
+    // Snapshots checked out on Windows may have gained carriage returns.
+    match expected.replace("\r\n", "\n") == actual {
+        true => Ok(()),
+        false => Err(SnapshotError::Mismatch {
+            path: path.to_path_buf(),
+            expected,
+            actual,
+        }),
+    }
+}
@@ -0,0 +1,67 @@
+use serde::Serialize;
+use tmpl::lexer::Token;
+
+use crate::diagnostics::Span;
+
+/// A token together with where it was found.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenInfo {
+    pub kind: &'static str,
+    /// The token as written in the source.
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+pub fn describe(src: &str, tokens: Vec<(Token, tmpl::lexer::Span)>) -> Vec<TokenInfo> {
+    tokens
+        .into_iter()
+        .map(|(token, span)| {
+            let position = Span::at(src, span.start);
+            TokenInfo {
+                kind: token.kind(),
+                text: src[span.clone()].to_string(),
+                start: span.start,
+                end: span.end,
+                line: position.line,
+                column: position.column,
+            }
+        })
+        .collect()
+}
+
+/// Formats `tokens` as an aligned table with one token per line.
+pub fn table(tokens: &[TokenInfo]) -> String {
+    let rows: Vec<[String; 4]> = tokens
+        .iter()
+        .map(|t| {
+            [
+                format!("{}:{}", t.line, t.column),
+                format!("{}..{}", t.start, t.end),
+                t.kind.to_string(),
+                format!("{:?}", t.text),
+            ]
+        })
+        .collect();
+    let header = ["POSITION", "BYTES", "KIND", "TEXT"].map(String::from);
+    let mut widths = [0; 4];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
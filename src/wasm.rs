@@ -0,0 +1,36 @@
+//! JavaScript bindings, enabled with the `wasm` feature.
+//!
+//! Build with `wasm-pack build tmpl-bindings -- --features wasm` and use it from JS:
+//!
+//! ```js
+//! const grammar = compile_grammar(grammarSource);
+//! const ast = parse(grammar, input);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::Grammar;
+
+/// A compiled grammar, returned by [`compile_grammar`].
+#[wasm_bindgen]
+pub struct GrammarHandle(Grammar);
+
+/// Parses grammar text, throwing an `Error` with the message if it's invalid.
+#[wasm_bindgen]
+pub fn compile_grammar(src: &str) -> Result<GrammarHandle, JsError> {
+    Ok(GrammarHandle(Grammar::parse(src)?))
+}
+
+/// Parses `input` with `grammar` and returns the AST as a plain JS object.
+#[wasm_bindgen]
+pub fn parse(grammar: &GrammarHandle, input: &str) -> Result<JsValue, JsError> {
+    let ast = grammar.0.parse_str(input)?;
+    Ok(serde_wasm_bindgen::to_value(&ast)?)
+}
+
+/// Same as [`parse`], starting at the rule named `entry`.
+#[wasm_bindgen(js_name = parseEntry)]
+pub fn parse_entry(grammar: &GrammarHandle, entry: &str, input: &str) -> Result<JsValue, JsError> {
+    let ast = grammar.0.parse_entry(entry, input)?;
+    Ok(serde_wasm_bindgen::to_value(&ast)?)
+}
@@ -0,0 +1,7 @@
+//! Links `tmpl` into a shared and a static library for its C, Python and
+//! JavaScript bindings, enabled with the `ffi`, `python` and `wasm` features.
+//!
+//! `tmpl` itself only builds as an rlib, as a `cdylib` can't be built
+//! without `std`.
+
+pub use tmpl::*;
@@ -0,0 +1,192 @@
+//! Compile time grammar embedding for `tmpl`.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Checks a grammar while compiling and expands to an expression building its
+/// `tmpl::definition::ParserDefinition`, so syntax errors and references to
+/// unknown rules fail `cargo build` instead of surfacing at runtime.
+///
+/// ```ignore
+/// let definition = tmpl_macros::grammar! {
+///     Main:
+///     <name:ident> = <value:int> ;
+///     ~~~
+/// };
+/// ```
+///
+/// The grammar is embedded precompiled, so no grammar text is parsed at
+/// runtime. The expansion isn't `const`, wrap it in a `LazyLock` to share it.
+///
+/// Grammars that aren't valid Rust tokens, e.g. regexes with a `\`, unbalanced
+/// brackets or `//`, which Rust reads as a comment, can be passed as a string
+/// literal instead: `grammar!(r#"..."#)`.
+#[proc_macro]
+pub fn grammar(input: TokenStream) -> TokenStream {
+    let source = match syn::parse::<syn::LitStr>(input.clone()) {
+        Ok(literal) => Source::literal(literal.value(), input),
+        Err(_) => Source::tokens(input),
+    };
+    match expand(&source) {
+        Ok(expansion) => expansion,
+        Err((message, span)) => compile_error(&message, span),
+    }
+}
+
+/// Grammar text with the spans it came from.
+struct Source {
+    text: String,
+    /// Line and column range in `text` of each token, with the token's span.
+    tokens: Vec<(usize, usize, usize, Span)>,
+    /// Span for errors that can't be pinned to a token.
+    fallback: Span,
+}
+
+impl Source {
+    fn literal(text: String, input: TokenStream) -> Self {
+        let fallback = input
+            .into_iter()
+            .next()
+            .map_or_else(Span::call_site, |t| t.span());
+        Self {
+            text,
+            tokens: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Rebuilds the grammar text from the tokens, keeping line breaks and
+    /// whether tokens were separated by whitespace, which the grammar syntax
+    /// depends on (`kw[x]`, `~~~`).
+    fn tokens(input: TokenStream) -> Self {
+        let mut source = Self {
+            text: String::new(),
+            tokens: Vec::new(),
+            fallback: Span::call_site(),
+        };
+        let mut position = None;
+        source.push_stream(input, &mut position);
+        source
+    }
+
+    fn push_stream(&mut self, stream: TokenStream, position: &mut Option<(usize, usize)>) {
+        for tree in stream {
+            match tree {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.push(open, group.span_open(), position);
+                    self.push_stream(group.stream(), position);
+                    self.push(close, group.span_close(), position);
+                }
+                tree => {
+                    let span = tree.span();
+                    let text = span.source_text().unwrap_or_else(|| tree.to_string());
+                    self.push(&text, span, position);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, text: &str, span: Span, position: &mut Option<(usize, usize)>) {
+        if text.is_empty() {
+            return;
+        }
+        let (line, column) = (span.start().line(), span.start().column());
+        match *position {
+            Some((last_line, _)) if line > last_line => {
+                self.text.push_str(&"\n".repeat(line - last_line));
+                self.text.push_str(&" ".repeat(column.saturating_sub(1)));
+            }
+            Some((_, last_column)) if column > last_column => {
+                self.text.push_str(&" ".repeat(column - last_column));
+            }
+            _ => {}
+        }
+        let text_line = self.text.matches('\n').count() + 1;
+        let text_column = self.text.len() - self.text.rfind('\n').map_or(0, |i| i + 1) + 1;
+        self.tokens
+            .push((text_line, text_column, text_column + text.len(), span));
+        self.text.push_str(text);
+        *position = Some((span.end().line(), span.end().column()));
+    }
+
+    /// Span of the token at `line` and `column` of the text.
+    fn span_at(&self, line: usize, column: usize) -> Span {
+        self.tokens
+            .iter()
+            .find(|(l, start, end, _)| {
+                *l == line && (*start..(*end).max(start + 1)).contains(&column)
+            })
+            .or_else(|| {
+                self.tokens
+                    .iter()
+                    .rev()
+                    .find(|(l, start, _, _)| (*l, *start) <= (line, column))
+            })
+            .map_or(self.fallback, |(_, _, _, span)| *span)
+    }
+}
+
+fn expand(source: &Source) -> Result<TokenStream, (String, Span)> {
+    let definition = match tmpl::definition::parse(&source.text) {
+        Ok(definition) => definition,
+        Err(tmpl::Error::Syntax(e)) => {
+            let span = source.span_at(e.location.line, e.location.column);
+            return Err((format!("invalid grammar: {e}"), span));
+        }
+        Err(tmpl::Error::Definition(e)) if e.span().is_some() => {
+            let offset = e.span().unwrap_or_default().start;
+            let before = &source.text[..offset];
+            let line = before.matches('\n').count() + 1;
+            let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+            return Err((
+                format!("invalid grammar: {e}"),
+                source.span_at(line, column),
+            ));
+        }
+        Err(e) => return Err((e.to_string(), source.fallback)),
+    };
+    let errors: Vec<_> = definition
+        .validate()
+        .into_iter()
+        .filter(|issue| issue.severity == tmpl::definition::Severity::Error)
+        .map(|issue| {
+            let issue = issue.to_string();
+            let issue = issue.strip_prefix("error: ").unwrap_or(&issue);
+            format!("invalid grammar: {issue}")
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err((errors.join("\n"), source.fallback));
+    }
+    let bytes = definition
+        .to_bytes()
+        .map_err(|e| (e.to_string(), source.fallback))?;
+    let bytes: Vec<_> = bytes.iter().map(u8::to_string).collect();
+    let expansion = format!(
+        "::tmpl::definition::ParserDefinition::from_bytes(&[{}]).expect(\"grammar was checked at compile time\")",
+        bytes.join(", ")
+    );
+    Ok(expansion.parse().expect("expansion is valid Rust"))
+}
+
+/// `compile_error!("message")` reported at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut literal = Literal::string(message);
+    literal.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut group = Group::new(Delimiter::Parenthesis, TokenTree::Literal(literal).into());
+    group.set_span(span);
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(group),
+    ]
+    .into_iter()
+    .collect()
+}